@@ -0,0 +1,123 @@
+//! Benchmarks of the hot paths: reading key logs, scoring layouts, and
+//! parsing keyboards.  Run with `cargo bench`, and compare against a
+//! baseline with `cargo bench -- --save-baseline before` and
+//! `cargo bench -- --baseline before`.
+
+use std::{path::PathBuf, sync::Arc};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use keyboard_layout_generator::{
+    capture::write_synthetic_log, delta::SwapScorer, prelude::*, scoring::layout_score_with,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const KEYBOARD: &str = include_str!("../kinesis.layout");
+
+/// Lengths of the generated text, from an afternoon to a few weeks of
+/// typing.
+const TEXT_LENGTHS: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+const WORDS: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "you", "that", "it", "he", "was", "for", "on", "are",
+    "as", "with", "his", "they", "at", "be", "this", "have", "from", "or", "one", "had", "by",
+    "word", "but", "not", "what", "all", "were", "we", "when", "your", "can", "said", "there",
+    "use", "each", "which", "she", "do", "how", "their", "if", "will", "up", "other", "about",
+    "out", "many", "then", "them", "these", "so", "some", "her", "would", "make", "like", "him",
+    "into", "time", "has", "look", "two", "more", "write", "go", "see", "number", "no", "way",
+    "could", "people", "my", "than", "first", "water", "been", "call", "who", "oil", "its", "now",
+    "find", "long", "down", "day", "did", "get", "come", "made", "may", "part", "fn", "let", "mut",
+    "self", "impl", "struct", "vec![]", "Some(x)", "Ok(())", "2024", "42",
+];
+
+/// English prose mixed with some code, the same for every run.
+fn generated_text(len: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(len as u64);
+    let mut text = String::with_capacity(len + 16);
+    while text.len() < len {
+        text.push_str(WORDS.choose(&mut rng).unwrap());
+        text.push_str(match rng.gen_range(0..20) {
+            0 => ". ",
+            1 => ", ",
+            2 => "\n",
+            _ => " ",
+        });
+    }
+    text
+}
+
+/// Writes the key log of typing `text` to a temporary file.
+fn generated_log(text: &str) -> PathBuf {
+    let mut log = vec![];
+    write_synthetic_log(&mut log, text).unwrap();
+    let path =
+        std::env::temp_dir().join(format!("bench_{}_{}.log", text.len(), std::process::id()));
+    std::fs::write(&path, log).unwrap();
+    path
+}
+
+fn bench_process_log(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_log");
+    group.sample_size(10);
+    for len in TEXT_LENGTHS {
+        let path = generated_log(&generated_text(len));
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &path, |b, path| {
+            b.iter(|| process_log(path.to_str().unwrap()).unwrap())
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+    group.finish();
+}
+
+fn bench_layout_score(c: &mut Criterion) {
+    let keymap_config = parse_keymap_config(KEYBOARD).unwrap();
+    let layout = Layout::from_keymap(&keymap_config);
+    let mut group = c.benchmark_group("layout_score");
+    for len in TEXT_LENGTHS {
+        let path = generated_log(&generated_text(len));
+        let stats: Stats = process_log(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(len), &stats, |b, stats| {
+            b.iter(|| layout_score(&layout, stats, &keymap_config))
+        });
+    }
+    group.finish();
+}
+
+fn bench_swap(c: &mut Criterion) {
+    let keymap_config = Arc::new(parse_keymap_config(KEYBOARD).unwrap());
+    let path = generated_log(&generated_text(100_000));
+    let stats = Arc::new(process_log(path.to_str().unwrap()).unwrap());
+    std::fs::remove_file(path).unwrap();
+    let scoring = ScoringConfig::default();
+    let scorer = SwapScorer::new(Arc::clone(&stats), Arc::clone(&keymap_config), &scoring);
+    let mut layout = Layout::from_keymap(&keymap_config);
+    let n = layout.keys().len();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut group = c.benchmark_group("swap");
+    group.bench_function("rescore", |b| {
+        b.iter(|| {
+            layout.swap(rng.gen_range(0..n), rng.gen_range(0..n));
+            layout_score_with(&layout, &stats, &keymap_config, &scoring)
+        })
+    });
+    group.bench_function("delta", |b| {
+        b.iter(|| scorer.swap(&mut layout, rng.gen_range(0..n), rng.gen_range(0..n)))
+    });
+    group.finish();
+}
+
+fn bench_parse_keymap_config(c: &mut Criterion) {
+    c.bench_function("parse_keymap_config", |b| {
+        b.iter(|| parse_keymap_config(KEYBOARD).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_process_log,
+    bench_layout_score,
+    bench_swap,
+    bench_parse_keymap_config
+);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! Prints the fingers and keys described by a `.layout` file.
+//!
+//! ```text
+//! cargo run --example print_keymap -- kinesis.layout
+//! ```
+
+use keyboard_layout_generator::{error::Result, prelude::*};
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "kinesis.layout".to_string());
+    let keymap = parse_keymap_config(&std::fs::read_to_string(path)?)?;
+
+    for finger in &keymap.fingers {
+        println!(
+            "{:?} {:?}: {}",
+            finger.finger.hand, finger.finger.finger, finger.score
+        );
+    }
+    for key in keymap.keys.keys() {
+        println!(
+            "{:?} at {:?} pressed with {:?} {:?}",
+            key.code, key.position, key.finger.hand, key.finger.finger
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Scores the layout printed on the keycaps of a physical keyboard against a
+//! key log.
+//!
+//! ```text
+//! cargo run --example score_keymap -- keys.log kinesis.layout
+//! ```
+
+use keyboard_layout_generator::{error::Result, prelude::*};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let log_file = args.next().expect("missing log file");
+    let keymap_file = args.next().expect("missing keymap config");
+
+    let mut stats = process_log(&log_file)?;
+    let keymap = parse_keymap_config(&std::fs::read_to_string(keymap_file)?)?;
+    let mut scoring = ScoringConfig::default();
+    prepare(
+        &mut stats,
+        &mut scoring,
+        &keymap,
+        &PrepareOptions::default(),
+    );
+    let layout = Layout::from_keymap(&keymap);
+
+    let score = scoring.score(&layout, &stats, &keymap);
+    println!(
+        "Score: {} ({:.2}% of max)",
+        score,
+        100.0 * score / scoring.max_possible_score(&stats)
+    );
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Deciding between two layouts from alternating trial sessions.
+
+use serde::{Deserialize, Serialize};
+
+use crate::trial::TrialReport;
+
+/// Every trial session recorded so far for layouts A and B.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AbHistory {
+    pub a: Vec<TrialReport>,
+    pub b: Vec<TrialReport>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    A,
+    B,
+}
+
+impl AbHistory {
+    pub fn load_or_default(path: &str) -> std::io::Result<Self> {
+        match std::fs::File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Sessions alternate between the layouts, starting with A.
+    pub fn next_variant(&self) -> Variant {
+        if self.a.len() <= self.b.len() {
+            Variant::A
+        } else {
+            Variant::B
+        }
+    }
+
+    pub fn push(&mut self, variant: Variant, report: TrialReport) {
+        match variant {
+            Variant::A => self.a.push(report),
+            Variant::B => self.b.push(report),
+        }
+    }
+
+    pub fn speed_test(&self) -> Option<TTest> {
+        welch_t_test(
+            &self
+                .a
+                .iter()
+                .map(|r| r.chars_per_minute())
+                .collect::<Vec<_>>(),
+            &self
+                .b
+                .iter()
+                .map(|r| r.chars_per_minute())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn error_test(&self) -> Option<TTest> {
+        welch_t_test(
+            &self.a.iter().map(|r| r.error_rate()).collect::<Vec<_>>(),
+            &self.b.iter().map(|r| r.error_rate()).collect::<Vec<_>>(),
+        )
+    }
+}
+
+/// The result of comparing the means of two samples.
+#[derive(Debug, Clone, Copy)]
+pub struct TTest {
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub t: f64,
+    pub degrees_of_freedom: f64,
+    /// Two sided p-value for the means being equal.
+    pub p: f64,
+}
+
+/// Welch's t-test, which doesn't assume both samples have the same
+/// variance.  Needs at least two samples on each side.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> Option<TTest> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (mean_a, var_a) = mean_variance(a);
+    let (mean_b, var_b) = mean_variance(b);
+    let se_a = var_a / a.len() as f64;
+    let se_b = var_b / b.len() as f64;
+    let se = se_a + se_b;
+    if se == 0.0 {
+        return Some(TTest {
+            mean_a,
+            mean_b,
+            t: 0.0,
+            degrees_of_freedom: (a.len() + b.len() - 2) as f64,
+            p: if mean_a == mean_b { 1.0 } else { 0.0 },
+        });
+    }
+    let t = (mean_a - mean_b) / se.sqrt();
+    let degrees_of_freedom =
+        se.powi(2) / (se_a.powi(2) / (a.len() - 1) as f64 + se_b.powi(2) / (b.len() - 1) as f64);
+    let p = student_t_two_sided_p(t, degrees_of_freedom);
+    Some(TTest {
+        mean_a,
+        mean_b,
+        t,
+        degrees_of_freedom,
+        p,
+    })
+}
+
+fn mean_variance(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let variance = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance)
+}
+
+fn student_t_two_sided_p(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// The regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-30;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..200 {
+        let m = m as f64;
+        let numerator = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + numerator / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        h *= d * c;
+
+        let numerator = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + numerator / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Lanczos approximation of ln(Γ(x)).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_student_t_p_value() {
+        let p = student_t_two_sided_p(2.0, 10.0);
+        assert!((p - 0.0734).abs() < 1e-3, "{p}");
+    }
+
+    #[test]
+    fn test_welch_t_test() {
+        let a = [300.0, 310.0, 305.0, 295.0];
+        let b = [250.0, 255.0, 260.0, 245.0];
+        let test = welch_t_test(&a, &b).unwrap();
+        assert!(test.t > 0.0);
+        assert!(test.p < 0.01);
+    }
+}
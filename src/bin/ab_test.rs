@@ -0,0 +1,139 @@
+/// Decide between two layouts by alternating timed trial sessions between
+/// them.
+///
+/// Every run does one trial session with whichever layout is due next,
+/// adds it to the history file, and prints a report comparing the
+/// layouts' speed and error rates with a Welch t-test.
+///
+/// Like `trial`, this is only supported on Linux.
+#[derive(clap::Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The first layout, in the format of `best.txt`.
+    layout_a: String,
+    /// The second layout, in the format of `best.txt`.
+    layout_b: String,
+    /// File the session results are accumulated in.
+    #[arg(long, default_value = "ab_history.json")]
+    history: String,
+    /// Length of the session in minutes.
+    #[arg(long, default_value_t = 10.0)]
+    minutes: f64,
+    /// Only print the report, without running a session.
+    #[arg(long)]
+    report_only: bool,
+    /// p-value below which a difference is considered significant.
+    #[arg(long, default_value_t = 0.05)]
+    significance: f64,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use clap::Parser;
+    use keyboard_layout_generator::{
+        ab_test::{AbHistory, Variant},
+        format::{parse_keymap_config, parse_layout},
+        remap::{uinput, RemapTable},
+        trial::run_session,
+    };
+    use std::time::Duration;
+
+    let args = Args::parse();
+    let mut history = AbHistory::load_or_default(&args.history).unwrap();
+
+    if !args.report_only {
+        let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+        let keymap_config = parse_keymap_config(&keymap_str)
+            .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+        let variant = history.next_variant();
+        let layout_path = match variant {
+            Variant::A => &args.layout_a,
+            Variant::B => &args.layout_b,
+        };
+        let layout = parse_layout(
+            &std::fs::read_to_string(layout_path).unwrap(),
+            &keymap_config,
+        )
+        .unwrap_or_else(|error| error.in_file(layout_path).exit());
+
+        let device =
+            uinput::find_keyboard().expect("No keyboard found, are you in the input group?");
+        let table = RemapTable::new(&layout, &keymap_config);
+        let mut remapper = uinput::UinputRemapper::new(device, table).unwrap();
+        println!(
+            "Session {} with layout {:?}, type away for {} minutes",
+            history.a.len() + history.b.len() + 1,
+            variant,
+            args.minutes
+        );
+        let duration = Duration::from_secs_f64(args.minutes * 60.0);
+        let report = run_session(&mut remapper, duration, |_, _| {}).unwrap();
+        drop(remapper);
+        history.push(variant, report);
+        history.save(&args.history).unwrap();
+    }
+
+    print_report(&history, args.significance);
+}
+
+#[cfg(target_os = "linux")]
+fn print_report(history: &keyboard_layout_generator::ab_test::AbHistory, significance: f64) {
+    println!(
+        "\nSessions: {} with A, {} with B",
+        history.a.len(),
+        history.b.len()
+    );
+    let Some(speed) = history.speed_test() else {
+        println!("At least two sessions with each layout are needed for a verdict");
+        return;
+    };
+    let errors = history.error_test().unwrap();
+
+    println!(
+        "Chars per minute: A {:.1}, B {:.1} (p = {:.3})",
+        speed.mean_a, speed.mean_b, speed.p
+    );
+    println!(
+        "Error rate: A {:.2}%, B {:.2}% (p = {:.3})",
+        errors.mean_a * 100.0,
+        errors.mean_b * 100.0,
+        errors.p
+    );
+
+    let faster = if speed.mean_a > speed.mean_b {
+        "A"
+    } else {
+        "B"
+    };
+    let more_accurate = if errors.mean_a < errors.mean_b {
+        "A"
+    } else {
+        "B"
+    };
+    let speed_significant = speed.p < significance;
+    let errors_significant = errors.p < significance;
+    match (speed_significant, errors_significant) {
+        (true, true) if faster == more_accurate => {
+            println!("Verdict: {faster} is both faster and more accurate")
+        }
+        (true, true) => println!(
+            "Verdict: {faster} is faster but {more_accurate} is more accurate, it's a trade-off"
+        ),
+        (true, false) => println!("Verdict: {faster} is faster, accuracy is indistinguishable"),
+        (false, true) => {
+            println!("Verdict: {more_accurate} is more accurate, speed is indistinguishable")
+        }
+        (false, false) => println!("Verdict: no significant difference yet, keep practicing"),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    use clap::Parser;
+
+    let _ = Args::parse();
+    eprintln!("A/B testing is only supported on Linux");
+    std::process::exit(1);
+}
@@ -0,0 +1,189 @@
+use clap::Parser;
+use keyboard_layout_generator::{
+    combos::{propose_combos, ComboConfig},
+    config::{Config, NumberFormat},
+    format::{layers_to_str, parse_keymap_config, parse_layout},
+    language::{
+        language_metrics, propose_language_layer, read_language_corpora, LanguageLayerConfig,
+    },
+    layout::Layout,
+    numeric::{
+        digit_counts, recommend_number_entry, NumberEntryConfig, NumberEntryOption, NumericUsage,
+    },
+    report::{ignored_keys_summary, BigramMatrix},
+    stats::read_merged_stats,
+};
+use std::io::IsTerminal;
+
+/// Analyze your typing against a layout and recommend improvements that
+/// go beyond placing keys, such as how to enter numbers.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`.
+    log_file: String,
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to analyze, in the format of `best.txt`.  Defaults
+    /// to the keys printed in the keymap configuration.
+    #[arg(long)]
+    layout: Option<String>,
+    /// If a numpad layer is recommended, write it to this file along
+    /// with the base layout.
+    #[arg(long)]
+    numpad_output: Option<String>,
+    /// Print the bigram matrix of this many most typed characters,
+    /// marking which bigrams the layout types with the same finger or hand.
+    #[arg(long)]
+    bigrams: Option<usize>,
+    /// Propose up to this many combos, pairs of adjacent keys pressed
+    /// together to type a frequent bigram or trigram.
+    #[arg(long)]
+    combos: Option<usize>,
+    /// Text in another language, like `es=novel.txt`, to report how well
+    /// the layout fits it and propose a layer for the characters it adds.
+    /// Can be given multiple times.
+    #[arg(long)]
+    language: Vec<String>,
+    /// Settings such as how numbers are formatted.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = Config::load_or_default(&args.config).unwrap();
+    let fmt = &config.number_format;
+    let log_options = config
+        .log_options(&[])
+        .unwrap_or_else(|error| error.in_file(&args.config).exit());
+    let stats =
+        read_merged_stats(&[&args.log_file], &log_options).unwrap_or_else(|error| error.exit());
+    if let Some(summary) = ignored_keys_summary(&stats, fmt) {
+        println!("Warning: {}", summary);
+    }
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = match &args.layout {
+        Some(path) => parse_layout(&std::fs::read_to_string(path).unwrap(), &keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Layout::from_keymap(&keymap_config),
+    };
+
+    print_number_entry(&args, &layout, &stats, &keymap_config, fmt);
+
+    if let Some(k) = args.bigrams {
+        let matrix = BigramMatrix::top(&stats, k);
+        println!("\nBigrams:");
+        print!(
+            "{}",
+            matrix.render(
+                &layout,
+                &keymap_config,
+                fmt,
+                std::io::stdout().is_terminal()
+            )
+        );
+    }
+
+    if let Some(max_combos) = args.combos {
+        let combo_config = ComboConfig {
+            max_combos,
+            ..Default::default()
+        };
+        println!("\nCombos:");
+        for combo in propose_combos(&layout, &stats, &keymap_config, &combo_config) {
+            let (a, b) = combo.keys;
+            println!(
+                "{:?} + {:?} -> {:?}: typed {} times, saves {}",
+                keymap_config.keys.keys()[a].code,
+                keymap_config.keys.keys()[b].code,
+                combo.output,
+                fmt.count(combo.count),
+                fmt.decimal(combo.benefit)
+            );
+        }
+    }
+
+    let languages = read_language_corpora(&args.language).unwrap_or_else(|error| error.exit());
+    for corpus in &languages {
+        let metrics = language_metrics(&layout, corpus, &keymap_config, &config.scoring);
+        println!("\nLanguage {}:", metrics.language);
+        println!(
+            "Score: {}, same finger bigrams: {}, hand alternation: {}, letters not on the layout: {}",
+            fmt.percent(metrics.score),
+            fmt.percent(metrics.same_finger_rate),
+            fmt.percent(metrics.alternation_rate),
+            fmt.percent(metrics.extra_char_rate)
+        );
+        let layer_config = LanguageLayerConfig::default();
+        if let Some(layer) = propose_language_layer(&layout, corpus, &keymap_config, &layer_config)
+        {
+            let physical = keymap_config.keys.keys();
+            println!(
+                "Layer for the extra letters held on {:?}:",
+                physical[layer.hold].code
+            );
+            for (c, i) in layer.chars {
+                println!(
+                    "  {} on {:?}: typed {} times",
+                    c,
+                    physical[i].code,
+                    fmt.count(corpus.extra_char_counts[&c])
+                );
+            }
+        }
+    }
+}
+
+fn print_number_entry(
+    args: &Args,
+    layout: &Layout,
+    stats: &keyboard_layout_generator::stats::Stats,
+    keymap_config: &keyboard_layout_generator::KeymapConfig,
+    fmt: &NumberFormat,
+) {
+    let usage = NumericUsage::from_stats(stats);
+    println!("Number entry:");
+    println!(
+        "{} digits typed, {} of them in {} bursts, {} on their own",
+        fmt.count(usage.digit_presses),
+        fmt.count(usage.burst_digits),
+        fmt.count(usage.bursts),
+        fmt.count(usage.isolated_digits())
+    );
+    for (code, count) in digit_counts(stats) {
+        println!("{:?}: {}", code, fmt.count(count));
+    }
+
+    let estimates =
+        recommend_number_entry(layout, stats, keymap_config, &NumberEntryConfig::default());
+    println!("\nProjected effort:");
+    for estimate in &estimates {
+        let name = match &estimate.option {
+            NumberEntryOption::NumberRow => "Number row".to_string(),
+            NumberEntryOption::NumpadLayer(layer) => format!(
+                "Numpad layer held on {:?}",
+                keymap_config.keys.keys()[layer.hold].code
+            ),
+            NumberEntryOption::NumpadBlock => "Separate numpad".to_string(),
+        };
+        println!("{}: {}", name, fmt.decimal(estimate.effort));
+    }
+
+    let best = &estimates[0];
+    match &best.option {
+        NumberEntryOption::NumberRow => println!("\nRecommendation: keep using the number row"),
+        NumberEntryOption::NumpadBlock => println!("\nRecommendation: use a separate numpad"),
+        NumberEntryOption::NumpadLayer(layer) => {
+            println!("\nRecommendation: use a numpad layer");
+            if let Some(path) = &args.numpad_output {
+                let s = layers_to_str(layout, std::slice::from_ref(layer), keymap_config).unwrap();
+                std::fs::write(path, s).unwrap();
+                println!("Layers written to {}", path);
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+/// Use a generated layout system-wide by remapping the keyboard live, for
+/// as long as this runs, without flashing firmware or installing an OS
+/// layout.
+///
+/// Pressing the panic hotkey, Ctrl+Alt+Escape on the physical keys by
+/// default, ends the remap at once and gives you your keyboard back as it
+/// was, wherever the layout moved those keys.
+///
+/// Remapping uses evdev and uinput, so this is only supported on Linux and
+/// usually needs root or membership in the `input` group.  It works the
+/// same under X11 and Wayland.
+#[derive(clap::Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to apply, in the format of `best.txt`.
+    layout: String,
+    /// Comma separated physical keys that end the remap when held
+    /// together.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "LControl,LAlt,Escape",
+        value_parser = device_query::Keycode::from_str
+    )]
+    panic_hotkey: Vec<device_query::Keycode>,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use clap::Parser;
+    use keyboard_layout_generator::{
+        format::{parse_keymap_config, parse_layout},
+        remap::{uinput, RemapTable},
+    };
+    use std::collections::HashSet;
+
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = parse_layout(
+        &std::fs::read_to_string(&args.layout).unwrap(),
+        &keymap_config,
+    )
+    .unwrap_or_else(|error| error.in_file(&args.layout).exit());
+
+    let device = uinput::find_keyboard().expect("No keyboard found, are you in the input group?");
+    let table = RemapTable::new(&layout, &keymap_config);
+    let mut remapper = uinput::UinputRemapper::new(device, table).unwrap();
+
+    println!(
+        "Applied {}, press {:?} to go back to your keyboard as it was",
+        args.layout, args.panic_hotkey
+    );
+    // The hotkey is matched on the physical keys, so it's where it always
+    // was no matter where the layout put those keys
+    let mut held = HashSet::new();
+    let mut panicked = false;
+    while !panicked {
+        remapper
+            .process_physical(|physical, _, press| {
+                if press {
+                    held.insert(physical);
+                    panicked |= !args.panic_hotkey.is_empty()
+                        && args.panic_hotkey.iter().all(|key| held.contains(key));
+                } else {
+                    held.remove(&physical);
+                }
+            })
+            .unwrap();
+    }
+    // Releases the keys still held and the grab on the keyboard
+    drop(remapper);
+    println!("Remap ended");
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    use clap::Parser;
+
+    let _ = Args::parse();
+    eprintln!("Applying a layout is only supported on Linux");
+    std::process::exit(1);
+}
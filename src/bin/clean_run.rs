@@ -0,0 +1,42 @@
+use clap::Parser;
+use keyboard_layout_generator::retention::{prune_history, Retention};
+
+/// Prune the `--history` of `explore_layouts` runs so they don't keep
+/// growing on disk.
+///
+/// Keeps the last few generations, the best generation of every day, and
+/// always the last one.  `best.txt`, `top/` and `pareto/` are only ever
+/// overwritten, so they're left alone.
+#[derive(Parser)]
+struct Args {
+    /// The histories to prune, written with `explore_layouts --history`.
+    #[arg(required = true)]
+    histories: Vec<String>,
+    /// How many of the last generations to keep.
+    #[arg(long, default_value_t = 1000)]
+    keep_last: usize,
+    /// Only keep the last generations, not the best one of every day.
+    #[arg(long)]
+    no_daily_bests: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let retention = Retention {
+        keep_last: args.keep_last,
+        daily_bests: !args.no_daily_bests,
+    };
+    for path in &args.histories {
+        let size = || std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let before = size();
+        let removed =
+            prune_history(path, &retention).unwrap_or_else(|error| error.in_file(path).exit());
+        println!(
+            "{}: removed {} generations, {} bytes to {} bytes",
+            path,
+            removed,
+            before,
+            size()
+        );
+    }
+}
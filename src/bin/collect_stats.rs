@@ -1,48 +1,308 @@
+use std::{
+    fs::File,
+    io::{BufWriter, ErrorKind, Write},
+    str::FromStr,
+};
+
 use clap::Parser;
-use keyboard_layout_generator::stats::{process_log, Stats};
+use keyboard_layout_generator::{
+    capture::parse_date,
+    config::{Config, NumberFormat},
+    error::Error,
+    privacy::{anonymize, PrivacyConfig},
+    report::ignored_keys_summary,
+    sessions::{find_sessions, last_sessions_start, TypingSession},
+    stats::{process_text, read_stats_by_device, Stats, INTERVAL_BUCKET_MS, WPM_WINDOW_CHARS},
+    stats_export::{frequency_tables, write_csv, write_json},
+};
+
+/// How often a pair of keys has to be timed to be listed among the fastest
+/// and slowest transitions.
+const MIN_TRANSITION_SAMPLES: u64 = 10;
+const TRANSITIONS_SHOWN: usize = 10;
 
 #[derive(Parser)]
 struct Args {
-    log_file: String,
+    /// Logs created by the keylogger, or stats saved with `--save`.  The
+    /// statistics of all of them are combined, e.g. to include both a
+    /// desktop and a laptop.
+    #[arg(required_unless_present = "corpus")]
+    log_files: Vec<String>,
+    /// Text files, like documents or source code, to count as if they
+    /// had been typed.  Can be given multiple times.
+    #[arg(long)]
+    corpus: Vec<String>,
+    /// Leave out log sessions with this tag, e.g. `gaming`, which the
+    /// keylogger marks with `--tag-hotkey`.  Can be given multiple times.
+    #[arg(long)]
+    exclude_tagged: Vec<String>,
+    /// Only count what was typed on this keyboard, as named with
+    /// `keylogger --device`.  Without it, logs typed on several keyboards
+    /// get a section for every keyboard.
+    #[arg(long)]
+    device: Option<String>,
+    /// The system layout the log was typed on, like `de`, `fr` or
+    /// `nordic`, or the path of a TOML table of what the keys typed.
+    /// Overrides `char_table` in the config.
+    #[arg(long)]
+    char_table: Option<String>,
+    /// Only count the last few typing sessions, split wherever typing
+    /// paused for `session_gap_minutes` in the config.  Needs a log with
+    /// timestamps.
+    #[arg(long)]
+    last_sessions: Option<usize>,
+    /// Only count what was typed from this UTC date on, like `2024-05-01`
+    /// or `2024-05-01T14:30`.  Needs a log with timestamps.
+    #[arg(long, value_parser = parse_date)]
+    since: Option<u64>,
+    /// Only count what was typed before this UTC date, like `2024-05-08`.
+    #[arg(long, value_parser = parse_date)]
+    until: Option<u64>,
+    /// Anonymize the statistics before printing them so they can be
+    /// shared.  Rare counts get noise added and are dropped if they
+    /// fall below `--min-count`.
+    #[arg(long)]
+    anonymize: bool,
+    /// Counts below this are perturbed when anonymizing.
+    #[arg(long, default_value_t = 100)]
+    noise_threshold: u64,
+    /// Scale of the Laplace noise added when anonymizing.
+    #[arg(long, default_value_t = 2.0)]
+    noise_scale: f64,
+    /// Counts below this are dropped when anonymizing.
+    #[arg(long, default_value_t = 5)]
+    min_count: u64,
+    /// Settings such as how numbers are formatted.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// Also save the statistics to this JSON file, which `explore_layouts`
+    /// and `analyze` accept in place of the log.
+    #[arg(long)]
+    save: Option<String>,
+    /// `text` prints the statistics to read them, `json` or `csv` writes
+    /// their frequency tables for spreadsheets or pandas.
+    #[arg(long, default_value = "text", value_parser = StatsFormat::from_str)]
+    format: StatsFormat,
+    /// Write the tables of `--format json` or `csv` to this file and print
+    /// the statistics as text too.  Without it, only the tables are
+    /// written, to the standard output.
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StatsFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            "csv" => Ok(StatsFormat::Csv),
+            _ => Err(format!("expected text, json or csv, got {s}")),
+        }
+    }
 }
 
 fn main() {
     let start = std::time::Instant::now();
 
     let args = Args::parse();
+    if args.format == StatsFormat::Text && args.output.is_some() {
+        Error::Invalid("--output needs --format json or csv".to_string()).exit();
+    }
+    let mut config = Config::load_or_default(&args.config).unwrap();
+    if args.char_table.is_some() {
+        config.char_table = args.char_table.clone();
+    }
+    let fmt = &config.number_format;
 
-    let stats = process_log(&args.log_file);
-    print_statistics(&stats);
+    let mut log_options = config
+        .log_options(&args.exclude_tagged)
+        .unwrap_or_else(|error| error.in_file(&args.config).exit());
+    log_options.device = args.device.clone();
+    log_options.since = args.since;
+    log_options.until = args.until;
+    let sessions = find_sessions(&args.log_files, config.session_gap_ms(), &log_options)
+        .unwrap_or_else(|error| error.exit());
+    if let Some(n) = args.last_sessions {
+        log_options.since = last_sessions_start(&sessions, n);
+    }
+    let mut devices =
+        read_stats_by_device(&args.log_files, &log_options).unwrap_or_else(|error| error.exit());
+    devices.retain(|(device, _)| args.device.is_none() || *device == args.device);
+    let mut stats = Stats::new();
+    for (_, device_stats) in &devices {
+        stats.merge(device_stats.clone());
+    }
+    for path in &args.corpus {
+        stats.merge(process_text(path, &log_options.chars).unwrap_or_else(|error| error.exit()));
+    }
+    if args.anonymize {
+        let privacy_config = PrivacyConfig {
+            noise_threshold: args.noise_threshold,
+            noise_scale: args.noise_scale,
+            min_count: args.min_count,
+        };
+        anonymize(&mut stats, &privacy_config, &mut rand::thread_rng());
+        for (_, device_stats) in &mut devices {
+            anonymize(device_stats, &privacy_config, &mut rand::thread_rng());
+        }
+    }
+    if let Some(path) = &args.save {
+        stats.save(path).unwrap();
+    }
+    if args.format != StatsFormat::Text {
+        export(&stats, args.format, args.output.as_deref());
+        if args.output.is_none() {
+            // Nothing but the tables, so they can be piped into other tools
+            return;
+        }
+    }
+    print_statistics(&stats, fmt);
+    if devices.len() > 1 {
+        for (device, device_stats) in &devices {
+            print_device_section(device.as_deref(), device_stats, fmt);
+        }
+        println!("Pass --device to collect the stats of a single keyboard");
+    }
+    print_sessions(&sessions, args.last_sessions, fmt);
 
     let elapsed = start.elapsed();
 
     println!(
-        "\nProcessed {} log lines in {}.{:03} seconds",
-        stats.total_log_lines,
-        elapsed.as_secs(),
-        elapsed.subsec_millis()
+        "\nProcessed {} log lines in {} seconds",
+        fmt.count(stats.total_log_lines),
+        fmt.decimal_with_precision(elapsed.as_secs_f64(), 3)
     );
     println!(
         "{} lines per second",
-        stats.total_log_lines as f64 / elapsed.as_secs_f64()
+        fmt.decimal(stats.total_log_lines as f64 / elapsed.as_secs_f64())
     );
+    if let Some(summary) = ignored_keys_summary(&stats, fmt) {
+        println!("Warning: {}", summary);
+    }
 }
 
-fn print_statistics(stats: &Stats) {
+/// Writes the frequency tables of `stats` in `format` to `path`, or to the
+/// standard output.
+fn export(stats: &Stats, format: StatsFormat, path: Option<&str>) {
+    let tables = frequency_tables(stats);
+    let write = |w: &mut dyn Write| match format {
+        StatsFormat::Json => write_json(&tables, &mut &mut *w),
+        StatsFormat::Csv => write_csv(&tables, &mut &mut *w),
+        StatsFormat::Text => unreachable!("text isn't exported"),
+    };
+    let result = match path {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut w = BufWriter::new(file);
+            write(&mut w)?;
+            w.flush()
+        }),
+        None => write(&mut std::io::stdout().lock()),
+    };
+    match result {
+        Ok(()) => {}
+        // Like piping into `head`, which needed no more
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => {}
+        Err(error) => Error::from(error)
+            .in_file(path.unwrap_or("the standard output"))
+            .exit(),
+    }
+}
+
+/// The start, length and key presses of every typing session, marking
+/// those that were counted.
+fn print_sessions(sessions: &[TypingSession], last: Option<usize>, fmt: &NumberFormat) {
+    if sessions.is_empty() {
+        return;
+    }
+    println!("\nTyping sessions:");
+    let counted_from = sessions
+        .len()
+        .saturating_sub(last.unwrap_or(sessions.len()));
+    for (i, session) in sessions.iter().enumerate() {
+        println!(
+            "{}Started at {}, {} minutes, {} key presses",
+            if i >= counted_from {
+                ""
+            } else {
+                "(not counted) "
+            },
+            session.start,
+            fmt.decimal(session.duration_ms() as f64 / 60_000.0),
+            fmt.count(session.key_presses)
+        );
+    }
+}
+
+/// A summary of what was typed on one keyboard, to compare keyboards
+/// without reading every table for each.
+fn print_device_section(device: Option<&str>, stats: &Stats, fmt: &NumberFormat) {
+    let presses: u64 = stats.individual_key_counts.values().sum();
+    let bigrams: u64 = stats.consecutive_key_counts.values().sum();
+    println!(
+        "\nDevice {}: {} key presses, {} log lines",
+        device.unwrap_or("without a name"),
+        fmt.count(presses),
+        fmt.count(stats.total_log_lines)
+    );
+    let mut individual_key_counts: Vec<_> = stats.individual_key_counts.iter().collect();
+    individual_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+    for (key, count) in individual_key_counts.iter().take(10) {
+        println!(
+            "  {:?}: {}",
+            key,
+            fmt.percent(**count as f64 / presses.max(1) as f64)
+        );
+    }
+    let mut consecutive_key_counts: Vec<_> = stats.consecutive_key_counts.iter().collect();
+    consecutive_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+    for (keys, count) in consecutive_key_counts.iter().take(10) {
+        println!(
+            "  {:?} -> {:?}: {}",
+            keys.0,
+            keys.1,
+            fmt.percent(**count as f64 / bigrams.max(1) as f64)
+        );
+    }
+}
+
+fn print_statistics(stats: &Stats, fmt: &NumberFormat) {
     let mut individual_key_counts: Vec<_> = stats.individual_key_counts.iter().collect();
     individual_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
 
     println!("Individual key counts:");
     for (key, count) in individual_key_counts {
-        println!("{:?}: {}", key, count);
+        println!("{:?}: {}", key, fmt.count(*count));
     }
 
-    let mut consectutive_key_counts: Vec<_> = stats.consectutive_key_counts.iter().collect();
-    consectutive_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+    let mut consecutive_key_counts: Vec<_> = stats.consecutive_key_counts.iter().collect();
+    consecutive_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
 
     println!("\nConsecutive key counts:");
-    for (keys, count) in consectutive_key_counts {
-        println!("{:?} -> {:?}: {}", keys.0, keys.1, count);
+    for (keys, count) in consecutive_key_counts {
+        println!("{:?} -> {:?}: {}", keys.0, keys.1, fmt.count(*count));
+    }
+
+    let mut trigram_key_counts: Vec<_> = stats.trigram_key_counts.iter().collect();
+    trigram_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nTrigram key counts:");
+    for (keys, count) in trigram_key_counts {
+        println!(
+            "{:?} -> {:?} -> {:?}: {}",
+            keys.0,
+            keys.1,
+            keys.2,
+            fmt.count(*count)
+        );
     }
 
     let mut simultaneous_key_counts: Vec<_> = stats.simultaneous_key_counts.iter().collect();
@@ -50,7 +310,15 @@ fn print_statistics(stats: &Stats) {
 
     println!("\nSimultaneous key counts:");
     for (keys, count) in simultaneous_key_counts {
-        println!("{:?}: {}", keys, count);
+        println!("{:?}: {}", keys, fmt.count(*count));
+    }
+
+    let mut rollover_key_counts: Vec<_> = stats.rollover_key_counts.iter().collect();
+    rollover_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nRollover key counts:");
+    for (keys, count) in rollover_key_counts {
+        println!("{:?}: {}", keys, fmt.count(*count));
     }
 
     let mut char_counts: Vec<_> = stats.char_counts.iter().collect();
@@ -58,7 +326,15 @@ fn print_statistics(stats: &Stats) {
 
     println!("\nCharacter counts:");
     for (c, count) in char_counts {
-        println!("{}: {}", c, count);
+        println!("{}: {}", c, fmt.count(*count));
+    }
+
+    let mut shifted_char_counts: Vec<_> = stats.shifted_char_counts.iter().collect();
+    shifted_char_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nShifted character counts:");
+    for ((c, hand), count) in shifted_char_counts {
+        println!("{} with the {:?} Shift: {}", c, hand, fmt.count(*count));
     }
 
     let mut consecutive_char_counts: Vec<_> = stats.consecutive_char_counts.iter().collect();
@@ -66,6 +342,138 @@ fn print_statistics(stats: &Stats) {
 
     println!("\nConsecutive character counts:");
     for (chars, count) in consecutive_char_counts {
-        println!("{:?} -> {:?}: {}", chars.0, chars.1, count);
+        println!("{:?} -> {:?}: {}", chars.0, chars.1, fmt.count(*count));
+    }
+
+    let mut trigram_char_counts: Vec<_> = stats.trigram_char_counts.iter().collect();
+    trigram_char_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nTrigram character counts:");
+    for (chars, count) in trigram_char_counts {
+        println!(
+            "{:?} -> {:?} -> {:?}: {}",
+            chars.0,
+            chars.1,
+            chars.2,
+            fmt.count(*count)
+        );
+    }
+
+    let mut interval_histogram: Vec<_> = stats.interval_histogram.iter().collect();
+    interval_histogram.sort_by_key(|x| x.0);
+
+    if !interval_histogram.is_empty() {
+        println!("\nIntervals between key presses:");
+        for (bucket, count) in interval_histogram {
+            println!(
+                "{}-{} ms: {}",
+                bucket,
+                bucket + INTERVAL_BUCKET_MS,
+                fmt.count(*count)
+            );
+        }
+    }
+
+    if !stats.rolling_wpm.is_empty() {
+        let mut wpm = stats.rolling_wpm.clone();
+        wpm.sort_by(f64::total_cmp);
+        println!(
+            "\nTyping speed over every {} characters: median {:.0} WPM, slowest {:.0}, fastest {:.0}, latest {:.0}",
+            WPM_WINDOW_CHARS,
+            wpm[wpm.len() / 2],
+            wpm[0],
+            wpm[wpm.len() - 1],
+            stats.rolling_wpm[stats.rolling_wpm.len() - 1]
+        );
+    }
+
+    let transitions = stats.latencies.transitions_by_speed(MIN_TRANSITION_SAMPLES);
+    if !transitions.is_empty() {
+        let shown = transitions.len().min(TRANSITIONS_SHOWN);
+        println!("\nFastest transitions:");
+        for (keys, median) in &transitions[..shown] {
+            println!("{:?} -> {:?}: {:.0} ms", keys.0, keys.1, median);
+        }
+        println!("\nSlowest transitions:");
+        for (keys, median) in transitions.iter().rev().take(shown) {
+            println!("{:?} -> {:?}: {:.0} ms", keys.0, keys.1, median);
+        }
+    }
+
+    let mut error_key_counts: Vec<_> = stats.error_key_counts.iter().collect();
+    error_key_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nMistyped keys:");
+    for (key, count) in error_key_counts {
+        println!(
+            "{:?}: {} ({})",
+            key,
+            fmt.count(*count),
+            fmt.percent(stats.error_rate(*key))
+        );
+    }
+
+    let mut confusion_counts: Vec<_> = stats.confusion_counts.iter().collect();
+    confusion_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nConfused keys:");
+    for (keys, count) in confusion_counts {
+        println!(
+            "{:?} instead of {:?}: {}",
+            keys.0,
+            keys.1,
+            fmt.count(*count)
+        );
+    }
+
+    if !stats.correction_lengths.is_empty() {
+        println!(
+            "\nCorrections: {} per character typed",
+            fmt.percent(stats.correction_rate())
+        );
+        let mut correction_lengths: Vec<_> = stats.correction_lengths.iter().collect();
+        correction_lengths.sort_by_key(|x| x.0);
+        for (length, count) in correction_lengths {
+            println!("{} backspaces: {}", length, fmt.count(*count));
+        }
+
+        let mut corrected_char_counts: Vec<_> = stats.corrected_char_counts.iter().collect();
+        corrected_char_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+        println!("\nCharacters typed before a correction:");
+        for (c, count) in corrected_char_counts {
+            println!("{:?}: {}", c, fmt.count(*count));
+        }
+
+        let mut correction_bigram_counts: Vec<_> = stats.correction_bigram_counts.iter().collect();
+        correction_bigram_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+        println!("\nBigrams typed before a correction:");
+        for (keys, count) in correction_bigram_counts {
+            println!("{:?} -> {:?}: {}", keys.0, keys.1, fmt.count(*count));
+        }
+    }
+
+    let mut word_counts: Vec<_> = stats.word_counts.iter().collect();
+    word_counts.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+    println!("\nWord counts:");
+    for (word, count) in word_counts {
+        println!("{}: {}", word, fmt.count(*count));
+    }
+
+    if !stats.log_headers.is_empty() {
+        println!("\nLog segments:");
+        for header in &stats.log_headers {
+            println!(
+                "Started at {} on {} ({}, layout {}) with keylogger {}, keyboards: {}",
+                header.start_time,
+                header.hostname.as_deref().unwrap_or("an unknown host"),
+                header.os,
+                header.os_layout.as_deref().unwrap_or("unknown"),
+                header.version,
+                header.devices.join(", ")
+            );
+        }
     }
 }
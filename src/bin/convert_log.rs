@@ -0,0 +1,49 @@
+use std::{fs::File, io::BufWriter};
+
+use clap::Parser;
+use keyboard_layout_generator::capture::{read_log, LogFormat, LogWriter};
+
+/// Convert a key log between the text format and the compact binary one.
+///
+/// Binary logs are several times smaller and much faster to read, and
+/// every tool reading logs reads both.  Converting back to text gives the
+/// same log, so nothing is lost either way.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log to convert, in either format.
+    input: String,
+    /// Where to write the converted log.
+    output: String,
+    /// Convert to `binary` or back to `text`.
+    #[arg(long, default_value = "binary")]
+    format: LogFormat,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.input == args.output {
+        println!(
+            "Write the converted log to another file than {}",
+            args.input
+        );
+        std::process::exit(1);
+    }
+    let lines = read_log(&args.input).unwrap_or_else(|error| error.in_file(&args.input).exit());
+    let file = BufWriter::new(File::create(&args.output).unwrap());
+    let mut writer = LogWriter::new(file, args.format).unwrap();
+    let mut count: u64 = 0;
+    for line in lines {
+        let line = line.unwrap_or_else(|error| error.in_file(&args.input).exit());
+        writer.write_line(&line).unwrap();
+        count += 1;
+    }
+    writer.flush().unwrap();
+
+    let size = |path: &str| std::fs::metadata(path).unwrap().len();
+    println!(
+        "Converted {} lines, {} bytes to {} bytes",
+        count,
+        size(&args.input),
+        size(&args.output)
+    );
+}
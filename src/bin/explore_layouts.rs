@@ -1,17 +1,42 @@
-use ahash::{HashMap, HashMapExt, HashSet};
 use clap::Parser;
 use device_query::Keycode;
-use genetic::{Crossover, DiversifyStrategy, Gen, Mutate};
+use genetic::{DiversifyStrategy, Gen, Mutate};
 use keyboard_layout_generator::{
-    layout_format::{map_keycode_to_str, parse_keymap_config, write_grid, GridItem},
-    stats::{process_log, Stats},
-    Finger, FingerKind, KeymapConfig, PhysicalKey,
+    cache::{layout_fingerprint, ScoreMemo},
+    capture::{now_millis, parse_date},
+    config::{Config, NumberFormat},
+    dashboard::{Dashboard, Progress},
+    delta::SwapScorer,
+    error::Error,
+    format::{layout_to_str, parse_keymap_config, parse_layout},
+    language::{parse_tagged, read_language_corpora},
+    layout::{adaptive_mutation_rate, layout_similarity, Key, Layout, Preset},
+    pareto::{pareto_ranks, random_weights, scalarize, Objective, Objectives, ParetoFront},
+    report::{ignored_keys_summary, key_moves, score_breakdown, IntuitionTracker},
+    retention::{HistoryWriter, Retention},
+    run_file::RunFile,
+    scenarios::{Aggregation, Scenarios},
+    scoring::{
+        hand_patterns, home_row_mods, load_intuitions, same_finger_bigrams, thumb_load,
+        LayoutScorer, ScoringConfig, Weights,
+    },
+    search::{
+        hill_climbing, simmulated_annealing, simmulated_annealing_in_place, tabu_search, Schedule,
+        SwapObjective,
+    },
+    sessions::{find_sessions, last_sessions_start},
+    stats::{prepare, process_text, read_merged_stats, PrepareOptions, Stats},
+    trial::LatencyStats,
+    KeymapConfig,
 };
-use rand::{seq::SliceRandom, Rng};
+use rand::Rng;
 use rayon::prelude::*;
 use std::{
-    cell::LazyCell,
-    io::{BufWriter, Write},
+    collections::HashMap,
+    io::{BufWriter, IsTerminal, Write},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 /// A tool to explore different keyboard layouts using a
@@ -22,868 +47,1154 @@ use std::{
 /// diversity of the population.  The program will also save
 /// the best layout to a file called `best.txt`.
 ///
-/// The program will run indefinitely, so you will need to
-/// manually stop it when you are satisfied with the results.
+/// The program will run until it's stopped, unless told to stop after
+/// a number of generations, a duration, or once the best score stops
+/// improving.
 #[derive(Parser)]
+#[command(args_override_self = true)]
 struct Args {
-    /// Path to the log file created by the keylogger.
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`.
     log_file: String,
     /// Path to a keymap configuration file describing the
     /// layout of the physical keyboard.  See the README for
     /// more information.
     keymap_config: String,
+    /// Latencies measured by `trial --typing-test`.  Consecutive keys
+    /// are weighted by how slow they were to type.
+    #[arg(long)]
+    latencies: Option<String>,
+    /// Weight consecutive keys by how slow they were to type according to
+    /// the timestamps in the log.
+    #[arg(long)]
+    weight_by_timing: bool,
+    /// Only count the last few typing sessions of the logs, split wherever
+    /// typing paused for `session_gap_minutes` in the config.
+    #[arg(long)]
+    last_sessions: Option<usize>,
+    /// Only count what was typed from this UTC date on, like `2024-05-01`
+    /// or `2024-05-01T14:30`.
+    #[arg(long, value_parser = parse_date)]
+    since: Option<u64>,
+    /// Only count what was typed before this UTC date, like `2024-05-08`.
+    #[arg(long, value_parser = parse_date)]
+    until: Option<u64>,
+    /// Settings such as how numbers are formatted.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// The constants of the effort model, like `weights.toml`.  Defaults
+    /// to the values written there.
+    #[arg(long)]
+    weights: Option<String>,
+    /// A file of intuitions to rate layouts against besides the built in
+    /// ones, one per line like `close(. ,)`.  See the README.
+    #[arg(long)]
+    intuitions: Option<String>,
+    /// Keep memory from growing over long runs: anneal in place instead of
+    /// copying a layout for every step, copy every new generation into the
+    /// layouts of the one before so the population is only allocated once,
+    /// and print the resident memory every few minutes to watch it.
+    #[arg(long)]
+    bounded_memory: bool,
+    /// More logs or saved stats to combine with the first, e.g. from
+    /// other machines.  Can be given multiple times.
+    #[arg(long)]
+    merge: Vec<String>,
+    /// Text files, like documents or source code, to count as if they
+    /// had been typed.  Prefixed with a language, like `es=novel.txt`,
+    /// they're scored as a scenario of that language instead.  Can be
+    /// given multiple times.
+    #[arg(long)]
+    corpus: Vec<String>,
+    /// Leave out log sessions with this tag, e.g. `gaming`, which the
+    /// keylogger marks with `--tag-hotkey`.  Can be given multiple times.
+    #[arg(long)]
+    exclude_tagged: Vec<String>,
+    /// Only count what was typed on this keyboard, as named with
+    /// `keylogger --device`, so typing on another keyboard doesn't skew
+    /// the layout for this one.
+    #[arg(long)]
+    device: Option<String>,
+    /// The system layout the log was typed on, like `de`, `fr` or
+    /// `nordic`, or the path of a TOML table of what the keys typed.
+    /// Overrides `char_table` in the config.
+    #[arg(long)]
+    char_table: Option<String>,
+    /// Print which keys of the best layout moved every generation and
+    /// how much each move changed the score on its own.
+    #[arg(long)]
+    show_moves: bool,
+    /// Presses assumed for keys that never appear in the log, so they
+    /// aren't placed as if they were free.  Defaults to the count of the
+    /// least pressed key.
+    #[arg(long)]
+    prior_count: Option<u64>,
+    /// Comma separated keys that never appear in the log and whose
+    /// placement doesn't matter, e.g. `PageDown,Insert`.
+    #[arg(long, value_delimiter = ',', value_parser = Keycode::from_str)]
+    dont_care: Vec<Keycode>,
+    /// Comma separated keys to keep where they are on the keyboard, e.g.
+    /// `Z,X,C,V`, on top of those pinned in the keymap configuration and
+    /// `config.json`.
+    #[arg(long, value_delimiter = ',', value_parser = Keycode::from_str)]
+    pin: Vec<Keycode>,
+    /// More logs or saved stats, like from coding or a second language,
+    /// each scored on its own instead of merged into the first.  The log
+    /// file, with everything merged into it, is the first scenario.  Can
+    /// be given multiple times.
+    #[arg(long)]
+    scenario: Vec<String>,
+    /// How the scores of the scenarios are combined: `mean`, `min` for the
+    /// worst scenario, or `cvar:<fraction>` for the mean of the worst
+    /// fraction of them, e.g. `cvar:0.5`.
+    #[arg(long, default_value = "min", value_parser = Aggregation::from_str)]
+    aggregate: Aggregation,
+    /// A layout to start part of the population from instead of a random
+    /// one: `qwerty`, `colemak`, `dvorak`, or the path to a `best.txt` of
+    /// a previous run.  Can be given multiple times.
+    #[arg(long)]
+    seed: Vec<String>,
+    /// The fraction of the population started from the seeds, which take
+    /// turns.
+    #[arg(long, default_value_t = 0.25)]
+    seed_share: f64,
+    /// Layouts in every generation.
+    #[arg(long, default_value_t = 1000)]
+    population: usize,
+    /// The chance of swapping each pair of keys in every layout but the
+    /// best after each generation, on top of the mutation of the genetic
+    /// algorithm itself.
+    #[arg(long, default_value_t = 0.0)]
+    mutation_rate: f64,
+    /// Mutates more as the diversity of the population drops below this,
+    /// from `--mutation-rate` up to `--max-mutation-rate` once every
+    /// layout is the same, so a collapsed population spreads out again.
+    #[arg(long)]
+    target_diversity: Option<f64>,
+    /// The mutation rate of a fully collapsed population with
+    /// `--target-diversity`.
+    #[arg(long, default_value_t = 0.01)]
+    max_mutation_rate: f64,
+    /// What the temperature is multiplied by after every annealing step.
+    /// Closer to 1 anneals longer and more thoroughly.  Overrides the
+    /// `annealing` section of the config, where the rest of the schedule
+    /// is set, and defaults to 0.9999.
+    #[arg(long, value_parser = parse_cooling)]
+    cooling: Option<f64>,
+    /// The temperature at which annealing stops, 0.0001 unless the config
+    /// says otherwise.
+    #[arg(long)]
+    min_temperature: Option<f64>,
+    /// Threads to anneal and evolve on.  Defaults to one less than the
+    /// number of cores, so the rest of the system stays responsive.
+    #[arg(long)]
+    threads: Option<usize>,
+    /// How the genetic algorithm keeps the population diverse:
+    /// `half-are-random` replaces half of the offspring with random
+    /// layouts, `none` keeps all of them.
+    #[arg(long, default_value = "half-are-random", value_parser = Diversify::from_str)]
+    diversify: Diversify,
+    /// Stop after this many generations.  Without any of the stopping
+    /// flags the search runs until it's interrupted.
+    #[arg(long)]
+    max_generations: Option<usize>,
+    /// Stop after the first generation that ends after this long, in
+    /// seconds or with a unit like `90m` or `8h`.
+    #[arg(long, value_parser = parse_duration)]
+    max_duration: Option<Duration>,
+    /// Stop when the best score hasn't improved for this many generations.
+    #[arg(long)]
+    converge_after: Option<usize>,
+    /// Comma separated objectives to optimize together instead of the
+    /// score alone: `score`, `sfb` for fewer same finger bigrams, and
+    /// `qwerty` for staying close to the keycaps.  With two or more, the
+    /// layouts that trade them off best are written to the `pareto`
+    /// directory every generation.
+    #[arg(long, value_delimiter = ',', value_parser = Objective::from_str)]
+    objective: Vec<Objective>,
+    /// How every layout is refined between generations: `annealing`,
+    /// `tabu` search, or `hill-climbing` with random restarts.  Tabu search
+    /// and hill climbing score as many layouts as annealing does before
+    /// it cools down, so they can be compared at the same cost.
+    #[arg(long, default_value = "annealing", value_parser = Search::from_str)]
+    search: Search,
+    /// Print the progress line by line instead of showing the dashboard,
+    /// which is also what happens when the output isn't a terminal.
+    #[arg(long)]
+    plain: bool,
+    /// Also write the best this many distinct layouts of every generation
+    /// to `top/1.txt` and on, best first, with their scores in
+    /// `top/scores.txt`, to look through the alternatives to `best.txt`.
+    #[arg(long, default_value_t = 0)]
+    top: usize,
+    /// Write a line of JSON for every generation to this file, with the
+    /// max, mean, and min scores, the diversity, and the best layout, to
+    /// plot the run or compare it to others afterwards.
+    #[arg(long)]
+    history: Option<String>,
+    /// Keep only the last this many generations in the `--history`, and
+    /// the best generation of every day, so a long run doesn't fill the
+    /// disk.  See `clean_run` to prune a history afterwards.
+    #[arg(long)]
+    keep_history: Option<usize>,
+    /// Read the arguments, scoring weights, and intuitions of the run from
+    /// an `optimize.toml`, see the README.  Flags on the command line
+    /// override those of the file, or add to them if they can be given more
+    /// than once.
+    #[arg(long)]
+    run: Option<String>,
 }
 
-fn main() {
-    // Leave one core so my UI doesn't lag
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(31)
-        .build_global()
-        .unwrap();
-
-    let args = Args::parse();
-    let stats = process_log(&args.log_file);
-    let max_possible_score = max_possible_score(&stats);
-    println!("Max possible score: {}", max_possible_score);
-    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
-    let keymap_config = parse_keymap_config(&keymap_str);
-    let mut population = (0..1000)
-        .map(|_| Layout::gen(&mut rand::thread_rng(), &keymap_config))
-        .collect::<Vec<_>>();
-    loop {
-        println!("Annealing");
-        population.par_iter_mut().for_each(|layout| {
-            *layout = simmulated_annealing(&stats, &keymap_config, 0.0001, layout.clone());
-        });
-        println!("Genetic");
-        let (new_population, gstats) = genetic::evolve(
-            &population,
-            &keymap_config,
-            |layout| layout_score(layout, &stats, &keymap_config) as f32,
-            layout_similarity,
-            DiversifyStrategy::HalfAreRandom,
-        );
-        let best = &new_population[0];
-        save_best(&keymap_config, best);
-        println!(
-            "Max: {}, Mean: {}, Min: {}, Div: {}",
-            gstats.max, gstats.mean, gstats.min, gstats.diversity,
-        );
-        population = new_population;
-    }
-}
-
-fn save_best(keymap_config: &KeymapConfig, best: &Layout) {
-    let rows = keymap_config
-        .keys
-        .keys()
-        .iter()
-        .map(|key| key.position.1 as u8)
-        .max()
-        .unwrap()
-        + 1;
-    let cols = keymap_config
-        .keys
-        .keys()
-        .iter()
-        .map(|key| key.position.0 as u8)
-        .max()
-        .unwrap()
-        + 1;
-
-    let mut best_grid = vec![vec![None; cols as usize]; rows as usize];
-    for (key, config) in best.keys().iter().zip(keymap_config.keys.keys().iter()) {
-        best_grid[config.position.1 as usize][config.position.0 as usize] = Some(key.clone());
-    }
-
-    let mut best_str = String::new();
-    write_grid(best_grid, &mut best_str, cols).unwrap();
-    let mut writer = BufWriter::new(std::fs::File::create(format!("best.txt")).unwrap());
-    writer.write_all(best_str.as_bytes()).unwrap();
+/// Why the search stopped.
+#[derive(Debug, Clone, Copy)]
+enum Stop {
+    MaxGenerations,
+    MaxDuration,
+    Converged,
 }
 
-fn simmulated_annealing(
-    stats: &Stats,
-    keymap_config: &KeymapConfig,
-    min_temperature: f64,
-    initial_layout: Layout,
-) -> Layout {
-    let mut rng = rand::thread_rng();
-    let mut layout = initial_layout;
-    let mut score = layout_score(&layout, &stats, &keymap_config);
-    let mut temperature = 1.0;
-    let mut best_layout = layout.clone();
-    let mut best_score = score;
-    let max_possible_score = max_possible_score(&stats);
-    loop {
-        let mut new_layout = layout.clone();
-        let i = rng.gen_range(0..new_layout.keys().len());
-        let j = rng.gen_range(0..new_layout.keys().len());
-        new_layout.swap(i, j);
-        let new_score = layout_score(&new_layout, &stats, &keymap_config);
-        if new_score > best_score {
-            best_layout = new_layout.clone();
-            best_score = new_score;
-        }
-        let delta = new_score - score;
-        let normalized_delta = delta / max_possible_score;
-        if delta > 0.0 || rng.gen_bool((normalized_delta / temperature).exp()) {
-            layout = new_layout;
-            score = new_score;
-        }
-        temperature *= 0.9999;
-        if temperature < min_temperature {
-            break;
+impl Args {
+    /// Whether to stop after `generations`, `elapsed` into the search, the
+    /// last `stale` of which didn't improve the best score.
+    fn stop(&self, generations: usize, elapsed: Duration, stale: usize) -> Option<Stop> {
+        if self.max_generations.is_some_and(|max| generations >= max) {
+            Some(Stop::MaxGenerations)
+        } else if self.max_duration.is_some_and(|max| elapsed >= max) {
+            Some(Stop::MaxDuration)
+        } else if self.converge_after.is_some_and(|after| stale >= after) {
+            Some(Stop::Converged)
+        } else {
+            None
         }
     }
-    best_layout
-}
 
-fn max_possible_score(stats: &Stats) -> f64 {
-    let mut score = 0.0;
-    for count in stats.individual_key_counts.values() {
-        score += *count as f64;
-    }
-    for count in stats.consectutive_key_counts.values() {
-        score += *count as f64;
+    /// The mutation rate after a generation whose diversity is
+    /// `diversity`.
+    fn mutation_rate(&self, diversity: f64) -> f64 {
+        let rate = match self.target_diversity {
+            Some(target) => adaptive_mutation_rate(
+                self.mutation_rate,
+                self.max_mutation_rate,
+                diversity,
+                target,
+            ),
+            None => self.mutation_rate,
+        };
+        rate.min(1.0)
     }
-    let n_intuitions = INTUITIONS.len() as f64;
-    score += n_intuitions * 100.0;
-    score
 }
 
-fn layout_similarity(l1: &Layout, l2: &Layout) -> f32 {
-    let mut score = 0.0;
-    for (key1, key2) in l1.keys().iter().zip(l2.keys().iter()) {
-        let key1_code = key1.keycode(false);
-        let key2_code = key2.keycode(false);
-        if key1_code == key2_code {
-            score += 1.0;
-        }
-        let shift_key1_code = key1.keycode(true);
-        let shift_key2_code = key2.keycode(true);
-        if shift_key1_code == shift_key2_code {
-            score += 1.0;
-        }
+/// Parses seconds, optionally followed by `s`, `m`, or `h` for seconds,
+/// minutes, or hours.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("expected a unit of s, m, or h, got {unit}")),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok(Duration::from_secs_f64(number * multiplier)),
+        _ => Err(format!("expected a duration like 90, 30m, or 8h, got {s}")),
     }
-    score / (l1.keys().len() * 2) as f32
 }
 
-fn layout_score(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
-    let individual_key_score = layout_individual_key_score(layout, stats, keymap_config);
-    let consecutive_key_score = layout_consecutive_key_score(layout, stats, keymap_config);
-    let intuition_score = intuition_score(layout, keymap_config, &INTUITIONS);
-
-    individual_key_score + consecutive_key_score + 100.0 * intuition_score
+/// How the genetic algorithm keeps the population diverse.
+#[derive(Debug, Clone, Copy)]
+enum Diversify {
+    HalfAreRandom,
+    None,
 }
 
-struct IntuitionPair(Key, Key);
+impl FromStr for Diversify {
+    type Err = String;
 
-impl IntuitionPair {
-    fn physical_keys<'a>(
-        &self,
-        layout: &Layout,
-        keymap_config: &'a KeymapConfig,
-    ) -> (&'a PhysicalKey, &'a PhysicalKey) {
-        (
-            get_physical_key_for_key(layout, keymap_config, &self.0),
-            get_physical_key_for_key(layout, keymap_config, &self.1),
-        )
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-are-random" => Ok(Diversify::HalfAreRandom),
+            "none" => Ok(Diversify::None),
+            _ => Err(format!("expected half-are-random or none, got {s}")),
+        }
     }
 }
 
-enum Intuition {
-    Close(IntuitionPair),
-    Symmetric(IntuitionPair),
-    SameRow(IntuitionPair),
-    SameColumn(IntuitionPair),
-    LeftOf(IntuitionPair),
-    RightOf(IntuitionPair),
-    Above(IntuitionPair),
-    Below(IntuitionPair),
-    Or(Box<Intuition>, Box<Intuition>),
-    And(Box<Intuition>, Box<Intuition>),
-}
-
-impl Intuition {
-    fn satisfied(&self, layout: &Layout, keymap_config: &KeymapConfig) -> bool {
+impl Diversify {
+    fn strategy(self) -> DiversifyStrategy {
         match self {
-            Intuition::Close(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                distance(key1, key2) < 1.1
-            }
-            Intuition::Symmetric(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                are_symmetric(keymap_config, key1.position, key2.position)
-            }
-            Intuition::SameRow(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.1 == key2.position.1
-            }
-            Intuition::SameColumn(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.0 == key2.position.0
-            }
-            Intuition::LeftOf(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.0 < key2.position.0
-            }
-            Intuition::RightOf(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.0 > key2.position.0
-            }
-            Intuition::Above(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.1 < key2.position.1
-            }
-            Intuition::Below(pair) => {
-                let (key1, key2) = pair.physical_keys(layout, keymap_config);
-                key1.position.1 > key2.position.1
-            }
-            Intuition::Or(a, b) => {
-                a.satisfied(layout, keymap_config) || b.satisfied(layout, keymap_config)
-            }
-            Intuition::And(a, b) => {
-                a.satisfied(layout, keymap_config) && b.satisfied(layout, keymap_config)
-            }
+            Diversify::HalfAreRandom => DiversifyStrategy::HalfAreRandom,
+            Diversify::None => DiversifyStrategy::None,
         }
     }
 }
 
-fn intuition_score(layout: &Layout, keymap_config: &KeymapConfig, intuitions: &[Intuition]) -> f64 {
-    let mut score = 0.0;
-    for intuition in intuitions {
-        if intuition.satisfied(layout, keymap_config) {
-            score += 1.0;
-        }
-    }
-    score
+/// The local search that refines every layout between generations.
+#[derive(Debug, Clone, Copy)]
+enum Search {
+    Annealing,
+    Tabu,
+    HillClimbing,
 }
 
-fn close(key1: Key, key2: Key) -> Intuition {
-    Intuition::Close(IntuitionPair(key1, key2))
-}
+impl FromStr for Search {
+    type Err = String;
 
-fn symmetric(key1: Key, key2: Key) -> Intuition {
-    Intuition::Symmetric(IntuitionPair(key1, key2))
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "annealing" => Ok(Search::Annealing),
+            "tabu" => Ok(Search::Tabu),
+            "hill-climbing" => Ok(Search::HillClimbing),
+            _ => Err(format!(
+                "expected annealing, tabu, or hill-climbing, got {s}"
+            )),
+        }
+    }
 }
 
-fn same_row(key1: Key, key2: Key) -> Intuition {
-    Intuition::SameRow(IntuitionPair(key1, key2))
+impl std::fmt::Display for Search {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Search::Annealing => write!(f, "Annealing"),
+            Search::Tabu => write!(f, "Tabu search"),
+            Search::HillClimbing => write!(f, "Hill climbing"),
+        }
+    }
 }
 
-fn same_column(key1: Key, key2: Key) -> Intuition {
-    Intuition::SameColumn(IntuitionPair(key1, key2))
+fn parse_cooling(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(cooling) if cooling > 0.0 && cooling < 1.0 => Ok(cooling),
+        _ => Err(format!("the cooling factor has to be in (0, 1), got {s}")),
+    }
 }
 
-fn left_of(key1: Key, key2: Key) -> Intuition {
-    Intuition::LeftOf(IntuitionPair(key1, key2))
-}
+/// Generations after which intuitions that were never satisfied together
+/// are reported as conflicting.
+const INTUITION_CONFLICT_GENERATIONS: usize = 10;
 
-fn right_of(key1: Key, key2: Key) -> Intuition {
-    Intuition::RightOf(IntuitionPair(key1, key2))
-}
+/// How often `--bounded-memory` prints the resident memory.
+const MEMORY_CHECKPOINT: Duration = Duration::from_secs(10 * 60);
 
-fn above(key1: Key, key2: Key) -> Intuition {
-    Intuition::Above(IntuitionPair(key1, key2))
-}
+fn main() {
+    let (args, run_file) = parse_args();
+    // Leave one core so the UI doesn't lag
+    let threads = args.threads.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1))
+    });
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .unwrap();
 
-fn below(key1: Key, key2: Key) -> Intuition {
-    Intuition::Below(IntuitionPair(key1, key2))
-}
+    let mut config = Config::load_or_default(&args.config).unwrap();
+    if let Some(run_file) = run_file {
+        if let Some(weights) = run_file.weights {
+            config.scoring.weights = weights;
+        }
+        config.scoring.custom_intuitions = run_file.intuitions;
+    }
+    if let Some(path) = &args.weights {
+        config.scoring.weights =
+            Weights::load(path).unwrap_or_else(|error| error.in_file(path).exit());
+    }
+    if let Some(path) = &args.intuitions {
+        config.scoring.custom_intuitions =
+            load_intuitions(path).unwrap_or_else(|error| error.in_file(path).exit());
+    }
+    if args.char_table.is_some() {
+        config.char_table = args.char_table.clone();
+    }
+    let fmt = &config.number_format;
+    let mut schedule = config.annealing.clone();
+    if let Some(cooling) = args.cooling {
+        schedule.cooling = cooling;
+    }
+    if let Some(min_temperature) = args.min_temperature {
+        schedule.min_temperature = min_temperature;
+    }
+    if let Err(error) = schedule.check() {
+        Error::Invalid(error).in_file(&args.config).exit();
+    }
+    let mut log_files = vec![args.log_file.clone()];
+    log_files.extend(args.merge.iter().cloned());
+    let mut log_options = config
+        .log_options(&args.exclude_tagged)
+        .unwrap_or_else(|error| error.in_file(&args.config).exit());
+    log_options.device = args.device.clone();
+    log_options.since = args.since;
+    log_options.until = args.until;
+    if let Some(n) = args.last_sessions {
+        let sessions = find_sessions(&log_files, config.session_gap_ms(), &log_options)
+            .unwrap_or_else(|error| error.exit());
+        log_options.since = last_sessions_start(&sessions, n);
+    }
+    let mut stats =
+        read_merged_stats(&log_files, &log_options).unwrap_or_else(|error| error.exit());
+    for arg in &args.corpus {
+        if let (None, path) = parse_tagged(arg) {
+            stats
+                .merge(process_text(path, &log_options.chars).unwrap_or_else(|error| error.exit()));
+        }
+    }
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let mut keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let keycaps = Layout::from_keymap(&keymap_config);
+    for intuition in &config.scoring.custom_intuitions {
+        if let Some(key) = intuition
+            .keys()
+            .into_iter()
+            .find(|key| !keycaps.keys().contains(key))
+        {
+            Error::Invalid(format!("{intuition}: {key:?} isn't on the keyboard"))
+                .in_file(args.intuitions.as_deref().or(args.run.as_deref()).unwrap())
+                .exit();
+        }
+    }
 
-fn or(a: Intuition, b: Intuition) -> Intuition {
-    Intuition::Or(Box::new(a), Box::new(b))
-}
+    let allowed = match config.constraints.allowed_positions(&keymap_config) {
+        Ok(allowed) => allowed,
+        Err(errors) => {
+            eprintln!("The constraints in {} can't all be met:", args.config);
+            for error in errors {
+                eprintln!("  {}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+    let mut pins: Vec<(usize, Key)> = vec![];
+    for code in &args.pin {
+        match keymap_config
+            .keys
+            .keys()
+            .iter()
+            .position(|key| key.code == *code)
+        {
+            Some(i) => pins.push((i, Key::from_keycode(*code))),
+            None => {
+                eprintln!("Can't pin {}, it isn't a key of the keyboard", code);
+                std::process::exit(1);
+            }
+        }
+    }
+    for (code, positions) in &allowed {
+        if config.constraints.pins.contains_key(&code.to_string()) {
+            pins.push((positions[0], Key::from_keycode(*code)));
+        }
+    }
+    for (i, key) in pins {
+        if let Err(error) = keymap_config.pin(i, key) {
+            eprintln!("Can't pin the keys: {}", error);
+            std::process::exit(1);
+        }
+    }
+    // Annealing only swaps keys that aren't pinned
+    let movable = keymap_config.unpinned();
+    if movable.len() < 2 {
+        eprintln!("Every key is pinned, there's nothing to optimize");
+        std::process::exit(1);
+    }
 
-fn and(a: Intuition, b: Intuition) -> Intuition {
-    Intuition::And(Box::new(a), Box::new(b))
-}
+    let prepare_options = PrepareOptions {
+        latencies: args.latencies.as_ref().map(|path| {
+            LatencyStats::load(path).unwrap_or_else(|error| Error::from(error).in_file(path).exit())
+        }),
+        weight_by_timing: args.weight_by_timing,
+        prior_count: args.prior_count,
+        dont_care: args.dont_care.clone(),
+    };
+    // The editor is detected from the first log, and stays the editor of
+    // every scenario
+    let configured_editor = config.scoring.editor_profile;
+    let mut scenarios = Scenarios::new(args.aggregate);
+    prepare_stats(
+        &mut stats,
+        &args.log_file,
+        &prepare_options,
+        &mut config.scoring,
+        fmt,
+        &keymap_config,
+    );
+    if config.scoring.editor_profile != configured_editor {
+        println!("Detected editor: {:?}", config.scoring.editor_profile);
+    }
+    scenarios.push(args.log_file.clone(), stats, &config.scoring);
+    for path in &args.scenario {
+        let mut stats =
+            read_merged_stats(&[path], &log_options).unwrap_or_else(|error| error.exit());
+        prepare_stats(
+            &mut stats,
+            path,
+            &prepare_options,
+            &mut config.scoring,
+            fmt,
+            &keymap_config,
+        );
+        scenarios.push(path.clone(), stats, &config.scoring);
+    }
+    let languages = read_language_corpora(&args.corpus).unwrap_or_else(|error| error.exit());
+    for mut language in languages {
+        prepare_stats(
+            &mut language.stats,
+            &language.language,
+            &prepare_options,
+            &mut config.scoring,
+            fmt,
+            &keymap_config,
+        );
+        scenarios.push(language.language, language.stats, &config.scoring);
+    }
 
-fn key(c: char) -> Key {
-    Key::from_char_default_shifted(c)
-}
+    // Loaded once and only ever read, the scorers share the same copy
+    // and every worker borrows it
+    let keymap_config = Arc::new(keymap_config);
+    let stats = Arc::clone(&scenarios.scenarios[0].stats);
+    let scoring = &config.scoring;
+    let multiple_scenarios = scenarios.scenarios.len() > 1;
+    // With several objectives every layout is annealed towards its own
+    // mix of them, so the population spreads out along the Pareto front
+    let objectives = (args.objective.len() > 1).then(|| {
+        Objectives::new(
+            args.objective.clone(),
+            Arc::clone(&stats),
+            Arc::clone(&keymap_config),
+        )
+    });
+    // Otherwise a single score is updated for every swap instead of
+    // rescoring the whole layout
+    let swap_scorer = (objectives.is_none() && !multiple_scenarios)
+        .then(|| SwapScorer::new(Arc::clone(&stats), Arc::clone(&keymap_config), scoring));
+    let scenarios = &scenarios;
+    let keymap_config: &KeymapConfig = &keymap_config;
+    let stats: &Stats = &stats;
 
-const INTUITIONS: LazyCell<Vec<Intuition>> = LazyCell::new(|| {
-    use Key::*;
-    vec![
-        and(same_row(Left, Right), left_of(Left, Right)),
-        and(same_column(Up, Down), above(Up, Down)),
-        or(close(Left, Right), symmetric(Left, Right)),
-        or(close(Up, Down), symmetric(Up, Down)),
-        or(close(PageUp, PageDown), symmetric(PageUp, PageDown)),
-        or(close(key('['), key(']')), symmetric(key('['), key(']'))),
-        symmetric(LShift, RShift),
-        symmetric(LCtrl, RCtrl),
-        close(key('1'), key('2')),
-        close(key('2'), key('3')),
-        close(key('4'), key('5')),
-        close(key('5'), key('6')),
-        close(key('7'), key('8')),
-        close(key('8'), key('9')),
-        close(key('1'), key('4')),
-        close(key('2'), key('5')),
-        close(key('3'), key('6')),
-        close(key('4'), key('7')),
-        close(key('5'), key('8')),
-        close(key('6'), key('9')),
-    ]
-});
-
-fn are_symmetric(config: &KeymapConfig, pos1: (f64, f64), pos2: (f64, f64)) -> bool {
-    if pos1.1 != pos2.1 {
-        return false;
-    }
-
-    let max = config
-        .keys
-        .keys()
+    // With several scenarios the layouts are rated by the aggregate of
+    // their normalized scores, which are at most 1
+    let max_possible_score = if multiple_scenarios {
+        println!(
+            "Scoring {} scenarios by their {}",
+            scenarios.scenarios.len(),
+            scenarios.aggregation
+        );
+        1.0
+    } else {
+        let max_possible_score = scoring.max_possible_score(stats);
+        println!("Max possible score: {}", fmt.decimal(max_possible_score));
+        max_possible_score
+    };
+    let rescore = |layout: &Layout| {
+        if multiple_scenarios {
+            scenarios.score(layout, keymap_config, scoring)
+        } else {
+            scoring.score(layout, stats, keymap_config)
+        }
+    };
+    // Elites and layouts annealing couldn't improve recur between
+    // generations, so whole layouts are only scored once.  The layouts in
+    // between the swaps of a search rarely recur, so they're rescored.
+    let memo = ScoreMemo::new(4 * args.population.max(1));
+    let score = |layout: &Layout| memo.get_or_insert_with(layout, || rescore(layout));
+    let evaluate = |layout: &Layout| {
+        let objectives = objectives.as_ref().unwrap();
+        objectives.evaluate(layout, rescore(layout) / max_possible_score)
+    };
+    let weights: Vec<Vec<f64>> = (0..args.population)
+        .map(|_| random_weights(args.objective.len(), &mut rand::thread_rng()))
+        .collect();
+    // The front of an earlier run for the same objectives is continued
+    let mut front = ParetoFront::default();
+    if let (Some(objectives), Ok(json)) = (&objectives, std::fs::read_to_string(FRONT_JSON)) {
+        match ParetoFront::from_json(&json, &objectives.objectives, keymap_config, evaluate) {
+            Ok(saved) => {
+                println!(
+                    "Continuing the Pareto front of {} layouts in {}",
+                    fmt.count(saved.members.len() as u64),
+                    FRONT_JSON
+                );
+                front = saved;
+            }
+            Err(error) => println!("Warning: starting a new Pareto front, {}", error),
+        }
+    }
+    let total_bigrams: u64 = stats.consecutive_key_counts.values().sum();
+    let mut prev_best: Option<Layout> = None;
+    let mut intuition_tracker = IntuitionTracker::new(scoring.intuitions());
+    let seeds = args
+        .seed
         .iter()
-        .map(|k| k.position.0)
-        .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap();
-    let smaller = pos1.0.min(pos2.0);
-    let larger = pos1.0.max(pos2.0);
-
-    (max - larger - smaller).abs() < 0.01
-}
-
-fn get_physical_key_for_key<'a>(
-    layout: &Layout,
-    config: &'a KeymapConfig,
-    key: &Key,
-) -> &'a PhysicalKey {
-    &config.keys.keys()[layout.get(key)]
-}
-
-fn layout_consecutive_key_score(
-    layout: &Layout,
-    stats: &Stats,
-    keymap_config: &KeymapConfig,
-) -> f64 {
-    let mut score = 0.0;
-    for ((i, key1), (j, key2)) in layout
-        .keys()
+        .map(|seed| read_seed(seed, keymap_config))
+        .collect::<Vec<_>>();
+    let seeded = if seeds.is_empty() {
+        0
+    } else {
+        ((args.population as f64 * args.seed_share.clamp(0.0, 1.0)) as usize).max(seeds.len())
+    };
+    let mut population = seeds
         .iter()
-        .enumerate()
-        .zip(layout.keys().iter().enumerate().skip(1))
-    {
-        let key1_code = key1.keycode(false);
-        let key2_code = key2.keycode(false);
-        let pkey1 = &keymap_config.keys.keys()[i];
-        let pkey2 = &keymap_config.keys.keys()[j];
-        let count = stats
-            .consectutive_key_counts
-            .get(&(key1_code, key2_code))
-            .unwrap_or(&0);
-        let distance = distance(&pkey1, &pkey2);
-        score += *count as f64 * consecutive_finger_score(pkey1.finger, pkey1.finger, distance);
-    }
-    score
-}
-
-fn distance(key1: &PhysicalKey, key2: &PhysicalKey) -> f64 {
-    let key1_pos = key1.position;
-    let key2_pos = key2.position;
-    ((key1_pos.0 - key2_pos.0).powi(2) + (key1_pos.1 - key2_pos.1).powi(2)).sqrt()
-}
-
-fn layout_individual_key_score(
-    layout: &Layout,
-    stats: &Stats,
-    keymap_config: &KeymapConfig,
-) -> f64 {
-    let mut score = 0.0;
-    for (key, config) in layout.keys().iter().zip(keymap_config.keys.keys().iter()) {
-        let count = stats
-            .individual_key_counts
-            .get(&key.keycode(false))
-            .unwrap_or(&0);
-        score += config.score
-            * keymap_config
-                .fingers
+        .cycle()
+        .take(seeded)
+        .cloned()
+        .chain(
+            (seeded..args.population).map(|_| Layout::gen(&mut rand::thread_rng(), keymap_config)),
+        )
+        .collect::<Vec<_>>();
+    let started = Instant::now();
+    let mut generations = 0;
+    let mut best_score = f64::NEG_INFINITY;
+    let mut stale = 0;
+    let mut last_memory_checkpoint: Option<Instant> = None;
+    let output = if args.plain || !std::io::stdout().is_terminal() {
+        Output::Plain
+    } else {
+        let mut progress = Progress::new(args.population, max_possible_score);
+        progress.max_generations = args.max_generations;
+        progress.max_duration = args.max_duration;
+        progress.started = started;
+        progress.temperature = matches!(args.search, Search::Annealing).then(|| schedule.clone());
+        Output::Dashboard(Dashboard::start(progress, fmt.clone()))
+    };
+    let mut history = args.history.as_ref().map(|path| {
+        let retention = args.keep_history.map(|keep_last| Retention {
+            keep_last,
+            daily_bests: true,
+        });
+        HistoryWriter::create(path, retention).unwrap_or_else(|error| error.in_file(path).exit())
+    });
+    let stop = loop {
+        output.phase(args.search.to_string());
+        population
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(i, layout)| {
+                let objective = |layout: &Layout| match &objectives {
+                    Some(_) => scalarize(&evaluate(layout), &weights[i]),
+                    None => rescore(layout),
+                };
+                let max_objective = if objectives.is_some() {
+                    1.0
+                } else {
+                    max_possible_score
+                };
+                let rng = &mut rand::thread_rng();
+                match &swap_scorer {
+                    Some(scorer) => refine(
+                        &args,
+                        scorer,
+                        &movable,
+                        max_objective,
+                        &schedule,
+                        layout,
+                        rng,
+                    ),
+                    None => refine(
+                        &args,
+                        &objective,
+                        &movable,
+                        max_objective,
+                        &schedule,
+                        layout,
+                        rng,
+                    ),
+                }
+                output.refined();
+            });
+        // Layouts are ranked by how many fronts lie above them
+        let ranks = objectives.as_ref().map(|_| {
+            let values: Vec<Vec<f64>> = population.par_iter().map(evaluate).collect();
+            let ranks = pareto_ranks(&values);
+            for (layout, values) in population.iter().zip(values) {
+                front.insert(layout, values);
+            }
+            population
+                .iter()
+                .zip(ranks)
+                .map(|(layout, rank)| (layout_fingerprint(layout), -(rank as f32)))
+                .collect::<HashMap<_, _>>()
+        });
+        output.phase("Genetic".to_string());
+        let (new_population, gstats) = genetic::evolve(
+            &population,
+            keymap_config,
+            |layout| match &ranks {
+                Some(ranks) => ranks
+                    .get(&layout_fingerprint(layout))
+                    .copied()
+                    .unwrap_or(f32::MIN),
+                None => score(layout) as f32,
+            },
+            layout_similarity,
+            args.diversify.strategy(),
+        );
+        let best = &new_population[0];
+        save_best(keymap_config, best, stats, scoring, fmt);
+        if args.top > 0 {
+            save_top(
+                keymap_config,
+                &new_population,
+                &score,
+                args.top,
+                max_possible_score,
+                fmt,
+            );
+        }
+        if let Some(objectives) = &objectives {
+            save_front(keymap_config, &front, &objectives.objectives);
+            output.message(format!(
+                "Pareto front: {} layouts, saved to pareto/",
+                fmt.count(front.members.len() as u64)
+            ));
+        }
+        for flip in intuition_tracker.record(best, &new_population, keymap_config) {
+            output.message(format!(
+                "Intuition {}: {}",
+                if flip.satisfied { "satisfied" } else { "lost" },
+                flip.intuition
+            ));
+        }
+        for (a, b) in intuition_tracker.conflicts(INTUITION_CONFLICT_GENERATIONS) {
+            output.message(format!(
+                "Warning: no layout has satisfied both {} and {} in {} generations, they may be impossible to satisfy together",
+                a, b, INTUITION_CONFLICT_GENERATIONS
+            ));
+        }
+        if args.show_moves {
+            if let Some(prev_best) = &prev_best {
+                let moves = key_moves(prev_best, best, score);
+                output.message(format!("Moved {} keys in the best layout:", moves.len()));
+                let physical = keymap_config.keys.keys();
+                for m in moves {
+                    output.message(format!(
+                        "  {:?}: {:?} -> {:?} ({}{})",
+                        m.key.keycode(false),
+                        physical[m.from].code,
+                        physical[m.to].code,
+                        if m.score_delta >= 0.0 { "+" } else { "" },
+                        fmt.decimal(m.score_delta)
+                    ));
+                }
+            }
+            prev_best = Some(best.clone());
+        }
+        // Printed, or shown next to the best layout on the dashboard
+        let mut summary = vec![];
+        summary.push(format!(
+            "Max: {}, Mean: {}, Min: {}, Div: {}",
+            fmt.decimal(gstats.max as f64),
+            fmt.decimal(gstats.mean as f64),
+            fmt.decimal(gstats.min as f64),
+            fmt.decimal(gstats.diversity as f64),
+        ));
+        let (hits, lookups) = memo.hits();
+        summary.push(format!(
+            "Score cache: {} hits of {} lookups ({})",
+            fmt.count(hits as u64),
+            fmt.count(lookups as u64),
+            fmt.percent(memo.hit_rate())
+        ));
+        if multiple_scenarios {
+            let scores = scenarios.scores(best, keymap_config, scoring);
+            for (scenario, score) in scenarios.scenarios.iter().zip(scores) {
+                summary.push(format!("  {}: {}", scenario.name, fmt.percent(score)));
+            }
+        }
+        let sfbs = same_finger_bigrams(best, stats, keymap_config);
+        summary.push(format!(
+            "Same finger bigrams: {} ({} of bigrams)",
+            fmt.count(sfbs.count),
+            fmt.percent(sfbs.count as f64 / total_bigrams.max(1) as f64)
+        ));
+        let patterns = hand_patterns(best, stats, keymap_config);
+        summary.push(format!(
+            "Hand alternation: {}, inward rolls: {}, outward rolls: {}",
+            fmt.percent(patterns.alternation_rate()),
+            fmt.count(patterns.inward_rolls),
+            fmt.count(patterns.outward_rolls)
+        ));
+        let thumbs = thumb_load(best, stats, keymap_config);
+        summary.push(format!(
+            "Thumb load: {} of presses (left {}, right {}), thumb hold conflicts: {}",
+            fmt.percent(thumbs.thumb_share()),
+            fmt.count(thumbs.left_thumb),
+            fmt.count(thumbs.right_thumb),
+            fmt.count(thumbs.hold_conflicts)
+        ));
+        if !scoring.home_row_mods.is_empty() {
+            let mods = home_row_mods(best, stats, keymap_config, &scoring.home_row_mods);
+            let placement: Vec<String> = mods
+                .placement
                 .iter()
-                .find(|c| c.finger == config.finger)
-                .unwrap()
-                .score
-            * *count as f64;
+                .map(|(modifier, i)| format!("{} on {}", modifier, best.keys()[*i].keycode(false)))
+                .collect();
+            summary.push(format!(
+                "Home row mods: {} (hold cost {})",
+                placement.join(", "),
+                fmt.decimal(mods.cost)
+            ));
+        }
+        let mutation_rate = args.mutation_rate(gstats.diversity as f64);
+        if args.target_diversity.is_some() {
+            summary.push(format!("Mutation rate: {}", mutation_rate));
+        }
+        generations += 1;
+        if args.bounded_memory
+            && last_memory_checkpoint.is_none_or(|at| at.elapsed() >= MEMORY_CHECKPOINT)
+        {
+            output.message(memory_report(generations, fmt));
+            last_memory_checkpoint = Some(Instant::now());
+        }
+        let generation_best = score(best);
+        if let Some(history) = &mut history {
+            let line = serde_json::json!({
+                "generation": generations,
+                "timestamp": now_millis(),
+                "elapsed_seconds": started.elapsed().as_secs_f64(),
+                "max": gstats.max,
+                "mean": gstats.mean,
+                "min": gstats.min,
+                "diversity": gstats.diversity,
+                "mutation_rate": mutation_rate,
+                "cache_hit_rate": memo.hit_rate(),
+                "best_score": generation_best,
+                "best": layout_to_str(best, keymap_config).unwrap(),
+            });
+            history
+                .write(&line)
+                .unwrap_or_else(|error| error.in_file(args.history.as_deref().unwrap()).exit());
+        }
+        output.generation(
+            best,
+            keymap_config,
+            generation_best,
+            gstats.diversity as f64,
+            summary,
+        );
+        if generation_best > best_score {
+            best_score = generation_best;
+            stale = 0;
+        } else {
+            stale += 1;
+        }
+        if args.bounded_memory {
+            // Keeps the keys and key maps of the layouts, the new
+            // generation is dropped right away
+            population.clone_from(&new_population);
+        } else {
+            population = new_population;
+        }
+        if let Some(stop) = args.stop(generations, started.elapsed(), stale) {
+            break stop;
+        }
+        if mutation_rate > 0.0 {
+            population
+                .par_iter_mut()
+                .skip(1)
+                .for_each(|layout| layout.mutate(&mut rand::thread_rng(), mutation_rate as f32));
+        }
+    };
+    // Puts the terminal back before the results are printed
+    drop(output);
+
+    let reason = match stop {
+        Stop::MaxGenerations => "reached the maximum number of generations".to_string(),
+        Stop::MaxDuration => "reached the maximum duration".to_string(),
+        Stop::Converged => format!(
+            "the best score hasn't improved in {} generations",
+            fmt.count(stale as u64)
+        ),
+    };
+    println!("\nStopped, {}", reason);
+    println!(
+        "Ran {} generations of {} in {} minutes",
+        fmt.count(generations as u64),
+        args.search.to_string().to_lowercase(),
+        fmt.decimal(started.elapsed().as_secs_f64() / 60.0)
+    );
+    // The best of the last generation is the one in best.txt
+    let final_score = score(&population[0]);
+    println!(
+        "Best score: {} ({} of max), saved to best.txt",
+        fmt.decimal(final_score),
+        fmt.percent(final_score / max_possible_score)
+    );
+    println!(
+        "{} of the layouts scored were already in the score cache",
+        fmt.percent(memo.hit_rate())
+    );
+    if args.bounded_memory {
+        println!("{}", memory_report(generations, fmt));
     }
-    score
 }
 
-fn consecutive_finger_score(f1: Finger, f2: Finger, distance: f64) -> f64 {
-    if distance == 0.0 {
-        return 1.0;
-    }
-
-    if f1.hand != f2.hand {
-        return 1.0;
-    }
-
-    let synergy = match (f1.finger, f2.finger) {
-        (FingerKind::Pinky, FingerKind::Pinky) => 0.1,
-        (FingerKind::Pinky, FingerKind::Ring) | (FingerKind::Ring, FingerKind::Pinky) => 0.2,
-        (FingerKind::Pinky, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Pinky) => 0.2,
-        (FingerKind::Pinky, FingerKind::Index) | (FingerKind::Index, FingerKind::Pinky) => 0.5,
-        (FingerKind::Pinky, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Pinky) => 0.6,
-        (FingerKind::Ring, FingerKind::Ring) => 0.1,
-        (FingerKind::Ring, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Ring) => 0.3,
-        (FingerKind::Ring, FingerKind::Index) | (FingerKind::Index, FingerKind::Ring) => 0.3,
-        (FingerKind::Ring, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Ring) => 0.2,
-        (FingerKind::Middle, FingerKind::Middle) => 0.2,
-        (FingerKind::Middle, FingerKind::Index) | (FingerKind::Index, FingerKind::Middle) => 0.7,
-        (FingerKind::Middle, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Middle) => 0.7,
-        (FingerKind::Index, FingerKind::Index) => 0.3,
-        (FingerKind::Index, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Index) => 0.9,
-        (FingerKind::Thumb, FingerKind::Thumb) => 0.3,
+/// The resident memory of the run after `generations`.
+fn memory_report(generations: usize, fmt: &NumberFormat) -> String {
+    let rss = match resident_memory_kb() {
+        Some(kb) => format!("{} MiB", fmt.count(kb / 1024)),
+        None => "unknown".to_string(),
     };
+    format!(
+        "RSS after {} generations: {}",
+        fmt.count(generations as u64),
+        rss
+    )
+}
 
-    let distance_importance = match (f1.finger, f2.finger) {
-        (FingerKind::Pinky, FingerKind::Pinky) => 1.0,
-        (FingerKind::Pinky, FingerKind::Ring) | (FingerKind::Ring, FingerKind::Pinky) => 0.9,
-        (FingerKind::Pinky, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Pinky) => 0.8,
-        (FingerKind::Pinky, FingerKind::Index) | (FingerKind::Index, FingerKind::Pinky) => 0.2,
-        (FingerKind::Pinky, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Pinky) => 0.1,
-        (FingerKind::Ring, FingerKind::Ring) => 1.0,
-        (FingerKind::Ring, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Ring) => 0.9,
-        (FingerKind::Ring, FingerKind::Index) | (FingerKind::Index, FingerKind::Ring) => 0.5,
-        (FingerKind::Ring, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Ring) => 0.1,
-        (FingerKind::Middle, FingerKind::Middle) => 1.0,
-        (FingerKind::Middle, FingerKind::Index) | (FingerKind::Index, FingerKind::Middle) => 0.7,
-        (FingerKind::Middle, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Middle) => 0.1,
-        (FingerKind::Index, FingerKind::Index) => 1.0,
-        (FingerKind::Index, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Index) => 0.2,
-        (FingerKind::Thumb, FingerKind::Thumb) => 1.0,
+/// Parses the command line, with the arguments of the run file of `--run`
+/// in front of it, and returns the run file too.
+fn parse_args() -> (Args, Option<RunFile>) {
+    let mut command_line: Vec<String> = std::env::args().collect();
+    let path = command_line
+        .iter()
+        .position(|arg| arg == "--run")
+        .and_then(|i| command_line.get(i + 1).cloned())
+        .or_else(|| {
+            command_line
+                .iter()
+                .find_map(|arg| arg.strip_prefix("--run=").map(str::to_string))
+        });
+    let Some(path) = path else {
+        return (Args::parse(), None);
     };
-
-    let raw_score = 1.0 / (distance as f64 + 1.0);
-    let distance_score = 1.0 * (1.0 - distance_importance) + raw_score * distance_importance;
-    distance_score * synergy
+    let run_file = RunFile::load(&path, &["log_file", "keymap_config"])
+        .unwrap_or_else(|error| error.in_file(&path).exit());
+    command_line.splice(1..1, run_file.args.iter().cloned());
+    (Args::parse_from(command_line), Some(run_file))
 }
 
-#[derive(Clone, Debug)]
-struct Layout {
-    keys: Vec<Key>,
-    key_map: HashMap<Key, usize>,
+/// Where the progress of the search goes: printed line by line, or shown
+/// on the dashboard.
+enum Output {
+    Plain,
+    Dashboard(Dashboard),
 }
 
-impl Layout {
-    fn new(keys: Vec<Key>) -> Self {
-        let mut key_map = HashMap::new();
-        for (i, key) in keys.iter().enumerate() {
-            key_map.insert(*key, i);
+impl Output {
+    /// Starts refining the layouts or evolving them.
+    fn phase(&self, phase: String) {
+        match self {
+            Output::Plain => println!("{}", phase),
+            Output::Dashboard(dashboard) => dashboard.update(|progress| progress.phase = phase),
         }
-        Self { keys, key_map }
-    }
-
-    fn swap(&mut self, i: usize, j: usize) {
-        self.keys.swap(i, j);
-        self.key_map.insert(self.keys[i], i);
-        self.key_map.insert(self.keys[j], j);
     }
 
-    fn get(&self, key: &Key) -> usize {
-        *self.key_map.get(key).unwrap()
-    }
-
-    fn keys(&self) -> &[Key] {
-        &self.keys
+    /// One more layout of the population was refined.
+    fn refined(&self) {
+        if let Output::Dashboard(dashboard) = self {
+            dashboard.update(|progress| progress.refined += 1);
+        }
     }
-}
 
-impl Gen for Layout {
-    type Config = KeymapConfig;
-
-    fn gen<R: rand::Rng>(rng: &mut R, config: &Self::Config) -> Self {
-        let mut pool = config
-            .keys
-            .keys()
-            .iter()
-            .map(|p| match p.code {
-                Keycode::Backspace => Key::Backspace,
-                Keycode::Tab => Key::Tab,
-                Keycode::Enter => Key::Enter,
-                Keycode::CapsLock => Key::CapsLock,
-                Keycode::LShift => Key::LShift,
-                Keycode::RShift => Key::RShift,
-                Keycode::LControl => Key::LCtrl,
-                Keycode::RControl => Key::RCtrl,
-                Keycode::LAlt => Key::LAlt,
-                Keycode::RAlt => Key::RAlt,
-                Keycode::LMeta => Key::LMeta,
-                Keycode::RMeta => Key::RMeta,
-                Keycode::Space => Key::Space,
-                Keycode::Escape => Key::Escape,
-                Keycode::Home => Key::Home,
-                Keycode::End => Key::End,
-                Keycode::PageUp => Key::PageUp,
-                Keycode::PageDown => Key::PageDown,
-                Keycode::Left => Key::Left,
-                Keycode::Right => Key::Right,
-                Keycode::Up => Key::Up,
-                Keycode::Down => Key::Down,
-                Keycode::Delete => Key::Delete,
-                _ => Key::Normal {
-                    normal: keycode_to_char(p.code),
-                    shifted: default_shifted(keycode_to_char(p.code)),
-                },
-            })
-            .collect::<Vec<_>>();
-        pool.shuffle(rng);
-        Self::new(pool)
+    fn message(&self, message: String) {
+        match self {
+            Output::Plain => println!("{}", message),
+            Output::Dashboard(dashboard) => dashboard.update(|progress| progress.message(message)),
+        }
     }
-}
 
-impl Crossover for Layout {
-    fn crossover<R: rand::Rng>(&self, rng: &mut R, other: &Self) -> (Self, Self) {
-        let mut child1 = Vec::with_capacity(self.keys().len());
-        let mut child2 = Vec::with_capacity(self.keys().len());
-        for (key1, key2) in self.keys().iter().zip(other.keys().iter()) {
-            let (child1_key, child2_key) = {
-                if rng.gen_bool(0.5) {
-                    (*key1, *key2)
-                } else {
-                    (*key2, *key1)
+    /// A generation ended with `best`, scored `score`.
+    fn generation(
+        &self,
+        best: &Layout,
+        keymap_config: &KeymapConfig,
+        score: f64,
+        diversity: f64,
+        summary: Vec<String>,
+    ) {
+        match self {
+            Output::Plain => {
+                for line in summary {
+                    println!("{}", line);
                 }
-            };
-            child1.push(child1_key);
-            child2.push(child2_key);
+            }
+            Output::Dashboard(dashboard) => {
+                let best = layout_to_str(best, keymap_config).unwrap();
+                dashboard.update(|progress| {
+                    progress.generation += 1;
+                    progress.refined = 0;
+                    progress.history.push(score);
+                    progress.best = best;
+                    progress.diversity = diversity;
+                    progress.summary = summary;
+                })
+            }
         }
-        fix_missing_keys(&mut child1, self.keys());
-        fix_missing_keys(&mut child2, self.keys());
-        (Layout::new(child1), Layout::new(child2))
     }
 }
 
-fn fix_missing_keys(child: &mut Vec<Key>, parent: &[Key]) {
-    let all_keys: HashSet<Key> = parent.iter().cloned().collect();
-    let child_keys: HashSet<Key> = child.iter().cloned().collect();
-    let missing_keys = all_keys.difference(&child_keys);
-    for key in missing_keys {
-        let dupe_i = find_duplicate_key_index(&child);
-        child[dupe_i] = *key;
+/// Refines `layout` with the local search chosen in `args`, maximizing
+/// `objective`.  Tabu search and hill climbing score as many layouts as
+/// annealing by `schedule` does before it cools down.
+fn refine(
+    args: &Args,
+    objective: &impl SwapObjective,
+    movable: &[usize],
+    max_objective: f64,
+    schedule: &Schedule,
+    layout: &mut Layout,
+    rng: &mut impl Rng,
+) {
+    let evaluations = schedule.evaluations();
+    match args.search {
+        Search::Annealing if args.bounded_memory => {
+            simmulated_annealing_in_place(objective, movable, max_objective, schedule, layout, rng)
+        }
+        Search::Annealing => {
+            *layout = simmulated_annealing(
+                objective,
+                movable,
+                max_objective,
+                schedule,
+                layout.clone(),
+                rng,
+            )
+        }
+        Search::Tabu => tabu_search(objective, movable, evaluations, layout, rng),
+        Search::HillClimbing => hill_climbing(objective, movable, evaluations, layout, rng),
     }
 }
 
-fn find_duplicate_key_index(keys: &Vec<Key>) -> usize {
-    for i in 0..keys.len() {
-        for j in (i + 1)..keys.len() {
-            if keys[i] == keys[j] {
-                return j;
-            }
-        }
-    }
-    panic!("No duplicate key found");
+/// The preset named `seed`, or the layout in the file at `seed`, with the
+/// pinned keys in place.  Exits if it doesn't fit the keyboard.
+fn read_seed(seed: &str, keymap_config: &KeymapConfig) -> Layout {
+    let layout = match seed.parse::<Preset>() {
+        Ok(preset) => preset.layout(keymap_config),
+        Err(_) => parse_layout(&std::fs::read_to_string(seed).unwrap(), keymap_config)
+            .unwrap_or_else(|error| error.in_file(seed).exit()),
+    };
+    layout.seed(keymap_config).unwrap_or_else(|error| {
+        eprintln!("Can't start from {}, {}", seed, error);
+        std::process::exit(1);
+    })
 }
 
-impl Mutate for Layout {
-    fn mutate<R: rand::Rng>(&mut self, rng: &mut R, rate: f32) {
-        for i in 0..self.keys().len() {
-            for j in (i + 1)..self.keys().len() {
-                if rng.gen_bool(rate as f64) {
-                    self.swap(i, j);
-                }
-            }
-        }
+/// Gets `stats` ready for scoring with [`prepare`], warning about keys
+/// that never appear in it and about keys that were skipped.
+fn prepare_stats(
+    stats: &mut Stats,
+    name: &str,
+    options: &PrepareOptions,
+    scoring: &mut ScoringConfig,
+    fmt: &NumberFormat,
+    keymap_config: &KeymapConfig,
+) {
+    let prepared = prepare(stats, scoring, keymap_config, options);
+    if !prepared.imputed.is_empty() {
+        println!(
+            "Warning: {} keys never appear in {} and are assumed to be pressed {} times each: {:?}",
+            prepared.imputed.len(),
+            name,
+            fmt.count(prepared.prior_count),
+            prepared.imputed
+        );
+        println!("Pass them to --dont-care if their placement doesn't matter");
+    }
+    if let Some(summary) = ignored_keys_summary(stats, fmt) {
+        println!("Warning: in {}, {}", name, summary);
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum Key {
-    Normal { normal: char, shifted: char },
-    Backspace,
-    Tab,
-    Enter,
-    CapsLock,
-    LShift,
-    RShift,
-    LCtrl,
-    RCtrl,
-    LAlt,
-    RAlt,
-    LMeta,
-    RMeta,
-    Space,
-    Escape,
-    Home,
-    End,
-    PageUp,
-    PageDown,
-    Left,
-    Right,
-    Up,
-    Down,
-    Delete,
+/// Writes `best` to `best.txt` and what its score is made of to
+/// `best_breakdown.txt`.
+fn save_best(
+    keymap_config: &KeymapConfig,
+    best: &Layout,
+    stats: &Stats,
+    scoring: &ScoringConfig,
+    fmt: &NumberFormat,
+) {
+    let best_str = layout_to_str(best, keymap_config).unwrap();
+    let mut writer = BufWriter::new(std::fs::File::create("best.txt").unwrap());
+    writer.write_all(best_str.as_bytes()).unwrap();
+    let breakdown = score_breakdown(best, stats, keymap_config, scoring, fmt);
+    std::fs::write("best_breakdown.txt", breakdown).unwrap();
 }
 
-impl Key {
-    fn from_char_default_shifted(c: char) -> Self {
-        Key::Normal {
-            normal: c,
-            shifted: default_shifted(c),
+/// Writes the `n` best distinct layouts of `population` by `score` to
+/// `top/<n>.txt`, counting from 1, and their scores to `top/scores.txt`.
+fn save_top(
+    keymap_config: &KeymapConfig,
+    population: &[Layout],
+    score: &(impl Fn(&Layout) -> f64 + Sync),
+    n: usize,
+    max_possible_score: f64,
+    fmt: &NumberFormat,
+) {
+    let mut scored: Vec<(f64, &Layout)> = population
+        .par_iter()
+        .map(|layout| (score(layout), layout))
+        .collect();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    let mut top: Vec<(f64, &Layout)> = vec![];
+    for (score, layout) in scored {
+        if top.len() == n {
+            break;
         }
-    }
-
-    fn keycode(&self, shift: bool) -> Keycode {
-        match self {
-            Key::Normal { normal, shifted } => {
-                if shift {
-                    char_to_keycode(*shifted)
-                } else {
-                    char_to_keycode(*normal)
-                }
-            }
-            Key::Backspace => Keycode::Backspace,
-            Key::Tab => Keycode::Tab,
-            Key::Enter => Keycode::Enter,
-            Key::CapsLock => Keycode::CapsLock,
-            Key::LShift => Keycode::LShift,
-            Key::RShift => Keycode::RShift,
-            Key::LCtrl => Keycode::LControl,
-            Key::RCtrl => Keycode::RControl,
-            Key::LAlt => Keycode::LAlt,
-            Key::RAlt => Keycode::RAlt,
-            Key::LMeta => Keycode::LMeta,
-            Key::RMeta => Keycode::RMeta,
-            Key::Space => Keycode::Space,
-            Key::Escape => Keycode::Escape,
-            Key::Home => Keycode::Home,
-            Key::End => Keycode::End,
-            Key::PageUp => Keycode::PageUp,
-            Key::PageDown => Keycode::PageDown,
-            Key::Left => Keycode::Left,
-            Key::Right => Keycode::Right,
-            Key::Up => Keycode::Up,
-            Key::Down => Keycode::Down,
-            Key::Delete => Keycode::Delete,
+        // The best layouts are often copies of each other
+        if !top.iter().any(|(_, kept)| kept.keys() == layout.keys()) {
+            top.push((score, layout));
         }
     }
-}
 
-impl GridItem for Key {
-    fn num_items() -> usize {
-        2
+    let dir = std::path::Path::new("top");
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).unwrap();
     }
-
-    fn get_item(&self, i: usize) -> Option<String> {
-        match i {
-            0 => {
-                let code = self.keycode(false);
-                Some(map_keycode_to_str(code).unwrap().to_string())
-            }
-            1 => {
-                let code = self.keycode(true);
-                Some(map_keycode_to_str(code).unwrap().to_string())
-            }
-            _ => None,
-        }
+    std::fs::create_dir_all(dir).unwrap();
+    let mut scores = BufWriter::new(std::fs::File::create(dir.join("scores.txt")).unwrap());
+    writeln!(scores, "layout score of_max").unwrap();
+    for (i, (score, layout)) in top.into_iter().enumerate() {
+        let name = format!("{}.txt", i + 1);
+        std::fs::write(
+            dir.join(&name),
+            layout_to_str(layout, keymap_config).unwrap(),
+        )
+        .unwrap();
+        writeln!(
+            scores,
+            "{} {} {}",
+            name,
+            fmt.decimal(score),
+            fmt.percent(score / max_possible_score)
+        )
+        .unwrap();
     }
 }
 
-fn char_to_keycode(c: char) -> Keycode {
-    match c {
-        'a' => Keycode::A,
-        'b' => Keycode::B,
-        'c' => Keycode::C,
-        'd' => Keycode::D,
-        'e' => Keycode::E,
-        'f' => Keycode::F,
-        'g' => Keycode::G,
-        'h' => Keycode::H,
-        'i' => Keycode::I,
-        'j' => Keycode::J,
-        'k' => Keycode::K,
-        'l' => Keycode::L,
-        'm' => Keycode::M,
-        'n' => Keycode::N,
-        'o' => Keycode::O,
-        'p' => Keycode::P,
-        'q' => Keycode::Q,
-        'r' => Keycode::R,
-        's' => Keycode::S,
-        't' => Keycode::T,
-        'u' => Keycode::U,
-        'v' => Keycode::V,
-        'w' => Keycode::W,
-        'x' => Keycode::X,
-        'y' => Keycode::Y,
-        'z' => Keycode::Z,
-        'A' => Keycode::A,
-        'B' => Keycode::B,
-        'C' => Keycode::C,
-        'D' => Keycode::D,
-        'E' => Keycode::E,
-        'F' => Keycode::F,
-        'G' => Keycode::G,
-        'H' => Keycode::H,
-        'I' => Keycode::I,
-        'J' => Keycode::J,
-        'K' => Keycode::K,
-        'L' => Keycode::L,
-        'M' => Keycode::M,
-        'N' => Keycode::N,
-        'O' => Keycode::O,
-        'P' => Keycode::P,
-        'Q' => Keycode::Q,
-        'R' => Keycode::R,
-        'S' => Keycode::S,
-        'T' => Keycode::T,
-        'U' => Keycode::U,
-        'V' => Keycode::V,
-        'W' => Keycode::W,
-        'X' => Keycode::X,
-        'Y' => Keycode::Y,
-        'Z' => Keycode::Z,
-        '0' => Keycode::Key0,
-        '1' => Keycode::Key1,
-        '2' => Keycode::Key2,
-        '3' => Keycode::Key3,
-        '4' => Keycode::Key4,
-        '5' => Keycode::Key5,
-        '6' => Keycode::Key6,
-        '7' => Keycode::Key7,
-        '8' => Keycode::Key8,
-        '9' => Keycode::Key9,
-        '!' => Keycode::Key1,
-        '@' => Keycode::Key2,
-        '#' => Keycode::Key3,
-        '$' => Keycode::Key4,
-        '%' => Keycode::Key5,
-        '^' => Keycode::Key6,
-        '&' => Keycode::Key7,
-        '*' => Keycode::Key8,
-        '(' => Keycode::Key9,
-        ')' => Keycode::Key0,
-        '-' => Keycode::Minus,
-        '_' => Keycode::Minus,
-        '=' => Keycode::Equal,
-        '+' => Keycode::Equal,
-        '[' => Keycode::LeftBracket,
-        '{' => Keycode::LeftBracket,
-        ']' => Keycode::RightBracket,
-        '}' => Keycode::RightBracket,
-        '\\' => Keycode::BackSlash,
-        '|' => Keycode::BackSlash,
-        ';' => Keycode::Semicolon,
-        ':' => Keycode::Semicolon,
-        '\'' => Keycode::Apostrophe,
-        '"' => Keycode::Apostrophe,
-        ',' => Keycode::Comma,
-        '<' => Keycode::Comma,
-        '.' => Keycode::Dot,
-        '>' => Keycode::Dot,
-        '/' => Keycode::Slash,
-        '?' => Keycode::Slash,
-        ' ' => Keycode::Space,
-        '`' => Keycode::Grave,
-        '~' => Keycode::Grave,
-        _ => unimplemented!(),
-    }
-}
+/// Where the Pareto front is saved to continue it in a later run.
+const FRONT_JSON: &str = "pareto/front.json";
 
-fn keycode_to_char(code: Keycode) -> char {
-    match code {
-        Keycode::A => 'a',
-        Keycode::B => 'b',
-        Keycode::C => 'c',
-        Keycode::D => 'd',
-        Keycode::E => 'e',
-        Keycode::F => 'f',
-        Keycode::G => 'g',
-        Keycode::H => 'h',
-        Keycode::I => 'i',
-        Keycode::J => 'j',
-        Keycode::K => 'k',
-        Keycode::L => 'l',
-        Keycode::M => 'm',
-        Keycode::N => 'n',
-        Keycode::O => 'o',
-        Keycode::P => 'p',
-        Keycode::Q => 'q',
-        Keycode::R => 'r',
-        Keycode::S => 's',
-        Keycode::T => 't',
-        Keycode::U => 'u',
-        Keycode::V => 'v',
-        Keycode::W => 'w',
-        Keycode::X => 'x',
-        Keycode::Y => 'y',
-        Keycode::Z => 'z',
-        Keycode::Key0 => '0',
-        Keycode::Key1 => '1',
-        Keycode::Key2 => '2',
-        Keycode::Key3 => '3',
-        Keycode::Key4 => '4',
-        Keycode::Key5 => '5',
-        Keycode::Key6 => '6',
-        Keycode::Key7 => '7',
-        Keycode::Key8 => '8',
-        Keycode::Key9 => '9',
-        Keycode::Minus => '-',
-        Keycode::Equal => '=',
-        Keycode::LeftBracket => '[',
-        Keycode::RightBracket => ']',
-        Keycode::BackSlash => '\\',
-        Keycode::Semicolon => ';',
-        Keycode::Apostrophe => '\'',
-        Keycode::Comma => ',',
-        Keycode::Dot => '.',
-        Keycode::Slash => '/',
-        Keycode::Grave => '`',
-        Keycode::Space => ' ',
-        code => unimplemented!("{:?}", code),
+/// Writes every layout of `front` to `pareto/<n>.txt`, best score first,
+/// their values to `pareto/front.txt`, the whole front to
+/// [`FRONT_JSON`], and a page to pick from it to `pareto/index.html`.
+fn save_front(keymap_config: &KeymapConfig, front: &ParetoFront, objectives: &[Objective]) {
+    let dir = std::path::Path::new("pareto");
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+    let mut summary = BufWriter::new(std::fs::File::create(dir.join("front.txt")).unwrap());
+    let names: Vec<String> = objectives.iter().map(|o| o.to_string()).collect();
+    writeln!(summary, "layout {}", names.join(" ")).unwrap();
+    for (n, (layout, values)) in front.sorted().into_iter().enumerate() {
+        let name = format!("{n}.txt");
+        let layout_str = layout_to_str(layout, keymap_config).unwrap();
+        std::fs::write(dir.join(&name), layout_str).unwrap();
+        let values: Vec<String> = values.iter().map(|v| format!("{v:.4}")).collect();
+        writeln!(summary, "{} {}", name, values.join(" ")).unwrap();
     }
+    std::fs::write(FRONT_JSON, front.to_json(objectives, keymap_config)).unwrap();
+    std::fs::write(
+        dir.join("index.html"),
+        front.to_html(objectives, keymap_config),
+    )
+    .unwrap();
 }
 
-fn default_shifted(c: char) -> char {
-    match c {
-        'a' => 'A',
-        'b' => 'B',
-        'c' => 'C',
-        'd' => 'D',
-        'e' => 'E',
-        'f' => 'F',
-        'g' => 'G',
-        'h' => 'H',
-        'i' => 'I',
-        'j' => 'J',
-        'k' => 'K',
-        'l' => 'L',
-        'm' => 'M',
-        'n' => 'N',
-        'o' => 'O',
-        'p' => 'P',
-        'q' => 'Q',
-        'r' => 'R',
-        's' => 'S',
-        't' => 'T',
-        'u' => 'U',
-        'v' => 'V',
-        'w' => 'W',
-        'x' => 'X',
-        'y' => 'Y',
-        'z' => 'Z',
-        '0' => ')',
-        '1' => '!',
-        '2' => '@',
-        '3' => '#',
-        '4' => '$',
-        '5' => '%',
-        '6' => '^',
-        '7' => '&',
-        '8' => '*',
-        '9' => '(',
-        '-' => '_',
-        '=' => '+',
-        '[' => '{',
-        ']' => '}',
-        '\\' => '|',
-        ';' => ':',
-        '\'' => '"',
-        ',' => '<',
-        '.' => '>',
-        '/' => '?',
-        '`' => '~',
-        _ => unimplemented!(),
-    }
+/// The resident set size of this process in KiB, where the OS reports it.
+fn resident_memory_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
 }
@@ -0,0 +1,105 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    config::Config, cost_model::CostModel, format::parse_keymap_config, scoring::Weights,
+};
+
+/// Export the effort model of a keyboard as costs, to optimize layouts
+/// with a QAP solver or your own optimizer.
+///
+/// Writes the effort of every physical key and the cost of every pair of
+/// physical keys pressed in a row, resolved under the scoring settings of
+/// the config and the weights.  A layout costs the presses of every key
+/// times the effort of its physical key, plus the presses of every bigram
+/// times the cost of its pair of physical keys.  Lower is better.
+#[derive(Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// Settings such as the scoring bonuses and penalties.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// The constants of the effort model, like `weights.toml`.  Defaults
+    /// to the values written there.
+    #[arg(long)]
+    weights: Option<String>,
+    /// `csv` for `effort.csv` and `transition.csv`, or `npy` for
+    /// `effort.npy` and `transition.npy` to read with `numpy.load`.
+    #[arg(long, default_value = "csv", value_parser = ExportFormat::from_str)]
+    format: ExportFormat,
+    /// The directory to write the files into.
+    #[arg(long, default_value = "cost_model")]
+    output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Npy,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "npy" => Ok(ExportFormat::Npy),
+            _ => Err(format!("expected csv or npy, got {s}")),
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut config = Config::load_or_default(&args.config).unwrap();
+    if let Some(path) = &args.weights {
+        config.scoring.weights =
+            Weights::load(path).unwrap_or_else(|error| error.in_file(path).exit());
+    }
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+
+    let model = CostModel::new(&keymap_config, &config.scoring);
+    let left_out = CostModel::left_out(&config.scoring, None);
+    if !left_out.is_empty() {
+        println!(
+            "Warning: the cost model leaves out the {} of the score",
+            left_out.join(", ")
+        );
+    }
+    std::fs::create_dir_all(&args.output).unwrap();
+    let create = |name: &str| BufWriter::new(File::create(args.output.join(name)).unwrap());
+    match args.format {
+        ExportFormat::Csv => {
+            model.write_effort_csv(&mut create("effort.csv")).unwrap();
+            model
+                .write_transition_csv(&mut create("transition.csv"))
+                .unwrap();
+        }
+        ExportFormat::Npy => {
+            model.write_effort_npy(&mut create("effort.npy")).unwrap();
+            model
+                .write_transition_npy(&mut create("transition.npy"))
+                .unwrap();
+            // The arrays have no room for names, so the keys go alongside
+            let mut keys = create("keys.txt");
+            for key in &model.keys {
+                writeln!(keys, "{}", key).unwrap();
+            }
+        }
+    }
+    println!(
+        "Wrote the costs of {} physical keys to {}",
+        model.keys.len(),
+        args.output.display()
+    );
+}
@@ -0,0 +1,90 @@
+use std::str::FromStr;
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    format::{parse_keymap_config, parse_layers, parse_layout},
+    kanata::{export_kanata, export_kmonad},
+};
+
+/// Export a generated layout as a kanata or kmonad config, to try it as a
+/// software remap without flashing firmware.
+#[derive(Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to export, either `best.txt` or layers written by
+    /// `propose_nav_layer` or `analyze`.
+    layout: String,
+    /// `kanata` or `kmonad`.
+    #[arg(long, default_value = "kanata", value_parser = RemapFormat::from_str)]
+    format: RemapFormat,
+    /// The keyboard kmonad reads from, usually under `/dev/input/by-id/`.
+    #[arg(long, default_value = "/dev/input/by-id/YOUR-KEYBOARD-event-kbd")]
+    device: String,
+    /// How long in milliseconds a layer key has to be held to activate
+    /// the layer instead of typing its key.
+    #[arg(long, default_value_t = 200)]
+    tapping_term: u32,
+    /// The config file to write.
+    #[arg(long, default_value = "layout.kbd")]
+    output: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RemapFormat {
+    Kanata,
+    Kmonad,
+}
+
+impl FromStr for RemapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kanata" => Ok(RemapFormat::Kanata),
+            "kmonad" => Ok(RemapFormat::Kmonad),
+            _ => Err(format!("expected kanata or kmonad, got {s}")),
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout_str = std::fs::read_to_string(&args.layout).unwrap();
+    let (base, layers) = if layout_str.lines().any(|line| line.starts_with("Layer ")) {
+        parse_layers(&layout_str, &keymap_config)
+    } else {
+        parse_layout(&layout_str, &keymap_config).map(|base| (base, vec![]))
+    }
+    .unwrap_or_else(|error| error.in_file(&args.layout).exit());
+
+    let config = match args.format {
+        RemapFormat::Kanata => export_kanata(&base, &layers, &keymap_config, args.tapping_term),
+        RemapFormat::Kmonad => {
+            let kmonad = export_kmonad(
+                &base,
+                &layers,
+                &keymap_config,
+                &args.device,
+                args.tapping_term,
+            );
+            if kmonad.dropped_overrides > 0 {
+                println!(
+                    "Warning: {} keys shift to a different character than on a US layout, which kmonad can't express, so they type the US shifted character",
+                    kmonad.dropped_overrides
+                );
+            }
+            kmonad.config
+        }
+    };
+    std::fs::write(&args.output, config).unwrap();
+    println!("Wrote the config to {}", args.output);
+    match args.format {
+        RemapFormat::Kanata => println!("Run it with: kanata --cfg {}", args.output),
+        RemapFormat::Kmonad => println!("Run it with: kmonad {}", args.output),
+    }
+}
@@ -0,0 +1,54 @@
+use clap::Parser;
+use keyboard_layout_generator::{
+    format::{parse_keymap_config, parse_layout},
+    kle::export_kle,
+    stats::read_stats,
+};
+
+/// Export a generated layout as KLE JSON, to look at and share on
+/// keyboard-layout-editor.com.
+///
+/// The keys are drawn on the grid of the keymap config, with the shifted
+/// character above the unshifted one.  Given stats, keys are colored by how
+/// often they're pressed, from grey through yellow to red.
+#[derive(Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to export, in the format of `best.txt`.
+    layout: String,
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`, to color the keys by.
+    #[arg(long)]
+    stats: Option<String>,
+    /// The JSON file to write.
+    #[arg(long, default_value = "layout.json")]
+    output: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = parse_layout(
+        &std::fs::read_to_string(&args.layout).unwrap(),
+        &keymap_config,
+    )
+    .unwrap_or_else(|error| error.in_file(&args.layout).exit());
+    let stats = args
+        .stats
+        .as_ref()
+        .map(|path| read_stats(path).unwrap_or_else(|error| error.in_file(path).exit()));
+
+    std::fs::write(
+        &args.output,
+        export_kle(&layout, &keymap_config, stats.as_ref()),
+    )
+    .unwrap();
+    println!(
+        "Wrote {}, open it with Upload JSON on keyboard-layout-editor.com",
+        args.output
+    );
+}
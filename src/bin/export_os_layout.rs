@@ -0,0 +1,93 @@
+use std::{path::PathBuf, str::FromStr};
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    format::{parse_keymap_config, parse_layout},
+    os_layout::{export_keylayout, export_klc, utf16_le, write_keylayout_bundle},
+};
+
+/// Export a generated layout as an OS keyboard layout, to type on it on
+/// Windows or macOS without running a remapper.
+///
+/// `klc` writes a source file for Microsoft Keyboard Layout Creator, which
+/// builds an installer from it.  `keylayout` writes a bundle to copy into
+/// `~/Library/Keyboard Layouts` on macOS.  Only the character keys can be
+/// changed this way, so keys the layout moves onto or off modifiers,
+/// thumb keys, or arrows are left out.
+#[derive(Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to export, in the format of `best.txt`.
+    layout: String,
+    /// `klc` for Windows or `keylayout` for macOS.
+    #[arg(long, value_parser = OsFormat::from_str)]
+    format: OsFormat,
+    /// The name the layout is listed under.  Defaults to the name of the
+    /// layout file.
+    #[arg(long)]
+    name: Option<String>,
+    /// The directory to write into.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OsFormat {
+    Klc,
+    Keylayout,
+}
+
+impl FromStr for OsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "klc" => Ok(OsFormat::Klc),
+            "keylayout" => Ok(OsFormat::Keylayout),
+            _ => Err(format!("expected klc or keylayout, got {s}")),
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = parse_layout(
+        &std::fs::read_to_string(&args.layout).unwrap(),
+        &keymap_config,
+    )
+    .unwrap_or_else(|error| error.in_file(&args.layout).exit());
+    let name = args.name.clone().unwrap_or_else(|| {
+        PathBuf::from(&args.layout)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    });
+
+    std::fs::create_dir_all(&args.output).unwrap();
+    let (exported, path) = match args.format {
+        OsFormat::Klc => {
+            let klc = export_klc(&layout, &keymap_config, &name);
+            let path = args.output.join(format!("{name}.klc"));
+            std::fs::write(&path, utf16_le(&klc.contents)).unwrap();
+            (klc, path)
+        }
+        OsFormat::Keylayout => {
+            let keylayout = export_keylayout(&layout, &keymap_config, &name);
+            write_keylayout_bundle(&args.output, &name, &keylayout.contents).unwrap();
+            (keylayout, args.output.join(format!("{name}.bundle")))
+        }
+    };
+    if exported.unmapped > 0 {
+        println!(
+            "Warning: {} keys are on or off keys that aren't character keys, which an OS layout can't change, so they're left out; use apply or export_kanata for those",
+            exported.unmapped
+        );
+    }
+    println!("Wrote {}", path.display());
+}
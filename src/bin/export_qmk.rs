@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    combos::{propose_combos, ComboConfig},
+    format::{parse_keymap_config, parse_layers, parse_layout},
+    qmk::{export_qmk, export_qmk_json},
+    stats::read_stats,
+};
+
+/// Scaffold a QMK userspace keymap from a generated layout, ready to build
+/// with `qmk compile`.
+#[derive(Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.  The keys have to be in the order
+    /// the keyboard's `LAYOUT` macro expects.
+    keymap_config: String,
+    /// The layout to export, either `best.txt` or layers written by
+    /// `propose_nav_layer` or `analyze`.
+    layout: String,
+    /// The QMK keyboard to build for, e.g. `kinesis/kint36`.
+    #[arg(long)]
+    keyboard: String,
+    /// Name of the keymap.  Defaults to the name of the layout file.
+    #[arg(long)]
+    name: Option<String>,
+    /// The userspace directory to write the keymap into.
+    #[arg(long, default_value = "qmk_userspace")]
+    output: PathBuf,
+    /// How long in milliseconds a layer key has to be held to activate
+    /// the layer instead of typing its key.
+    #[arg(long, default_value_t = 200)]
+    tapping_term: u32,
+    /// Add up to this many combos for frequent bigrams and trigrams,
+    /// chosen from the stats given with `--stats`.
+    #[arg(long, requires = "stats")]
+    combos: Option<usize>,
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`, to choose combos from.
+    #[arg(long)]
+    stats: Option<String>,
+    /// Write a `keymap.json` instead of `keymap.c`, which QMK Configurator
+    /// can open too.  JSON keymaps have no key overrides or combos.
+    #[arg(long, conflicts_with = "combos")]
+    json: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout_str = std::fs::read_to_string(&args.layout).unwrap();
+    let (base, layers) = if layout_str.lines().any(|line| line.starts_with("Layer ")) {
+        parse_layers(&layout_str, &keymap_config)
+    } else {
+        parse_layout(&layout_str, &keymap_config).map(|base| (base, vec![]))
+    }
+    .unwrap_or_else(|error| error.in_file(&args.layout).exit());
+
+    let name = args.name.clone().unwrap_or_else(|| {
+        Path::new(&args.layout)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    });
+    let dir = args
+        .output
+        .join("keyboards")
+        .join(&args.keyboard)
+        .join("keymaps")
+        .join(&name);
+    if args.json {
+        let keymap = export_qmk_json(&base, &layers, &args.keyboard, &name, args.tapping_term);
+        if keymap.dropped_overrides > 0 {
+            println!(
+                "Warning: {} keys shift to a different character than on a US layout, which keymap.json can't express, so they type the US shifted character",
+                keymap.dropped_overrides
+            );
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("keymap.json"),
+            serde_json::to_string_pretty(&keymap.keymap_json).unwrap(),
+        )
+        .unwrap();
+    } else {
+        let combos = match (&args.stats, args.combos) {
+            (Some(path), Some(max_combos)) => {
+                let stats = read_stats(path).unwrap_or_else(|error| error.exit());
+                let combo_config = ComboConfig {
+                    max_combos,
+                    ..Default::default()
+                };
+                propose_combos(&base, &stats, &keymap_config, &combo_config)
+            }
+            _ => vec![],
+        };
+        export_qmk(&base, &layers, &combos, &keymap_config, args.tapping_term)
+            .write(&dir)
+            .unwrap();
+    }
+
+    // A userspace lists what to build in `qmk.json`, which is left alone
+    // if it already lists other keymaps.
+    let qmk_json = args.output.join("qmk.json");
+    if !qmk_json.exists() {
+        let manifest = serde_json::json!({
+            "userspace_version": "1.0",
+            "build_targets": [[args.keyboard, name]],
+        });
+        std::fs::write(&qmk_json, serde_json::to_string_pretty(&manifest).unwrap()).unwrap();
+    }
+
+    println!("Wrote the keymap to {}", dir.display());
+    println!(
+        "Build it with: qmk compile -kb {} -km {}",
+        args.keyboard, name
+    );
+}
@@ -0,0 +1,93 @@
+use std::io::Write;
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    error::{self, Error},
+    format::{keymap_config_to_str, map_keycode_to_str},
+    kle::{finger_name, parse_key_annotation, parse_kle, Annotations, KleGrid},
+};
+
+/// Import a physical keyboard from the JSON downloaded from
+/// keyboard-layout-editor.com and write it as a `.layout` file.
+///
+/// KLE doesn't say which finger presses a key or how comfortable it is, so
+/// those come from the annotations file, and whatever it leaves out is
+/// asked for here.  Legends are matched to keys by their bottom line, like
+/// `1` on a `!` over `1` key, and unlabeled keys at least 4 wide are space
+/// bars.  Keys whose legends don't match any key, like `Fn`, are left out.
+#[derive(Parser)]
+struct Args {
+    /// The JSON file downloaded from keyboard-layout-editor.com.
+    kle: String,
+    /// A TOML file with the fingers and scores of the keys, see the README.
+    #[arg(long)]
+    annotations: Option<String>,
+    /// The `.layout` file to write.
+    #[arg(long, default_value = "keyboard.layout")]
+    output: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    let json = std::fs::read_to_string(&args.kle).unwrap();
+    let keys = parse_kle(&json).unwrap_or_else(|error| error.in_file(&args.kle).exit());
+    let grid = KleGrid::new(&keys).unwrap_or_else(|error| error.in_file(&args.kle).exit());
+    let annotations = match &args.annotations {
+        Some(path) => Annotations::load(path).unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Annotations::default(),
+    };
+    for legend in &grid.skipped {
+        println!("Warning: left out the key labeled {legend:?}, which isn't a known key");
+    }
+
+    let config = grid
+        .keymap_config(
+            |code, (col, row)| match annotations.key(code) {
+                Some(annotation) => annotation,
+                None => ask(
+                    &format!(
+                        "Finger and score of {} (row {}, column {}), like `LP 75`: ",
+                        map_keycode_to_str(code).unwrap(),
+                        row + 1.0,
+                        col + 1.0
+                    ),
+                    parse_key_annotation,
+                ),
+            },
+            |finger| match annotations.finger(finger) {
+                Some(score) => Ok(score),
+                None => ask(
+                    &format!("Score of {}, like `70`: ", finger_name(finger)),
+                    |s| {
+                        s.parse::<f64>()
+                            .map(|score| score / 100.0)
+                            .map_err(|_| Error::Invalid(format!("invalid score: {s:?}")))
+                    },
+                ),
+            },
+        )
+        .unwrap_or_else(|error| error.exit());
+
+    std::fs::write(&args.output, keymap_config_to_str(&config).unwrap()).unwrap();
+    println!("Wrote {} keys to {}", config.keys.keys().len(), args.output);
+}
+
+/// Asks `question` until the answer parses, or fails at the end of the
+/// input.
+fn ask<T>(question: &str, parse: impl Fn(&str) -> error::Result<T>) -> error::Result<T> {
+    loop {
+        print!("{question}");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Err(Error::Invalid(format!(
+                "no answer to {:?}",
+                question.trim_end_matches(": ")
+            )));
+        }
+        match parse(line.trim()) {
+            Ok(answer) => return Ok(answer),
+            Err(error) => println!("{error}"),
+        }
+    }
+}
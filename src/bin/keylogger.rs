@@ -1,13 +1,21 @@
 use std::{
     collections::HashSet,
     fs::File,
-    io::{BufWriter, Write},
+    io::BufWriter,
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use clap::Parser;
-use device_query::{DeviceQuery, DeviceState};
+use device_query::Keycode;
+use keyboard_layout_generator::{
+    capture::{
+        now_millis, ActiveWindow, Backend, KeyEvent, LogFormat, LogHeader, LogLine, LogWriter,
+    },
+    config::Config,
+    diagnostics::{diagnose, worst, Environment, Problem, Severity},
+    privacy::WindowExclusions,
+};
 
 /// A simple keylogger that logs key presses and releases to a file.
 #[derive(Parser)]
@@ -16,33 +24,231 @@ struct Args {
     /// If the file does not exist, it will be created, otherwise  
     /// it will be appended to.
     log_file: String,
+    /// Toggle a session with this tag by pressing Ctrl+Alt+G, e.g. to
+    /// mark gaming sessions so they can be left out with
+    /// `--exclude-tagged`.
+    #[arg(long)]
+    tag_hotkey: Option<String>,
+    /// A name for the keyboard you type on, like `laptop` or `split`,
+    /// recorded in the log so its stats can be kept apart from those of
+    /// other keyboards with `--device`.  Without it, the `evdev` backend
+    /// records which keyboard every key was typed on by itself.
+    #[arg(long)]
+    device: Option<String>,
+    /// Read key presses by polling the held keys with `poll`, on every
+    /// platform, or on Linux from the keyboards with `evdev`, which misses
+    /// no key presses and works under Wayland but needs the input group.
+    #[arg(long, default_value = "poll")]
+    backend: Backend,
+    /// Write a new log as `text` or in the compact `binary` format.  An
+    /// existing log is continued in its own format.
+    #[arg(long, default_value = "text")]
+    format: LogFormat,
+    /// Settings such as the windows not to log.
+    #[arg(long, default_value = "config.json")]
+    config: String,
 }
 
+/// How often to check again for a permission that's being granted.
+const PERMISSION_RETRY: Duration = Duration::from_secs(2);
+
+/// How long to wait for the first key press before suspecting that key
+/// presses aren't reaching the keylogger.
+const SILENCE_WARNING: Duration = Duration::from_secs(60);
+
+/// How long to trust the focused window before asking again.  Asking
+/// takes a process or two, so not for every key.
+const FOCUS_CHECK: Duration = Duration::from_millis(250);
+
 fn main() {
     let args = Args::parse();
-    let mut log_file = BufWriter::new(
+    let config = Config::load_or_default(&args.config).unwrap();
+    let problems = check_permissions(args.backend);
+    let mut focus = Focus::new(WindowExclusions::new(&config.excluded_windows));
+    let existing = LogFormat::detect(&args.log_file).unwrap_or_else(|error| error.exit());
+    if existing.is_some_and(|format| format != args.format) {
+        println!(
+            "Warning: {} is a log in the other format, continuing it in that one",
+            args.log_file
+        );
+    }
+    let file = BufWriter::new(
         File::options()
             .append(true)
             .create(true)
             .open(&args.log_file)
             .unwrap(),
     );
-    let device_state = DeviceState::new();
-    let mut keys = HashSet::new();
+    let mut log_file = match existing {
+        Some(format) => LogWriter::append(file, format),
+        None => LogWriter::new(file, args.format).unwrap(),
+    };
+    log_file
+        .write_line(&LogLine::Header(LogHeader::detect(
+            args.device.clone(),
+            now_millis(),
+        )))
+        .unwrap();
+    log_file.flush().unwrap();
+    let mut source = args.backend.open().unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        std::process::exit(1)
+    });
+    let mut held = HashSet::new();
+    // Keys whose press was logged, so their release is logged even in an
+    // excluded window instead of leaving them held
+    let mut logged_held = HashSet::new();
+    let mut tagged = false;
+    let started = Instant::now();
+    let mut seen_key = false;
+    let mut warned_silence = false;
+    let mut current_device: Option<String> = None;
 
     loop {
-        let current: HashSet<_> = device_state.get_keys().into_iter().collect();
-        let press: Vec<_> = current.difference(&keys).cloned().collect();
-        let release: Vec<_> = keys.difference(&current).cloned().collect();
-        for key in press {
-            keys.insert(key);
-            writeln!(log_file, "{key} 1").unwrap();
-        }
-        for key in release {
-            keys.remove(&key);
-            writeln!(log_file, "{key} 0").unwrap();
+        for KeyEvent {
+            code: key,
+            press,
+            timestamp,
+            device,
+        } in source.next_events().unwrap()
+        {
+            seen_key = true;
+            if press {
+                held.insert(key);
+            } else {
+                held.remove(&key);
+            }
+            let excluded = focus.excluded();
+            if (press && !excluded) || (!press && logged_held.remove(&key)) {
+                if press {
+                    logged_held.insert(key);
+                }
+                let device = device
+                    .filter(|_| args.device.is_none())
+                    .map(|name| config.device_names.get(&name).cloned().unwrap_or(name))
+                    .filter(|name| current_device.as_ref() != Some(name));
+                if let Some(name) = device {
+                    log_file.write_line(&LogLine::Device(name.clone())).unwrap();
+                    current_device = Some(name);
+                }
+                log_file
+                    .write_line(&LogLine::Event(key, press, Some(timestamp)))
+                    .unwrap();
+            }
+
+            let hotkey = press
+                && key == Keycode::G
+                && held.contains(&Keycode::LControl)
+                && held.contains(&Keycode::LAlt);
+            if let (true, Some(tag)) = (hotkey, &args.tag_hotkey) {
+                tagged = !tagged;
+                let marker = if tagged {
+                    LogLine::Tag(tag.clone())
+                } else {
+                    LogLine::Untag(tag.clone())
+                };
+                log_file.write_line(&marker).unwrap();
+                println!(
+                    "{} session {}",
+                    if tagged { "Started" } else { "Ended" },
+                    tag
+                );
+            }
         }
         log_file.flush().unwrap();
-        sleep(Duration::from_millis(50))
+        if !seen_key && !warned_silence && started.elapsed() > SILENCE_WARNING {
+            warned_silence = true;
+            warn_silence(&problems);
+        }
+    }
+}
+
+/// Prints what keeps key presses from being captured and how to fix it.
+/// Exits if nothing can be captured, and waits if a permission has to be
+/// granted first, checking again until it is.  Returns the problems that
+/// are left.
+fn check_permissions(backend: Backend) -> Vec<Problem> {
+    let mut problems = diagnose(&Environment::detect(backend));
+    for problem in &problems {
+        eprint!("{}", problem);
+    }
+    match worst(&problems) {
+        Some(Severity::Fatal) => std::process::exit(1),
+        Some(Severity::Blocking) => {
+            while worst(&problems) == Some(Severity::Blocking) {
+                sleep(PERMISSION_RETRY);
+                problems = diagnose(&Environment::detect(backend));
+            }
+            if worst(&problems) == Some(Severity::Fatal) {
+                problems.iter().for_each(|problem| eprint!("{}", problem));
+                std::process::exit(1);
+            }
+            println!("Permission granted, logging key presses");
+        }
+        _ => {}
+    }
+    problems
+}
+
+/// Whether the focused window is excluded from logging, asked at most
+/// every [`FOCUS_CHECK`].
+struct Focus {
+    exclusions: WindowExclusions,
+    checked: Option<Instant>,
+    excluded: bool,
+}
+
+impl Focus {
+    fn new(exclusions: WindowExclusions) -> Self {
+        if !exclusions.is_empty() && ActiveWindow::detect().is_none() {
+            eprintln!("Warning: can't tell which window is focused, so nothing is logged while excluded_windows is set");
+            eprintln!("  Under Wayland, log out and pick an X11 session on the login screen");
+            eprintln!("  Or install xprop, which tells the focused window on X11");
+        }
+        Self {
+            exclusions,
+            checked: None,
+            excluded: false,
+        }
+    }
+
+    fn excluded(&mut self) -> bool {
+        if self.exclusions.is_empty() {
+            return false;
+        }
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < FOCUS_CHECK)
+        {
+            return self.excluded;
+        }
+        self.checked = Some(Instant::now());
+        // Better to miss keys than to log those of an excluded window
+        let matching = match ActiveWindow::detect() {
+            Some(window) => self.exclusions.matching(&window).map(str::to_string),
+            None => Some("no window can be told apart".to_string()),
+        };
+        if matching.is_some() != self.excluded {
+            match &matching {
+                Some(pattern) => println!("Paused logging, the focused window matches {}", pattern),
+                None => println!("Resumed logging"),
+            }
+        }
+        self.excluded = matching.is_some();
+        self.excluded
+    }
+}
+
+fn warn_silence(problems: &[Problem]) {
+    eprintln!(
+        "Warning: no key presses in the first {} seconds, they may not be reaching the keylogger",
+        SILENCE_WARNING.as_secs()
+    );
+    if problems.is_empty() {
+        eprintln!("  Type something in another window and check that the log grows");
+        eprintln!("  Remote desktops and virtual machines may keep key presses to themselves");
+    } else {
+        eprintln!("  These could be why:");
+        problems.iter().for_each(|problem| eprint!("{}", problem));
     }
 }
@@ -0,0 +1,74 @@
+use clap::Parser;
+use keyboard_layout_generator::{
+    format::{layers_to_str, parse_keymap_config, parse_layout},
+    layout::Layout,
+    nav::{nav_key_counts, propose_nav_layer, NavLayerConfig},
+    stats::process_log,
+};
+
+/// Propose a navigation layer for the arrow and editing keys based on how
+/// you use them, with the effort it would save compared to reaching for
+/// them on the base layout.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log file created by the keylogger.
+    log_file: String,
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The base layout, in the format of `best.txt`.  Defaults to the
+    /// keys printed in the keymap configuration.
+    #[arg(long)]
+    layout: Option<String>,
+    /// Where to write the base layout and the navigation layer.
+    #[arg(long, default_value = "layers.txt")]
+    output: String,
+    /// Effort of holding the layer key relative to pressing it.
+    #[arg(long, default_value_t = 0.5)]
+    hold_cost: f64,
+    /// Effort added for every normal press of the hold key.
+    #[arg(long, default_value_t = 0.1)]
+    tap_hold_penalty: f64,
+}
+
+fn main() {
+    let args = Args::parse();
+    let stats = process_log(&args.log_file).unwrap_or_else(|error| error.exit());
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = match &args.layout {
+        Some(path) => parse_layout(&std::fs::read_to_string(path).unwrap(), &keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Layout::from_keymap(&keymap_config),
+    };
+
+    println!("Navigation key presses:");
+    for (code, count) in nav_key_counts(&stats) {
+        println!("{:?}: {}", code, count);
+    }
+
+    let nav_config = NavLayerConfig {
+        hold_cost: args.hold_cost,
+        tap_hold_penalty: args.tap_hold_penalty,
+    };
+    let Some(proposal) = propose_nav_layer(&layout, &stats, &keymap_config, &nav_config) else {
+        println!("No navigation keys were used, a layer wouldn't help");
+        return;
+    };
+
+    println!(
+        "\nHold {:?} for the navigation layer",
+        keymap_config.keys.keys()[proposal.layer.hold].code
+    );
+    println!(
+        "Effort: {:.1} on the base layout, {:.1} on the layer ({:.1}% saved)",
+        proposal.base_cost,
+        proposal.layer_cost,
+        proposal.savings() * 100.0
+    );
+
+    let s = layers_to_str(&layout, &[proposal.layer], &keymap_config).unwrap();
+    std::fs::write(&args.output, s).unwrap();
+    println!("Layers written to {}", args.output);
+}
@@ -0,0 +1,52 @@
+use clap::Parser;
+use keyboard_layout_generator::{
+    format::{parse_keymap_config, parse_layout},
+    heatmap::{render_svg, HeatmapColor},
+    layout::Layout,
+    stats::read_stats,
+};
+
+/// Draw how often every key of the keyboard is pressed as an SVG heatmap.
+///
+/// Keys are placed on the grid of the keymap config and show their legend
+/// and their share of all presses.  Without `--layout`, the keys are those
+/// printed on the keycaps.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`.
+    log_file: String,
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to draw, in the format of `best.txt`.
+    #[arg(long)]
+    layout: Option<String>,
+    /// Color the keys by `frequency` or by the `finger` pressing them.
+    #[arg(long, default_value = "frequency")]
+    color: HeatmapColor,
+    /// The SVG file to write.
+    #[arg(long, default_value = "heatmap.svg")]
+    output: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = match &args.layout {
+        Some(path) => parse_layout(&std::fs::read_to_string(path).unwrap(), &keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Layout::from_keymap(&keymap_config),
+    };
+    let stats =
+        read_stats(&args.log_file).unwrap_or_else(|error| error.in_file(&args.log_file).exit());
+
+    std::fs::write(
+        &args.output,
+        render_svg(&layout, &keymap_config, &stats, args.color),
+    )
+    .unwrap();
+    println!("Wrote {}", args.output);
+}
@@ -0,0 +1,258 @@
+use std::{
+    cell::RefCell,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    cache::{CachedScorer, ScoreCache},
+    capture::LogTail,
+    config::Config,
+    error::Error,
+    format::{parse_keymap_config, parse_layout},
+    layout::Layout,
+    report::{ignored_keys_summary, metrics_table, LayoutMetrics},
+    scoring::LayoutScorer,
+    stats::{prepare, read_merged_stats, LogProcessor, PrepareOptions},
+    KeymapConfig,
+};
+
+/// Score layouts on your typing and break them down, metric by metric,
+/// side by side.
+///
+/// Every layout gets a column with its score, same finger bigrams, hand
+/// alternation and rolls, and the load of every finger, so you can see
+/// where a layout like Colemak-DH gains or loses against QWERTY or a
+/// generated layout.  With `--rank`, it only ranks them by score instead,
+/// for many layouts.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`.
+    log_file: String,
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layouts to score, in the format of `best.txt`.
+    layouts: Vec<String>,
+    /// Settings such as how numbers are formatted.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// Add a column for the layout printed on the keycaps, as the keymap
+    /// configuration lists it.
+    #[arg(long)]
+    keycaps: bool,
+    /// Only print the score of every layout, best first, instead of
+    /// breaking them down.
+    #[arg(long, conflicts_with = "watch")]
+    rank: bool,
+    /// Keep the scores in this file between runs, so scoring the same
+    /// layouts again is instant.  They're cached by the layout, the stats,
+    /// the scoring settings, and the physical keyboard, so changing any of
+    /// them scores the layouts afresh.
+    #[arg(long)]
+    cache: Option<String>,
+    /// Keep reading the log as the keylogger adds to it, and every this
+    /// many seconds show how the layouts do on everything typed so far
+    /// next to how they did when it started.
+    #[arg(long)]
+    watch: Option<u64>,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.layouts.is_empty() && !args.keycaps {
+        println!("Nothing to score, pass layouts or --keycaps");
+        return;
+    }
+    let mut config = Config::load_or_default(&args.config)
+        .unwrap_or_else(|error| Error::from(error).in_file(&args.config).exit());
+    let log_options = config
+        .log_options(&[])
+        .unwrap_or_else(|error| error.in_file(&args.config).exit());
+    let keymap_config = parse_keymap_config(&read_file(&args.keymap_config))
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let fmt = &config.number_format;
+    let scoring = &mut config.scoring;
+    let cache = RefCell::new(match &args.cache {
+        Some(path) => {
+            ScoreCache::load(path).unwrap_or_else(|error| Error::from(error).in_file(path).exit())
+        }
+        None => ScoreCache::default(),
+    });
+    let save_cache = || {
+        if let Some(path) = &args.cache {
+            cache
+                .borrow()
+                .save(path)
+                .unwrap_or_else(|error| Error::from(error).in_file(path).exit());
+        }
+    };
+
+    let Some(interval) = args.watch else {
+        let mut stats =
+            read_merged_stats(&[&args.log_file], &log_options).unwrap_or_else(|error| error.exit());
+        prepare(
+            &mut stats,
+            scoring,
+            &keymap_config,
+            &PrepareOptions::default(),
+        );
+        if let Some(summary) = ignored_keys_summary(&stats, fmt) {
+            println!("Warning: {}", summary);
+        }
+        let scorer = CachedScorer::new(scoring, &keymap_config, &stats, &cache);
+        let layouts = read_layouts(&args, &keymap_config);
+        if args.rank {
+            let max_possible_score = scorer.max_possible_score(&stats);
+            let mut scores: Vec<(String, f64)> = layouts
+                .into_iter()
+                .map(|(name, layout)| {
+                    let score = scorer.score(&layout, &stats, &keymap_config);
+                    (name, score)
+                })
+                .collect();
+            scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+            for (name, score) in &scores {
+                println!(
+                    "{}: {} ({} of max)",
+                    name,
+                    fmt.decimal(*score),
+                    fmt.percent(score / max_possible_score)
+                );
+            }
+        } else {
+            let metrics: Vec<(String, LayoutMetrics)> = layouts
+                .into_iter()
+                .map(|(name, layout)| {
+                    let metrics = LayoutMetrics::new(&layout, &stats, &keymap_config, &scorer);
+                    (name, metrics)
+                })
+                .collect();
+            print!("{}", metrics_table(&metrics, fmt));
+        }
+        save_cache();
+        if args.cache.is_some() {
+            let scored = args.layouts.len() + args.keycaps as usize;
+            println!(
+                "Scored {} layouts, {} of them from the cache",
+                fmt.count(scored as u64),
+                fmt.count((scored - scorer.computed()) as u64)
+            );
+        }
+        return;
+    };
+
+    if args.log_file.ends_with(".json") {
+        eprintln!("Can't watch {}, saved stats don't grow", args.log_file);
+        std::process::exit(1);
+    }
+    let mut tail = LogTail::new(&args.log_file);
+    let mut processor = LogProcessor::new(&log_options);
+    let read_new = |tail: &mut LogTail| {
+        tail.read_new()
+            .unwrap_or_else(|error| error.in_file(&args.log_file).exit())
+    };
+    for line in read_new(&mut tail) {
+        processor.push_line(line);
+    }
+    let mut at_start = processor.snapshot();
+    let presses_at_start: u64 = at_start.individual_key_counts.values().sum();
+    // The editor detected at the start stays the editor of every report
+    prepare(
+        &mut at_start,
+        scoring,
+        &keymap_config,
+        &PrepareOptions::default(),
+    );
+    if let Some(summary) = ignored_keys_summary(&at_start, fmt) {
+        println!("Warning: {}", summary);
+    }
+    let scoring = &*scoring;
+    let scorer_at_start = CachedScorer::new(scoring, &keymap_config, &at_start, &cache);
+    let metrics: Vec<(String, LayoutMetrics)> = read_layouts(&args, &keymap_config)
+        .into_iter()
+        .map(|(name, layout)| {
+            let metrics = LayoutMetrics::new(&layout, &at_start, &keymap_config, &scorer_at_start);
+            (name, metrics)
+        })
+        .collect();
+    print!("{}", metrics_table(&metrics, fmt));
+    save_cache();
+
+    // Every report puts the layouts on the stats so far next to the same
+    // layouts on the stats at the start
+    let started = Instant::now();
+    let mut reported = presses_at_start;
+    loop {
+        std::thread::sleep(Duration::from_secs(interval));
+        for line in read_new(&mut tail) {
+            processor.push_line(line);
+        }
+        let mut stats = processor.snapshot();
+        let presses: u64 = stats.individual_key_counts.values().sum();
+        if presses == reported {
+            continue;
+        }
+        reported = presses;
+        // The editor is already resolved, so preparing leaves the scoring
+        // as it is
+        prepare(
+            &mut stats,
+            &mut scoring.clone(),
+            &keymap_config,
+            &PrepareOptions::default(),
+        );
+        let scorer = CachedScorer::new(scoring, &keymap_config, &stats, &cache);
+        let mut metrics = vec![];
+        // Layouts are read again, since explore_layouts may have saved a
+        // better best.txt in the meantime
+        for (name, layout) in read_layouts(&args, &keymap_config) {
+            let before = LayoutMetrics::new(&layout, &at_start, &keymap_config, &scorer_at_start);
+            let now = LayoutMetrics::new(&layout, &stats, &keymap_config, &scorer);
+            metrics.push((format!("{name} (start)"), before));
+            metrics.push((name, now));
+        }
+        println!(
+            "\nAfter {} minutes, {} more presses:",
+            fmt.count(started.elapsed().as_secs() / 60),
+            fmt.count(presses - presses_at_start)
+        );
+        print!("{}", metrics_table(&metrics, fmt));
+        save_cache();
+    }
+}
+
+/// The keycaps if asked for and every layout of `args`, named by their
+/// file.
+fn read_layouts(args: &Args, keymap_config: &KeymapConfig) -> Vec<(String, Layout)> {
+    let mut layouts = vec![];
+    if args.keycaps {
+        layouts.push(("keycaps".to_string(), Layout::from_keymap(keymap_config)));
+    }
+    let stem = |path: &String| {
+        Path::new(path)
+            .file_stem()
+            .map_or(path.clone(), |stem| stem.to_string_lossy().into_owned())
+    };
+    for path in &args.layouts {
+        let layout = parse_layout(&read_file(path), keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit());
+        // Layouts of several runs are all called best.txt
+        let unique = args
+            .layouts
+            .iter()
+            .filter(|p| stem(p) == stem(path))
+            .count()
+            == 1;
+        let name = if unique { stem(path) } else { path.clone() };
+        layouts.push((name, layout));
+    }
+    layouts
+}
+
+/// Reads the file at `path`, or exits with an error naming it.
+fn read_file(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|error| Error::from(error).in_file(path).exit())
+}
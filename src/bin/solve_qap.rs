@@ -0,0 +1,190 @@
+use std::{fs::File, io::BufWriter, path::PathBuf, process::Command};
+
+use clap::Parser;
+use keyboard_layout_generator::{
+    config::Config,
+    cost_model::CostModel,
+    format::{layout_to_str, parse_keymap_config, parse_layout},
+    layout::Layout,
+    qap::Qap,
+    report::ignored_keys_summary,
+    scoring::Weights,
+    stats::{prepare, read_merged_stats, PrepareOptions},
+};
+
+/// Place keys optimally, or bound how good any placement can be, by
+/// solving the quadratic assignment problem of the cost model.
+///
+/// The keys given with `--free` are placed among the physical keys they
+/// are on in the layout, with the rest staying put.  Up to 20 of them are
+/// placed by an exact branch and bound; more need an external solver given
+/// with `--solver`.  Either way the lower bound shows how far the layout,
+/// for example the `best.txt` of `explore_layouts`, is from the best
+/// possible placement of those keys.
+#[derive(Parser)]
+struct Args {
+    /// Path to the log file created by the keylogger, or to stats saved
+    /// with `collect_stats --save`.
+    log_file: String,
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// The layout to start from, in the format of `best.txt`.  Defaults
+    /// to the keycaps.
+    #[arg(long)]
+    layout: Option<String>,
+    /// The characters of the keys to place, like `etaoinshr`.  Defaults to
+    /// every key that isn't pinned.
+    #[arg(long)]
+    free: Option<String>,
+    /// Settings such as the scoring bonuses and penalties.
+    #[arg(long, default_value = "config.json")]
+    config: String,
+    /// The constants of the effort model, like `weights.toml`.  Defaults
+    /// to the values written there.
+    #[arg(long)]
+    weights: Option<String>,
+    /// Stop the branch and bound after searching this many partial
+    /// placements, with the best placement and lower bound so far.
+    #[arg(long, default_value_t = 10_000_000)]
+    max_nodes: u64,
+    /// Write the instance in the format of QAPLIB to this file.
+    #[arg(long)]
+    emit: Option<PathBuf>,
+    /// A command that solves the instance written by `--emit`, given its
+    /// path as the last argument, and prints a solution in the format of
+    /// QAPLIB.
+    #[arg(long, requires = "emit")]
+    solver: Option<String>,
+    /// Where to write the layout with the keys placed.
+    #[arg(long, default_value = "qap.txt")]
+    output: String,
+}
+
+/// More keys than this take too long to place by branch and bound.
+const MAX_EXACT_KEYS: usize = 20;
+
+fn main() {
+    let args = Args::parse();
+    let mut config = Config::load_or_default(&args.config).unwrap();
+    if let Some(path) = &args.weights {
+        config.scoring.weights =
+            Weights::load(path).unwrap_or_else(|error| error.in_file(path).exit());
+    }
+    let fmt = &config.number_format;
+    let log_options = config
+        .log_options(&[])
+        .unwrap_or_else(|error| error.in_file(&args.config).exit());
+    let mut stats =
+        read_merged_stats(&[&args.log_file], &log_options).unwrap_or_else(|error| error.exit());
+    if let Some(summary) = ignored_keys_summary(&stats, fmt) {
+        println!("Warning: {}", summary);
+    }
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    prepare(
+        &mut stats,
+        &mut config.scoring,
+        &keymap_config,
+        &PrepareOptions::default(),
+    );
+    let layout = match &args.layout {
+        Some(path) => parse_layout(&std::fs::read_to_string(path).unwrap(), &keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Layout::from_keymap(&keymap_config),
+    };
+
+    let free = match &args.free {
+        Some(chars) => chars
+            .chars()
+            .map(|c| {
+                layout.position_of_char(c).unwrap_or_else(|| {
+                    eprintln!("The layout has no key for {:?}", c);
+                    std::process::exit(1);
+                })
+            })
+            .collect(),
+        None => keymap_config.unpinned(),
+    };
+    let model = CostModel::new(&keymap_config, &config.scoring);
+    let left_out = CostModel::left_out(&config.scoring, Some(&stats));
+    if !left_out.is_empty() {
+        println!(
+            "Warning: the cost model leaves out the {} of the score",
+            left_out.join(", ")
+        );
+    }
+    let qap = Qap::new(&layout, &free, &stats, &model);
+    let start_cost = qap.cost(&qap.identity());
+    println!("Placing {} keys", fmt.count(qap.len() as u64));
+    println!("Cost of the layout: {}", fmt.decimal(start_cost));
+    println!(
+        "Lower bound before searching: {}",
+        fmt.decimal(qap.lower_bound())
+    );
+
+    if let Some(path) = &args.emit {
+        qap.write_qaplib(&mut BufWriter::new(File::create(path).unwrap()))
+            .unwrap();
+        println!("Wrote the instance to {}", path.display());
+    }
+    let (assignment, lower_bound) = if let Some(solver) = &args.solver {
+        let mut words = solver.split_whitespace();
+        let program = words.next().unwrap_or_else(|| {
+            eprintln!("The solver command is empty");
+            std::process::exit(1);
+        });
+        let output = Command::new(program)
+            .args(words)
+            .arg(args.emit.as_ref().unwrap())
+            .output()
+            .unwrap_or_else(|error| {
+                eprintln!("Failed to run {}: {}", program, error);
+                std::process::exit(1);
+            });
+        if !output.status.success() {
+            eprintln!("{} failed: {}", program, output.status);
+            std::process::exit(1);
+        }
+        let assignment = qap
+            .parse_solution(&String::from_utf8_lossy(&output.stdout))
+            .unwrap_or_else(|error| {
+                eprintln!("Failed to read the solution of {}: {}", program, error);
+                std::process::exit(1);
+            });
+        (assignment, None)
+    } else if qap.len() <= MAX_EXACT_KEYS {
+        let solution = qap.branch_and_bound(qap.identity(), args.max_nodes);
+        println!("Searched {} partial placements", fmt.count(solution.nodes));
+        if solution.optimal() {
+            println!("The placement is optimal");
+        } else {
+            println!("Stopped after --max-nodes, the placement may not be optimal");
+        }
+        (solution.assignment, Some(solution.lower_bound))
+    } else {
+        println!(
+            "Placing more than {} keys exactly takes too long, pass fewer keys with --free or a solver with --solver",
+            MAX_EXACT_KEYS
+        );
+        return;
+    };
+
+    let cost = qap.cost(&assignment);
+    println!("Cost of the placement: {}", fmt.decimal(cost));
+    if let Some(lower_bound) = lower_bound {
+        println!("Lower bound: {}", fmt.decimal(lower_bound));
+        println!(
+            "The layout costs {} more than the bound",
+            fmt.decimal(start_cost - lower_bound)
+        );
+    }
+    let placed = qap.apply(&layout, &assignment);
+    std::fs::write(
+        &args.output,
+        layout_to_str(&placed, &keymap_config).unwrap(),
+    )
+    .unwrap();
+    println!("Saved to {}", args.output);
+}
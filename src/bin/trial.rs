@@ -0,0 +1,177 @@
+/// Try a generated layout for a timed session by remapping the keyboard
+/// live, then report error rate and speed.
+///
+/// Run without `--layout` first to record a baseline session on your
+/// current layout, saving it with `--report`.  Later sessions can then be
+/// compared against it with `--baseline`.
+///
+/// Remapping uses evdev and uinput, so this is only supported on Linux and
+/// usually needs root or membership in the `input` group.  The remap ends
+/// as soon as the program exits.
+#[derive(clap::Parser)]
+struct Args {
+    /// Path to a keymap configuration file describing the
+    /// layout of the physical keyboard.
+    keymap_config: String,
+    /// Layout to try, in the format of `best.txt`.  Without it the
+    /// keyboard is left as is to record a baseline.
+    #[arg(long)]
+    layout: Option<String>,
+    /// Length of the session in minutes.  The session ends with the
+    /// first key event after this.
+    #[arg(long, default_value_t = 10.0)]
+    minutes: f64,
+    /// Append the remapped key events to this key log.
+    #[arg(long)]
+    log_file: Option<String>,
+    /// Save the session report to this file.
+    #[arg(long)]
+    report: Option<String>,
+    /// A report from a previous session to compare against.
+    #[arg(long)]
+    baseline: Option<String>,
+    /// Prompt text to type, drawn from the words in this key log,
+    /// instead of typing freely.
+    #[arg(long)]
+    typing_test: Option<String>,
+    /// Accumulate the key and bigram latencies measured during the
+    /// typing test in this file.  Pass it to `explore_layouts
+    /// --latencies` to weight slow transitions more heavily.
+    #[arg(long, requires = "typing_test")]
+    latencies: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use clap::Parser;
+    use keyboard_layout_generator::{
+        capture::write_event,
+        format::{parse_keymap_config, parse_layout},
+        layout::Layout,
+        remap::{uinput, RemapTable},
+        stats::{process_log, translate_key_to_char},
+        trial::{run_session, typing_test_prompt, LatencyRecorder, LatencyStats, TrialReport},
+    };
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    let args = Args::parse();
+    let keymap_str = std::fs::read_to_string(&args.keymap_config).unwrap();
+    let keymap_config = parse_keymap_config(&keymap_str)
+        .unwrap_or_else(|error| error.in_file(&args.keymap_config).exit());
+    let layout = match &args.layout {
+        Some(path) => parse_layout(&std::fs::read_to_string(path).unwrap(), &keymap_config)
+            .unwrap_or_else(|error| error.in_file(path).exit()),
+        None => Layout::from_keymap(&keymap_config),
+    };
+    let baseline = args
+        .baseline
+        .as_ref()
+        .map(|path| TrialReport::load(path).unwrap());
+    let mut log_file = args.log_file.as_ref().map(|path| {
+        BufWriter::new(
+            File::options()
+                .append(true)
+                .create(true)
+                .open(path)
+                .unwrap(),
+        )
+    });
+
+    let word_stats = args
+        .typing_test
+        .as_ref()
+        .map(|path| process_log(path).unwrap_or_else(|error| error.exit()));
+    let mut recorder = match &args.latencies {
+        Some(path) if Path::new(path).exists() => {
+            LatencyRecorder::new(LatencyStats::load(path).unwrap())
+        }
+        _ => LatencyRecorder::default(),
+    };
+    let mut rng = rand::thread_rng();
+    let mut next_prompt = || {
+        let stats = word_stats.as_ref()?;
+        let prompt = typing_test_prompt(stats, 20, 200, &mut rng)
+            .expect("The typing test log contains no words");
+        println!("\n{prompt}\n");
+        Some(prompt.len())
+    };
+
+    let device = uinput::find_keyboard().expect("No keyboard found, are you in the input group?");
+    let table = RemapTable::new(&layout, &keymap_config);
+    let mut remapper = uinput::UinputRemapper::new(device, table).unwrap();
+
+    println!("Trial started, type away for {} minutes", args.minutes);
+    let mut prompt_len = next_prompt();
+    let mut typed: usize = 0;
+    let duration = Duration::from_secs_f64(args.minutes * 60.0);
+    let report = run_session(&mut remapper, duration, |code, press| {
+        if let Some(log_file) = &mut log_file {
+            write_event(log_file, code, press).unwrap();
+        }
+        let Some(len) = prompt_len else {
+            return;
+        };
+        recorder.record(code, press, Instant::now());
+        if !press {
+            return;
+        }
+        if code == device_query::Keycode::Backspace {
+            typed = typed.saturating_sub(1);
+        } else if translate_key_to_char(&code, false).is_some() {
+            typed += 1;
+        }
+        if typed >= len {
+            typed = 0;
+            recorder.pause();
+            prompt_len = next_prompt();
+        }
+    })
+    .unwrap();
+    drop(remapper);
+    if let Some(log_file) = &mut log_file {
+        log_file.flush().unwrap();
+    }
+
+    println!("Chars per minute: {:.1}", report.chars_per_minute());
+    println!("Error rate: {:.2}%", report.error_rate() * 100.0);
+    let mut mistyped_keys: Vec<_> = report.mistyped_keys.iter().collect();
+    mistyped_keys.sort_by_key(|x| std::cmp::Reverse(x.1));
+    if !mistyped_keys.is_empty() {
+        println!("Most mistyped keys:");
+        for (key, count) in mistyped_keys.iter().take(5) {
+            println!("  {:?}: {}", key, count);
+        }
+    }
+    if let Some(baseline) = baseline {
+        println!(
+            "Baseline chars per minute: {:.1} ({:+.1})",
+            baseline.chars_per_minute(),
+            report.chars_per_minute() - baseline.chars_per_minute()
+        );
+        println!(
+            "Baseline error rate: {:.2}% ({:+.2})",
+            baseline.error_rate() * 100.0,
+            (report.error_rate() - baseline.error_rate()) * 100.0
+        );
+    }
+    if let Some(path) = &args.report {
+        report.save(path).unwrap();
+    }
+    if let Some(path) = &args.latencies {
+        recorder.stats.save(path).unwrap();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    use clap::Parser;
+
+    let _ = Args::parse();
+    eprintln!("Trial mode is only supported on Linux");
+    std::process::exit(1);
+}
@@ -0,0 +1,448 @@
+//! A compact binary encoding of the key event log, for logs that have
+//! grown too big to read quickly as text.
+//!
+//! A binary log starts with [`MAGIC`] followed by one record per line of
+//! the text log.  Every record starts with a varint holding what it is
+//! shifted left by two, then whether it has a timestamp, then whether it's
+//! a press:
+//!
+//! - `0` to `4` are a tag, an untag, a header, a key [`Keycode`] has no
+//!   name for, and a device, followed by a varint length and the tag, the
+//!   header as JSON, the name of the key, or the name of the device in
+//!   UTF-8.  `5` to `7` are reserved for more of them.
+//! - From `8` on, a key of [`KEYCODES`] by its index.
+//!
+//! Events with a timestamp follow with the difference to the timestamp of
+//! the last event as a zigzag varint.  Headers start every run of the
+//! keylogger, so they reset the last timestamp to 0, and runs can be
+//! appended to a log without knowing what came before.
+//!
+//! A typical event takes 2 to 3 bytes instead of around 20 as text, and
+//! reading it doesn't parse any names.
+
+use std::io::{self, BufRead, Read, Write};
+
+use device_query::Keycode;
+
+use crate::{
+    capture::{LogHeader, LogLine},
+    error::{Error, Result},
+};
+
+/// The bytes every binary log starts with.
+pub const MAGIC: &[u8] = b"KLG\x01";
+
+/// The keys binary logs know by index.  Only ever append to this, indices
+/// are stored in logs.
+pub const KEYCODES: [Keycode; 111] = [
+    Keycode::Key0,
+    Keycode::Key1,
+    Keycode::Key2,
+    Keycode::Key3,
+    Keycode::Key4,
+    Keycode::Key5,
+    Keycode::Key6,
+    Keycode::Key7,
+    Keycode::Key8,
+    Keycode::Key9,
+    Keycode::A,
+    Keycode::B,
+    Keycode::C,
+    Keycode::D,
+    Keycode::E,
+    Keycode::F,
+    Keycode::G,
+    Keycode::H,
+    Keycode::I,
+    Keycode::J,
+    Keycode::K,
+    Keycode::L,
+    Keycode::M,
+    Keycode::N,
+    Keycode::O,
+    Keycode::P,
+    Keycode::Q,
+    Keycode::R,
+    Keycode::S,
+    Keycode::T,
+    Keycode::U,
+    Keycode::V,
+    Keycode::W,
+    Keycode::X,
+    Keycode::Y,
+    Keycode::Z,
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+    Keycode::F9,
+    Keycode::F10,
+    Keycode::F11,
+    Keycode::F12,
+    Keycode::F13,
+    Keycode::F14,
+    Keycode::F15,
+    Keycode::F16,
+    Keycode::F17,
+    Keycode::F18,
+    Keycode::F19,
+    Keycode::F20,
+    Keycode::Escape,
+    Keycode::Space,
+    Keycode::LControl,
+    Keycode::RControl,
+    Keycode::LShift,
+    Keycode::RShift,
+    Keycode::LAlt,
+    Keycode::RAlt,
+    Keycode::Command,
+    Keycode::LOption,
+    Keycode::ROption,
+    Keycode::LMeta,
+    Keycode::RMeta,
+    Keycode::Enter,
+    Keycode::Up,
+    Keycode::Down,
+    Keycode::Left,
+    Keycode::Right,
+    Keycode::Backspace,
+    Keycode::CapsLock,
+    Keycode::Tab,
+    Keycode::Home,
+    Keycode::End,
+    Keycode::PageUp,
+    Keycode::PageDown,
+    Keycode::Insert,
+    Keycode::Delete,
+    Keycode::Numpad0,
+    Keycode::Numpad1,
+    Keycode::Numpad2,
+    Keycode::Numpad3,
+    Keycode::Numpad4,
+    Keycode::Numpad5,
+    Keycode::Numpad6,
+    Keycode::Numpad7,
+    Keycode::Numpad8,
+    Keycode::Numpad9,
+    Keycode::NumpadSubtract,
+    Keycode::NumpadAdd,
+    Keycode::NumpadDivide,
+    Keycode::NumpadMultiply,
+    Keycode::NumpadEquals,
+    Keycode::NumpadEnter,
+    Keycode::NumpadDecimal,
+    Keycode::Grave,
+    Keycode::Minus,
+    Keycode::Equal,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::BackSlash,
+    Keycode::Semicolon,
+    Keycode::Apostrophe,
+    Keycode::Comma,
+    Keycode::Dot,
+    Keycode::Slash,
+];
+
+const TAG: u64 = 0;
+const UNTAG: u64 = 1;
+const HEADER: u64 = 2;
+const UNKNOWN_KEY: u64 = 3;
+const DEVICE: u64 = 4;
+const FIRST_KEYCODE: u64 = 8;
+
+/// Whether `start`, the first bytes of a log, start a binary log.
+pub fn is_binary(start: &[u8]) -> bool {
+    start.starts_with(MAGIC)
+}
+
+/// Writes [`LogLine`]s as a binary log.
+pub struct BinaryLogWriter<W> {
+    writer: W,
+    last_timestamp: u64,
+}
+
+impl<W: Write> BinaryLogWriter<W> {
+    /// Starts a new binary log, writing [`MAGIC`] to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(Self::append(writer))
+    }
+
+    /// Continues a binary log that `writer` is at the end of.
+    pub fn append(writer: W) -> Self {
+        Self {
+            writer,
+            last_timestamp: 0,
+        }
+    }
+
+    pub fn write_line(&mut self, line: &LogLine) -> io::Result<()> {
+        match line {
+            LogLine::Event(key_code, press, timestamp) => {
+                match KEYCODES.iter().position(|code| code == key_code) {
+                    Some(index) => {
+                        self.write_event(FIRST_KEYCODE + index as u64, *press, *timestamp)
+                    }
+                    None => self.write_unknown_key(&key_code.to_string(), *press, *timestamp),
+                }
+            }
+            LogLine::UnknownKey(name, press, timestamp) => {
+                self.write_unknown_key(name, *press, *timestamp)
+            }
+            LogLine::Tag(tag) => self.write_marker(TAG, tag),
+            LogLine::Untag(tag) => self.write_marker(UNTAG, tag),
+            LogLine::Device(device) => self.write_marker(DEVICE, device),
+            LogLine::Header(header) => {
+                self.last_timestamp = 0;
+                self.write_marker(HEADER, &serde_json::to_string(header)?)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_event(&mut self, kind: u64, press: bool, timestamp: Option<u64>) -> io::Result<()> {
+        write_varint(
+            &mut self.writer,
+            kind << 2 | (timestamp.is_some() as u64) << 1 | press as u64,
+        )?;
+        if let Some(timestamp) = timestamp {
+            let delta = timestamp.wrapping_sub(self.last_timestamp) as i64;
+            write_varint(&mut self.writer, zigzag(delta))?;
+            self.last_timestamp = timestamp;
+        }
+        Ok(())
+    }
+
+    fn write_unknown_key(
+        &mut self,
+        name: &str,
+        press: bool,
+        timestamp: Option<u64>,
+    ) -> io::Result<()> {
+        self.write_event(UNKNOWN_KEY, press, timestamp)?;
+        write_bytes(&mut self.writer, name.as_bytes())
+    }
+
+    fn write_marker(&mut self, kind: u64, payload: &str) -> io::Result<()> {
+        write_varint(&mut self.writer, kind << 2)?;
+        write_bytes(&mut self.writer, payload.as_bytes())
+    }
+}
+
+/// Reads the records of a binary log as [`LogLine`]s.
+pub struct BinaryLogReader<R> {
+    reader: R,
+    last_timestamp: u64,
+    records: usize,
+}
+
+impl<R: BufRead> BinaryLogReader<R> {
+    /// Reads the binary log of `reader`, checking that it starts with
+    /// [`MAGIC`].
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if !is_binary(&magic) {
+            return Err(Error::Invalid("not a binary key log".to_string()));
+        }
+        Ok(Self {
+            reader,
+            last_timestamp: 0,
+            records: 0,
+        })
+    }
+
+    fn read_line(&mut self) -> Result<Option<LogLine>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let record = read_varint(&mut self.reader)?;
+        let (kind, has_timestamp, press) = (record >> 2, record & 2 != 0, record & 1 != 0);
+        let mut timestamp = None;
+        if (kind == UNKNOWN_KEY || kind >= FIRST_KEYCODE) && has_timestamp {
+            let delta = unzigzag(read_varint(&mut self.reader)?);
+            self.last_timestamp = self.last_timestamp.wrapping_add(delta as u64);
+            timestamp = Some(self.last_timestamp);
+        }
+        Ok(Some(match kind {
+            TAG => LogLine::Tag(self.read_string()?),
+            UNTAG => LogLine::Untag(self.read_string()?),
+            DEVICE => LogLine::Device(self.read_string()?),
+            HEADER => {
+                self.last_timestamp = 0;
+                let header = self.read_string()?;
+                let header: LogHeader = serde_json::from_str(&header).map_err(|error| {
+                    Error::parse(&header, format!("invalid log header: {error}"))
+                })?;
+                LogLine::Header(header)
+            }
+            UNKNOWN_KEY => {
+                let name = self.read_string()?;
+                match name.parse() {
+                    Ok(key_code) => LogLine::Event(key_code, press, timestamp),
+                    Err(_) => LogLine::UnknownKey(name, press, timestamp),
+                }
+            }
+            kind if kind >= FIRST_KEYCODE => match KEYCODES.get((kind - FIRST_KEYCODE) as usize) {
+                Some(&key_code) => LogLine::Event(key_code, press, timestamp),
+                None => return Err(Error::Invalid(format!("unknown record kind {kind}"))),
+            },
+            kind => return Err(Error::Invalid(format!("unknown record kind {kind}"))),
+        }))
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let len = read_varint(&mut self.reader)? as usize;
+        let mut bytes = vec![0; len];
+        self.reader.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| Error::Invalid("invalid UTF-8".to_string()))
+    }
+}
+
+/// Reads the complete records at the start of `bytes`, records of a binary
+/// log after its [`MAGIC`] that may still be written to, and returns them
+/// with the number of bytes they took.  A record cut off by the end of
+/// `bytes` is left for when it's complete.  `last_timestamp` is that of
+/// the last event before `bytes`, and is updated to that of the last one
+/// read.
+pub fn read_complete_records(
+    bytes: &[u8],
+    last_timestamp: &mut u64,
+) -> Result<(Vec<LogLine>, usize)> {
+    let mut reader = BinaryLogReader {
+        reader: bytes,
+        last_timestamp: *last_timestamp,
+        records: 0,
+    };
+    let mut lines = vec![];
+    let mut read = 0;
+    loop {
+        let before = reader.last_timestamp;
+        match reader.read_line() {
+            Ok(Some(line)) => {
+                lines.push(line);
+                read = bytes.len() - reader.reader.len();
+            }
+            Ok(None) => break,
+            // Reading past the end of a slice empties it
+            Err(_) if reader.reader.is_empty() => {
+                reader.last_timestamp = before;
+                break;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    *last_timestamp = reader.last_timestamp;
+    Ok((lines, read))
+}
+
+impl<R: BufRead> Iterator for BinaryLogReader<R> {
+    type Item = Result<LogLine>;
+
+    /// The next record, with errors saying which record they're in,
+    /// counting from 1.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records += 1;
+        let records = self.records;
+        self.read_line()
+            .map_err(|error| Error::Invalid(format!("record {records}: {error}")))
+            .transpose()
+    }
+}
+
+fn write_varint(writer: &mut impl Write, mut n: u64) -> io::Result<()> {
+    while n >= 0x80 {
+        writer.write_all(&[n as u8 | 0x80])?;
+        n >>= 7;
+    }
+    writer.write_all(&[n as u8])
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        n |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(Error::Invalid("varint too long".to_string()))
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_varint(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+/// Maps signed numbers to unsigned ones so small negative numbers stay
+/// small: 0, -1, 1, -2 to 0, 1, 2, 3.
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_log_round_trip() {
+        let lines = vec![
+            LogLine::Header(LogHeader {
+                start_time: 1_700_000_000_000,
+                ..LogHeader::default()
+            }),
+            LogLine::Event(Keycode::A, true, Some(1_700_000_000_010)),
+            LogLine::Event(Keycode::A, false, Some(1_700_000_000_005)),
+            LogLine::Tag("gaming".to_string()),
+            LogLine::UnknownKey("VolumeUp".to_string(), true, Some(1_700_000_000_400)),
+            LogLine::Untag("gaming".to_string()),
+            LogLine::Device("laptop".to_string()),
+            LogLine::Event(Keycode::Slash, true, None),
+            LogLine::Header(LogHeader::default()),
+            LogLine::Event(Keycode::Space, false, Some(3)),
+        ];
+        let mut bytes = vec![];
+        let mut writer = BinaryLogWriter::new(&mut bytes).unwrap();
+        for line in &lines {
+            writer.write_line(line).unwrap();
+        }
+
+        let read: Vec<LogLine> = BinaryLogReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, lines);
+
+        let truncated = BinaryLogReader::new(&bytes[..bytes.len() - 1])
+            .unwrap()
+            .collect::<Result<Vec<_>>>();
+        assert!(truncated.is_err());
+        assert!(BinaryLogReader::new(&b"A 1\n"[..]).is_err());
+
+        // A key and a small delta take a byte each
+        let mut bytes = vec![];
+        BinaryLogWriter::append(&mut bytes)
+            .write_line(&LogLine::Event(Keycode::A, true, Some(5)))
+            .unwrap();
+        assert_eq!(bytes.len(), 2);
+
+        // Every key has an index of its own
+        for (i, code) in KEYCODES.iter().enumerate() {
+            assert_eq!(KEYCODES.iter().position(|other| other == code), Some(i));
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! An on-disk cache of layout scores, so comparing the same layouts against
+//! the same stats again doesn't recompute anything.
+//!
+//! Scores are keyed by fingerprints of the layout, the stats, and the
+//! scoring settings together with the physical keyboard.  Changing any of
+//! them changes its fingerprint, so stale scores are never looked up rather
+//! than having to be invalidated.
+//!
+//! [`CachedScorer`] scores layouts through a cache, so everything that
+//! takes a [`LayoutScorer`] can use it.
+//!
+//! [`ScoreMemo`] is the in-memory counterpart for a single run, where the
+//! stats and the scoring don't change, shared by the threads scoring the
+//! population.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    layout::{Key, Layout},
+    scoring::{LayoutScorer, ScoringConfig},
+    serde_util::entries,
+    stats::Stats,
+    KeymapConfig,
+};
+
+/// A stable hash of anything that can be serialized.  Maps that are
+/// written as lists of entries are sorted first, so the fingerprint doesn't
+/// depend on the iteration order of a `HashMap`.
+pub fn fingerprint<T: Serialize + ?Sized>(value: &T) -> u64 {
+    let mut value = serde_json::to_value(value).unwrap_or(Value::Null);
+    canonicalize(&mut value);
+    fnv1a(value.to_string().as_bytes())
+}
+
+/// The fingerprint of the keys of `layout`.
+pub fn layout_fingerprint(layout: &Layout) -> u64 {
+    fnv1a(format!("{:?}", layout.keys()).as_bytes())
+}
+
+/// The fingerprint of everything but the layout and the stats that scores
+/// depend on: the scoring settings with their weights and the intuitions
+/// read from a file, which `config.json` doesn't hold, and the fingers,
+/// keys and geometry of the physical keyboard.
+pub fn scoring_fingerprint(scoring: &ScoringConfig, keymap_config: &KeymapConfig) -> u64 {
+    let intuitions: Vec<String> = scoring
+        .custom_intuitions
+        .iter()
+        .map(|intuition| intuition.to_string())
+        .collect();
+    // The home keys are a map, so they're sorted to not depend on its order
+    let mut home: Vec<String> = keymap_config
+        .home
+        .iter()
+        .map(|(finger, i)| format!("{finger:?}={i}"))
+        .collect();
+    home.sort();
+    let keyboard = format!(
+        "{:?} {:?} {:?} {:?}",
+        keymap_config.fingers, keymap_config.keys, keymap_config.columns, home
+    );
+    fingerprint(&(scoring, &scoring.weights, intuitions, keyboard))
+}
+
+/// Sorts lists whose items are all lists, which is how maps with keys that
+/// can't be JSON object keys are written.
+fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Array(items) => {
+            items.iter_mut().for_each(canonicalize);
+            if !items.is_empty() && items.iter().all(Value::is_array) {
+                items.sort_by_cached_key(|item| item.to_string());
+            }
+        }
+        Value::Object(map) => map.values_mut().for_each(canonicalize),
+        _ => {}
+    }
+}
+
+/// 64 bit FNV-1a, which unlike the hashers of the standard library is the
+/// same in every build.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Scores by the fingerprints of `(layout, stats, scoring)`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScoreCache {
+    #[serde(with = "entries")]
+    scores: HashMap<(u64, u64, u64), f64>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl ScoreCache {
+    /// Reads the cache at `path`, or starts an empty one if there's none.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => Ok(serde_json::from_str(&s)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the cache to `path` if any scores were added.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if self.changed {
+            std::fs::write(path, serde_json::to_string(self)?)?;
+        }
+        Ok(())
+    }
+
+    /// The cached score of the layout, or the one computed by `score`.
+    pub fn get_or_insert_with(
+        &mut self,
+        layout: u64,
+        stats: u64,
+        scoring: u64,
+        score: impl FnOnce() -> f64,
+    ) -> f64 {
+        *self
+            .scores
+            .entry((layout, stats, scoring))
+            .or_insert_with(|| {
+                self.changed = true;
+                score()
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+/// Scores layouts with a scoring config, looking them up in a
+/// [`ScoreCache`] first.  It's made for one set of stats, whose fingerprint
+/// it takes once, and must only score layouts against those.
+pub struct CachedScorer<'a> {
+    scoring: &'a ScoringConfig,
+    cache: &'a RefCell<ScoreCache>,
+    stats: u64,
+    scoring_fingerprint: u64,
+    computed: Cell<usize>,
+}
+
+impl<'a> CachedScorer<'a> {
+    pub fn new(
+        scoring: &'a ScoringConfig,
+        keymap_config: &KeymapConfig,
+        stats: &Stats,
+        cache: &'a RefCell<ScoreCache>,
+    ) -> Self {
+        Self {
+            scoring,
+            cache,
+            stats: fingerprint(stats),
+            scoring_fingerprint: scoring_fingerprint(scoring, keymap_config),
+            computed: Cell::new(0),
+        }
+    }
+
+    /// How many scores weren't in the cache.
+    pub fn computed(&self) -> usize {
+        self.computed.get()
+    }
+}
+
+impl LayoutScorer for CachedScorer<'_> {
+    fn score(&self, layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
+        self.cache.borrow_mut().get_or_insert_with(
+            layout_fingerprint(layout),
+            self.stats,
+            self.scoring_fingerprint,
+            || {
+                self.computed.set(self.computed.get() + 1);
+                self.scoring.score(layout, stats, keymap_config)
+            },
+        )
+    }
+
+    fn max_possible_score(&self, stats: &Stats) -> f64 {
+        self.scoring.max_possible_score(stats)
+    }
+}
+
+/// The scores remembered by a [`ScoreMemo`], by the fingerprint of the
+/// layout, with its keys to tell layouts whose fingerprints collide apart.
+type Scores = HashMap<u64, (Vec<Key>, f64)>;
+
+/// Scores of the layouts seen in a run, so layouts that recur between
+/// generations aren't rescored.
+///
+/// Once it has remembered `capacity` scores, it starts over but keeps the
+/// ones before as the previous scores, which are still looked up and kept
+/// when they are.  So the layouts that recur from one generation to the
+/// next stay, and it never holds more than twice its capacity.
+#[derive(Debug)]
+pub struct ScoreMemo {
+    /// The current and the previous scores.
+    scores: Mutex<(Scores, Scores)>,
+    capacity: usize,
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+impl ScoreMemo {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            scores: Mutex::new((HashMap::new(), HashMap::new())),
+            capacity,
+            hits: AtomicUsize::new(0),
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    /// The remembered score of `layout`, or the one computed by `score`.
+    /// Other threads can look up scores while it's computed.
+    pub fn get_or_insert_with(&self, layout: &Layout, score: impl FnOnce() -> f64) -> f64 {
+        let key = layout_fingerprint(layout);
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let found = |scores: &Scores| {
+            scores
+                .get(&key)
+                .filter(|(keys, _)| keys == layout.keys())
+                .map(|&(_, score)| score)
+        };
+        {
+            let mut scores = self.lock();
+            let (current, previous) = &mut *scores;
+            if let Some(score) = found(current) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return score;
+            }
+            if let Some(score) = found(previous) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                let entry = previous.remove(&key).unwrap();
+                self.insert(&mut scores, key, entry);
+                return score;
+            }
+        }
+        let score = score();
+        self.insert(&mut self.lock(), key, (layout.keys().to_vec(), score));
+        score
+    }
+
+    /// Remembers `entry`, starting over with the current scores as the
+    /// previous ones if there are `capacity` of them.
+    fn insert(&self, scores: &mut (Scores, Scores), key: u64, entry: (Vec<Key>, f64)) {
+        let (current, previous) = scores;
+        if current.len() >= self.capacity {
+            *previous = std::mem::take(current);
+        }
+        current.insert(key, entry);
+    }
+
+    /// How many lookups found a score, and how many there were.
+    pub fn hits(&self) -> (usize, usize) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.lookups.load(Ordering::Relaxed),
+        )
+    }
+
+    /// The share of lookups that found a score, or 0 before any.
+    pub fn hit_rate(&self) -> f64 {
+        let (hits, lookups) = self.hits();
+        hits as f64 / lookups.max(1) as f64
+    }
+
+    fn lock(&self) -> MutexGuard<'_, (Scores, Scores)> {
+        self.scores
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device_query::Keycode;
+
+    #[test]
+    fn test_fingerprint() {
+        let mut a = Stats::new();
+        let mut b = Stats::new();
+        for (i, code) in [Keycode::A, Keycode::B, Keycode::C, Keycode::D]
+            .into_iter()
+            .enumerate()
+        {
+            a.consecutive_key_counts
+                .insert((code, Keycode::E), i as u64);
+            a.consecutive_key_counts.insert((Keycode::E, code), 10);
+        }
+        for (i, code) in [Keycode::D, Keycode::C, Keycode::B, Keycode::A]
+            .into_iter()
+            .enumerate()
+        {
+            b.consecutive_key_counts.insert((Keycode::E, code), 10);
+            b.consecutive_key_counts
+                .insert((code, Keycode::E), 3 - i as u64);
+        }
+        assert_eq!(fingerprint(&a), fingerprint(&b));
+
+        b.consecutive_key_counts
+            .insert((Keycode::E, Keycode::A), 11);
+        assert_ne!(fingerprint(&a), fingerprint(&b));
+
+        let mut cache = ScoreCache::default();
+        assert_eq!(cache.get_or_insert_with(1, 2, 3, || 0.5), 0.5);
+        assert_eq!(cache.get_or_insert_with(1, 2, 3, || unreachable!()), 0.5);
+        assert_eq!(cache.get_or_insert_with(1, 2, 4, || 0.7), 0.7);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_scoring_fingerprint() {
+        let parse =
+            || crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut scoring = ScoringConfig::default();
+        let keymap_config = parse();
+        let before = scoring_fingerprint(&scoring, &keymap_config);
+        assert_eq!(scoring_fingerprint(&scoring, &parse()), before);
+
+        let mut weaker = parse();
+        weaker.fingers[0].score += 0.1;
+        assert_ne!(scoring_fingerprint(&scoring, &weaker), before);
+        let mut staggered = parse();
+        staggered.columns.stagger = vec![0.5];
+        assert_ne!(scoring_fingerprint(&scoring, &staggered), before);
+
+        scoring.custom_intuitions = crate::scoring::parse_intuitions("close(. ,)").unwrap();
+        assert_ne!(scoring_fingerprint(&scoring, &keymap_config), before);
+    }
+
+    #[test]
+    fn test_cached_scorer() {
+        let config = crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let scoring = ScoringConfig::default();
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::A, 3);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::A, Keycode::B), 2);
+        let expected = scoring.score(&layout, &stats, &config);
+
+        let cache = RefCell::new(ScoreCache::default());
+        let scorer = CachedScorer::new(&scoring, &config, &stats, &cache);
+        assert_eq!(scorer.score(&layout, &stats, &config), expected);
+        assert_eq!(scorer.score(&layout, &stats, &config), expected);
+        assert_eq!(scorer.computed(), 1);
+        // Another scorer for the same stats finds the score too
+        let scorer = CachedScorer::new(&scoring, &config, &stats, &cache);
+        assert_eq!(scorer.score(&layout, &stats, &config), expected);
+        assert_eq!(scorer.computed(), 0);
+        assert_eq!(cache.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_score_memo() {
+        let config = crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let a = Layout::from_keymap(&config);
+        let mut b = a.clone();
+        b.swap(0, 1);
+
+        let memo = ScoreMemo::new(2);
+        assert_eq!(memo.hit_rate(), 0.0);
+        assert_eq!(memo.get_or_insert_with(&a, || 1.0), 1.0);
+        assert_eq!(memo.get_or_insert_with(&a, || unreachable!()), 1.0);
+        assert_eq!(memo.get_or_insert_with(&b, || 2.0), 2.0);
+        assert_eq!(memo.hits(), (1, 3));
+
+        // Full, so it starts over, but still finds the previous scores
+        let swapped = |i| {
+            let mut layout = a.clone();
+            layout.swap(0, i);
+            layout
+        };
+        assert_eq!(memo.get_or_insert_with(&swapped(2), || 3.0), 3.0);
+        assert_eq!(memo.get_or_insert_with(&a, || unreachable!()), 1.0);
+        assert_eq!(memo.hits(), (2, 5));
+        assert_eq!(memo.hit_rate(), 0.4);
+        // Only the scores found again are kept when it starts over again
+        assert_eq!(memo.get_or_insert_with(&swapped(3), || 4.0), 4.0);
+        assert_eq!(memo.get_or_insert_with(&b, || 5.0), 5.0);
+        assert_eq!(memo.get_or_insert_with(&a, || unreachable!()), 1.0);
+
+        // A layout whose fingerprint collides with another isn't mistaken
+        // for it
+        let c = swapped(4);
+        memo.lock()
+            .0
+            .insert(layout_fingerprint(&c), (a.keys().to_vec(), 1.0));
+        assert_eq!(memo.get_or_insert_with(&c, || 6.0), 6.0);
+    }
+}
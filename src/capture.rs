@@ -0,0 +1,781 @@
+//! The key event log written by the `keylogger` binary.
+//!
+//! Every line of the log is a single event: the [`Keycode`] name followed by
+//! `1` for a press or `0` for a release, e.g. `LShift 1`.  Newer logs add
+//! the time of the event in milliseconds since the Unix epoch, e.g.
+//! `LShift 1 1700000000000`.
+//!
+//! Keys that [`Keycode`] has no name for, like media keys logged on another
+//! machine, are read as [`LogLine::UnknownKey`] so they can be skipped.
+//!
+//! Lines starting with `#` mark sessions instead: `# tag gaming` starts a
+//! session tagged `gaming` and `# untag gaming` ends it.  Every run of the
+//! keylogger starts a new segment of the log with a `# header` line
+//! followed by a [`LogHeader`] as JSON, describing where it was recorded.
+//! `# device laptop` says that the events after it, up to the next header
+//! or device line, were typed on the keyboard `laptop` instead of the one
+//! of the header.
+//!
+//! Logs can also be written in the compact format of [`binlog`](crate::binlog),
+//! and [`read_log`] reads either.
+//!
+//! The keylogger reads key presses from a [`KeyEventSource`], picked with
+//! its [`Backend`]: by default [`device_query::DeviceQuerySource`], which
+//! polls the held keys on every platform, or on Linux
+//! [`evdev::EvdevSource`], which reads every event of the keyboards.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    process::Command,
+    str::FromStr,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ::device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    binlog::{self, is_binary, BinaryLogReader, BinaryLogWriter, MAGIC},
+    char_table::CharTable,
+    error::{Error, Result},
+};
+
+pub mod device_query;
+#[cfg(target_os = "linux")]
+pub mod evdev;
+
+/// A single line of the log.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogLine {
+    /// A key event and its timestamp, if the log has timestamps.
+    Event(Keycode, bool, Option<u64>),
+    /// An event of a key that [`Keycode`] can't parse, by its name.
+    UnknownKey(String, bool, Option<u64>),
+    Tag(String),
+    Untag(String),
+    Header(LogHeader),
+    /// The keyboard the following events were typed on.
+    Device(String),
+}
+
+/// Where and with what a segment of the log was recorded, so old logs can
+/// still be told apart.  Anything that couldn't be detected is left empty.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogHeader {
+    pub hostname: Option<String>,
+    /// The operating system and architecture, e.g. `linux x86_64`.
+    pub os: String,
+    /// The keyboard layout the OS was set to, e.g. `us` or `de`.
+    pub os_layout: Option<String>,
+    /// The names of the keyboards attached.
+    pub devices: Vec<String>,
+    /// The keyboard typed on, as named with `keylogger --device`, like
+    /// `laptop` or `split`.
+    pub device: Option<String>,
+    /// The version of the keylogger.
+    pub version: String,
+    /// Milliseconds since the Unix epoch.
+    pub start_time: u64,
+}
+
+impl LogHeader {
+    /// Describes the machine the keylogger runs on, starting at
+    /// `start_time` on `device`.
+    pub fn detect(device: Option<String>, start_time: u64) -> Self {
+        Self {
+            hostname: detect_hostname(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            os_layout: detect_os_layout(),
+            devices: detect_keyboards(),
+            device,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            start_time,
+        }
+    }
+}
+
+/// Writes the header that starts a segment of the log.
+pub fn write_header(writer: &mut impl Write, header: &LogHeader) -> std::io::Result<()> {
+    writeln!(writer, "# header {}", serde_json::to_string(header)?)
+}
+
+/// The window that has the keyboard focus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveWindow {
+    /// The application, like `firefox` or `KeePassXC`.
+    pub app: String,
+    pub title: String,
+}
+
+impl ActiveWindow {
+    /// Asks the OS which window is focused, through `xprop` on X11,
+    /// AppleScript on macOS, and PowerShell on Windows.  `None` if that
+    /// can't be told, like under Wayland.
+    pub fn detect() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            let root = command_output("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+            let id = root.rsplit(' ').next()?;
+            let window = command_output("xprop", &["-id", id, "WM_CLASS", "_NET_WM_NAME"])?;
+            parse_xprop_window(&window)
+        } else if cfg!(target_os = "macos") {
+            let script = [
+                "tell application \"System Events\"",
+                "set p to first application process whose frontmost is true",
+                "set t to \"\"",
+                "try",
+                "set t to name of front window of p",
+                "end try",
+                "return (name of p) & linefeed & t",
+                "end tell",
+            ];
+            let args: Vec<&str> = script.iter().flat_map(|line| ["-e", line]).collect();
+            parse_app_and_title(&command_output("osascript", &args)?)
+        } else if cfg!(target_os = "windows") {
+            let script = "Add-Type -Name W -Namespace U -MemberDefinition '\
+                [DllImport(\"user32.dll\")] public static extern IntPtr GetForegroundWindow();\
+                [DllImport(\"user32.dll\")] public static extern int GetWindowThreadProcessId(IntPtr h, out int p);';\
+                $p = 0; [void][U.W]::GetWindowThreadProcessId([U.W]::GetForegroundWindow(), [ref]$p);\
+                $x = Get-Process -Id $p; $x.ProcessName; $x.MainWindowTitle";
+            parse_app_and_title(&command_output(
+                "powershell",
+                &["-NoProfile", "-Command", script],
+            )?)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses the output of `xprop -id <window> WM_CLASS _NET_WM_NAME`, taking
+/// the class of the window as its application.
+fn parse_xprop_window(output: &str) -> Option<ActiveWindow> {
+    let value = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|line| line.split_once(" = "))
+            .map(|(_, value)| value)
+    };
+    // WM_CLASS(STRING) = "instance", "Class"
+    let app = value("WM_CLASS")?.rsplit(", ").next()?.trim_matches('"');
+    let title = value("_NET_WM_NAME").unwrap_or_default().trim_matches('"');
+    Some(ActiveWindow {
+        app: app.to_string(),
+        title: title.to_string(),
+    })
+}
+
+/// Parses the application and the title on the first two lines of
+/// `output`.
+fn parse_app_and_title(output: &str) -> Option<ActiveWindow> {
+    let mut lines = output.lines();
+    Some(ActiveWindow {
+        app: lines.next()?.trim().to_string(),
+        title: lines.next().unwrap_or_default().trim().to_string(),
+    })
+}
+
+/// Writes `line` as a line of the text log.
+pub fn write_line(writer: &mut impl Write, line: &LogLine) -> std::io::Result<()> {
+    let (name, press, timestamp) = match line {
+        LogLine::Event(key_code, press, timestamp) => (key_code.to_string(), press, timestamp),
+        LogLine::UnknownKey(name, press, timestamp) => (name.clone(), press, timestamp),
+        LogLine::Tag(tag) => return write_tag(writer, tag, true),
+        LogLine::Untag(tag) => return write_tag(writer, tag, false),
+        LogLine::Header(header) => return write_header(writer, header),
+        LogLine::Device(device) => return writeln!(writer, "# device {device}"),
+    };
+    let press = if *press { 1 } else { 0 };
+    match timestamp {
+        Some(timestamp) => writeln!(writer, "{name} {press} {timestamp}"),
+        None => writeln!(writer, "{name} {press}"),
+    }
+}
+
+/// The formats a log can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// The compact format of [`binlog`](crate::binlog).
+    Binary,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "binary" => Ok(LogFormat::Binary),
+            _ => Err(format!("expected text or binary, got {s}")),
+        }
+    }
+}
+
+impl LogFormat {
+    /// The format of the log at `path`, or `None` if it's empty or doesn't
+    /// exist yet.
+    pub fn detect(path: &str) -> Result<Option<Self>> {
+        let mut start = vec![];
+        match File::open(path) {
+            Ok(file) => {
+                file.take(MAGIC.len() as u64).read_to_end(&mut start)?;
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        }
+        Ok(if start.is_empty() {
+            None
+        } else if is_binary(&start) {
+            Some(LogFormat::Binary)
+        } else {
+            Some(LogFormat::Text)
+        })
+    }
+}
+
+/// Writes the lines of a log in either [`LogFormat`].
+pub enum LogWriter<W: Write> {
+    Text(W),
+    Binary(BinaryLogWriter<W>),
+}
+
+impl<W: Write> LogWriter<W> {
+    /// Starts a new log.
+    pub fn new(writer: W, format: LogFormat) -> std::io::Result<Self> {
+        Ok(match format {
+            LogFormat::Text => LogWriter::Text(writer),
+            LogFormat::Binary => LogWriter::Binary(BinaryLogWriter::new(writer)?),
+        })
+    }
+
+    /// Continues a log that `writer` is at the end of.
+    pub fn append(writer: W, format: LogFormat) -> Self {
+        match format {
+            LogFormat::Text => LogWriter::Text(writer),
+            LogFormat::Binary => LogWriter::Binary(BinaryLogWriter::append(writer)),
+        }
+    }
+
+    pub fn write_line(&mut self, line: &LogLine) -> std::io::Result<()> {
+        match self {
+            LogWriter::Text(writer) => write_line(writer, line),
+            LogWriter::Binary(writer) => writer.write_line(line),
+        }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            LogWriter::Text(writer) => writer.flush(),
+            LogWriter::Binary(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Reads the log at `path` line by line, in either [`LogFormat`].  Errors
+/// say which line or record they're in, but not the path.
+pub fn read_log(path: &str) -> Result<Box<dyn Iterator<Item = Result<LogLine>>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if is_binary(reader.fill_buf()?) {
+        return Ok(Box::new(BinaryLogReader::new(reader)?));
+    }
+    Ok(Box::new(TextLogReader::new(reader)))
+}
+
+/// Reads the lines of a text log as [`LogLine`]s, into a single buffer
+/// reused for every line.
+pub struct TextLogReader<R> {
+    reader: R,
+    line: String,
+    lines: usize,
+}
+
+impl<R: BufRead> TextLogReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            lines: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TextLogReader<R> {
+    type Item = Result<LogLine>;
+
+    /// The next line, with errors saying which line they're in.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+        let index = self.lines;
+        self.lines += 1;
+        match self.reader.read_line(&mut self.line) {
+            Ok(0) => None,
+            Ok(_) => {
+                let line = self.line.strip_suffix('\n').unwrap_or(&self.line);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                Some(parse_line(line).map_err(|error| error.at_line(index)))
+            }
+            Err(error) => Some(Err(Error::from(error).at_line(index))),
+        }
+    }
+}
+
+/// Follows a log that's still being written, like `tail -f`: every
+/// [`read_new`](Self::read_new) reads the lines added since the last one.
+/// Only complete lines are read, so a line the keylogger is halfway through
+/// writing is read once it's done.
+pub struct LogTail {
+    path: String,
+    /// How many bytes of the log have been read.
+    offset: u64,
+    /// Unknown until the log has anything in it.
+    format: Option<LogFormat>,
+    /// The timestamp the next record of a binary log is relative to.
+    last_timestamp: u64,
+    /// Lines of a text log read so far, for the line numbers of errors.
+    lines: usize,
+}
+
+impl LogTail {
+    /// Follows the log at `path` from its start.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            offset: 0,
+            format: None,
+            last_timestamp: 0,
+            lines: 0,
+        }
+    }
+
+    /// The complete lines added to the log since the last call, or since
+    /// its start the first time.  Errors don't say the path.
+    pub fn read_new(&mut self) -> Result<Vec<LogLine>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let mut start = 0;
+        let format = match self.format {
+            Some(format) => format,
+            // Wait for the whole magic before deciding
+            None if bytes.len() < MAGIC.len() && MAGIC.starts_with(&bytes) => return Ok(vec![]),
+            None => {
+                let format = if is_binary(&bytes) {
+                    start = MAGIC.len();
+                    LogFormat::Binary
+                } else {
+                    LogFormat::Text
+                };
+                self.format = Some(format);
+                format
+            }
+        };
+        let bytes = &bytes[start..];
+        let (lines, read) = match format {
+            LogFormat::Text => {
+                let read = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                let mut reader = TextLogReader {
+                    reader: &bytes[..read],
+                    line: String::new(),
+                    lines: self.lines,
+                };
+                let lines = reader.by_ref().collect::<Result<Vec<_>>>()?;
+                // The reader counts the end of the log as a line too
+                self.lines = reader.lines - 1;
+                (lines, read)
+            }
+            LogFormat::Binary => binlog::read_complete_records(bytes, &mut self.last_timestamp)?,
+        };
+        self.offset += (start + read) as u64;
+        Ok(lines)
+    }
+}
+
+/// The trimmed output of a command, if it ran and printed anything.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let output = String::from_utf8(output.stdout).ok()?;
+    let output = output.trim();
+    (!output.is_empty()).then(|| output.to_string())
+}
+
+fn detect_hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .filter(|name| !name.is_empty())
+        .or_else(|| command_output("hostname", &[]))
+}
+
+fn detect_os_layout() -> Option<String> {
+    if cfg!(target_os = "linux") {
+        let query = command_output("setxkbmap", &["-query"])?;
+        query
+            .lines()
+            .find_map(|line| line.strip_prefix("layout:"))
+            .map(|layout| layout.trim().to_string())
+    } else if cfg!(target_os = "macos") {
+        command_output(
+            "defaults",
+            &[
+                "read",
+                "com.apple.HIToolbox",
+                "AppleCurrentKeyboardLayoutInputSourceID",
+            ],
+        )
+    } else if cfg!(target_os = "windows") {
+        command_output(
+            "powershell",
+            &["-NoProfile", "-Command", "(Get-Culture).KeyboardLayoutId"],
+        )
+    } else {
+        None
+    }
+}
+
+/// The names of the input devices that have a keyboard handler, on Linux.
+fn detect_keyboards() -> Vec<String> {
+    let Ok(devices) = std::fs::read_to_string("/proc/bus/input/devices") else {
+        return vec![];
+    };
+    devices
+        .split("\n\n")
+        .filter(|device| {
+            device
+                .lines()
+                .any(|line| line.starts_with("H: Handlers=") && line.contains("kbd"))
+        })
+        .filter_map(|device| {
+            device.lines().find_map(|line| {
+                let name = line.strip_prefix("N: Name=")?;
+                Some(name.trim_matches('"').to_string())
+            })
+        })
+        .collect()
+}
+
+/// Parses a line that is either an event or a session marker.
+pub fn parse_line(line: &str) -> Result<LogLine> {
+    match line.strip_prefix('#') {
+        Some(marker) => match marker.trim().split_once(' ') {
+            Some(("tag", tag)) => Ok(LogLine::Tag(tag.trim().to_string())),
+            Some(("untag", tag)) => Ok(LogLine::Untag(tag.trim().to_string())),
+            Some(("device", device)) => Ok(LogLine::Device(device.trim().to_string())),
+            Some(("header", header)) => serde_json::from_str(header)
+                .map(LogLine::Header)
+                .map_err(|error| Error::parse(line, format!("invalid log header: {error}"))),
+            _ => Err(Error::parse(line, "unknown log marker")),
+        },
+        None => {
+            let (name, press, timestamp) = parse_fields(line)?;
+            Ok(match keycode_from_name(name) {
+                Some(key_code) => LogLine::Event(key_code, press, timestamp),
+                None => LogLine::UnknownKey(name.to_string(), press, timestamp),
+            })
+        }
+    }
+}
+
+/// Writes a marker that starts (`on`) or ends a session tagged `tag`.
+pub fn write_tag(writer: &mut impl Write, tag: &str, on: bool) -> std::io::Result<()> {
+    writeln!(writer, "# {} {tag}", if on { "tag" } else { "untag" })
+}
+
+/// Parses a single log line into its keycode and whether it was a press.
+pub fn parse_event(line: &str) -> Result<(Keycode, bool)> {
+    let (key_code, press, _) = parse_timed_event(line)?;
+    Ok((key_code, press))
+}
+
+/// Like [`parse_event`], but also returns the timestamp if the line has one.
+pub fn parse_timed_event(line: &str) -> Result<(Keycode, bool, Option<u64>)> {
+    let (name, press, timestamp) = parse_fields(line)?;
+    let key_code = keycode_from_name(name).ok_or_else(|| Error::parse(line, "unknown keycode"))?;
+    Ok((key_code, press, timestamp))
+}
+
+/// The keycode called `name` in logs.  Names are looked up in a table
+/// instead of being matched against every name in turn, which took most of
+/// the time reading a big log.
+pub fn keycode_from_name(name: &str) -> Option<Keycode> {
+    static KEYCODES: OnceLock<HashMap<String, Keycode>> = OnceLock::new();
+    let keycodes = KEYCODES.get_or_init(|| {
+        binlog::KEYCODES
+            .iter()
+            .map(|code| (code.to_string(), *code))
+            .collect()
+    });
+    match keycodes.get(name) {
+        Some(code) => Some(*code),
+        None => Keycode::from_str(name).ok(),
+    }
+}
+
+/// Splits an event into the name of its key, whether it was a press, and
+/// its timestamp.
+fn parse_fields(line: &str) -> Result<(&str, bool, Option<u64>)> {
+    let mut fields = line.split(' ');
+    let name = fields.next().unwrap_or_default();
+    let press = match fields.next() {
+        Some("1") => true,
+        Some("0") => false,
+        _ => return Err(Error::parse(line, "expected 1 or 0 for press or release")),
+    };
+    let timestamp = fields
+        .next()
+        .map(|t| t.parse())
+        .transpose()
+        .map_err(|_| Error::parse(line, "invalid timestamp"))?;
+    Ok((name, press, timestamp))
+}
+
+/// Writes a single event with the time it happened, in milliseconds since
+/// the Unix epoch.
+pub fn write_timed_event(
+    writer: &mut impl Write,
+    key_code: Keycode,
+    press: bool,
+    timestamp: u64,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "{key_code} {} {timestamp}",
+        if press { 1 } else { 0 }
+    )
+}
+
+/// Writes a single event in the format understood by [`parse_event`].
+pub fn write_event(writer: &mut impl Write, key_code: Keycode, press: bool) -> std::io::Result<()> {
+    writeln!(writer, "{key_code} {}", if press { 1 } else { 0 })
+}
+
+/// Writes the log the keylogger would have written while `text` was typed
+/// on a US QWERTY system layout, for tests and demos without a real log.
+pub fn write_synthetic_log(writer: &mut impl Write, text: &str) -> std::io::Result<()> {
+    for (key_code, press) in crate::stats::typing_events(text, &CharTable::default()) {
+        write_event(writer, key_code, press)?;
+    }
+    Ok(())
+}
+
+/// Turns snapshots of the currently held keys into press and release events.
+#[derive(Default)]
+pub struct KeyStateTracker {
+    keys: HashSet<Keycode>,
+}
+
+impl KeyStateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the events needed to go from the previous snapshot to
+    /// `current`, presses first.
+    pub fn update(&mut self, current: impl IntoIterator<Item = Keycode>) -> Vec<(Keycode, bool)> {
+        let current: HashSet<_> = current.into_iter().collect();
+        let mut events: Vec<_> = current
+            .difference(&self.keys)
+            .map(|&key| (key, true))
+            .collect();
+        events.extend(self.keys.difference(&current).map(|&key| (key, false)));
+        self.keys = current;
+        events
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Parses a UTC date like `2024-05-01`, optionally with a time like
+/// `2024-05-01T14:30`, into milliseconds since the Unix epoch.
+pub fn parse_date(s: &str) -> std::result::Result<u64, String> {
+    let invalid = || format!("expected a date like 2024-05-01 or 2024-05-01T14:30, got {s}");
+    let (date, time) = s.split_once('T').unwrap_or((s, "00:00"));
+    let numbers = |s: &str, separator| -> std::result::Result<Vec<u64>, String> {
+        s.split(separator)
+            .map(|n| n.parse().map_err(|_| invalid()))
+            .collect()
+    };
+    let (date, time) = (numbers(date, '-')?, numbers(time, ':')?);
+    let (&[year, month, day], &[hour, minute]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+    {
+        return Err(invalid());
+    }
+    // Days since the epoch of the proleptic Gregorian calendar, counting
+    // years from March so leap days come last
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era_days = year / 400 * 146_097;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era_days + day_of_era - 719_468;
+    Ok(((days * 24 + hour) * 60 + minute) * 60_000)
+}
+
+/// A key press or release as a [`KeyEventSource`] saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: Keycode,
+    pub press: bool,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The name of the keyboard the key is on, if the source can tell
+    /// keyboards apart.
+    pub device: Option<String>,
+}
+
+/// Key presses and releases as they happen, read from the OS by one of
+/// the [`Backend`]s.
+pub trait KeyEventSource {
+    /// Blocks until keys are pressed or released, or for about a second,
+    /// and returns what happened, oldest first.  Returns no events when
+    /// nothing did.
+    fn next_events(&mut self) -> std::io::Result<Vec<KeyEvent>>;
+}
+
+/// The ways the keylogger can read key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// [`device_query::DeviceQuerySource`], on every platform.
+    DeviceQuery,
+    /// [`evdev::EvdevSource`], on Linux.
+    Evdev,
+}
+
+impl Backend {
+    /// The backends that can be opened on this platform, the default
+    /// first.
+    pub fn available() -> Vec<Self> {
+        let mut backends = vec![Backend::DeviceQuery];
+        if cfg!(target_os = "linux") {
+            backends.push(Backend::Evdev);
+        }
+        backends
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::DeviceQuery => "poll",
+            Backend::Evdev => "evdev",
+        }
+    }
+
+    /// Starts reading key presses.
+    pub fn open(self) -> std::io::Result<Box<dyn KeyEventSource>> {
+        match self {
+            Backend::DeviceQuery => Ok(Box::new(device_query::DeviceQuerySource::new())),
+            #[cfg(target_os = "linux")]
+            Backend::Evdev => Ok(Box::new(evdev::EvdevSource::new()?)),
+            #[cfg(not(target_os = "linux"))]
+            Backend::Evdev => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "evdev is only available on Linux",
+            )),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let available = Backend::available();
+        available
+            .iter()
+            .copied()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = available.iter().map(|backend| backend.name()).collect();
+                format!("expected {}, got {s}", names.join(" or "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_log_reader() {
+        let log = "A 1 5\r\nA 0 9\n# tag gaming\nVolumeUp 1\nSlash 1";
+        let lines: Vec<LogLine> = TextLogReader::new(log.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            lines,
+            [
+                LogLine::Event(Keycode::A, true, Some(5)),
+                LogLine::Event(Keycode::A, false, Some(9)),
+                LogLine::Tag("gaming".to_string()),
+                LogLine::UnknownKey("VolumeUp".to_string(), true, None),
+                LogLine::Event(Keycode::Slash, true, None),
+            ]
+        );
+        let error = TextLogReader::new("A 1\nA 2\n".as_bytes())
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{error}");
+
+        for code in binlog::KEYCODES {
+            assert_eq!(keycode_from_name(&code.to_string()), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_log_tail() {
+        let lines = [
+            LogLine::Header(LogHeader::default()),
+            LogLine::Event(Keycode::A, true, Some(5)),
+            LogLine::Event(Keycode::A, false, Some(9)),
+            LogLine::Tag("gaming".to_string()),
+        ];
+        for format in [LogFormat::Text, LogFormat::Binary] {
+            let mut log = vec![];
+            {
+                let mut writer = LogWriter::new(&mut log, format).unwrap();
+                for line in &lines {
+                    writer.write_line(line).unwrap();
+                }
+                writer.flush().unwrap();
+            }
+            let path = std::env::temp_dir()
+                .join(format!("keyboard_layout_generator_tail_{:?}.log", format));
+            let path = path.to_str().unwrap();
+            std::fs::write(path, &log[..2]).unwrap();
+
+            let mut tail = LogTail::new(path);
+            assert_eq!(tail.read_new().unwrap(), []);
+            // The last line is still being written
+            std::fs::write(path, &log[..log.len() - 2]).unwrap();
+            let mut read = tail.read_new().unwrap();
+            assert_eq!(read, lines[..3]);
+            std::fs::write(path, &log).unwrap();
+            read.extend(tail.read_new().unwrap());
+            assert_eq!(tail.read_new().unwrap(), []);
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(read, lines, "{:?}", format);
+        }
+    }
+}
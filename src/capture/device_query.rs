@@ -0,0 +1,103 @@
+//! The default backend of the keylogger, which polls the held keys with
+//! `device_query` on every platform.
+//!
+//! Polling can't see a key pressed and released between two polls, so the
+//! keys are polled every few milliseconds while typing, fast enough for
+//! quick rolls and taps, and less often after a while without typing to
+//! save power.  Every event of a poll gets the time of the poll.  Under
+//! Wayland, only keys typed into X11 windows are seen.
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use device_query::{DeviceQuery, DeviceState};
+
+use super::{now_millis, KeyEvent, KeyEventSource, KeyStateTracker};
+
+/// How often to poll while typing.
+const ACTIVE_INTERVAL: Duration = Duration::from_millis(4);
+/// How often to poll at most when not typing.
+const IDLE_INTERVAL: Duration = Duration::from_millis(50);
+/// How long after the last key is released to keep polling quickly.
+const ACTIVE_FOR: Duration = Duration::from_secs(2);
+
+pub struct DeviceQuerySource {
+    state: DeviceState,
+    tracker: KeyStateTracker,
+    interval: Duration,
+    last_active: Instant,
+}
+
+impl DeviceQuerySource {
+    pub fn new() -> Self {
+        Self {
+            state: DeviceState::new(),
+            tracker: KeyStateTracker::new(),
+            interval: IDLE_INTERVAL,
+            last_active: Instant::now(),
+        }
+    }
+}
+
+impl Default for DeviceQuerySource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyEventSource for DeviceQuerySource {
+    fn next_events(&mut self) -> std::io::Result<Vec<KeyEvent>> {
+        sleep(self.interval);
+        let timestamp = now_millis();
+        let events: Vec<KeyEvent> = self
+            .tracker
+            .update(self.state.get_keys())
+            .into_iter()
+            .map(|(code, press)| KeyEvent {
+                code,
+                press,
+                timestamp,
+                device: None,
+            })
+            .collect();
+        if !events.is_empty() || !self.tracker.keys.is_empty() {
+            self.last_active = Instant::now();
+        }
+        self.interval = next_interval(self.interval, self.last_active.elapsed());
+        Ok(events)
+    }
+}
+
+/// How long to wait for the next poll after waiting `interval`, when the
+/// last key was pressed or released `idle_for` ago: quickly while typing,
+/// then backing off to [`IDLE_INTERVAL`].
+fn next_interval(interval: Duration, idle_for: Duration) -> Duration {
+    if idle_for < ACTIVE_FOR {
+        ACTIVE_INTERVAL
+    } else {
+        (interval * 2).min(IDLE_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_interval() {
+        assert_eq!(
+            next_interval(IDLE_INTERVAL, Duration::ZERO),
+            ACTIVE_INTERVAL
+        );
+        let mut interval = ACTIVE_INTERVAL;
+        let mut steps = 0;
+        while interval < IDLE_INTERVAL {
+            interval = next_interval(interval, ACTIVE_FOR);
+            steps += 1;
+        }
+        assert_eq!(interval, IDLE_INTERVAL);
+        assert_eq!(steps, 4);
+    }
+}
@@ -0,0 +1,101 @@
+//! A Linux backend for the keylogger that reads the keyboards through
+//! evdev.  Unlike polling, it sees every press and release, however short,
+//! in every window under X11 and Wayland alike, with the time the kernel
+//! recorded it.
+//!
+//! Reading input devices usually requires root or membership in the
+//! `input` group.  Only keyboards connected when the keylogger starts are
+//! read.
+
+use std::{
+    io,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use evdev::{InputEvent, InputEventKind};
+
+use super::{KeyEvent, KeyEventSource};
+use crate::remap::uinput::{evdev_to_keycode, is_keyboard};
+
+/// How long [`KeyEventSource::next_events`] waits for an event.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Reads every keyboard on a thread of its own, without grabbing them.
+/// Events are from the device named like the keyboard, so two keyboards
+/// of the same model count as one.
+pub struct EvdevSource {
+    events: Receiver<KeyEvent>,
+}
+
+impl EvdevSource {
+    pub fn new() -> io::Result<Self> {
+        let keyboards: Vec<_> = evdev::enumerate()
+            .map(|(_, device)| device)
+            .filter(is_keyboard)
+            .collect();
+        if keyboards.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no keyboard could be opened in /dev/input, which needs root or the input group",
+            ));
+        }
+        let (sender, events) = channel();
+        for mut keyboard in keyboards {
+            let sender = sender.clone();
+            let name = keyboard.name().unwrap_or("unnamed keyboard").to_string();
+            // A keyboard that's unplugged fails to read and ends its thread
+            std::thread::spawn(move || {
+                while let Ok(events) = keyboard.fetch_events() {
+                    for event in events.filter_map(|event| key_event(event, &name)) {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        Ok(Self { events })
+    }
+}
+
+impl KeyEventSource for EvdevSource {
+    fn next_events(&mut self) -> io::Result<Vec<KeyEvent>> {
+        let first = match self.events.recv_timeout(TIMEOUT) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Ok(vec![]),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "every keyboard was disconnected",
+                ))
+            }
+        };
+        let mut events = vec![first];
+        events.extend(self.events.try_iter());
+        Ok(events)
+    }
+}
+
+/// The press or release in `event` on the keyboard `device`, skipping key
+/// repeats and keys [`Keycode`](device_query::Keycode) has no name for.
+fn key_event(event: InputEvent, device: &str) -> Option<KeyEvent> {
+    let InputEventKind::Key(key) = event.kind() else {
+        return None;
+    };
+    let press = match event.value() {
+        0 => false,
+        1 => true,
+        _ => return None,
+    };
+    let timestamp = event
+        .timestamp()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64);
+    Some(KeyEvent {
+        code: evdev_to_keycode(key)?,
+        press,
+        timestamp,
+        device: Some(device.to_string()),
+    })
+}
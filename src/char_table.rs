@@ -0,0 +1,283 @@
+//! What the keys type on the system layout a key log was recorded on.
+//!
+//! The key log only records which keys were pressed, so the characters
+//! counted in [`Stats`](crate::stats::Stats) depend on the layout the OS
+//! translated them with.  The US layout is built in, along with tables for
+//! German, French and Nordic layouts in `char_tables/`.  Others are read
+//! from TOML files listing every key that types something else than on a
+//! US layout, followed by what it types unshifted and shifted:
+//!
+//! ```toml
+//! Y = "zZ"
+//! Z = "yY"
+//! Semicolon = "öÖ"
+//! ```
+//!
+//! A key with a single character types nothing shifted, and a key with an
+//! empty string types nothing at all.
+//!
+//! Characters a key types as a dead key are listed in a `[dead]` table with
+//! what they turn the next character into, so `´` followed by `e` counts as
+//! `é`.  Characters that don't combine are typed after the dead key, and a
+//! space types the dead key alone:
+//!
+//! ```toml
+//! [dead]
+//! "´" = { e = "é", E = "É" }
+//! ```
+
+use std::{collections::HashMap, str::FromStr, sync::OnceLock};
+
+use device_query::Keycode;
+use serde::Deserialize;
+
+use crate::{
+    error::{self, Error},
+    stats::{translate_key_to_char, CHAR_KEYCODES},
+};
+
+const BUILT_IN: [(&str, &str); 3] = [
+    ("de", include_str!("../char_tables/de.toml")),
+    ("fr", include_str!("../char_tables/fr.toml")),
+    ("nordic", include_str!("../char_tables/nordic.toml")),
+];
+
+/// The character every key types, unshifted and shifted, and how dead keys
+/// combine with the next character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharTable {
+    chars: HashMap<(Keycode, bool), char>,
+    dead: HashMap<char, HashMap<char, char>>,
+}
+
+/// A table as written in TOML.
+#[derive(Deserialize)]
+struct TableFile {
+    #[serde(default)]
+    dead: HashMap<char, HashMap<char, char>>,
+    #[serde(flatten)]
+    keys: HashMap<String, String>,
+}
+
+impl Default for CharTable {
+    /// The US layout of [`translate_key_to_char`].
+    fn default() -> Self {
+        let mut chars = HashMap::new();
+        for key in CHAR_KEYCODES.into_iter().chain([Keycode::Space]) {
+            for shift in [false, true] {
+                if let Some(c) = translate_key_to_char(&key, shift) {
+                    chars.insert((key, shift), c);
+                }
+            }
+        }
+        Self {
+            chars,
+            dead: HashMap::new(),
+        }
+    }
+}
+
+impl CharTable {
+    /// The built-in table called `name`, like `de`, or else the table in
+    /// the file at `name`.
+    pub fn named_or_load(name: &str) -> error::Result<Self> {
+        if name == "us" {
+            return Ok(Self::default());
+        }
+        match BUILT_IN.iter().find(|(built_in, _)| *built_in == name) {
+            Some((_, s)) => Self::parse(s),
+            None => {
+                let s = std::fs::read_to_string(name)
+                    .map_err(|error| Error::from(error).in_file(name))?;
+                Self::parse(&s).map_err(|error| error.in_file(name))
+            }
+        }
+    }
+
+    /// Parses a table in the TOML format described in the module docs.
+    pub fn parse(s: &str) -> error::Result<Self> {
+        let file: TableFile =
+            toml::from_str(s).map_err(|error| Error::Invalid(error.to_string()))?;
+        let mut table = Self {
+            dead: file.dead,
+            ..Self::default()
+        };
+        for (name, typed) in file.keys {
+            let key = Keycode::from_str(&name)
+                .map_err(|_| Error::Invalid(format!("unknown key {name}")))?;
+            let mut typed = typed.chars();
+            for shift in [false, true] {
+                match typed.next() {
+                    Some(c) => table.chars.insert((key, shift), c),
+                    None => table.chars.remove(&(key, shift)),
+                };
+            }
+            if typed.next().is_some() {
+                return Err(Error::Invalid(format!(
+                    "{name} can only type two characters, unshifted and shifted"
+                )));
+            }
+        }
+        Ok(table)
+    }
+
+    /// The character `key` types, with shift held or not.
+    pub fn char(&self, key: Keycode, shift_held: bool) -> Option<char> {
+        self.chars.get(&(key, shift_held)).copied()
+    }
+
+    /// Whether typing `c` waits for the next character to combine with.
+    pub fn is_dead(&self, c: char) -> bool {
+        self.dead.contains_key(&c)
+    }
+
+    /// What the dead key `dead` followed by `c` types, if they combine.
+    pub fn compose(&self, dead: char, c: char) -> Option<char> {
+        self.dead.get(&dead)?.get(&c).copied()
+    }
+
+    /// The key and shift state that types `c`, preferring unshifted keys.
+    pub fn key_event(&self, c: char) -> Option<(Keycode, bool)> {
+        self.chars
+            .iter()
+            .filter(|(_, typed)| **typed == c)
+            .map(|(event, _)| *event)
+            .min_by_key(|(key, shift)| (*shift, key.to_string()))
+    }
+
+    /// The keys to press to type `c`, through a dead key if no key types it
+    /// directly.
+    pub fn key_events(&self, c: char) -> Option<Vec<(Keycode, bool)>> {
+        if let Some(event) = self.key_event(c) {
+            return Some(vec![event]);
+        }
+        let mut sequences: Vec<_> = self
+            .dead
+            .iter()
+            .flat_map(|(dead, combined)| {
+                combined
+                    .iter()
+                    .filter(|(_, typed)| **typed == c)
+                    .filter_map(|(base, _)| {
+                        Some(vec![self.key_event(*dead)?, self.key_event(*base)?])
+                    })
+            })
+            .collect();
+        sequences.sort_by_key(|events| format!("{events:?}"));
+        sequences.into_iter().next()
+    }
+}
+
+/// The key that types `c` on the first built-in table that has it, for
+/// characters a US layout can't type.
+pub(crate) fn built_in_keycode(c: char) -> Option<Keycode> {
+    static KEYCODES: OnceLock<HashMap<char, Keycode>> = OnceLock::new();
+    KEYCODES
+        .get_or_init(|| {
+            let mut keycodes = HashMap::new();
+            for (_, s) in BUILT_IN.iter().rev() {
+                let table = CharTable::parse(s).unwrap();
+                for (&(key, _), &typed) in &table.chars {
+                    keycodes.insert(typed, key);
+                }
+            }
+            keycodes
+        })
+        .get(&c)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{default_shifted, Key},
+        stats::{typing_events, KeyProcessor},
+    };
+
+    #[test]
+    fn test_char_table() {
+        let us = CharTable::default();
+        assert_eq!(us.char(Keycode::Key2, true), Some('@'));
+        assert_eq!(us.char(Keycode::Space, true), Some(' '));
+
+        let de = CharTable::named_or_load("de").unwrap();
+        assert_eq!(de.char(Keycode::Y, false), Some('z'));
+        assert_eq!(de.char(Keycode::Semicolon, true), Some('Ö'));
+        assert_eq!(de.char(Keycode::Key2, true), Some('"'));
+        assert_eq!(de.char(Keycode::A, true), Some('A'));
+        for (name, _) in BUILT_IN {
+            CharTable::named_or_load(name).unwrap();
+        }
+
+        let mut processor = KeyProcessor::new().with_chars(de);
+        processor.push_event(Keycode::Y, true);
+        processor.push_event(Keycode::Y, false);
+        let stats = processor.finish();
+        assert_eq!(stats.char_counts.get(&'z'), Some(&1));
+
+        // ´ e types é, ´ x types both, and ´ followed by a space types ´
+        let de = CharTable::named_or_load("de").unwrap();
+        let mut processor = KeyProcessor::new().with_chars(de);
+        for key in [
+            Keycode::Equal,
+            Keycode::E,
+            Keycode::Equal,
+            Keycode::X,
+            Keycode::Equal,
+            Keycode::Space,
+            Keycode::Equal,
+            Keycode::Backspace,
+            Keycode::A,
+        ] {
+            processor.push_event(key, true);
+            processor.push_event(key, false);
+        }
+        let stats = processor.finish();
+        assert_eq!(stats.char_counts.get(&'é'), Some(&1));
+        assert_eq!(stats.char_counts.get(&'´'), Some(&2));
+        assert_eq!(stats.char_counts.get(&'x'), Some(&1));
+        assert_eq!(stats.char_counts.get(&'a'), Some(&1));
+        assert_eq!(stats.char_counts.get(&'e'), None);
+        assert_eq!(stats.error_key_counts.get(&Keycode::X), None);
+
+        let table = CharTable::parse("Grave = \"²\"\nBackSlash = \"\"").unwrap();
+        assert_eq!(table.char(Keycode::Grave, false), Some('²'));
+        assert_eq!(table.char(Keycode::Grave, true), None);
+        assert_eq!(table.char(Keycode::BackSlash, false), None);
+        assert!(CharTable::parse("Grave = \"abc\"").is_err());
+        assert!(CharTable::parse("Nope = \"ab\"").is_err());
+        let table = CharTable::parse("[dead]\n\"~\" = { n = \"ñ\" }").unwrap();
+        assert_eq!(table.compose('~', 'n'), Some('ñ'));
+    }
+
+    #[test]
+    fn test_unicode_text() {
+        let de = CharTable::named_or_load("de").unwrap();
+        assert_eq!(de.key_event('Ü'), Some((Keycode::LeftBracket, true)));
+        assert_eq!(
+            de.key_events('é'),
+            Some(vec![(Keycode::Equal, false), (Keycode::E, false)])
+        );
+        let mut processor = KeyProcessor::new().with_chars(de.clone());
+        for (key, press) in typing_events("Grüße, é ´", &de) {
+            processor.push_event(key, press);
+        }
+        let stats = processor.finish();
+        for c in ['G', 'ü', 'ß', 'é', '´'] {
+            assert_eq!(stats.char_counts.get(&c), Some(&1), "{c}");
+        }
+        assert_eq!(stats.individual_key_counts.get(&Keycode::Minus), Some(&1));
+
+        let key = Key::from_char_default_shifted('ü');
+        assert_eq!(
+            key,
+            Key::Normal {
+                normal: 'ü',
+                shifted: 'Ü'
+            }
+        );
+        assert_eq!(key.keycode(false), Keycode::LeftBracket);
+        assert_eq!(default_shifted('ß'), 'ß');
+    }
+}
@@ -0,0 +1,192 @@
+//! Proposing combos: pairs of adjacent keys that type a frequent sequence
+//! like "th" or "ing" when pressed together.
+
+use std::collections::HashSet;
+
+use crate::{
+    layout::{Key, Layout},
+    nav::key_effort,
+    stats::Stats,
+    FingerKind, KeymapConfig,
+};
+
+pub struct ComboConfig {
+    /// The most combos to propose.
+    pub max_combos: usize,
+    /// Effort of a single key press, on top of the effort of the key.  A
+    /// combo saves it for every key of the sequence but one.
+    pub press_cost: f64,
+    /// Effort of pressing two keys at once on top of the harder of them.
+    pub chord_cost: f64,
+    /// Effort added for every time the two keys were typed one after the
+    /// other, since a fast roll can trigger the combo by accident.
+    pub misfire_penalty: f64,
+}
+
+impl Default for ComboConfig {
+    fn default() -> Self {
+        Self {
+            max_combos: 8,
+            press_cost: 1.0,
+            chord_cost: 0.3,
+            misfire_penalty: 0.5,
+        }
+    }
+}
+
+/// Two physical keys that type `output` when pressed together.
+#[derive(Debug, Clone)]
+pub struct Combo {
+    pub keys: (usize, usize),
+    pub output: String,
+    /// How often `output` was typed.
+    pub count: u64,
+    /// Effort saved by typing `output` with the combo, less the expected
+    /// cost of misfires.
+    pub benefit: f64,
+}
+
+/// Pairs the most frequent bigrams and trigrams with pairs of adjacent
+/// keys, greedily by benefit, using every pair of keys and every sequence
+/// at most once.  Only sequences of unshifted characters on `layout` are
+/// considered, and only combos that save effort are returned.
+pub fn propose_combos(
+    layout: &Layout,
+    stats: &Stats,
+    config: &KeymapConfig,
+    combo_config: &ComboConfig,
+) -> Vec<Combo> {
+    let sequences: Vec<(String, u64, f64)> = stats
+        .consecutive_char_counts
+        .iter()
+        .map(|(&(a, b), &count)| (String::from_iter([a, b]), count))
+        .chain(
+            stats
+                .trigram_char_counts
+                .iter()
+                .map(|(&(a, b, c), &count)| (String::from_iter([a, b, c]), count)),
+        )
+        .filter_map(|(sequence, count)| {
+            let effort = sequence_effort(layout, config, combo_config, &sequence)?;
+            Some((sequence, count, effort))
+        })
+        .collect();
+
+    let pairs = combo_pairs(config);
+    let mut candidates = vec![];
+    for (sequence, count, effort) in &sequences {
+        for &(i, j) in &pairs {
+            let combo_effort = combo_config.press_cost
+                + combo_config.chord_cost
+                + key_effort(config, i).max(key_effort(config, j));
+            let benefit = *count as f64 * (effort - combo_effort)
+                - roll_count(layout, stats, i, j) as f64 * combo_config.misfire_penalty;
+            if benefit > 0.0 {
+                candidates.push(Combo {
+                    keys: (i, j),
+                    output: sequence.clone(),
+                    count: *count,
+                    benefit,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.benefit.total_cmp(&a.benefit));
+
+    let mut used_keys = HashSet::new();
+    let mut used_outputs = HashSet::new();
+    let mut combos = vec![];
+    for combo in candidates {
+        if combos.len() == combo_config.max_combos {
+            break;
+        }
+        if used_keys.contains(&combo.keys) || used_outputs.contains(&combo.output) {
+            continue;
+        }
+        used_keys.insert(combo.keys);
+        used_outputs.insert(combo.output.clone());
+        combos.push(combo);
+    }
+    combos
+}
+
+/// The effort of typing `sequence` one key at a time, or `None` if one of
+/// its characters isn't an unshifted key of `layout`.
+fn sequence_effort(
+    layout: &Layout,
+    config: &KeymapConfig,
+    combo_config: &ComboConfig,
+    sequence: &str,
+) -> Option<f64> {
+    sequence
+        .chars()
+        .map(|c| {
+            let i = layout
+                .keys()
+                .iter()
+                .position(|key| matches!(key, Key::Normal { normal, .. } if *normal == c))?;
+            Some(combo_config.press_cost + key_effort(config, i))
+        })
+        .sum()
+}
+
+/// Pairs of keys that are easy to press together: next to each other in
+/// the same row, on the same hand but different fingers.  Thumbs are left
+/// out since their keys are usually far apart.
+fn combo_pairs(config: &KeymapConfig) -> Vec<(usize, usize)> {
+    let keys = config.keys.keys();
+    let mut pairs = vec![];
+    for (i, a) in keys.iter().enumerate() {
+        for (j, b) in keys.iter().enumerate().skip(i + 1) {
+            if a.position.1 == b.position.1
+                && (a.position.0 - b.position.0).abs() == 1.0
+                && a.finger.hand == b.finger.hand
+                && a.finger != b.finger
+                && a.finger.finger != FingerKind::Thumb
+                && b.finger.finger != FingerKind::Thumb
+            {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+/// How often the keys at `i` and `j` were typed right after each other, in
+/// either order.
+fn roll_count(layout: &Layout, stats: &Stats, i: usize, j: usize) -> u64 {
+    let a = layout.keys()[i].keycode(false);
+    let b = layout.keys()[j].keycode(false);
+    [(a, b), (b, a)]
+        .iter()
+        .map(|pair| stats.consecutive_key_counts.get(pair).copied().unwrap_or(0))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_propose_combos() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        stats.consecutive_char_counts.insert(('t', 'h'), 1000);
+        stats.consecutive_char_counts.insert(('q', 'z'), 1);
+        stats.trigram_char_counts.insert(('i', 'n', 'g'), 100);
+
+        let combo_config = ComboConfig {
+            max_combos: 1,
+            ..Default::default()
+        };
+        let combos = propose_combos(&layout, &stats, &config, &combo_config);
+        assert_eq!(combos.len(), 1);
+        assert_eq!(combos[0].output, "th");
+
+        let combos = propose_combos(&layout, &stats, &config, &ComboConfig::default());
+        let outputs: Vec<_> = combos.iter().map(|c| c.output.as_str()).collect();
+        assert_eq!(outputs, ["th", "ing", "qz"]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Settings shared by every binary.
+
+use std::{collections::HashMap, str::FromStr};
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    char_table::CharTable,
+    constraints::Constraints,
+    error::{self, Error},
+    scoring::ScoringConfig,
+    search::Schedule,
+    sessions::DEFAULT_GAP_MINUTES,
+    stats::LogOptions,
+};
+
+/// Settings read from `config.json`.  Missing settings take their defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub number_format: NumberFormat,
+    pub scoring: ScoringConfig,
+    pub constraints: Constraints,
+    /// How `explore_layouts` anneals every layout.
+    pub annealing: Schedule,
+    /// Keys the OS remapped while the key log was recorded, by keycode
+    /// name, e.g. `{"CapsLock": "LControl"}`.
+    pub os_remaps: HashMap<String, String>,
+    /// Names in the key log of keys that aren't keycodes, like media keys
+    /// logged on another machine, and the keycode to count them as, e.g.
+    /// `{"VolumeUp": "F13"}`.  Other unknown keys are skipped.
+    pub key_aliases: HashMap<String, String>,
+    /// Windows the keylogger doesn't log while they're focused, by
+    /// patterns of their application or title like `KeePassXC` or
+    /// `*ssh *`, see [`WindowExclusions`](crate::privacy::WindowExclusions).
+    pub excluded_windows: Vec<String>,
+    /// Short names for keyboards the keylogger tells apart by their
+    /// system name, e.g. `{"AT Translated Set 2 keyboard": "laptop"}`.
+    pub device_names: HashMap<String, String>,
+    /// The system layout the key log was typed on, as the name of a
+    /// built-in table like `de` or the path of a TOML table, see
+    /// [`CharTable`].  Defaults to US.
+    pub char_table: Option<String>,
+    /// How many minutes typing has to pause for to start a new session.
+    /// Defaults to [`DEFAULT_GAP_MINUTES`].
+    pub session_gap_minutes: Option<u64>,
+    /// See [`LogOptions::transparent_modifiers`].
+    pub transparent_modifiers: bool,
+}
+
+impl Config {
+    pub fn load_or_default(path: &str) -> std::io::Result<Self> {
+        match std::fs::File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Options for reading key logs with the OS remaps and key aliases,
+    /// leaving out sessions tagged with any of `exclude_tagged`.
+    pub fn log_options(&self, exclude_tagged: &[String]) -> error::Result<LogOptions> {
+        let parse = |setting: &str, name: &str| {
+            Keycode::from_str(name)
+                .map_err(|_| Error::Invalid(format!("unknown key in {setting}: {name}")))
+        };
+        let remaps = self
+            .os_remaps
+            .iter()
+            .map(|(from, to)| Ok((parse("os_remaps", from)?, parse("os_remaps", to)?)))
+            .collect::<error::Result<_>>()?;
+        let aliases = self
+            .key_aliases
+            .iter()
+            .map(|(name, to)| Ok((name.clone(), parse("key_aliases", to)?)))
+            .collect::<error::Result<_>>()?;
+        let chars = match &self.char_table {
+            Some(name) => CharTable::named_or_load(name)?,
+            None => CharTable::default(),
+        };
+        Ok(LogOptions {
+            exclude_tagged: exclude_tagged.to_vec(),
+            remaps,
+            aliases,
+            device: None,
+            chars,
+            since: None,
+            until: None,
+            transparent_modifiers: self.transparent_modifiers,
+        })
+    }
+
+    /// [`Config::session_gap_minutes`] in milliseconds.
+    pub fn session_gap_ms(&self) -> u64 {
+        self.session_gap_minutes.unwrap_or(DEFAULT_GAP_MINUTES) * 60_000
+    }
+}
+
+/// How numbers are printed in reports.  The defaults print numbers the way
+/// Rust does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormat {
+    /// Put between every three digits of the integer part, e.g. `,` or `.`.
+    pub thousands_separator: Option<char>,
+    pub decimal_mark: char,
+    /// Digits after the decimal mark for scores and other decimals.
+    pub decimal_precision: usize,
+    /// Digits after the decimal mark for percentages.
+    pub percent_precision: usize,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            thousands_separator: None,
+            decimal_mark: '.',
+            decimal_precision: 2,
+            percent_precision: 2,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn count(&self, n: u64) -> String {
+        self.group(&n.to_string())
+    }
+
+    pub fn decimal(&self, x: f64) -> String {
+        self.decimal_with_precision(x, self.decimal_precision)
+    }
+
+    pub fn decimal_with_precision(&self, x: f64, precision: usize) -> String {
+        let s = format!("{:.*}", precision, x);
+        let (sign, s) = match s.strip_prefix('-') {
+            Some(s) => ("-", s),
+            None => ("", s.as_str()),
+        };
+        match s.split_once('.') {
+            Some((int, frac)) => {
+                format!("{}{}{}{}", sign, self.group(int), self.decimal_mark, frac)
+            }
+            None => format!("{}{}", sign, self.group(s)),
+        }
+    }
+
+    /// Formats a fraction as a percentage, e.g. `0.5` as `50.00%`.
+    pub fn percent(&self, fraction: f64) -> String {
+        format!(
+            "{}%",
+            self.decimal_with_precision(fraction * 100.0, self.percent_precision)
+        )
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let Some(separator) = self.thousands_separator else {
+            return digits.to_string();
+        };
+        let mut s = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                s.push(separator);
+            }
+            s.push(c);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_format() {
+        let format = NumberFormat {
+            thousands_separator: Some('.'),
+            decimal_mark: ',',
+            decimal_precision: 1,
+            percent_precision: 0,
+        };
+        assert_eq!(format.count(1234567), "1.234.567");
+        assert_eq!(format.count(123), "123");
+        assert_eq!(format.decimal(-1234.56), "-1.234,6");
+        assert_eq!(format.percent(0.256), "26%");
+        assert_eq!(NumberFormat::default().decimal(1234.5), "1234.50");
+    }
+}
@@ -0,0 +1,223 @@
+//! Restrictions on where keys may be placed, and checking that they can all
+//! be met before spending hours optimizing against them.
+//!
+//! Keys and physical keys are both named by their keycode as written in the
+//! key log, e.g. `A`, `Key1`, or `LShift`.  A key is the one whose unshifted
+//! keycode matches, a physical key the one that sends that keycode.
+
+use std::{collections::HashMap, str::FromStr};
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::{scoring::are_symmetric, KeymapConfig};
+
+/// Read from the `constraints` section of `config.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Constraints {
+    /// Keys that have to stay on one physical key.
+    pub pins: HashMap<String, String>,
+    /// Keys that have to go on one of the listed physical keys.
+    pub regions: HashMap<String, Vec<String>>,
+    /// Keys that must not go on any of the listed physical keys.
+    pub forbidden: HashMap<String, Vec<String>>,
+    /// Pairs of keys that have to mirror each other.
+    pub symmetric: Vec<(String, String)>,
+}
+
+impl Constraints {
+    /// The physical keys each constrained key may go on.  Fails with a
+    /// message for every constraint that can't be met.
+    pub fn allowed_positions(
+        &self,
+        config: &KeymapConfig,
+    ) -> Result<HashMap<Keycode, Vec<usize>>, Vec<String>> {
+        let mut errors = vec![];
+        let all: Vec<usize> = (0..config.keys.keys().len()).collect();
+        let mut allowed: HashMap<Keycode, Vec<usize>> = HashMap::new();
+
+        for (key, region) in &self.regions {
+            let Some(code) = parse_key(key, &mut errors) else {
+                continue;
+            };
+            let positions = region
+                .iter()
+                .filter_map(|name| physical_key(config, name, &mut errors))
+                .collect();
+            allowed.insert(code, positions);
+        }
+        for (key, forbidden) in &self.forbidden {
+            let Some(code) = parse_key(key, &mut errors) else {
+                continue;
+            };
+            let forbidden: Vec<usize> = forbidden
+                .iter()
+                .filter_map(|name| physical_key(config, name, &mut errors))
+                .collect();
+            allowed
+                .entry(code)
+                .or_insert_with(|| all.clone())
+                .retain(|i| !forbidden.contains(i));
+        }
+
+        let mut pinned: HashMap<usize, Keycode> = HashMap::new();
+        for (key, name) in &self.pins {
+            let (Some(code), Some(i)) = (
+                parse_key(key, &mut errors),
+                physical_key(config, name, &mut errors),
+            ) else {
+                continue;
+            };
+            if let Some(other) = pinned.insert(i, code) {
+                errors.push(format!(
+                    "{} and {} are both pinned to {}, pin one of them somewhere else",
+                    other, code, name
+                ));
+            }
+            let positions = allowed.entry(code).or_insert_with(|| all.clone());
+            if !positions.contains(&i) {
+                errors.push(format!(
+                    "{} is pinned to {}, but its region or forbidden list rules that out",
+                    code, name
+                ));
+            }
+            *positions = vec![i];
+        }
+        // Nothing else can go where a key is pinned
+        for (code, positions) in allowed.iter_mut() {
+            positions.retain(|i| pinned.get(i).is_none_or(|pin| pin == code));
+        }
+
+        for (code, positions) in &allowed {
+            if positions.is_empty() {
+                errors.push(format!(
+                    "{} has nowhere to go, widen its region or shorten its forbidden list",
+                    code
+                ));
+            }
+        }
+        if errors.is_empty() {
+            errors.extend(matching_conflicts(&allowed));
+        }
+
+        for (a, b) in &self.symmetric {
+            let (Some(a), Some(b)) = (parse_key(a, &mut errors), parse_key(b, &mut errors)) else {
+                continue;
+            };
+            let positions_a = allowed.get(&a).unwrap_or(&all);
+            let positions_b = allowed.get(&b).unwrap_or(&all);
+            let physical = config.keys.keys();
+            let possible = positions_a.iter().any(|&i| {
+                positions_b.iter().any(|&j| {
+                    i != j && are_symmetric(config, physical[i].position, physical[j].position)
+                })
+            });
+            if !possible {
+                errors.push(format!(
+                    "{} and {} have to be symmetric, but none of the places they may go mirror each other",
+                    a, b
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(allowed)
+        } else {
+            errors.sort();
+            Err(errors)
+        }
+    }
+}
+
+fn parse_key(name: &str, errors: &mut Vec<String>) -> Option<Keycode> {
+    match Keycode::from_str(name) {
+        Ok(code) => Some(code),
+        Err(_) => {
+            errors.push(format!("{} isn't a keycode name", name));
+            None
+        }
+    }
+}
+
+fn physical_key(config: &KeymapConfig, name: &str, errors: &mut Vec<String>) -> Option<usize> {
+    let code = parse_key(name, errors)?;
+    let i = config.keys.keys().iter().position(|key| key.code == code);
+    if i.is_none() {
+        errors.push(format!("{} isn't a key of the physical keyboard", name));
+    }
+    i
+}
+
+/// Finds groups of keys that need more places than their regions have
+/// between them, by matching every key to a place of its own.
+fn matching_conflicts(allowed: &HashMap<Keycode, Vec<usize>>) -> Vec<String> {
+    let mut keys: Vec<&Keycode> = allowed.keys().collect();
+    keys.sort_by_key(|code| code.to_string());
+    let mut owner: HashMap<usize, Keycode> = HashMap::new();
+    let mut errors = vec![];
+
+    for &code in keys {
+        let mut visited = vec![];
+        if !augment(code, allowed, &mut owner, &mut visited) {
+            // Every place this key could go is taken by a key that can't
+            // move elsewhere, so together they outnumber the places
+            let mut competing: Vec<String> = visited
+                .iter()
+                .map(|i| owner[i].to_string())
+                .chain([code.to_string()])
+                .collect();
+            competing.sort();
+            errors.push(format!(
+                "{} keys ({}) have to share {} places, give some of them more room",
+                competing.len(),
+                competing.join(", "),
+                visited.len()
+            ));
+        }
+    }
+    errors
+}
+
+/// Tries to give `code` a place, moving already placed keys if needed.
+fn augment(
+    code: Keycode,
+    allowed: &HashMap<Keycode, Vec<usize>>,
+    owner: &mut HashMap<usize, Keycode>,
+    visited: &mut Vec<usize>,
+) -> bool {
+    for &i in &allowed[&code] {
+        if visited.contains(&i) {
+            continue;
+        }
+        visited.push(i);
+        let free = match owner.get(&i) {
+            None => true,
+            Some(&other) => augment(other, allowed, owner, visited),
+        };
+        if free {
+            owner.insert(i, code);
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching_conflicts() {
+        let mut allowed = HashMap::new();
+        allowed.insert(Keycode::A, vec![0, 1]);
+        allowed.insert(Keycode::B, vec![1]);
+        assert!(matching_conflicts(&allowed).is_empty());
+
+        allowed.insert(Keycode::C, vec![0, 1]);
+        allowed.insert(Keycode::D, vec![2, 3]);
+        let errors = matching_conflicts(&allowed);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("3 keys (A, B, C) have to share 2 places"));
+    }
+}
@@ -0,0 +1,261 @@
+//! The effort model resolved into costs of the physical keys, for solving
+//! the layout problem outside this crate.
+//!
+//! Placing keys is a quadratic assignment problem: the cost of a layout is
+//! the presses of every key times the [`CostModel::effort`] of the
+//! physical key it's on, plus the presses of every bigram times the
+//! [`CostModel::transition`] between their physical keys.  That covers the
+//! presses, the bigrams with their same finger penalty and hand pattern
+//! bonuses, and the finger travel.
+//!
+//! Minimizing it only maximizes the score of the scoring config the model
+//! was resolved from, up to a constant, if the score has no other terms.
+//! The intuitions, confusable pairs, thumb conflicts and home row mods
+//! depend on which keys go where rather than on how often they're typed,
+//! and the corrections and the keys typed by mistake on counts other than
+//! the bigrams.  [`CostModel::left_out`] lists those a score has.
+
+use std::io::{self, Write};
+
+use crate::{
+    nav::key_effort,
+    scoring::{roll_order, ScoringConfig},
+    stats::Stats,
+    FingerKind, KeymapConfig,
+};
+
+/// The costs of the physical keys of a keyboard, in the order of its
+/// keymap config.  Lower is better.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostModel {
+    /// Names of the physical keys, like `A` for the key QWERTY puts `a` on.
+    pub keys: Vec<String>,
+    /// The effort of pressing each physical key, from 0 to 1.
+    pub effort: Vec<f64>,
+    /// The cost of pressing the physical key of the column right after the
+    /// one of the row.
+    pub transition: Vec<Vec<f64>>,
+}
+
+impl CostModel {
+    pub fn new(keymap_config: &KeymapConfig, scoring: &ScoringConfig) -> Self {
+        let physical = keymap_config.keys.keys();
+        let distances = keymap_config.distances();
+        let home = keymap_config.home_keys();
+        let from_home = |i: usize| distances.get(home[&physical[i].finger], i);
+        let transition = physical
+            .iter()
+            .enumerate()
+            .map(|(i, pkey1)| {
+                physical
+                    .iter()
+                    .enumerate()
+                    .map(|(j, pkey2)| {
+                        let (f1, f2) = (pkey1.finger, pkey2.finger);
+                        let distance = distances.get(i, j);
+                        let mut cost = 1.0 - scoring.weights.transition_score(pkey1, pkey2);
+                        if i != j && f1 == f2 {
+                            cost += scoring.sfb_weight * distance;
+                        }
+                        // Like finger travel, a finger pressing twice moves
+                        // between the keys, and otherwise from its home key
+                        let moved = if f1 == f2 {
+                            distance
+                        } else {
+                            from_home(i) + from_home(j)
+                        };
+                        cost += scoring.travel_weight * moved;
+                        if f1.hand != f2.hand {
+                            cost -= scoring.alternation_bonus;
+                        } else if f1.finger != FingerKind::Thumb && f2.finger != FingerKind::Thumb {
+                            match roll_order(f1.finger).cmp(&roll_order(f2.finger)) {
+                                std::cmp::Ordering::Less => cost -= scoring.inward_roll_bonus,
+                                std::cmp::Ordering::Greater => cost -= scoring.outward_roll_bonus,
+                                std::cmp::Ordering::Equal => {}
+                            }
+                        }
+                        cost
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            keys: physical
+                .iter()
+                .map(|pkey| format!("{:?}", pkey.code))
+                .collect(),
+            effort: (0..physical.len())
+                .map(|i| key_effort(keymap_config, i))
+                .collect(),
+            transition,
+        }
+    }
+
+    /// The terms of the score of `scoring` that the model leaves out, so
+    /// the cheapest placement isn't the best scoring one if there are any.
+    /// The keys typed by mistake only count if `stats` has any, or always
+    /// without stats.
+    pub fn left_out(scoring: &ScoringConfig, stats: Option<&Stats>) -> Vec<&'static str> {
+        let mut terms = vec![];
+        if scoring.weights.intuition_weight != 0.0 && !scoring.intuitions().is_empty() {
+            terms.push("intuitions");
+        }
+        if scoring.confusable_weight != 0.0 && !scoring.confusable_pairs.is_empty() {
+            terms.push("confusable pairs");
+        }
+        if scoring.thumb_conflict_weight != 0.0 {
+            terms.push("thumb conflicts");
+        }
+        if scoring.home_row_mod_weight != 0.0 && !scoring.home_row_mods.is_empty() {
+            terms.push("home row mods");
+        }
+        if scoring.correction_weight != 0.0 {
+            terms.push("corrections");
+        }
+        if stats.is_none_or(|stats| !stats.confusion_counts.is_empty()) {
+            terms.push("keys typed by mistake");
+        }
+        terms
+    }
+
+    /// Writes the effort as a CSV with a row for every physical key.
+    pub fn write_effort_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "index,key,effort")?;
+        for (i, (key, effort)) in self.keys.iter().zip(&self.effort).enumerate() {
+            writeln!(w, "{},{},{}", i, key, effort)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the transitions as a CSV matrix, with the names of the keys
+    /// as the header and the first column.
+    pub fn write_transition_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "from,{}", self.keys.join(","))?;
+        for (key, row) in self.keys.iter().zip(&self.transition) {
+            let row: Vec<String> = row.iter().map(|cost| cost.to_string()).collect();
+            writeln!(w, "{},{}", key, row.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the effort as a one dimensional NumPy array.
+    pub fn write_effort_npy(&self, w: &mut impl Write) -> io::Result<()> {
+        write_npy(w, &[self.effort.len()], &self.effort)
+    }
+
+    /// Writes the transitions as a square NumPy array.
+    pub fn write_transition_npy(&self, w: &mut impl Write) -> io::Result<()> {
+        let n = self.transition.len();
+        let values: Vec<f64> = self.transition.iter().flatten().copied().collect();
+        write_npy(w, &[n, n], &values)
+    }
+}
+
+/// Writes `values` as a `.npy` file of little endian doubles in row major
+/// order, which `numpy.load` reads without any other dependency.
+fn write_npy(w: &mut impl Write, shape: &[usize], values: &[f64]) -> io::Result<()> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // The magic, version, and header length take 10 bytes, and the whole
+    // preamble has to be padded to a multiple of 64 ending in a newline
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    for value in values {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_cost_model() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let model = CostModel::new(&config, &ScoringConfig::default());
+        let n = config.keys.keys().len();
+        assert_eq!(model.effort.len(), n);
+        assert_eq!(model.transition.len(), n);
+        assert!(model.transition.iter().all(|row| row.len() == n));
+        assert!(model
+            .effort
+            .iter()
+            .all(|effort| (0.0..=1.0).contains(effort)));
+
+        let mut npy = vec![];
+        model.write_transition_npy(&mut npy).unwrap();
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(npy.len(), 10 + header_len + n * n * 8);
+        let first = f64::from_le_bytes(npy[10 + header_len..18 + header_len].try_into().unwrap());
+        assert_eq!(first, model.transition[0][0]);
+
+        let mut csv = vec![];
+        model.write_transition_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), n + 1);
+    }
+
+    #[test]
+    fn test_travel_and_left_out() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut scoring = ScoringConfig {
+            travel_weight: 0.0,
+            ..Default::default()
+        };
+        let still = CostModel::new(&config, &scoring);
+        scoring.travel_weight = 2.0;
+        let moving = CostModel::new(&config, &scoring);
+        let distances = config.distances();
+        let physical = config.keys.keys();
+        let home = config.home_keys();
+        let from_home = |i: usize| distances.get(home[&physical[i].finger], i);
+        let n = physical.len();
+        for i in 0..n {
+            for j in 0..n {
+                let moved = if physical[i].finger == physical[j].finger {
+                    distances.get(i, j)
+                } else {
+                    from_home(i) + from_home(j)
+                };
+                let added = moving.transition[i][j] - still.transition[i][j];
+                assert!((added - 2.0 * moved).abs() < 1e-9);
+            }
+        }
+
+        scoring.thumb_conflict_weight = 0.0;
+        scoring.correction_weight = 0.0;
+        scoring.home_row_mods.clear();
+        scoring.weights.intuition_weight = 0.0;
+        let stats = Stats::new();
+        assert!(CostModel::left_out(&scoring, Some(&stats)).is_empty());
+        assert_eq!(
+            CostModel::left_out(&scoring, None),
+            ["keys typed by mistake"]
+        );
+        scoring.correction_weight = 1.0;
+        scoring.home_row_mods.push(device_query::Keycode::LShift);
+        assert_eq!(
+            CostModel::left_out(&scoring, Some(&stats)),
+            ["home row mods", "corrections"]
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! A live view of `explore_layouts` in the terminal, with the history of
+//! the best score, the best layout on the keyboard, and how long is left.
+//!
+//! The search updates a [`Progress`] and a thread redraws it a few times a
+//! second, so the view keeps moving while the layouts are refined.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout as Areas},
+    widgets::{Block, Gauge, Paragraph, Sparkline},
+    Frame,
+};
+
+use crate::{config::NumberFormat, search::Schedule};
+
+/// How often the dashboard is redrawn.
+const REFRESH: Duration = Duration::from_millis(250);
+/// Messages kept for the bottom of the dashboard.
+const MAX_MESSAGES: usize = 100;
+
+/// Everything the dashboard shows, updated by the search as it goes.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// What the search is doing, like `Annealing` or `Genetic`.
+    pub phase: String,
+    /// Generations finished so far.
+    pub generation: usize,
+    pub max_generations: Option<usize>,
+    pub max_duration: Option<Duration>,
+    pub started: Instant,
+    /// Layouts refined so far in this generation, of `population`.
+    pub refined: usize,
+    pub population: usize,
+    /// The best score of every generation.
+    pub history: Vec<f64>,
+    pub max_possible_score: f64,
+    /// The best layout, as written to `best.txt`.
+    pub best: String,
+    pub diversity: f64,
+    /// How the temperature changes, when the layouts are refined by
+    /// annealing.
+    pub temperature: Option<Schedule>,
+    /// Lines about the best layout, like its same finger bigrams.
+    pub summary: Vec<String>,
+    /// Things that happened during the search, the latest last.
+    pub messages: Vec<String>,
+}
+
+impl Progress {
+    pub fn new(population: usize, max_possible_score: f64) -> Self {
+        Self {
+            phase: String::new(),
+            generation: 0,
+            max_generations: None,
+            max_duration: None,
+            started: Instant::now(),
+            refined: 0,
+            population,
+            history: vec![],
+            max_possible_score,
+            best: String::new(),
+            diversity: 0.0,
+            temperature: None,
+            summary: vec![],
+            messages: vec![],
+        }
+    }
+
+    pub fn message(&mut self, message: String) {
+        self.messages.push(message);
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    /// How much longer the search runs `elapsed` into it, if it stops
+    /// after a number of generations or a duration.  Generations are
+    /// assumed to take as long as they have so far.
+    pub fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        let by_generations = self
+            .max_generations
+            .filter(|_| self.generation > 0)
+            .map(|max| {
+                let left = max.saturating_sub(self.generation);
+                elapsed.mul_f64(left as f64 / self.generation as f64)
+            });
+        let by_duration = self.max_duration.map(|max| max.saturating_sub(elapsed));
+        by_generations.into_iter().chain(by_duration).min()
+    }
+}
+
+/// Draws `progress` on the whole of `frame`.
+pub fn draw(frame: &mut Frame, progress: &Progress, fmt: &NumberFormat) {
+    let [header, refining, history, middle, messages] = Areas::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(8),
+        Constraint::Min(progress.best.lines().count() as u16 + 2),
+        Constraint::Min(3),
+    ])
+    .areas(frame.area());
+
+    let elapsed = progress.started.elapsed();
+    let mut status = format!("Generation {}", fmt.count(progress.generation as u64 + 1));
+    if let Some(max) = progress.max_generations {
+        status += &format!(" of {}", fmt.count(max as u64));
+    }
+    status += &format!(", {} elapsed", clock(elapsed));
+    match progress.eta(elapsed) {
+        Some(eta) => status += &format!(", {} left", clock(eta)),
+        None => status += ", runs until it's stopped",
+    }
+    frame.render_widget(
+        Paragraph::new(status).block(Block::bordered().title("explore_layouts")),
+        header,
+    );
+
+    let ratio = progress.refined as f64 / progress.population.max(1) as f64;
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title(progress.phase.as_str()))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(format!(
+                "{} of {} layouts",
+                fmt.count(progress.refined as u64),
+                fmt.count(progress.population as u64)
+            )),
+        refining,
+    );
+
+    // The sparkline starts at the worst score it shows, so the
+    // improvements of the latest generations stay visible
+    let shown = &progress.history[progress
+        .history
+        .len()
+        .saturating_sub(history.width.saturating_sub(2) as usize)..];
+    let low = shown.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = shown.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let bars: Vec<u64> = shown
+        .iter()
+        .map(|score| {
+            if high > low {
+                1 + ((score - low) / (high - low) * 99.0) as u64
+            } else {
+                100
+            }
+        })
+        .collect();
+    let title = match progress.history.last() {
+        Some(best) => format!(
+            "Best score: {} ({} of max)",
+            fmt.decimal(*best),
+            fmt.percent(best / progress.max_possible_score)
+        ),
+        None => "Best score".to_string(),
+    };
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(title))
+            .max(100)
+            .data(bars),
+        history,
+    );
+
+    let [best, details] = Areas::horizontal([Constraint::Min(0), Constraint::Min(0)]).areas(middle);
+    frame.render_widget(
+        Paragraph::new(progress.best.as_str()).block(Block::bordered().title("Best layout")),
+        best,
+    );
+    let mut lines = vec![format!("Diversity: {}", fmt.decimal(progress.diversity))];
+    if let Some(schedule) = &progress.temperature {
+        lines.push(format!(
+            "Temperature: {} to {}, times {} every {} swaps",
+            schedule.initial_temperature,
+            schedule.min_temperature,
+            schedule.cooling,
+            schedule.swaps_per_step
+        ));
+        if schedule.reheat_after > 0 || schedule.min_acceptance > 0.0 {
+            lines.push(format!("Reheats up to {} times", schedule.max_reheats));
+        }
+    }
+    lines.extend(progress.summary.iter().cloned());
+    frame.render_widget(
+        Paragraph::new(lines.join("\n")).block(Block::bordered().title("Generation")),
+        details,
+    );
+
+    let shown = &progress.messages[progress
+        .messages
+        .len()
+        .saturating_sub(messages.height.saturating_sub(2) as usize)..];
+    frame.render_widget(
+        Paragraph::new(shown.join("\n")).block(Block::bordered().title("Messages (q to quit)")),
+        messages,
+    );
+}
+
+/// A duration as hours, minutes, and seconds, like `1:05:09`.
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Shows [`Progress`] on the whole terminal until it's dropped.  The
+/// terminal doesn't turn Ctrl-C into an interrupt while it's shown, so
+/// Ctrl-C and `q` put the terminal back and exit.
+pub struct Dashboard {
+    progress: Arc<Mutex<Progress>>,
+    done: Arc<AtomicBool>,
+    drawer: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    pub fn start(progress: Progress, fmt: NumberFormat) -> Self {
+        let mut terminal = ratatui::init();
+        let progress = Arc::new(Mutex::new(progress));
+        let done = Arc::new(AtomicBool::new(false));
+        let drawer = {
+            let progress = progress.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    // A failed draw is retried on the next refresh
+                    let _ = terminal.draw(|frame| draw(frame, &lock(&progress), &fmt));
+                    if !event::poll(REFRESH).unwrap_or(false) {
+                        continue;
+                    }
+                    if let Ok(Event::Key(key)) = event::read() {
+                        let ctrl_c = key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL);
+                        if key.kind == KeyEventKind::Press
+                            && (ctrl_c || key.code == KeyCode::Char('q'))
+                        {
+                            ratatui::restore();
+                            std::process::exit(130);
+                        }
+                    }
+                }
+            })
+        };
+        Self {
+            progress,
+            done,
+            drawer: Some(drawer),
+        }
+    }
+
+    /// Changes what's shown, which is redrawn on the next refresh.
+    pub fn update(&self, update: impl FnOnce(&mut Progress)) {
+        update(&mut lock(&self.progress));
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(drawer) = self.drawer.take() {
+            let _ = drawer.join();
+        }
+        ratatui::restore();
+    }
+}
+
+/// Locks `progress` even if a thread panicked while holding it, since
+/// showing it can't make it any worse.
+fn lock(progress: &Mutex<Progress>) -> MutexGuard<'_, Progress> {
+    progress
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn test_dashboard() {
+        let mut progress = Progress::new(10, 200.0);
+        assert_eq!(progress.eta(Duration::from_secs(60)), None);
+        progress.max_generations = Some(4);
+        progress.generation = 1;
+        assert_eq!(
+            progress.eta(Duration::from_secs(60)),
+            Some(Duration::from_secs(180))
+        );
+        progress.max_duration = Some(Duration::from_secs(100));
+        assert_eq!(
+            progress.eta(Duration::from_secs(60)),
+            Some(Duration::from_secs(40))
+        );
+
+        progress.phase = "Annealing".to_string();
+        progress.refined = 5;
+        progress.history = vec![100.0, 150.0];
+        progress.best = "| a | b |".to_string();
+        progress.message("Intuition satisfied: a".to_string());
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, &progress, &NumberFormat::default()))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        for shown in [
+            "Generation 2 of 4",
+            "5 of 10 layouts",
+            "Best score: 150.00 (75.00% of max)",
+            "| a | b |",
+            "Intuition satisfied: a",
+        ] {
+            assert!(screen.contains(shown), "{shown} isn't shown");
+        }
+    }
+}
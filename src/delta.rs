@@ -0,0 +1,304 @@
+//! Updating the score of a layout for a swap of two of its keys without
+//! rescoring all of it, which is most of what the local searches do.
+//!
+//! Most terms of [`layout_score_with`] belong to a single key or to a pair
+//! of keys, so a swap only changes the terms of the two keys it moves and
+//! of the pairs they're in.  The others, like the intuitions, are
+//! recomputed when the swap moves one of their keys.
+
+use std::{collections::HashMap, sync::Arc};
+
+use device_query::Keycode;
+
+use crate::{
+    layout::{Key, Layout},
+    scoring::{
+        confusable_separation_score, home_row_mods, layout_score_with, roll_order, thumb_load,
+        Intuition, ScoringConfig,
+    },
+    search::SwapObjective,
+    stats::Stats,
+    FingerKind, KeymapConfig,
+};
+
+/// Scores layouts like [`layout_score_with`], and swaps by how much they
+/// change the score.  Only layouts with the keys of the keyboard can be
+/// scored, like every layout of a population.
+pub struct SwapScorer<'a> {
+    stats: Arc<Stats>,
+    keymap_config: Arc<KeymapConfig>,
+    scoring: &'a ScoringConfig,
+    /// What a press is worth on every physical key.
+    key_scores: Vec<f64>,
+    /// Every intuition with the keys it's about.
+    intuitions: Vec<(Intuition, Vec<Key>)>,
+    /// The number of every key of the keyboard, in the order of
+    /// [`Layout::from_keymap`], indexed by its keycode, which is quicker
+    /// than hashing the key for every position of every swap.
+    numbers: Vec<usize>,
+    /// Presses of every key, by its number.
+    presses: Vec<f64>,
+    /// Bigrams, corrections and confusions: how often every pair of keys
+    /// has them, by `a * n + b` for the numbers `a` and `b` of the keys,
+    /// and what one is worth on every pair of physical keys, by `i * n + j`.
+    /// Those that never happened are left out.
+    pair_terms: Vec<(Vec<f64>, Vec<f64>)>,
+}
+
+impl<'a> SwapScorer<'a> {
+    /// The stats and the keyboard are shared, so scorers of several
+    /// threads or runs can all read the same copy.
+    pub fn new(
+        stats: Arc<Stats>,
+        keymap_config: Arc<KeymapConfig>,
+        scoring: &'a ScoringConfig,
+    ) -> Self {
+        let physical = keymap_config.keys.keys();
+        let key_scores = physical
+            .iter()
+            .map(|pkey| {
+                let finger = keymap_config
+                    .fingers
+                    .iter()
+                    .find(|c| c.finger == pkey.finger)
+                    .unwrap();
+                pkey.score * finger.score
+            })
+            .collect();
+        let distances = keymap_config.distances();
+        let transitions = keymap_config.transition_scores(&scoring.weights);
+        let home = keymap_config.home_keys();
+        let from_home = |i: usize| distances.get(home[&physical[i].finger], i);
+        let n = physical.len();
+        let mut bigram_scores = vec![0.0; n * n];
+        let mut correction_scores = vec![0.0; n * n];
+        let mut confusion_scores = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                let (f1, f2) = (physical[i].finger, physical[j].finger);
+                let mut score = transitions.get(i, j);
+                if f1 == f2 && i != j {
+                    score -= scoring.sfb_weight * distances.get(i, j);
+                }
+                if f1.hand != f2.hand {
+                    score += scoring.alternation_bonus;
+                } else if f1.finger != FingerKind::Thumb && f2.finger != FingerKind::Thumb {
+                    match roll_order(f1.finger).cmp(&roll_order(f2.finger)) {
+                        std::cmp::Ordering::Less => score += scoring.inward_roll_bonus,
+                        std::cmp::Ordering::Greater => score += scoring.outward_roll_bonus,
+                        std::cmp::Ordering::Equal => {}
+                    }
+                }
+                let moved = if f1 == f2 {
+                    distances.get(i, j)
+                } else {
+                    from_home(i) + from_home(j)
+                };
+                score -= scoring.travel_weight * moved;
+                bigram_scores[i * n + j] = score;
+                correction_scores[i * n + j] =
+                    -scoring.correction_weight * (1.0 - transitions.get(i, j));
+                if f1 != f2 || distances.get(i, j) >= 1.1 {
+                    confusion_scores[i * n + j] = 1.0;
+                }
+            }
+        }
+        let intuitions = scoring
+            .intuitions()
+            .into_iter()
+            .map(|intuition| {
+                let keys = intuition.keys();
+                (intuition, keys)
+            })
+            .collect();
+
+        let keys = Layout::from_keymap(&keymap_config).keys().to_vec();
+        let codes: Vec<Keycode> = keys.iter().map(|key| key.keycode(false)).collect();
+        let pairs = |count: &dyn Fn((Keycode, Keycode)) -> f64| {
+            let mut dense = vec![0.0; codes.len() * codes.len()];
+            for (a, code1) in codes.iter().enumerate() {
+                for (b, code2) in codes.iter().enumerate() {
+                    dense[a * codes.len() + b] = count((*code1, *code2));
+                }
+            }
+            dense
+        };
+        Self {
+            scoring,
+            key_scores,
+            intuitions,
+            numbers: {
+                let mut numbers = vec![
+                    usize::MAX;
+                    codes
+                        .iter()
+                        .map(|code| *code as usize + 1)
+                        .max()
+                        .unwrap_or(0)
+                ];
+                for (a, code) in codes.iter().enumerate() {
+                    numbers[*code as usize] = a;
+                }
+                numbers
+            },
+            presses: codes
+                .iter()
+                .map(|code| stats.individual_key_counts.get(code).copied().unwrap_or(0) as f64)
+                .collect(),
+            pair_terms: [
+                (pairs(&|pair| stats.key_bigram_weight(pair)), bigram_scores),
+                (
+                    pairs(&|pair| count(&stats.correction_bigram_counts, pair)),
+                    correction_scores,
+                ),
+                (
+                    pairs(&|pair| count(&stats.confusion_counts, pair)),
+                    confusion_scores,
+                ),
+            ]
+            .into_iter()
+            .filter(|(counts, _)| counts.iter().any(|count| *count != 0.0))
+            .collect(),
+            stats,
+            keymap_config,
+        }
+    }
+
+    /// Swaps the keys at `i` and `j` of `layout` and returns how much that
+    /// changed its score.
+    pub fn swap(&self, layout: &mut Layout, i: usize, j: usize) -> f64 {
+        if i == j {
+            return 0.0;
+        }
+        let mut numbers: Vec<usize> = layout
+            .keys()
+            .iter()
+            .map(|key| self.numbers[key.keycode(false) as usize])
+            .collect();
+        let before = self.local_score(layout, &numbers, i, j);
+        layout.swap(i, j);
+        numbers.swap(i, j);
+        self.local_score(layout, &numbers, i, j) - before
+    }
+
+    /// The terms of the score that change when the keys at `i` and `j`
+    /// swap places, with `numbers` the number of the key at every
+    /// position.
+    fn local_score(&self, layout: &Layout, numbers: &[usize], i: usize, j: usize) -> f64 {
+        let mut score = self.key_scores[i] * self.presses[numbers[i]]
+            + self.key_scores[j] * self.presses[numbers[j]];
+        let n = numbers.len();
+        for (counts, scores) in &self.pair_terms {
+            for k in 0..n {
+                for moved in [i, j] {
+                    score += counts[numbers[moved] * n + numbers[k]] * scores[moved * n + k];
+                    if k != i && k != j {
+                        score += counts[numbers[k] * n + numbers[moved]] * scores[k * n + moved];
+                    }
+                }
+            }
+        }
+
+        let scoring = self.scoring;
+        let moved = [layout.keys()[i], layout.keys()[j]];
+        let satisfied = self
+            .intuitions
+            .iter()
+            .filter(|(_, keys)| keys.iter().any(|key| moved.contains(key)))
+            .filter(|(intuition, _)| intuition.satisfied(layout, &self.keymap_config))
+            .count();
+        score += scoring.weights.intuition_weight * satisfied as f64;
+        if !scoring.confusable_pairs.is_empty() {
+            score += scoring.confusable_weight
+                * confusable_separation_score(
+                    layout,
+                    &self.keymap_config,
+                    &scoring.confusable_pairs,
+                );
+        }
+        // Only moving a key between the thumbs of either hand or the other
+        // fingers changes which chords hold two keys with one thumb
+        let physical = self.keymap_config.keys.keys();
+        let thumb = |k: usize| {
+            let finger = physical[k].finger;
+            (finger.finger == FingerKind::Thumb).then_some(finger.hand)
+        };
+        if scoring.thumb_conflict_weight != 0.0 && thumb(i) != thumb(j) {
+            score -= scoring.thumb_conflict_weight
+                * thumb_load(layout, &self.stats, &self.keymap_config).hold_conflicts as f64;
+        }
+        if !scoring.home_row_mods.is_empty() {
+            score -= scoring.home_row_mod_weight
+                * home_row_mods(
+                    layout,
+                    &self.stats,
+                    &self.keymap_config,
+                    &scoring.home_row_mods,
+                )
+                .cost;
+        }
+        score
+    }
+}
+
+impl SwapObjective for SwapScorer<'_> {
+    fn score(&self, layout: &Layout) -> f64 {
+        layout_score_with(layout, &self.stats, &self.keymap_config, self.scoring)
+    }
+
+    fn score_swap(&self, layout: &mut Layout, score: f64, i: usize, j: usize) -> f64 {
+        score + self.swap(layout, i, j)
+    }
+}
+
+/// The count of `pair` in `counts`, or 0.
+fn count(counts: &HashMap<(Keycode, Keycode), u64>, pair: (Keycode, Keycode)) -> f64 {
+    counts.get(&pair).copied().unwrap_or(0) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+    use device_query::Keycode;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_swap_scorer() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut layout = Layout::from_keymap(&config);
+        let codes: Vec<Keycode> = layout.keys().iter().map(|key| key.keycode(false)).collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut stats = Stats::new();
+        for _ in 0..500 {
+            let a = codes[rng.gen_range(0..codes.len())];
+            let b = codes[rng.gen_range(0..codes.len())];
+            *stats.individual_key_counts.entry(a).or_insert(0) += 3;
+            *stats.consecutive_key_counts.entry((a, b)).or_insert(0) += 2;
+        }
+        stats.confusion_counts.insert((Keycode::E, Keycode::R), 4);
+        stats
+            .correction_bigram_counts
+            .insert((Keycode::T, Keycode::H), 5);
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LShift, Keycode::Space], 6);
+        let scoring = ScoringConfig {
+            travel_weight: 0.5,
+            correction_weight: 2.0,
+            confusable_pairs: vec![('b', 'v')],
+            home_row_mods: vec![Keycode::LShift],
+            ..ScoringConfig::default()
+        };
+
+        let scorer = SwapScorer::new(Arc::new(stats), Arc::new(config), &scoring);
+        let mut score = scorer.score(&layout);
+        for _ in 0..200 {
+            let i = rng.gen_range(0..codes.len());
+            let j = rng.gen_range(0..codes.len());
+            score = scorer.score_swap(&mut layout, score, i, j);
+            let rescored = scorer.score(&layout);
+            assert!((score - rescored).abs() < 1e-6, "{score} != {rescored}");
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Checking that the `keylogger` can see key presses before it starts, so a
+//! missing permission doesn't leave it silently logging nothing.
+//!
+//! [`Environment::detect`] gathers what matters on the current platform,
+//! and [`diagnose`] turns it into [`Problem`]s with steps to fix them.
+//! Keeping the two apart lets the checks be tested on any platform.
+
+use std::fmt;
+
+use crate::capture::Backend;
+
+/// The platforms the keylogger captures key presses on differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Linux,
+    MacOs,
+    Windows,
+    Other,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else {
+            Platform::Other
+        }
+    }
+}
+
+/// What decides whether key presses can be captured.  Checks that don't
+/// apply to the platform, or that couldn't be made, are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Environment {
+    pub platform: Platform,
+    /// Where the keylogger reads key presses from.
+    pub backend: Backend,
+    /// Whether `DISPLAY` is set.  Key presses are read from the X server
+    /// on Linux.
+    pub x11_display: bool,
+    /// Whether the session is a Wayland session, which only shares key
+    /// presses with X11 apps running through XWayland.
+    pub wayland: bool,
+    /// Whether the process is root or in the `input` group, which reading
+    /// keyboards directly, like `trial --remap` does, requires.
+    pub input_group: Option<bool>,
+    /// Whether macOS trusts the process with Accessibility, without which
+    /// no key presses are reported at all.
+    pub accessibility: Option<bool>,
+    /// Whether the process runs as administrator on Windows.  Key presses
+    /// in elevated windows are hidden from processes that aren't.
+    pub elevated: Option<bool>,
+}
+
+impl Environment {
+    pub fn detect(backend: Backend) -> Self {
+        let platform = Platform::current();
+        let var = |name: &str| std::env::var(name).is_ok_and(|value| !value.is_empty());
+        let linux = platform == Platform::Linux;
+        Self {
+            platform,
+            backend,
+            x11_display: var("DISPLAY"),
+            wayland: var("WAYLAND_DISPLAY")
+                || std::env::var("XDG_SESSION_TYPE").is_ok_and(|value| value == "wayland"),
+            input_group: if linux { detect_input_group() } else { None },
+            accessibility: detect_accessibility(),
+            elevated: if platform == Platform::Windows {
+                detect_elevated()
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// How badly a problem affects logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Some key presses are missed.
+    Warning,
+    /// Nothing is captured until a permission is granted, which can be
+    /// done while the keylogger waits.
+    Blocking,
+    /// Nothing can be captured, and that won't change without restarting
+    /// the keylogger somewhere else.
+    Fatal,
+}
+
+/// Something that keeps key presses from being captured, and how to fix
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub summary: String,
+    /// Steps to take, in order.
+    pub remediation: Vec<String>,
+}
+
+impl Problem {
+    fn new(severity: Severity, summary: &str, remediation: &[&str]) -> Self {
+        Self {
+            severity,
+            summary: summary.to_string(),
+            remediation: remediation.iter().map(|step| step.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Warning => "Warning",
+            Severity::Blocking => "Waiting",
+            Severity::Fatal => "Error",
+        };
+        writeln!(f, "{}: {}", label, self.summary)?;
+        for (i, step) in self.remediation.iter().enumerate() {
+            writeln!(f, "  {}. {}", i + 1, step)?;
+        }
+        Ok(())
+    }
+}
+
+/// The problems of `env`, worst first.
+pub fn diagnose(env: &Environment) -> Vec<Problem> {
+    let mut problems = vec![];
+    match env.platform {
+        Platform::Linux if env.backend == Backend::Evdev => {
+            if env.input_group == Some(false) {
+                problems.push(Problem::new(
+                    Severity::Fatal,
+                    "you aren't in the input group, which reading the keyboards through evdev needs",
+                    &[
+                        "Run `sudo usermod -aG input $USER`",
+                        "Log out and back in for the group to apply",
+                        "Or start the keylogger without `--backend evdev`",
+                    ],
+                ));
+            }
+        }
+        Platform::Linux => {
+            if !env.x11_display && env.wayland {
+                problems.push(Problem::new(
+                    Severity::Fatal,
+                    "this is a Wayland session without XWayland, so key presses can't be read",
+                    &[
+                        "Log out and pick an X11 session, like \"Ubuntu on Xorg\", on the login screen",
+                        "Or enable XWayland in your compositor and start the keylogger again",
+                        "Or start the keylogger with `--backend evdev` to read the keyboards directly",
+                    ],
+                ));
+            } else if !env.x11_display {
+                problems.push(Problem::new(
+                    Severity::Fatal,
+                    "DISPLAY isn't set, so there's no X server to read key presses from",
+                    &[
+                        "Start the keylogger from a terminal in your graphical session",
+                        "Or set DISPLAY to your session's display, e.g. `DISPLAY=:0`",
+                    ],
+                ));
+            } else if env.wayland {
+                problems.push(Problem::new(
+                    Severity::Warning,
+                    "Wayland only shares key presses with X11 apps, so keys typed into native Wayland windows are missed",
+                    &[
+                        "Start the keylogger with `--backend evdev` to read the keyboards directly",
+                        "Or log out and pick an X11 session on the login screen",
+                    ],
+                ));
+            }
+            if env.input_group == Some(false) {
+                problems.push(Problem::new(
+                    Severity::Warning,
+                    "you aren't in the input group, which `trial --remap` needs to read the keyboard directly",
+                    &[
+                        "Run `sudo usermod -aG input $USER`",
+                        "Log out and back in for the group to apply",
+                    ],
+                ));
+            }
+        }
+        Platform::MacOs => {
+            if env.accessibility == Some(false) {
+                problems.push(Problem::new(
+                    Severity::Blocking,
+                    "the terminal running the keylogger isn't allowed to read key presses",
+                    &[
+                        "Open System Settings > Privacy & Security > Accessibility",
+                        "Turn on the terminal app you start the keylogger from, adding it with + if it's missing",
+                        "Do the same under Privacy & Security > Input Monitoring",
+                        "If nothing changes after a while, restart the terminal and the keylogger",
+                    ],
+                ));
+            }
+        }
+        Platform::Windows => {
+            if env.elevated == Some(false) {
+                problems.push(Problem::new(
+                    Severity::Warning,
+                    "key presses in windows running as administrator are missed",
+                    &["To log those too, start the keylogger from a terminal opened with \"Run as administrator\""],
+                ));
+            }
+        }
+        Platform::Other => {}
+    }
+    problems.sort_by_key(|problem| std::cmp::Reverse(problem.severity));
+    problems
+}
+
+/// The worst severity of `problems`, if there are any.
+pub fn worst(problems: &[Problem]) -> Option<Severity> {
+    problems.iter().map(|problem| problem.severity).max()
+}
+
+fn detect_input_group() -> Option<bool> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let groups = std::fs::read_to_string("/etc/group").ok()?;
+    Some(in_input_group(&status, &groups))
+}
+
+/// Whether the process described by `status`, in the format of
+/// `/proc/self/status`, is root or in the `input` group of `groups`, in the
+/// format of `/etc/group`.
+fn in_input_group(status: &str, groups: &str) -> bool {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(|value| value.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    if field("Uid:").first() == Some(&"0") {
+        return true;
+    }
+    let input_gid = groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next() != Some("input") {
+            return None;
+        }
+        fields.nth(1)
+    });
+    match input_gid {
+        Some(gid) => field("Groups:").contains(&gid),
+        None => false,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn detect_accessibility() -> Option<bool> {
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> bool;
+    }
+    // Safe to call from any thread, it only reads the permission
+    Some(unsafe { AXIsProcessTrusted() })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn detect_accessibility() -> Option<bool> {
+    None
+}
+
+/// `net session` only succeeds as administrator, which saves binding to
+/// the Windows security APIs for a single check.
+fn detect_elevated() -> Option<bool> {
+    std::process::Command::new("net")
+        .arg("session")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .ok()
+        .map(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose() {
+        let env = Environment {
+            platform: Platform::Linux,
+            backend: Backend::DeviceQuery,
+            x11_display: true,
+            wayland: false,
+            input_group: Some(true),
+            accessibility: None,
+            elevated: None,
+        };
+        assert!(diagnose(&env).is_empty());
+
+        let wayland = Environment {
+            x11_display: false,
+            wayland: true,
+            input_group: Some(false),
+            ..env.clone()
+        };
+        let problems = diagnose(&wayland);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(worst(&problems), Some(Severity::Fatal));
+        assert_eq!(problems[0].severity, Severity::Fatal);
+        let evdev = Environment {
+            backend: Backend::Evdev,
+            ..wayland.clone()
+        };
+        assert_eq!(worst(&diagnose(&evdev)), Some(Severity::Fatal));
+        let evdev = Environment {
+            input_group: Some(true),
+            ..evdev
+        };
+        assert!(diagnose(&evdev).is_empty());
+
+        let mac = Environment {
+            platform: Platform::MacOs,
+            accessibility: Some(false),
+            ..env
+        };
+        assert_eq!(worst(&diagnose(&mac)), Some(Severity::Blocking));
+
+        let groups = "root:x:0:\ninput:x:104:\nagent:x:1000:\n";
+        assert!(in_input_group(
+            "Uid:\t1000\t1000\t1000\t1000\nGroups:\t104 1000\n",
+            groups
+        ));
+        assert!(!in_input_group(
+            "Uid:\t1000\t1000\t1000\t1000\nGroups:\t1000\n",
+            groups
+        ));
+        assert!(in_input_group("Uid:\t0\t0\t0\t0\nGroups:\t0\n", groups));
+    }
+}
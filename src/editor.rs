@@ -0,0 +1,240 @@
+//! Profiles for users of modal and chord-based editors, whose command keys
+//! matter more than their share of the typing suggests.
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layout::Key,
+    scoring::{above, and, close, key, left_of, same_column, same_hand, same_row, Intuition},
+    stats::Stats,
+};
+
+/// Command presses per thousand key presses above which an editor counts as
+/// used.
+const DETECTION_THRESHOLD: f64 = 5.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditorProfile {
+    #[default]
+    None,
+    /// Pick a profile with [`EditorProfile::detect`].
+    Auto,
+    Vim,
+    Emacs,
+}
+
+impl EditorProfile {
+    /// Guesses the editor from its command patterns.  The key log doesn't
+    /// record which application had focus, so Vim is recognized by
+    /// `Escape` and `:w`/`:q`, and Emacs by `Ctrl` chords like `C-x`.
+    pub fn detect(stats: &Stats) -> Self {
+        let total: u64 = stats.individual_key_counts.values().sum();
+        if total == 0 {
+            return EditorProfile::None;
+        }
+
+        let vim = stats
+            .individual_key_counts
+            .get(&Keycode::Escape)
+            .copied()
+            .unwrap_or(0)
+            + [(':', 'w'), (':', 'q')]
+                .iter()
+                .map(|pair| {
+                    stats
+                        .consecutive_char_counts
+                        .get(pair)
+                        .copied()
+                        .unwrap_or(0)
+                })
+                .sum::<u64>();
+        let emacs: u64 = stats
+            .simultaneous_key_counts
+            .iter()
+            .filter(|(keys, _)| {
+                let ctrl = keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl);
+                ctrl && keys.iter().any(|k| EMACS_CHORD_KEYS.contains(k))
+            })
+            .map(|(_, count)| count)
+            .sum();
+
+        let per_thousand = |count: u64| count as f64 * 1000.0 / total as f64;
+        let (profile, count) = if vim >= emacs {
+            (EditorProfile::Vim, vim)
+        } else {
+            (EditorProfile::Emacs, emacs)
+        };
+        if per_thousand(count) >= DETECTION_THRESHOLD {
+            profile
+        } else {
+            EditorProfile::None
+        }
+    }
+
+    /// Replaces [`EditorProfile::Auto`] with the detected profile.
+    pub fn resolve(self, stats: &Stats) -> Self {
+        match self {
+            EditorProfile::Auto => Self::detect(stats),
+            profile => profile,
+        }
+    }
+
+    /// The keys used for editor commands.
+    pub fn command_keys(&self) -> &'static [Keycode] {
+        match self {
+            EditorProfile::None | EditorProfile::Auto => &[],
+            EditorProfile::Vim => &VIM_COMMAND_KEYS,
+            EditorProfile::Emacs => &EMACS_COMMAND_KEYS,
+        }
+    }
+
+    /// Multiplies the counts of the command keys by `weight`, so their
+    /// placement counts for more than their share of the typing.
+    pub fn weight_stats(&self, stats: &mut Stats, weight: f64) {
+        for code in self.command_keys() {
+            if let Some(count) = stats.individual_key_counts.get_mut(code) {
+                *count = (*count as f64 * weight).round() as u64;
+            }
+        }
+    }
+
+    /// Placements that make the editor's commands easy to remember, scored
+    /// on top of [`crate::scoring::intuitions`].
+    pub fn intuitions(&self) -> Vec<Intuition> {
+        match self {
+            EditorProfile::None | EditorProfile::Auto => vec![],
+            EditorProfile::Vim => {
+                let (h, j, k, l) = (key('h'), key('j'), key('k'), key('l'));
+                vec![
+                    and(same_row(h, j), left_of(h, j)),
+                    and(same_row(j, k), left_of(j, k)),
+                    and(same_row(k, l), left_of(k, l)),
+                    same_hand(h, l),
+                    close(h, j),
+                    close(j, k),
+                    close(k, l),
+                ]
+            }
+            EditorProfile::Emacs => {
+                let (b, f, n, p) = (key('b'), key('f'), key('n'), key('p'));
+                vec![
+                    and(same_row(b, f), left_of(b, f)),
+                    and(same_column(p, n), above(p, n)),
+                    same_hand(Key::LCtrl, key('x')),
+                ]
+            }
+        }
+    }
+}
+
+const VIM_COMMAND_KEYS: [Keycode; 10] = [
+    Keycode::H,
+    Keycode::J,
+    Keycode::K,
+    Keycode::L,
+    Keycode::W,
+    Keycode::B,
+    Keycode::Semicolon,
+    Keycode::Slash,
+    Keycode::Escape,
+    Keycode::Dot,
+];
+
+const EMACS_CHORD_KEYS: [Keycode; 8] = [
+    Keycode::X,
+    Keycode::F,
+    Keycode::B,
+    Keycode::N,
+    Keycode::P,
+    Keycode::A,
+    Keycode::E,
+    Keycode::G,
+];
+
+const EMACS_COMMAND_KEYS: [Keycode; 11] = [
+    Keycode::LControl,
+    Keycode::RControl,
+    Keycode::LAlt,
+    Keycode::X,
+    Keycode::F,
+    Keycode::B,
+    Keycode::N,
+    Keycode::P,
+    Keycode::A,
+    Keycode::E,
+    Keycode::G,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats of 1000 presses of plain typing.
+    fn prose() -> Stats {
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::E, 600);
+        stats.individual_key_counts.insert(Keycode::T, 400);
+        stats
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(EditorProfile::detect(&Stats::new()), EditorProfile::None);
+        assert_eq!(EditorProfile::detect(&prose()), EditorProfile::None);
+
+        // Escape and :w count for Vim, but a few aren't enough
+        let mut vim = prose();
+        vim.individual_key_counts.insert(Keycode::Escape, 2);
+        vim.consecutive_char_counts.insert((':', 'w'), 2);
+        assert_eq!(EditorProfile::detect(&vim), EditorProfile::None);
+        vim.consecutive_char_counts.insert((':', 'q'), 2);
+        assert_eq!(EditorProfile::detect(&vim), EditorProfile::Vim);
+
+        let mut emacs = prose();
+        emacs
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LControl, Keycode::X], 4);
+        emacs
+            .simultaneous_key_counts
+            .insert(vec![Keycode::RControl, Keycode::F], 3);
+        // Chords without Control, or with keys that aren't Emacs commands,
+        // don't count
+        emacs
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LShift, Keycode::X], 100);
+        emacs
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LControl, Keycode::C], 100);
+        assert_eq!(EditorProfile::detect(&emacs), EditorProfile::Emacs);
+        assert_eq!(EditorProfile::Auto.resolve(&emacs), EditorProfile::Emacs);
+        assert_eq!(EditorProfile::Vim.resolve(&emacs), EditorProfile::Vim);
+
+        // The editor with more commands wins
+        emacs.individual_key_counts.insert(Keycode::Escape, 8);
+        assert_eq!(EditorProfile::detect(&emacs), EditorProfile::Vim);
+    }
+
+    #[test]
+    fn test_weight_stats() {
+        let mut stats = prose();
+        stats.individual_key_counts.insert(Keycode::J, 5);
+        stats.individual_key_counts.insert(Keycode::X, 3);
+        EditorProfile::Vim.weight_stats(&mut stats, 2.5);
+        // Only the command keys that were pressed change
+        assert_eq!(stats.individual_key_counts[&Keycode::J], 13);
+        assert_eq!(stats.individual_key_counts[&Keycode::X], 3);
+        assert_eq!(stats.individual_key_counts[&Keycode::E], 600);
+        assert!(!stats.individual_key_counts.contains_key(&Keycode::H));
+
+        EditorProfile::Emacs.weight_stats(&mut stats, 2.0);
+        assert_eq!(stats.individual_key_counts[&Keycode::X], 6);
+        assert_eq!(stats.individual_key_counts[&Keycode::E], 1200);
+
+        let before = stats.individual_key_counts.clone();
+        EditorProfile::None.weight_stats(&mut stats, 2.0);
+        EditorProfile::Auto.weight_stats(&mut stats, 2.0);
+        assert_eq!(stats.individual_key_counts, before);
+    }
+}
@@ -0,0 +1,100 @@
+//! The error returned when a key log, saved stats or a layout file can't be
+//! read.
+
+use std::fmt::{self, Display};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// A line that couldn't be parsed.
+    Parse {
+        /// The number of the line, counting from 1, if known.
+        line: Option<usize>,
+        content: String,
+        message: String,
+    },
+    /// Something missing from an otherwise well-formed file, like a layout
+    /// without a key for every physical key.
+    Invalid(String),
+    /// An error in the file at `path`.
+    File {
+        path: String,
+        error: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn parse(content: &str, message: impl Into<String>) -> Self {
+        Error::Parse {
+            line: None,
+            content: content.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Sets the line number of a parse error, counting from 0 like
+    /// `enumerate` does.
+    pub(crate) fn at_line(self, index: usize) -> Self {
+        match self {
+            Error::Parse {
+                content, message, ..
+            } => Error::Parse {
+                line: Some(index + 1),
+                content,
+                message,
+            },
+            error => error,
+        }
+    }
+
+    pub fn in_file(self, path: &str) -> Self {
+        Error::File {
+            path: path.to_string(),
+            error: Box::new(self),
+        }
+    }
+
+    /// Prints the error and exits, for the binaries.
+    pub fn exit(self) -> ! {
+        eprintln!("Error: {self}");
+        std::process::exit(1);
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{error}"),
+            Error::Parse {
+                line,
+                content,
+                message,
+            } => {
+                if let Some(line) = line {
+                    write!(f, "line {line}: ")?;
+                }
+                write!(f, "{message}: {content:?}")
+            }
+            Error::Invalid(message) => write!(f, "{message}"),
+            Error::File { path, error } => write!(f, "{path}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Parse { .. } | Error::Invalid(_) => None,
+            Error::File { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
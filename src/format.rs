@@ -0,0 +1,1080 @@
+//! This parses a special layout file that simiplifies the definition of a keyboard layout.
+//! They look like this:
+//!
+//! ```plaintext
+//! Fingers
+//! LP: 70
+//! LR: 50
+//! LM: 80
+//! LI: 100
+//! LT: 100
+//! RT: 100
+//! RI: 100
+//! RM: 80
+//! RR: 50
+//! RP: 70
+//!
+//! Keys
+//! -----------------------------------------------------------------
+//! | = | 1 | 2 | 3 | 4 | 5 |   |   |   |   | 6 | 7 | 8 | 9 | 0 | - |
+//! |LP |LP |LR |LM |LI |LI |   |   |   |   |RI |RI |RM |RR |RP |RP |
+//! |25 |35 |45 |50 |50 |50 |   |   |   |   |50 |50 |50 |45 |35 |25 |
+//! -----------------------------------------------------------------
+//! |Tab| Q | W | E | R | T |   |   |   |   | Y | U | I | O | P | \ |
+//! |LP |LP |LR |LM |LI |LI |   |   |   |   |RI |RI |RM |RR |RP |RP |
+//! |35 |75 |75 |75 |75 |75 |   |   |   |   |75 |75 |75 |75 |75 |35 |
+//! -----------------------------------------------------------------
+//! |Esc| A | S | D | F | G |   |   |   |   | H | J | K | L | ; | ' |
+//! |LP |LP |LR |LM |LI |LI |   |   |   |   |RI |RI |RM |RR |RP |RP |
+//! |75 |100|100|100|100|100|   |   |   |   |100|100|100|100|100|75 |
+//! -----------------------------------------------------------------
+//! |LSh| Z | X | C | V | B |LCt|LAt|LMt|RCt| N | M | , | . | / |RSh|
+//! |LP |LP |LR |LM |LI |LI |LT |LT |RT |RT |RI |RI |RM |RR |RP |RP |
+//! |65 |85 |85 |85 |85 |85 |70 |50 |50 |70 |85 |85 |85 |85 |85 |65 |
+//! -----------------------------------------------------------------
+//! |   | ~ |Cap|<--|-->|Bks|Del|Hom|PUp|Etr|Spc| Up| Dn| [ | ] |   |
+//! |   |LP |LR |LM |LI |LT |LT |LT |RT |RT |RT |RI |RI |RP |RP |   |
+//! |   |50 |50 |50 |50 |100|100|70 |70 |70 |100|100|50 |50 |50 |   |
+//! -----------------------------------------------------------------
+//! |   |   |   |   |   |   |   |End|PDn|   |   |   |   |   |   |   |
+//! |   |   |   |   |   |   |   |LT |RT |   |   |   |   |   |   |   |
+//! |   |   |   |   |   |   |   |80 |80 |   |   |   |   |   |   |   |
+//! -----------------------------------------------------------------
+//! ```
+//!
+//! The first section is the finger section. It defines the score for each finger.
+//! The second section is the key section. It defines the layout of the keyboard.
+//! Each key is defined by a character, a finger, and a score.
+//! The layout is defined by a grid of keys.
+//!
+//! An optional `Pinned` section after the grid lists keys, named like in the
+//! grid, that stay where they are while optimizing:
+//!
+//! ```plaintext
+//! Pinned
+//! Z X C V 1 2 3 4 5 6 7 8 9 0
+//! ```
+//!
+//! Every key takes one square cell of the grid.  Keys that are wider, moved
+//! off their cell or turned, like the thumb clusters of split keyboards, can
+//! be described in an optional `Geometry` section so distances between keys
+//! are measured where the keys really are.  Every line names a key followed
+//! by how far it's moved right (`x`) and down (`y`) in key widths, its width
+//! (`w`), how many degrees it's turned clockwise (`r`) about the center of
+//! its cell and how far it sits below the keys around it in a keywell (`z`),
+//! all optional:
+//!
+//! ```plaintext
+//! Geometry
+//! Bks y=0.25 r=15
+//! Spc w=1.5 r=-15
+//! ```
+//!
+//! Column-staggered and split keyboards can move whole columns instead, with
+//! a `Stagger` line listing how far every column is moved down, from the
+//! left, and a `Split` line with how many columns the left half has and how
+//! far the right half is moved right:
+//!
+//! ```plaintext
+//! Geometry
+//! Stagger 0.5 0.5 0.125 0 0.125 0.25
+//! Split after=6 gap=3
+//! ```
+//!
+//! An optional `Home` section lists the key every finger rests on, named
+//! like in the grid.  Fingers without one rest on their best scored key:
+//!
+//! ```plaintext
+//! Home
+//! A S D F J K L ; Spc Bks
+//! ```
+//!
+//! This layout makes it easy to define a layout for a keyboard without having to
+//! consider the position of each key while writing something like JSON.
+
+use crate::{
+    error::{self, Error},
+    kle::finger_name,
+    layout::{Key, Layer, Layout},
+    scoring::KeyPairs,
+    ColumnGeometry, Finger, FingerConfig, FingerKind, Hand, KeyGeometry, KeymapConfig, PhysicalKey,
+    PhysicalKeyboard, Split,
+};
+use device_query::Keycode;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+};
+
+macro_rules! enum_strings {
+    ($type:ty,$($variant:ident:$str:literal),*) => {
+        paste::paste! {
+            #[allow(unreachable_patterns)]
+            pub fn [<map_ $type:lower _to_str>] (variant: $type) -> Option<&'static str> {
+                match variant {
+                    $($type::$variant => Some($str),)*
+                    _ => None,
+                }
+            }
+
+            pub(crate) fn [<map_str_to_ $type:lower>](s: &str) -> Option<$type> {
+                match s {
+                    $($str => Some(<$type>::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+enum_strings! {
+    Keycode,
+    A: "A",
+    B: "B",
+    C: "C",
+    D: "D",
+    E: "E",
+    F: "F",
+    G: "G",
+    H: "H",
+    I: "I",
+    J: "J",
+    K: "K",
+    L: "L",
+    M: "M",
+    N: "N",
+    O: "O",
+    P: "P",
+    Q: "Q",
+    R: "R",
+    S: "S",
+    T: "T",
+    U: "U",
+    V: "V",
+    W: "W",
+    X: "X",
+    Y: "Y",
+    Z: "Z",
+    Key1: "1",
+    Key2: "2",
+    Key3: "3",
+    Key4: "4",
+    Key5: "5",
+    Key6: "6",
+    Key7: "7",
+    Key8: "8",
+    Key9: "9",
+    Key0: "0",
+    Grave: "~",
+    Tab: "Tab",
+    Escape: "Esc",
+    LShift: "LSh",
+    LControl: "LCt",
+    LAlt: "LAt",
+    LMeta: "LMt",
+    RShift: "RSh",
+    RControl: "RCt",
+    RAlt: "RAt",
+    RMeta: "RMt",
+    CapsLock: "Cap",
+    Left: "<--",
+    Right: "-->",
+    Backspace: "Bks",
+    Delete: "Del",
+    Home: "Hom",
+    End: "End",
+    PageDown: "PDn",
+    PageUp: "PUp",
+    Enter: "Etr",
+    Space: "Spc",
+    Up: "Up",
+    Down: "Dn",
+    LeftBracket: "[",
+    RightBracket: "]",
+    Comma: ",",
+    Dot: ".",
+    Slash: "/",
+    Semicolon: ";",
+    Apostrophe: "'",
+    BackSlash: "\\",
+    Minus: "-",
+    Equal: "="
+}
+
+enum_strings! {
+    FingerKind,
+    Pinky: "P",
+    Ring: "R",
+    Middle: "M",
+    Index: "I",
+    Thumb: "T"
+}
+
+enum_strings! {
+    Hand,
+    Left: "L",
+    Right: "R"
+}
+
+pub fn parse_keymap_config(layout: &str) -> error::Result<KeymapConfig> {
+    let mut lines = layout.lines().enumerate();
+    let fingers = parser_fingers(&mut lines)?;
+    let keys = parser_keys(&mut lines)?;
+    let mut config = KeymapConfig {
+        fingers,
+        keys,
+        pins: HashMap::new(),
+        columns: ColumnGeometry::default(),
+        home: HashMap::new(),
+        pairs: KeyPairs::default(),
+    };
+    parse_sections(&mut lines, &mut config)?;
+    Ok(config)
+}
+
+/// Reads the optional sections after the grid, `Geometry`, `Home` and
+/// `Pinned`, in any order.
+fn parse_sections(lines: &mut NumberedLines, config: &mut KeymapConfig) -> error::Result<()> {
+    let mut section = None;
+    for (index, line) in lines {
+        match line.trim() {
+            "" => continue,
+            "Geometry" | "Home" | "Pinned" => {
+                section = Some(line.trim());
+                continue;
+            }
+            _ => {}
+        }
+        match section {
+            Some("Geometry") => {
+                parse_geometry(line, config).map_err(|error| error.at_line(index))?
+            }
+            Some("Home") => parse_home(line, config).map_err(|error| error.at_line(index))?,
+            Some(_) => parse_pins(line, config).map_err(|error| error.at_line(index))?,
+            None => {
+                return Err(Error::parse(
+                    line,
+                    "expected `Geometry`, `Home` or `Pinned` after the keys",
+                )
+                .at_line(index))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The index of the key of the keyboard named `name`, like in the grid.
+fn key_index(name: &str, config: &KeymapConfig) -> error::Result<usize> {
+    map_str_to_keycode(name)
+        .and_then(|code| config.keys.keys().iter().position(|key| key.code == code))
+        .ok_or_else(|| Error::parse(name, "not a key of the keyboard"))
+}
+
+/// Makes the keys listed on a line of the `Home` section the home keys of
+/// the fingers that press them.
+fn parse_home(line: &str, config: &mut KeymapConfig) -> error::Result<()> {
+    for name in line.split_whitespace() {
+        let i = key_index(name, config)?;
+        let finger = config.keys.keys()[i].finger;
+        if let Some(&other) = config.home.get(&finger).filter(|&&other| other != i) {
+            return Err(Error::parse(
+                name,
+                format!(
+                    "{} already rests on {}",
+                    finger_name(finger),
+                    map_keycode_to_str(config.keys.keys()[other].code).unwrap_or_default()
+                ),
+            ));
+        }
+        config.home.insert(finger, i);
+    }
+    Ok(())
+}
+
+/// Pins the keys listed on a line of the `Pinned` section to where they are
+/// on the keyboard.
+fn parse_pins(line: &str, config: &mut KeymapConfig) -> error::Result<()> {
+    for name in line.split_whitespace() {
+        let i = key_index(name, config)?;
+        let key = Key::from_keycode(config.keys.keys()[i].code);
+        config
+            .pin(i, key)
+            .map_err(|error| Error::parse(name, error.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Sets the geometry of a key from a line of the `Geometry` section, like
+/// `Spc w=2 x=0.5 r=15 z=0.5`, or of the columns, like `Stagger 0 0.25 0.5` or
+/// `Split after=6 gap=2`.
+fn parse_geometry(line: &str, config: &mut KeymapConfig) -> error::Result<()> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    match name {
+        "Stagger" => {
+            let stagger = words
+                .map(|word| parse_number(word).ok_or_else(|| Error::parse(word, "invalid offset")))
+                .collect::<error::Result<_>>()?;
+            let columns = ColumnGeometry {
+                stagger,
+                ..config.columns.clone()
+            };
+            config.set_columns(columns);
+        }
+        "Split" => {
+            let mut split = Split { after: 0, gap: 0.0 };
+            for word in words {
+                let parsed = word.split_once('=').and_then(|(property, value)| {
+                    match property {
+                        "after" => split.after = value.parse().ok()?,
+                        "gap" => split.gap = parse_number(value)?,
+                        _ => return None,
+                    }
+                    Some(())
+                });
+                if parsed.is_none() {
+                    return Err(Error::parse(
+                        word,
+                        "expected `after=` followed by a column or `gap=` followed by a number",
+                    ));
+                }
+            }
+            if split.after == 0 {
+                return Err(Error::parse(
+                    line,
+                    "expected `after=` and the columns of the left half",
+                ));
+            }
+            let columns = ColumnGeometry {
+                split: Some(split),
+                ..config.columns.clone()
+            };
+            config.set_columns(columns);
+        }
+        name => {
+            let i = key_index(name, config)?;
+            let mut geometry = KeyGeometry::default();
+            for word in words {
+                let parsed = word.split_once('=').and_then(|(property, value)| {
+                    let value = parse_number(value)?;
+                    match property {
+                        "x" => geometry.offset.0 = value,
+                        "y" => geometry.offset.1 = value,
+                        "w" if value > 0.0 => geometry.width = value,
+                        "r" => geometry.rotation = value,
+                        "z" => geometry.depth = value,
+                        _ => return None,
+                    }
+                    Some(())
+                });
+                if parsed.is_none() {
+                    return Err(Error::parse(
+                        word,
+                        "expected `x=`, `y=`, `w=`, `r=` or `z=` followed by a number",
+                    ));
+                }
+            }
+            config.keys.0[i].geometry = geometry;
+        }
+    }
+    Ok(())
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// Lines of a file along with their index, for reporting line numbers.
+type NumberedLines<'a> = std::iter::Enumerate<std::str::Lines<'a>>;
+
+fn parser_fingers(lines: &mut NumberedLines) -> error::Result<Vec<FingerConfig>> {
+    let mut fingers = vec![];
+    let mut lines = lines.skip_while(|(_, line)| line.trim() != "Fingers");
+    let _ = lines.next();
+    for (index, line) in lines {
+        let line = line.trim();
+        if line == "Keys" {
+            break;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (finger, score) = line
+            .split_once(":")
+            .ok_or_else(|| Error::parse(line, "expected `<finger>: <score>`").at_line(index))?;
+        let finger = finger.trim();
+        let finger = parse_finger(finger).map_err(|error| error.at_line(index))?;
+        let score = score.trim();
+        let score = parse_score(score).map_err(|error| error.at_line(index))?;
+        fingers.push(FingerConfig { finger, score });
+    }
+    Ok(fingers)
+}
+
+fn parse_score(score: &str) -> error::Result<f64> {
+    let score = score
+        .parse::<f64>()
+        .map_err(|_| Error::parse(score, "invalid score"))?;
+    Ok(score / 100.0)
+}
+
+pub(crate) fn parse_finger(s: &str) -> error::Result<Finger> {
+    let hand = s.get(..1).and_then(|hand| map_str_to_hand(hand.trim()));
+    let finger = s
+        .get(1..)
+        .and_then(|finger| map_str_to_fingerkind(finger.trim()));
+    match (hand, finger) {
+        (Some(hand), Some(finger)) => Ok(Finger { hand, finger }),
+        _ => Err(Error::parse(s, "unknown finger")),
+    }
+}
+
+fn parser_keys(lines: &mut NumberedLines) -> error::Result<PhysicalKeyboard> {
+    let mut keys = PhysicalKeyboard::new();
+    let mut r = 0;
+    while let Some(row) = parse_row(lines)? {
+        for (key, c) in row {
+            let Some((code, finger, score)) = key else {
+                continue;
+            };
+            keys.add_key(PhysicalKey {
+                code,
+                finger,
+                score,
+                position: (c as f64, r as f64),
+                geometry: KeyGeometry::default(),
+                column_offset: (0.0, 0.0),
+            });
+        }
+        r += 1;
+    }
+    Ok(keys)
+}
+
+type Row = Vec<(Option<(Keycode, Finger, f64)>, u8)>;
+
+/// Parses the next row of keys, or returns `None` at the end of the grid.
+fn parse_row(lines: &mut NumberedLines) -> error::Result<Option<Row>> {
+    match lines.next() {
+        Some((index, line)) if !line.trim().is_empty() => {
+            parse_hr(line).map_err(|error| error.at_line(index))?
+        }
+        _ => return Ok(None),
+    }
+    let Some((codes_index, codes_line)) = lines.next().filter(|(_, line)| !line.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let codes = parse_keycodes(codes_line).map_err(|error| error.at_line(codes_index))?;
+    let (fingers_index, fingers_line) = next_row_line(lines, codes_index, codes_line)?;
+    let fingers = parse_key_fingers(fingers_line).map_err(|error| error.at_line(fingers_index))?;
+    let (scores_index, scores_line) = next_row_line(lines, fingers_index, fingers_line)?;
+    let scores = parse_key_scores(scores_line).map_err(|error| error.at_line(scores_index))?;
+
+    for (index, line, len) in [
+        (fingers_index, fingers_line, fingers.len()),
+        (scores_index, scores_line, scores.len()),
+    ] {
+        if len != codes.len() {
+            return Err(Error::parse(
+                line,
+                format!(
+                    "expected {} keys like the row above, found {len}",
+                    codes.len()
+                ),
+            )
+            .at_line(index));
+        }
+    }
+
+    let mut row = vec![];
+
+    for (col, (key, (finger, score))) in codes
+        .into_iter()
+        .zip(fingers.into_iter().zip(scores))
+        .enumerate()
+    {
+        match (key, finger, score) {
+            (None, _, _) => row.push((None, col as u8)),
+            (Some(key), Some(finger), Some(score)) => {
+                row.push((Some((key, finger, score)), col as u8))
+            }
+            (Some(key), None, _) => {
+                return Err(
+                    Error::parse(fingers_line, format!("missing finger for {key}"))
+                        .at_line(fingers_index),
+                )
+            }
+            (Some(key), _, None) => {
+                return Err(
+                    Error::parse(scores_line, format!("missing score for {key}"))
+                        .at_line(scores_index),
+                )
+            }
+        }
+    }
+
+    Ok(Some(row))
+}
+
+/// The line after `line` at `index`, which must be there to finish a row.
+fn next_row_line<'a>(
+    lines: &mut NumberedLines<'a>,
+    index: usize,
+    line: &str,
+) -> error::Result<(usize, &'a str)> {
+    lines
+        .next()
+        .ok_or_else(|| Error::parse(line, "row ends early").at_line(index))
+}
+
+fn parse_hr(line: &str) -> error::Result<()> {
+    if !line.starts_with("-") {
+        return Err(Error::parse(line, "expected a line of `-` between rows"));
+    }
+    Ok(())
+}
+
+fn parse_keycodes(line: &str) -> error::Result<Vec<Option<Keycode>>> {
+    parse_pipe_separated(line)?
+        .map(|s| {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                map_str_to_keycode(s)
+                    .map(Some)
+                    .ok_or_else(|| Error::parse(s, "unknown key"))
+            }
+        })
+        .collect()
+}
+
+fn parse_key_fingers(line: &str) -> error::Result<Vec<Option<Finger>>> {
+    parse_pipe_separated(line)?
+        .map(|s| {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                parse_finger(s).map(Some)
+            }
+        })
+        .collect()
+}
+
+fn parse_key_scores(line: &str) -> error::Result<Vec<Option<f64>>> {
+    parse_pipe_separated(line)?
+        .map(|s| {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                parse_score(s).map(Some)
+            }
+        })
+        .collect()
+}
+
+fn parse_pipe_separated(line: &str) -> error::Result<impl Iterator<Item = &str>> {
+    let inner = line
+        .split_once("|")
+        .and_then(|(_, line)| line.rsplit_once("|"))
+        .map(|(line, _)| line)
+        .ok_or_else(|| Error::parse(line, "expected keys between `|`"))?;
+    Ok(inner.split("|").map(|s| s.trim()))
+}
+
+pub fn keymap_config_to_str(config: &KeymapConfig) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    writeln!(s, "Fingers")?;
+    for finger in &config.fingers {
+        writeln!(
+            s,
+            "{}{}: {}",
+            map_hand_to_str(finger.finger.hand).unwrap(),
+            map_fingerkind_to_str(finger.finger.finger).unwrap(),
+            (finger.score * 100.0) as i32
+        )?;
+    }
+    writeln!(s)?;
+    writeln!(s, "Keys")?;
+    let keys = &config.keys;
+    let (rows, cols) = grid_size(config);
+
+    let mut grid = vec![vec![None; cols as usize]; rows as usize];
+    for key in keys.keys() {
+        grid[key.position.1 as usize][key.position.0 as usize] = Some(PhysicalKeyGridItem(key));
+    }
+
+    write_grid(grid, &mut s, cols)?;
+
+    let shaped: Vec<&PhysicalKey> = keys
+        .keys()
+        .iter()
+        .filter(|key| key.geometry != KeyGeometry::default())
+        .collect();
+    if !shaped.is_empty() || config.columns != ColumnGeometry::default() {
+        writeln!(s)?;
+        writeln!(s, "Geometry")?;
+        let stagger = &config.columns.stagger;
+        if let Some(last) = stagger.iter().rposition(|&offset| offset != 0.0) {
+            let offsets: Vec<String> = stagger[..=last].iter().map(f64::to_string).collect();
+            writeln!(s, "Stagger {}", offsets.join(" "))?;
+        }
+        if let Some(split) = config.columns.split {
+            writeln!(s, "Split after={} gap={}", split.after, split.gap)?;
+        }
+        for key in shaped {
+            let Some(name) = map_keycode_to_str(key.code) else {
+                continue;
+            };
+            let geometry = key.geometry;
+            let mut properties = vec![];
+            for (property, value, default) in [
+                ("x", geometry.offset.0, 0.0),
+                ("y", geometry.offset.1, 0.0),
+                ("w", geometry.width, 1.0),
+                ("r", geometry.rotation, 0.0),
+                ("z", geometry.depth, 0.0),
+            ] {
+                if value != default {
+                    properties.push(format!("{property}={value}"));
+                }
+            }
+            writeln!(s, "{name} {}", properties.join(" "))?;
+        }
+    }
+
+    let home: Vec<&str> = Finger::all()
+        .into_iter()
+        .filter_map(|finger| config.home.get(&finger))
+        .filter_map(|&i| map_keycode_to_str(keys.keys()[i].code))
+        .collect();
+    if !home.is_empty() {
+        writeln!(s)?;
+        writeln!(s, "Home")?;
+        writeln!(s, "{}", home.join(" "))?;
+    }
+
+    // Only keys pinned where they are can be written back
+    let mut pinned: Vec<usize> = config
+        .pins
+        .iter()
+        .filter(|(&i, key)| Key::from_keycode(keys.keys()[i].code) == **key)
+        .map(|(&i, _)| i)
+        .collect();
+    if !pinned.is_empty() {
+        pinned.sort();
+        let names: Vec<&str> = pinned
+            .iter()
+            .filter_map(|&i| map_keycode_to_str(keys.keys()[i].code))
+            .collect();
+        writeln!(s)?;
+        writeln!(s, "Pinned")?;
+        writeln!(s, "{}", names.join(" "))?;
+    }
+
+    Ok(s)
+}
+
+/// Writes `layout` as a grid shaped like the physical keyboard, the format
+/// of the `best.txt` files written by `explore_layouts`.
+pub fn layout_to_str(layout: &Layout, config: &KeymapConfig) -> Result<String, std::fmt::Error> {
+    let (rows, cols) = grid_size(config);
+    let mut grid = vec![vec![None; cols as usize]; rows as usize];
+    for (key, physical) in layout.keys().iter().zip(config.keys.keys()) {
+        grid[physical.position.1 as usize][physical.position.0 as usize] = Some(*key);
+    }
+
+    let mut s = String::new();
+    write_grid(grid, &mut s, cols)?;
+    Ok(s)
+}
+
+/// Parses a layout written by [`layout_to_str`] for the same keymap.
+pub fn parse_layout(layout: &str, config: &KeymapConfig) -> error::Result<Layout> {
+    let keys = parse_key_grid(&mut layout.lines().enumerate(), config)?;
+    Ok(Layout::new(complete_keys(keys, config)?))
+}
+
+/// Unwraps the keys of a layout that must have a key on every physical key.
+fn complete_keys(keys: Vec<Option<Key>>, config: &KeymapConfig) -> error::Result<Vec<Key>> {
+    keys.into_iter()
+        .zip(config.keys.keys())
+        .map(|(key, physical)| {
+            key.ok_or_else(|| {
+                Error::Invalid(format!(
+                    "no key in the layout for the physical key at {:?}",
+                    physical.position
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Reads a key grid and returns the key on each physical key of `config`,
+/// if any.
+fn parse_key_grid<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    config: &KeymapConfig,
+) -> error::Result<Vec<Option<Key>>> {
+    let mut grid = vec![];
+    while lines.next().is_some() {
+        let Some((index, line)) = lines.next() else {
+            break;
+        };
+        grid.push(parse_keycodes(line).map_err(|error| error.at_line(index))?);
+        for _ in 1..Key::num_items() {
+            if lines.next().is_none() {
+                return Err(Error::parse(line, "row ends early").at_line(index));
+            }
+        }
+    }
+
+    Ok(config
+        .keys
+        .keys()
+        .iter()
+        .map(|physical| {
+            grid.get(physical.position.1 as usize)
+                .and_then(|row: &Vec<_>| row.get(physical.position.0 as usize))
+                .copied()
+                .flatten()
+                .map(Key::from_keycode)
+        })
+        .collect())
+}
+
+/// Writes a base layout followed by its extra layers.  Every layer starts
+/// with a `Layer <name>` line followed by the key held to activate it and
+/// a grid in the format of [`layout_to_str`].  Blank keys on a layer fall
+/// through to the base layout.
+///
+/// ```plaintext
+/// Layer base
+/// ---------
+/// | Q | W |
+/// | Q | W |
+/// ---------
+///
+/// Layer nav
+/// Hold: Cap
+/// ---------
+/// |<--|-->|
+/// |<--|-->|
+/// ---------
+/// ```
+pub fn layers_to_str(
+    base: &Layout,
+    layers: &[Layer],
+    config: &KeymapConfig,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    writeln!(s, "Layer base")?;
+    s.push_str(&layout_to_str(base, config)?);
+    let (rows, cols) = grid_size(config);
+    for layer in layers {
+        writeln!(s)?;
+        writeln!(s, "Layer {}", layer.name)?;
+        writeln!(
+            s,
+            "Hold: {}",
+            map_keycode_to_str(config.keys.keys()[layer.hold].code).unwrap()
+        )?;
+        let mut grid = vec![vec![None; cols as usize]; rows as usize];
+        for (key, physical) in layer.keys.iter().zip(config.keys.keys()) {
+            grid[physical.position.1 as usize][physical.position.0 as usize] = *key;
+        }
+        write_grid(grid, &mut s, cols)?;
+    }
+    Ok(s)
+}
+
+/// Parses layers written by [`layers_to_str`].
+pub fn parse_layers(s: &str, config: &KeymapConfig) -> error::Result<(Layout, Vec<Layer>)> {
+    let mut base = None;
+    let mut layers = vec![];
+    let mut lines = s.lines().enumerate().peekable();
+    while let Some((_, line)) = lines.next() {
+        let Some(name) = line.trim().strip_prefix("Layer ") else {
+            continue;
+        };
+        let mut hold = None;
+        if let Some(&(index, line)) = lines.peek() {
+            if let Some(code) = line.trim().strip_prefix("Hold:") {
+                let code = map_str_to_keycode(code.trim())
+                    .ok_or_else(|| Error::parse(line, "unknown key").at_line(index))?;
+                hold = Some(
+                    config
+                        .keys
+                        .keys()
+                        .iter()
+                        .position(|key| key.code == code)
+                        .ok_or_else(|| {
+                            Error::parse(line, "key isn't on the keyboard").at_line(index)
+                        })?,
+                );
+                lines.next();
+            }
+        }
+        let mut section = std::iter::from_fn(|| lines.next_if(|(_, line)| !line.trim().is_empty()));
+        let keys = parse_key_grid(&mut section, config)?;
+        match hold {
+            None => base = Some(Layout::new(complete_keys(keys, config)?)),
+            Some(hold) => layers.push(Layer {
+                name: name.trim().to_string(),
+                hold,
+                keys,
+            }),
+        }
+    }
+    let base = base.ok_or_else(|| Error::Invalid("no base layer".to_string()))?;
+    Ok((base, layers))
+}
+
+fn grid_size(config: &KeymapConfig) -> (u8, u8) {
+    let rows = config
+        .keys
+        .keys()
+        .iter()
+        .map(|key| key.position.1 as u8)
+        .max()
+        .unwrap()
+        + 1;
+    let cols = config
+        .keys
+        .keys()
+        .iter()
+        .map(|key| key.position.0 as u8)
+        .max()
+        .unwrap()
+        + 1;
+    (rows, cols)
+}
+
+pub trait GridItem {
+    fn num_items() -> usize;
+    fn get_item(&self, i: usize) -> Option<String>;
+}
+
+#[derive(Debug, Clone)]
+struct PhysicalKeyGridItem<'a>(&'a PhysicalKey);
+
+impl<'a> GridItem for PhysicalKeyGridItem<'a> {
+    fn num_items() -> usize {
+        3
+    }
+
+    fn get_item(&self, i: usize) -> Option<String> {
+        match i {
+            0 => Some(map_keycode_to_str(self.0.code).unwrap().to_string()),
+            1 => Some(format!(
+                "{}{}",
+                map_hand_to_str(self.0.finger.hand).unwrap(),
+                map_fingerkind_to_str(self.0.finger.finger).unwrap()
+            )),
+            2 => Some(((self.0.score * 100.0) as i32).to_string()),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_grid<T: GridItem>(
+    grid: Vec<Vec<Option<T>>>,
+    s: &mut String,
+    cols: u8,
+) -> Result<(), std::fmt::Error> {
+    for row in grid {
+        write!(s, "-")?;
+        for _ in 0..row.len() {
+            write!(s, "----")?;
+        }
+        writeln!(s)?;
+
+        fn write_separated(
+            s: &mut String,
+            items: impl Iterator<Item = Option<impl Display>>,
+        ) -> Result<(), std::fmt::Error> {
+            write!(s, "|")?;
+            for item in items {
+                if let Some(item) = item {
+                    let item = item.to_string();
+                    if item.len() == 1 {
+                        write!(s, " {} |", item)?;
+                    } else {
+                        write!(s, "{:<3}|", item)?;
+                    }
+                } else {
+                    write!(s, "   |")?;
+                }
+            }
+            writeln!(s)
+        }
+
+        for i in 0..T::num_items() {
+            write_separated(
+                s,
+                row.iter()
+                    .map(|key| key.as_ref().map(|key| key.get_item(i).unwrap_or_default())),
+            )?;
+        }
+    }
+    write!(s, "-")?;
+    for _ in 0..cols {
+        write!(s, "----")?;
+    }
+    writeln!(s)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_e2e() {
+        let s = include_str!("../kinesis.layout");
+        let config = parse_keymap_config(s).unwrap();
+        let s2 = keymap_config_to_str(&config).unwrap();
+        assert_eq!(s, s2);
+    }
+
+    #[test]
+    fn test_geometry() {
+        let s = format!(
+            "{}\nGeometry\nBks y=0.25 r=90\nSpc w=2 z=0.5\n\nPinned\nZ\n",
+            include_str!("../kinesis.layout")
+        );
+        let config = parse_keymap_config(&s).unwrap();
+        assert_eq!(keymap_config_to_str(&config).unwrap(), s);
+        let key = |code| {
+            config
+                .keys
+                .keys()
+                .iter()
+                .find(|key| key.code == code)
+                .unwrap()
+        };
+        let backspace = key(Keycode::Backspace);
+        let (x, y) = backspace.center();
+        // Turned a quarter clockwise, moving down the key moves it left
+        assert!((x - (backspace.position.0 - 0.25)).abs() < 1e-9);
+        assert!((y - backspace.position.1).abs() < 1e-9);
+        assert_eq!(
+            key(Keycode::Space).center().0,
+            key(Keycode::Space).position.0 + 0.5
+        );
+
+        // Sunk half a key below Up, and half a key wide to the left of it
+        let distance = crate::scoring::distance(key(Keycode::Space), key(Keycode::Up));
+        assert!((distance - 0.5f64.sqrt()).abs() < 1e-9);
+
+        let geometry = KeyGeometry::displaced((0.3, -0.2), 1.5, 30.0);
+        let (dx, dy) = geometry.displacement();
+        assert!((dx - 0.3).abs() < 1e-9 && (dy + 0.2).abs() < 1e-9);
+
+        let s = format!("{}\nGeometry\nSpc q=1\n", include_str!("../kinesis.layout"));
+        assert!(parse_keymap_config(&s).is_err());
+
+        let s = include_str!("../corne.layout");
+        let config = parse_keymap_config(s).unwrap();
+        assert_eq!(keymap_config_to_str(&config).unwrap(), s);
+        let key = |code| {
+            config
+                .keys
+                .keys()
+                .iter()
+                .find(|key| key.code == code)
+                .unwrap()
+        };
+        // The pinky column is lower and the right half is past the gap
+        assert_eq!(key(Keycode::Q).center(), (1.0, 0.5));
+        assert_eq!(key(Keycode::Y).center(), (9.0, 0.25));
+        assert_eq!(
+            crate::scoring::distance(key(Keycode::Q), key(Keycode::A)),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_pins() {
+        let s = format!("{}\nPinned\n1 Z X\n", include_str!("../kinesis.layout"));
+        let config = parse_keymap_config(&s).unwrap();
+        assert_eq!(config.pins.len(), 3);
+        assert_eq!(keymap_config_to_str(&config).unwrap(), s);
+
+        use genetic::{Gen, Mutate};
+
+        let unpinned = config.unpinned();
+        for _ in 0..10 {
+            let mut layout = Layout::gen(&mut rand::thread_rng(), &config);
+            layout.mutate(&mut rand::thread_rng(), 0.5);
+            for (&i, key) in &config.pins {
+                assert_eq!(layout.keys()[i], *key);
+                assert!(!unpinned.contains(&i));
+            }
+        }
+
+        use crate::layout::{Key, Preset};
+
+        let qwerty = Layout::from_keymap(&config);
+        let dvorak = Preset::Dvorak.layout(&config).seed(&config).unwrap();
+        let e = Key::from_char_default_shifted('e');
+        assert_eq!(
+            dvorak.get(&e),
+            qwerty.get(&Key::from_char_default_shifted('d'))
+        );
+        for (&i, key) in &config.pins {
+            assert_eq!(dvorak.keys()[i], *key);
+        }
+        let mut keys = qwerty.keys().to_vec();
+        keys[0] = keys[1];
+        assert!(Layout::new(keys).seed(&config).is_err());
+
+        let mut config = config;
+        assert!(config.pin(config.keys.keys().len(), e).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_line() {
+        let s = include_str!("../kinesis.layout").replacen("|LP |", "|XP |", 1);
+        let expected = s.lines().position(|line| line.contains("XP")).unwrap() + 1;
+        match parse_keymap_config(&s) {
+            Err(Error::Parse { line, content, .. }) => {
+                assert_eq!(line, Some(expected));
+                assert_eq!(content, "XP");
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_layout_roundtrip() {
+        use genetic::Gen;
+
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::gen(&mut rand::thread_rng(), &config);
+        let s = layout_to_str(&layout, &config).unwrap();
+        let parsed = parse_layout(&s, &config).unwrap();
+        assert_eq!(layout.keys(), parsed.keys());
+    }
+
+    #[test]
+    fn test_layers_roundtrip() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let base = Layout::from_keymap(&config);
+        let mut keys = vec![None; config.keys.keys().len()];
+        keys[0] = Some(Key::Left);
+        keys[1] = Some(Key::Right);
+        let layer = Layer {
+            name: "nav".to_string(),
+            hold: 5,
+            keys,
+        };
+        let s = layers_to_str(&base, std::slice::from_ref(&layer), &config).unwrap();
+        let (parsed_base, parsed_layers) = parse_layers(&s, &config).unwrap();
+        assert_eq!(base.keys(), parsed_base.keys());
+        assert_eq!(parsed_layers.len(), 1);
+        assert_eq!(parsed_layers[0].name, layer.name);
+        assert_eq!(parsed_layers[0].hold, layer.hold);
+        assert_eq!(parsed_layers[0].keys, layer.keys);
+    }
+}
@@ -0,0 +1,179 @@
+//! Drawing a layout on its physical keyboard as an SVG image, with every
+//! key colored by how often it's pressed or by the finger that presses it.
+//!
+//! Keys are drawn on the grid of the `.layout` file, one unit per key moved,
+//! widened and turned by their geometry and the stagger of their column, with the legend of [`export_kle`](crate::kle::export_kle) and the key's share
+//! of all presses below it.
+
+use std::{fmt::Write, str::FromStr};
+
+use crate::{
+    kle::{heat_color, key_legend},
+    layout::Layout,
+    os_layout::xml_escape,
+    stats::Stats,
+    FingerKind, KeymapConfig,
+};
+
+/// The size of a key in pixels, including the gap around it.
+const UNIT: f64 = 60.0;
+const GAP: f64 = 4.0;
+
+/// What the keys of a heatmap are colored by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapColor {
+    /// How often the key is pressed, from grey through yellow to red.
+    Frequency,
+    /// The finger that presses the key, the same for both hands.
+    Finger,
+}
+
+impl FromStr for HeatmapColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frequency" => Ok(HeatmapColor::Frequency),
+            "finger" => Ok(HeatmapColor::Finger),
+            _ => Err(format!("expected frequency or finger, got {s}")),
+        }
+    }
+}
+
+/// Renders `layout` on the keyboard of `keymap_config` as an SVG document,
+/// with the presses of every key taken from `stats`.
+pub fn render_svg(
+    layout: &Layout,
+    keymap_config: &KeymapConfig,
+    stats: &Stats,
+    color: HeatmapColor,
+) -> String {
+    let physical = keymap_config.keys.keys();
+    let counts: Vec<u64> = layout
+        .keys()
+        .iter()
+        .map(|key| {
+            stats
+                .individual_key_counts
+                .get(&key.keycode(false))
+                .copied()
+                .unwrap_or(0)
+        })
+        .collect();
+    let max_count = counts.iter().copied().max().unwrap_or(0).max(1);
+    let total: u64 = counts.iter().sum();
+    let columns = physical
+        .iter()
+        .map(|key| key.center().0 + key.geometry.width / 2.0 + 0.5)
+        .fold(0.0, f64::max);
+    let rows = physical
+        .iter()
+        .map(|key| key.center().1 + 1.0)
+        .fold(0.0, f64::max);
+
+    let mut s = String::new();
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" text-anchor="middle">"#,
+        width = columns * UNIT + GAP,
+        height = rows * UNIT + GAP,
+    )
+    .unwrap();
+    writeln!(s, r##"<rect width="100%" height="100%" fill="#ffffff"/>"##).unwrap();
+    for ((pkey, key), count) in physical.iter().zip(layout.keys()).zip(&counts) {
+        let fill = match color {
+            HeatmapColor::Frequency => heat_color(*count as f64 / max_count as f64),
+            HeatmapColor::Finger => finger_color(pkey.finger.finger).to_string(),
+        };
+        let geometry = pkey.geometry;
+        let cell_x = pkey.position.0 + pkey.column_offset.0;
+        let cell_y = pkey.position.1 + pkey.column_offset.1;
+        let x = (cell_x + geometry.offset.0) * UNIT + GAP;
+        let y = (cell_y + geometry.offset.1) * UNIT + GAP;
+        let width = geometry.width * UNIT - GAP;
+        let size = UNIT - GAP;
+        let center = x + width / 2.0;
+        if geometry.rotation == 0.0 {
+            writeln!(s, "<g>").unwrap();
+        } else {
+            // Turned about the center of the key's cell, like the geometry
+            let pivot_x = cell_x * UNIT + (UNIT + GAP) / 2.0;
+            let pivot_y = cell_y * UNIT + (UNIT + GAP) / 2.0;
+            writeln!(
+                s,
+                r#"<g transform="rotate({} {pivot_x} {pivot_y})">"#,
+                geometry.rotation
+            )
+            .unwrap();
+        }
+        writeln!(
+            s,
+            r##"  <rect x="{x}" y="{y}" width="{width}" height="{size}" rx="6" fill="{fill}" stroke="#555555"/>"##
+        )
+        .unwrap();
+        let legend: Vec<String> = key_legend(key).lines().map(xml_escape).collect();
+        let font_size = if legend.iter().any(|line| line.chars().count() > 3) {
+            10
+        } else {
+            16
+        };
+        for (i, line) in legend.iter().enumerate() {
+            let line_y = y + 20.0 + i as f64 * font_size as f64;
+            writeln!(
+                s,
+                r#"  <text x="{center}" y="{line_y}" font-size="{font_size}">{line}</text>"#
+            )
+            .unwrap();
+        }
+        if *count > 0 {
+            let share = *count as f64 / total as f64 * 100.0;
+            writeln!(
+                s,
+                r##"  <text x="{center}" y="{}" font-size="9" fill="#333333">{share:.1}%</text>"##,
+                y + size - 6.0
+            )
+            .unwrap();
+        }
+        writeln!(s, "</g>").unwrap();
+    }
+    writeln!(s, "</svg>").unwrap();
+    s
+}
+
+/// A color for every kind of finger, easy to tell apart next to each other.
+fn finger_color(finger: FingerKind) -> &'static str {
+    match finger {
+        FingerKind::Pinky => "#8dd3c7",
+        FingerKind::Ring => "#bebada",
+        FingerKind::Middle => "#fb8072",
+        FingerKind::Index => "#80b1d3",
+        FingerKind::Thumb => "#fdb462",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+    use device_query::Keycode;
+
+    #[test]
+    fn test_render_svg() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::default();
+        stats.individual_key_counts.insert(Keycode::A, 3);
+        stats.individual_key_counts.insert(Keycode::LShift, 1);
+
+        let svg = render_svg(&layout, &config, &stats, HeatmapColor::Frequency);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), config.keys.keys().len() + 1);
+        assert!(svg.contains(r##"fill="#d73027""##));
+        assert!(svg.contains(">75.0%<"));
+        assert!(svg.contains(">Left Shift<"));
+
+        let svg = render_svg(&layout, &config, &stats, HeatmapColor::Finger);
+        assert!(svg.contains(r##"fill="#fdb462""##));
+    }
+}
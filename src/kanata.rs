@@ -0,0 +1,359 @@
+//! Exporting a layout as a kanata or kmonad config, so a generated layout
+//! can be tried as a software remap on any keyboard, without flashing
+//! firmware and without keeping `apply` running.
+//!
+//! Both read the same kind of config: `defsrc` lists the physical keys in
+//! the order of the `.layout` file, and every `deflayer` lists what those
+//! keys type.  Layers are held on tap-hold keys like in the QMK export.
+//! Shifted characters that differ from a US layout become `fork` aliases
+//! in kanata, which kmonad has no equivalent for.
+
+use std::fmt::Write;
+
+use device_query::Keycode;
+
+use crate::{
+    layout::{default_shifted, Key, Layer, Layout},
+    stats::char_to_key_event,
+    KeymapConfig,
+};
+
+/// Builds a kanata config with `base` as the default layer followed by
+/// `layers`.  `tapping_term_ms` is how long a layer's hold key has to be
+/// held to activate the layer instead of typing its key.
+pub fn export_kanata(
+    base: &Layout,
+    layers: &[Layer],
+    config: &KeymapConfig,
+    tapping_term_ms: u32,
+) -> String {
+    let mut s = String::new();
+    writeln!(s, ";; Generated by keyboard_layout_generator").unwrap();
+    writeln!(s, "(defcfg").unwrap();
+    writeln!(s, "  process-unmapped-keys no").unwrap();
+    writeln!(s, ")").unwrap();
+    writeln!(s).unwrap();
+
+    let mut aliases = vec![];
+    let mut base_codes: Vec<String> = base.keys().iter().map(key_to_kanata).collect();
+    let overrides = base
+        .keys()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, key)| match key {
+            Key::Normal { shifted, .. } if has_override(key) => Some((i, *shifted)),
+            _ => None,
+        });
+    for (i, shifted) in overrides {
+        let name = format!("shift{}", aliases.len());
+        aliases.push((
+            name.clone(),
+            format!(
+                "(fork {} {} (lsft rsft))",
+                base_codes[i],
+                shifted_output(shifted)
+            ),
+        ));
+        base_codes[i] = format!("@{name}");
+    }
+    for layer in layers {
+        let name = layer_id(&layer.name);
+        let tap = key_name(base.keys()[layer.hold].keycode(false)).unwrap_or("XX");
+        aliases.push((
+            name.clone(),
+            format!(
+                "(tap-hold {tapping_term_ms} {tapping_term_ms} {tap} (layer-while-held {name}))"
+            ),
+        ));
+        base_codes[layer.hold] = format!("@{name}");
+    }
+    if !aliases.is_empty() {
+        writeln!(s, "(defalias").unwrap();
+        for (name, action) in aliases {
+            writeln!(s, "  {name} {action}").unwrap();
+        }
+        writeln!(s, ")").unwrap();
+        writeln!(s).unwrap();
+    }
+
+    write_layers(&mut s, base_codes, layers, config);
+    s
+}
+
+/// A kmonad config, and what it had to leave out.
+pub struct KmonadConfig {
+    pub config: String,
+    /// Keys whose shifted character differs from a US layout, which type
+    /// the US shifted character instead.
+    pub dropped_overrides: usize,
+}
+
+/// Builds a kmonad config reading the keyboard at `device`, like
+/// [`export_kanata`].  kmonad only grabs devices on Linux this way.
+pub fn export_kmonad(
+    base: &Layout,
+    layers: &[Layer],
+    config: &KeymapConfig,
+    device: &str,
+    tapping_term_ms: u32,
+) -> KmonadConfig {
+    let mut s = String::new();
+    writeln!(s, ";; Generated by keyboard_layout_generator").unwrap();
+    writeln!(s, "(defcfg").unwrap();
+    writeln!(s, "  input (device-file \"{device}\")").unwrap();
+    writeln!(s, "  output (uinput-sink \"keyboard_layout_generator\")").unwrap();
+    writeln!(s, "  fallthrough true").unwrap();
+    writeln!(s, ")").unwrap();
+    writeln!(s).unwrap();
+
+    let mut base_codes: Vec<String> = base.keys().iter().map(key_to_kanata).collect();
+    if !layers.is_empty() {
+        writeln!(s, "(defalias").unwrap();
+        for layer in layers {
+            let name = layer_id(&layer.name);
+            let tap = key_name(base.keys()[layer.hold].keycode(false)).unwrap_or("XX");
+            writeln!(
+                s,
+                "  {name} (tap-hold {tapping_term_ms} {tap} (layer-toggle {name}))"
+            )
+            .unwrap();
+            base_codes[layer.hold] = format!("@{name}");
+        }
+        writeln!(s, ")").unwrap();
+        writeln!(s).unwrap();
+    }
+
+    write_layers(&mut s, base_codes, layers, config);
+    KmonadConfig {
+        config: s,
+        dropped_overrides: base.keys().iter().filter(|key| has_override(key)).count(),
+    }
+}
+
+/// Writes `defsrc` and a `deflayer` for the base layer and every layer,
+/// with keys a layer leaves alone as `_`.
+fn write_layers(s: &mut String, base_codes: Vec<String>, layers: &[Layer], config: &KeymapConfig) {
+    let src: Vec<String> = config
+        .keys
+        .keys()
+        .iter()
+        .map(|key| key_name(key.code).unwrap_or("XX").to_string())
+        .collect();
+    write_block(s, "defsrc", &src, config);
+    writeln!(s).unwrap();
+    write_block(s, "deflayer base", &base_codes, config);
+    for layer in layers {
+        let codes: Vec<String> = layer
+            .keys
+            .iter()
+            .map(|key| key.as_ref().map_or("_".to_string(), key_to_kanata))
+            .collect();
+        writeln!(s).unwrap();
+        write_block(
+            s,
+            &format!("deflayer {}", layer_id(&layer.name)),
+            &codes,
+            config,
+        );
+    }
+}
+
+/// Writes a block of keys, breaking the lines where the rows of the
+/// physical keyboard end and aligning the columns.
+fn write_block(s: &mut String, head: &str, codes: &[String], config: &KeymapConfig) {
+    let width = codes
+        .iter()
+        .map(|code| code.chars().count())
+        .max()
+        .unwrap_or(0);
+    let keys = config.keys.keys();
+    writeln!(s, "({head}").unwrap();
+    for (i, code) in codes.iter().enumerate() {
+        if i == 0 || keys[i - 1].position.1 != keys[i].position.1 {
+            write!(s, " ").unwrap();
+        }
+        write!(s, " {code:<width$}").unwrap();
+        if i + 1 == codes.len() || keys[i + 1].position.1 != keys[i].position.1 {
+            // Padding the last key of a row would leave trailing spaces
+            s.truncate(s.trim_end().len());
+            writeln!(s).unwrap();
+        }
+    }
+    writeln!(s, ")").unwrap();
+}
+
+/// Whether the shifted character of `key` differs from a US layout.
+fn has_override(key: &Key) -> bool {
+    matches!(key, Key::Normal { normal, shifted } if *shifted != default_shifted(*normal))
+}
+
+/// The name of a layer as a kanata or kmonad identifier.
+fn layer_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn key_to_kanata(key: &Key) -> String {
+    match key {
+        Key::Normal { normal, .. } => char_output(*normal).unwrap_or("XX".to_string()),
+        key => key_name(key.keycode(false)).unwrap_or("XX").to_string(),
+    }
+}
+
+/// What to send for `c` on a US system layout, shifted if needed.
+fn char_output(c: char) -> Option<String> {
+    let (code, shift) = match c {
+        ' ' => (Keycode::Space, false),
+        c => char_to_key_event(c)?,
+    };
+    let name = key_name(code)?;
+    Some(if shift {
+        format!("S-{name}")
+    } else {
+        name.to_string()
+    })
+}
+
+/// What to send for `c` while shift is already held.
+fn shifted_output(c: char) -> String {
+    let Some((code, shift)) = char_to_key_event(c) else {
+        return "XX".to_string();
+    };
+    let name = key_name(code).unwrap_or("XX");
+    if shift {
+        name.to_string()
+    } else {
+        format!("(unshift {name})")
+    }
+}
+
+/// The name of `code` in kanata and kmonad, which share the names of the
+/// keys they can remap.
+fn key_name(code: Keycode) -> Option<&'static str> {
+    Some(match code {
+        Keycode::A => "a",
+        Keycode::B => "b",
+        Keycode::C => "c",
+        Keycode::D => "d",
+        Keycode::E => "e",
+        Keycode::F => "f",
+        Keycode::G => "g",
+        Keycode::H => "h",
+        Keycode::I => "i",
+        Keycode::J => "j",
+        Keycode::K => "k",
+        Keycode::L => "l",
+        Keycode::M => "m",
+        Keycode::N => "n",
+        Keycode::O => "o",
+        Keycode::P => "p",
+        Keycode::Q => "q",
+        Keycode::R => "r",
+        Keycode::S => "s",
+        Keycode::T => "t",
+        Keycode::U => "u",
+        Keycode::V => "v",
+        Keycode::W => "w",
+        Keycode::X => "x",
+        Keycode::Y => "y",
+        Keycode::Z => "z",
+        Keycode::Key1 => "1",
+        Keycode::Key2 => "2",
+        Keycode::Key3 => "3",
+        Keycode::Key4 => "4",
+        Keycode::Key5 => "5",
+        Keycode::Key6 => "6",
+        Keycode::Key7 => "7",
+        Keycode::Key8 => "8",
+        Keycode::Key9 => "9",
+        Keycode::Key0 => "0",
+        Keycode::Grave => "grv",
+        Keycode::Minus => "-",
+        Keycode::Equal => "=",
+        Keycode::LeftBracket => "[",
+        Keycode::RightBracket => "]",
+        Keycode::BackSlash => "\\",
+        Keycode::Semicolon => ";",
+        Keycode::Apostrophe => "'",
+        Keycode::Comma => ",",
+        Keycode::Dot => ".",
+        Keycode::Slash => "/",
+        Keycode::Space => "spc",
+        Keycode::Enter => "ret",
+        Keycode::Tab => "tab",
+        Keycode::Backspace => "bspc",
+        Keycode::Delete => "del",
+        Keycode::Escape => "esc",
+        Keycode::CapsLock => "caps",
+        Keycode::LShift => "lsft",
+        Keycode::RShift => "rsft",
+        Keycode::LControl => "lctl",
+        Keycode::RControl => "rctl",
+        Keycode::LAlt => "lalt",
+        Keycode::RAlt => "ralt",
+        Keycode::LMeta => "lmet",
+        Keycode::RMeta => "rmet",
+        Keycode::Home => "home",
+        Keycode::End => "end",
+        Keycode::PageUp => "pgup",
+        Keycode::PageDown => "pgdn",
+        Keycode::Left => "left",
+        Keycode::Right => "rght",
+        Keycode::Up => "up",
+        Keycode::Down => "down",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_export_kanata() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut keys = Layout::from_keymap(&config).keys().to_vec();
+        let q = keys
+            .iter()
+            .position(|key| *key == Key::from_keycode(Keycode::Q))
+            .unwrap();
+        keys[q] = Key::Normal {
+            normal: 'q',
+            shifted: '!',
+        };
+        let base = Layout::new(keys);
+        let mut layer_keys = vec![None; config.keys.keys().len()];
+        layer_keys[0] = Some(Key::Left);
+        let layers = [Layer {
+            name: "Nav".to_string(),
+            hold: 5,
+            keys: layer_keys,
+        }];
+
+        let kanata = export_kanata(&base, &layers, &config, 200);
+        assert!(kanata.contains("(defsrc\n  = "));
+        assert!(kanata.contains("shift0 (fork q 1 (lsft rsft))"));
+        assert!(kanata.contains("nav (tap-hold 200 200 5 (layer-while-held nav))"));
+        assert!(kanata.contains("(deflayer nav\n  left"));
+        assert!(!kanata.contains(" \n"));
+        let rows = kanata.split("(deflayer base\n").nth(1).unwrap();
+        assert_eq!(rows.lines().next().unwrap().split_whitespace().count(), 12);
+
+        let kmonad = export_kmonad(&base, &layers, &config, "/dev/input/event0", 200);
+        assert_eq!(kmonad.dropped_overrides, 1);
+        assert!(kmonad
+            .config
+            .contains("nav (tap-hold 200 5 (layer-toggle nav))"));
+        assert!(kmonad
+            .config
+            .contains("(device-file \"/dev/input/event0\")"));
+    }
+}
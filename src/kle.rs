@@ -0,0 +1,659 @@
+//! Importing a physical keyboard from the JSON that
+//! [keyboard-layout-editor.com](http://www.keyboard-layout-editor.com)
+//! downloads, as an alternative to drawing its grid by hand.
+//!
+//! KLE knows where the keys are, how big they are, and how they're rotated,
+//! but not which finger presses them or how comfortable they are, so those
+//! come from [`Annotations`] or from asking.  The keys are snapped to the
+//! grid of the `.layout` format: rows by the height of their centers and
+//! columns by their distance from the left edge, moved right when two keys
+//! of a row would share a column.
+//!
+//! Going the other way, [`export_kle`] draws a layout on the grid of its
+//! keyboard, colored by how often each key is pressed, to share or look at
+//! on the site.
+
+use std::collections::HashMap;
+
+use device_query::Keycode;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    error::{self, Error},
+    format::{
+        map_fingerkind_to_str, map_hand_to_str, map_keycode_to_str, map_str_to_keycode,
+        parse_finger,
+    },
+    layout::{Key, Layout},
+    scoring::KeyPairs,
+    stats::Stats,
+    ColumnGeometry, Finger, FingerConfig, KeyGeometry, KeymapConfig, PhysicalKey, PhysicalKeyboard,
+};
+
+/// A key as drawn in KLE, in units of a regular key with `y` going down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KleKey {
+    /// The legends of the key, one per line, like `!\n1`.
+    pub legend: String,
+    /// The center of the key after rotating it.
+    pub center: (f64, f64),
+    /// The width and height of the key.
+    pub size: (f64, f64),
+    /// The rotation of the key in degrees, clockwise.
+    pub rotation: f64,
+}
+
+/// Where the next key goes, following the properties of the keys before.
+#[derive(Default)]
+struct Cursor {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    rotation: f64,
+    rotation_x: f64,
+    rotation_y: f64,
+    decal: bool,
+}
+
+impl Cursor {
+    fn apply(&mut self, properties: &Map<String, Value>) {
+        let number = |name: &str| properties.get(name).and_then(Value::as_f64);
+        if let Some(rotation) = number("r") {
+            self.rotation = rotation;
+        }
+        // Setting the rotation origin starts a new cluster of keys there
+        if let Some(rotation_x) = number("rx") {
+            self.rotation_x = rotation_x;
+            (self.x, self.y) = (self.rotation_x, self.rotation_y);
+        }
+        if let Some(rotation_y) = number("ry") {
+            self.rotation_y = rotation_y;
+            (self.x, self.y) = (self.rotation_x, self.rotation_y);
+        }
+        self.x += number("x").unwrap_or(0.0);
+        self.y += number("y").unwrap_or(0.0);
+        if let Some(width) = number("w") {
+            self.width = width;
+        }
+        if let Some(height) = number("h") {
+            self.height = height;
+        }
+        if let Some(decal) = properties.get("d").and_then(Value::as_bool) {
+            self.decal = decal;
+        }
+    }
+
+    fn key(&self, legend: &str) -> KleKey {
+        let (x, y) = (
+            self.x + self.width / 2.0 - self.rotation_x,
+            self.y + self.height / 2.0 - self.rotation_y,
+        );
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        KleKey {
+            legend: legend.to_string(),
+            center: (
+                self.rotation_x + x * cos - y * sin,
+                self.rotation_y + x * sin + y * cos,
+            ),
+            size: (self.width, self.height),
+            rotation: self.rotation,
+        }
+    }
+
+    /// Moves past the key just placed, whose size and decal don't carry
+    /// over to the next one.
+    fn next_key(&mut self) {
+        self.x += self.width;
+        self.width = 1.0;
+        self.height = 1.0;
+        self.decal = false;
+    }
+
+    fn next_row(&mut self) {
+        self.y += 1.0;
+        self.x = self.rotation_x;
+    }
+}
+
+/// Parses the keys of a KLE JSON file, skipping decals.
+pub fn parse_kle(json: &str) -> error::Result<Vec<KleKey>> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|error| Error::Invalid(error.to_string()))?;
+    let rows = value
+        .as_array()
+        .ok_or_else(|| Error::Invalid("expected an array of rows".to_string()))?;
+    let mut cursor = Cursor {
+        width: 1.0,
+        height: 1.0,
+        ..Default::default()
+    };
+    let mut keys = vec![];
+    for row in rows {
+        let Some(row) = row.as_array() else {
+            // The properties of the whole keyboard, like its name
+            if row.is_object() {
+                continue;
+            }
+            return Err(Error::Invalid(format!(
+                "expected a row of keys, found {row}"
+            )));
+        };
+        for item in row {
+            match item {
+                Value::String(legend) => {
+                    if !cursor.decal {
+                        keys.push(cursor.key(legend));
+                    }
+                    cursor.next_key();
+                }
+                Value::Object(properties) => cursor.apply(properties),
+                _ => {
+                    return Err(Error::Invalid(format!(
+                        "expected a legend or the properties of a key, found {item}"
+                    )))
+                }
+            }
+        }
+        cursor.next_row();
+    }
+    Ok(keys)
+}
+
+/// The keycode of a key from its legends, trying the bottom one first since
+/// KLE puts the shifted symbol above the unshifted one.  Unlabeled keys at
+/// least 4 wide are space bars.  Modifiers go to the side of the keyboard
+/// the key is on, unless named like `Left Shift`.
+pub fn legend_keycode(legend: &str, width: f64, left: bool) -> Option<Keycode> {
+    let lines: Vec<&str> = legend
+        .split('\n')
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() {
+        return (width >= 4.0).then_some(Keycode::Space);
+    }
+    lines.iter().rev().find_map(|line| name_keycode(line, left))
+}
+
+fn name_keycode(name: &str, left: bool) -> Option<Keycode> {
+    if let Some(code) = map_str_to_keycode(name) {
+        return Some(code);
+    }
+    let lower = name.to_lowercase();
+    // A side in the legend wins over the side of the keyboard
+    let (left, lower) = match (lower.strip_prefix("left "), lower.strip_prefix("right ")) {
+        (Some(rest), _) => (true, rest),
+        (_, Some(rest)) => (false, rest),
+        _ => (left, lower.as_str()),
+    };
+    let side = |left_code, right_code| Some(if left { left_code } else { right_code });
+    match lower {
+        "shift" => side(Keycode::LShift, Keycode::RShift),
+        "ctrl" | "control" => side(Keycode::LControl, Keycode::RControl),
+        "alt" | "option" | "opt" => side(Keycode::LAlt, Keycode::RAlt),
+        "win" | "super" | "cmd" | "command" | "meta" | "gui" => {
+            side(Keycode::LMeta, Keycode::RMeta)
+        }
+        "esc" | "escape" => Some(Keycode::Escape),
+        "tab" => Some(Keycode::Tab),
+        "caps" | "caps lock" => Some(Keycode::CapsLock),
+        "space" | "spacebar" => Some(Keycode::Space),
+        "backspace" | "bksp" | "back space" => Some(Keycode::Backspace),
+        "enter" | "return" => Some(Keycode::Enter),
+        "del" | "delete" => Some(Keycode::Delete),
+        "home" => Some(Keycode::Home),
+        "end" => Some(Keycode::End),
+        "pgup" | "pg up" | "page up" => Some(Keycode::PageUp),
+        "pgdn" | "pg dn" | "page down" => Some(Keycode::PageDown),
+        "←" | "left" => Some(Keycode::Left),
+        "→" | "right" => Some(Keycode::Right),
+        "↑" | "up" => Some(Keycode::Up),
+        "↓" | "down" => Some(Keycode::Down),
+        "`" => Some(Keycode::Grave),
+        "_" => Some(Keycode::Minus),
+        "+" => Some(Keycode::Equal),
+        "{" => Some(Keycode::LeftBracket),
+        "}" => Some(Keycode::RightBracket),
+        "|" => Some(Keycode::BackSlash),
+        ":" => Some(Keycode::Semicolon),
+        "\"" => Some(Keycode::Apostrophe),
+        "<" => Some(Keycode::Comma),
+        ">" => Some(Keycode::Dot),
+        "?" => Some(Keycode::Slash),
+        "!" => Some(Keycode::Key1),
+        "@" => Some(Keycode::Key2),
+        "#" => Some(Keycode::Key3),
+        "$" => Some(Keycode::Key4),
+        "%" => Some(Keycode::Key5),
+        "^" => Some(Keycode::Key6),
+        "&" => Some(Keycode::Key7),
+        "*" => Some(Keycode::Key8),
+        "(" => Some(Keycode::Key9),
+        ")" => Some(Keycode::Key0),
+        _ => map_str_to_keycode(&name.to_uppercase()),
+    }
+}
+
+/// The keys of a KLE keyboard placed on a grid, before they have fingers
+/// and scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KleGrid {
+    /// The keycode, grid position and geometry of every key, row by row.
+    pub keys: Vec<(Keycode, (f64, f64), KeyGeometry)>,
+    /// The legends of keys that don't match a keycode, like `Fn`.
+    pub skipped: Vec<String>,
+}
+
+impl KleGrid {
+    /// Places `keys` on a grid.  Fails if two keys have the same keycode,
+    /// since layouts need every keycode at most once.
+    pub fn new(keys: &[KleKey]) -> error::Result<Self> {
+        let (min_x, max_x) = keys.iter().fold((f64::MAX, f64::MIN), |(min, max), key| {
+            (min.min(key.center.0), max.max(key.center.0))
+        });
+        let middle = (min_x + max_x) / 2.0;
+
+        let mut placed: Vec<(Keycode, &KleKey)> = vec![];
+        let mut skipped = vec![];
+        for key in keys {
+            match legend_keycode(&key.legend, key.size.0, key.center.0 < middle) {
+                Some(code) if placed.iter().any(|(placed, _)| *placed == code) => {
+                    return Err(Error::Invalid(format!(
+                        "{} is on the keyboard twice, change the legend of one of them",
+                        map_keycode_to_str(code).unwrap()
+                    )))
+                }
+                Some(code) => placed.push((code, key)),
+                None => skipped.push(key.legend.replace('\n', " ")),
+            }
+        }
+
+        // The top row of the grid is where the keys are highest
+        let min_y = placed
+            .iter()
+            .map(|(_, key)| key.center.1)
+            .fold(f64::MAX, f64::min);
+        let mut rows: Vec<i64> = placed.iter().map(|(_, key)| grid_row(key)).collect();
+        rows.sort();
+        rows.dedup();
+        let mut grid_keys = vec![];
+        for (r, &row) in rows.iter().enumerate() {
+            let mut row_keys: Vec<&(Keycode, &KleKey)> = placed
+                .iter()
+                .filter(|(_, key)| grid_row(key) == row)
+                .collect();
+            row_keys.sort_by(|(_, a), (_, b)| a.center.0.total_cmp(&b.center.0));
+            let mut next_free = 0;
+            for (code, key) in row_keys {
+                let c = ((key.center.0 - min_x).round() as i64).max(next_free);
+                next_free = c + 1;
+                let position = (c as f64, r as f64);
+                let displacement = (
+                    key.center.0 - min_x - position.0,
+                    key.center.1 - min_y - position.1,
+                );
+                let geometry = KeyGeometry::displaced(displacement, key.size.0, key.rotation);
+                grid_keys.push((*code, position, rounded(geometry)));
+            }
+        }
+        Ok(Self {
+            keys: grid_keys,
+            skipped,
+        })
+    }
+
+    /// The keymap config of the grid, with the finger and score of every
+    /// key from `annotate_key` and the score of every finger used from
+    /// `annotate_finger`.
+    pub fn keymap_config(
+        &self,
+        mut annotate_key: impl FnMut(Keycode, (f64, f64)) -> error::Result<(Finger, f64)>,
+        mut annotate_finger: impl FnMut(Finger) -> error::Result<f64>,
+    ) -> error::Result<KeymapConfig> {
+        let mut keys = PhysicalKeyboard::new();
+        for &(code, position, geometry) in &self.keys {
+            let (finger, score) = annotate_key(code, position)?;
+            keys.add_key(PhysicalKey {
+                code,
+                finger,
+                score,
+                position,
+                geometry,
+                column_offset: (0.0, 0.0),
+            });
+        }
+        let fingers = Finger::all()
+            .into_iter()
+            .filter(|finger| keys.keys().iter().any(|key| key.finger == *finger))
+            .map(|finger| {
+                Ok(FingerConfig {
+                    finger,
+                    score: annotate_finger(finger)?,
+                })
+            })
+            .collect::<error::Result<_>>()?;
+        Ok(KeymapConfig {
+            fingers,
+            keys,
+            pins: HashMap::new(),
+            columns: ColumnGeometry::default(),
+            home: HashMap::new(),
+            pairs: KeyPairs::default(),
+        })
+    }
+}
+
+/// `geometry` without the noise of floating point math, which would end up
+/// in the `.layout` file.
+fn rounded(geometry: KeyGeometry) -> KeyGeometry {
+    let round = |value: f64| (value * 1000.0).round() / 1000.0 + 0.0;
+    KeyGeometry {
+        offset: (round(geometry.offset.0), round(geometry.offset.1)),
+        width: round(geometry.width),
+        rotation: round(geometry.rotation),
+        depth: geometry.depth,
+    }
+}
+
+/// The row of the grid `key` is on before renumbering, from the top of the
+/// key rather than its center so regular keys never land between rows.
+fn grid_row(key: &KleKey) -> i64 {
+    (key.center.1 - 0.5).round() as i64
+}
+
+/// Fingers and scores for the keys of a KLE keyboard, read from a TOML
+/// sidecar file:
+///
+/// ```toml
+/// [fingers]
+/// LP = 70
+/// LI = 100
+///
+/// [keys]
+/// Q = { finger = "LP", score = 75 }
+/// LSh = { finger = "LP", score = 65 }
+/// ```
+///
+/// Keys and fingers are named like in the `.layout` format, and scores go
+/// from 0 to 100 like there too.
+#[derive(Debug, Default, Deserialize)]
+pub struct Annotations {
+    #[serde(default)]
+    pub fingers: HashMap<String, f64>,
+    #[serde(default)]
+    pub keys: HashMap<String, KeyAnnotation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyAnnotation {
+    pub finger: String,
+    pub score: f64,
+}
+
+impl Annotations {
+    pub fn load(path: &str) -> error::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        toml::from_str(&s).map_err(|error| Error::Invalid(error.to_string()))
+    }
+
+    /// The finger and score of the key with `code`, if annotated.
+    pub fn key(&self, code: Keycode) -> Option<error::Result<(Finger, f64)>> {
+        let name = map_keycode_to_str(code)?;
+        let annotation = self.keys.get(name)?;
+        Some(
+            parse_finger(&annotation.finger)
+                .map(|finger| (finger, annotation.score / 100.0))
+                .map_err(|_| {
+                    Error::parse(&annotation.finger, format!("unknown finger for {name}"))
+                }),
+        )
+    }
+
+    /// The score of `finger`, if annotated.
+    pub fn finger(&self, finger: Finger) -> Option<f64> {
+        self.fingers
+            .get(&finger_name(finger))
+            .map(|score| score / 100.0)
+    }
+}
+
+/// The name of `finger` in the `.layout` format, like `LP`.
+pub fn finger_name(finger: Finger) -> String {
+    format!(
+        "{}{}",
+        map_hand_to_str(finger.hand).unwrap(),
+        map_fingerkind_to_str(finger.finger).unwrap()
+    )
+}
+
+/// Parses the finger and score of a key typed like `LP 75`.
+pub fn parse_key_annotation(s: &str) -> error::Result<(Finger, f64)> {
+    let mut words = s.split_whitespace();
+    let (Some(finger), Some(score), None) = (words.next(), words.next(), words.next()) else {
+        return Err(Error::parse(s, "expected `<finger> <score>`"));
+    };
+    let score = score
+        .parse::<f64>()
+        .map_err(|_| Error::parse(score, "invalid score"))?;
+    Ok((parse_finger(finger)?, score / 100.0))
+}
+
+/// Writes `layout` on the grid of `keymap_config` as KLE JSON, which
+/// keyboard-layout-editor.com opens with its upload button or as raw data.
+/// With `stats`, keys go from grey when never pressed to red for the most
+/// pressed key.
+pub fn export_kle(layout: &Layout, keymap_config: &KeymapConfig, stats: Option<&Stats>) -> String {
+    let physical = keymap_config.keys.keys();
+    let counts: Vec<u64> = layout
+        .keys()
+        .iter()
+        .map(|key| {
+            stats
+                .and_then(|stats| stats.individual_key_counts.get(&key.keycode(false)))
+                .copied()
+                .unwrap_or(0)
+        })
+        .collect();
+    let max_count = counts.iter().copied().max().unwrap_or(0).max(1);
+    let rows = physical
+        .iter()
+        .map(|key| key.position.1 as usize + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut kle_rows = vec![];
+    for row in 0..rows {
+        let mut keys: Vec<usize> = (0..physical.len())
+            .filter(|&i| physical[i].position.1 as usize == row)
+            .collect();
+        keys.sort_by(|&a, &b| physical[a].position.0.total_cmp(&physical[b].position.0));
+        let mut items = vec![];
+        let mut x = 0.0;
+        for i in keys {
+            let mut properties = Map::new();
+            if physical[i].position.0 > x {
+                properties.insert("x".to_string(), json!(physical[i].position.0 - x));
+            }
+            if stats.is_some() {
+                let heat = counts[i] as f64 / max_count as f64;
+                properties.insert("c".to_string(), json!(heat_color(heat)));
+            }
+            if !properties.is_empty() {
+                items.push(Value::Object(properties));
+            }
+            items.push(json!(key_legend(&layout.keys()[i])));
+            x = physical[i].position.0 + 1.0;
+        }
+        kle_rows.push(Value::Array(items).to_string());
+    }
+    format!("[\n{}\n]\n", kle_rows.join(",\n"))
+}
+
+/// The legend of `key` in KLE, with the shifted character above the
+/// unshifted one when they differ by more than case.  Other keys get the
+/// names [`parse_kle`] reads back.
+pub(crate) fn key_legend(key: &Key) -> String {
+    let name = match key {
+        Key::Normal { normal, shifted } => {
+            return if normal.to_uppercase().eq([*shifted]) {
+                shifted.to_string()
+            } else if normal == shifted {
+                normal.to_string()
+            } else {
+                format!("{shifted}\n{normal}")
+            }
+        }
+        Key::Backspace => "Backspace",
+        Key::Tab => "Tab",
+        Key::Enter => "Enter",
+        Key::CapsLock => "Caps Lock",
+        Key::LShift => "Left Shift",
+        Key::RShift => "Right Shift",
+        Key::LCtrl => "Left Ctrl",
+        Key::RCtrl => "Right Ctrl",
+        Key::LAlt => "Left Alt",
+        Key::RAlt => "Right Alt",
+        Key::LMeta => "Left Win",
+        Key::RMeta => "Right Win",
+        Key::Space => "Space",
+        Key::Escape => "Esc",
+        Key::Home => "Home",
+        Key::End => "End",
+        Key::PageUp => "PgUp",
+        Key::PageDown => "PgDn",
+        Key::Left => "←",
+        Key::Right => "→",
+        Key::Up => "↑",
+        Key::Down => "↓",
+        Key::Delete => "Delete",
+    };
+    name.to_string()
+}
+
+/// The color of a key pressed `heat` times as often as the most pressed
+/// one, from grey through yellow to red.
+pub(crate) fn heat_color(heat: f64) -> String {
+    const STOPS: [(f64, f64, f64); 3] = [
+        (0xee as f64, 0xee as f64, 0xee as f64),
+        (0xfe as f64, 0xe0 as f64, 0x8b as f64),
+        (0xd7 as f64, 0x30 as f64, 0x27 as f64),
+    ];
+    let position = heat.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (position as usize).min(STOPS.len() - 2);
+    let t = position - i as f64;
+    let (from, to) = (STOPS[i], STOPS[i + 1]);
+    let mix = |a: f64, b: f64| (a + (b - a) * t).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(from.0, to.0),
+        mix(from.1, to.1),
+        mix(from.2, to.2)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format::keymap_config_to_str, FingerKind, Hand};
+
+    #[test]
+    fn test_kle_import() {
+        let json = r#"[
+            {"name": "Tiny"},
+            ["Q", "W", {"w": 1.5}, "E", "Fn"],
+            [{"w": 1.25}, "Shift", {"d": true}, "logo", "!\n1", "Shift"],
+            [{"w": 0.5}, "A", {"w": 0.5}, "S", {"x": 1}, ""],
+            [{"r": 90, "rx": 6, "ry": 0}, "Enter"]
+        ]"#;
+        let keys = parse_kle(json).unwrap();
+        assert_eq!(keys.len(), 11);
+        assert_eq!(keys[2].center, (2.75, 0.5));
+        assert_eq!(keys[2].size, (1.5, 1.0));
+        let enter = &keys[10];
+        assert_eq!(enter.rotation, 90.0);
+        assert!((enter.center.0 - 5.5).abs() < 1e-9 && (enter.center.1 - 0.5).abs() < 1e-9);
+
+        let grid = KleGrid::new(&keys).unwrap();
+        assert_eq!(grid.skipped, vec!["Fn", ""]);
+        let codes: Vec<Keycode> = grid.keys.iter().map(|(code, ..)| *code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                Keycode::Q,
+                Keycode::W,
+                Keycode::E,
+                Keycode::Enter,
+                Keycode::LShift,
+                Keycode::Key1,
+                Keycode::RShift,
+                Keycode::A,
+                Keycode::S
+            ]
+        );
+        // The rotated Enter ends up right of E on the first row, and S is
+        // pushed right of A where they would share a column
+        assert_eq!(grid.keys[3].1, (5.0, 0.0));
+        assert_eq!(grid.keys[7].1, (0.0, 2.0));
+        assert_eq!(grid.keys[8].1, (1.0, 2.0));
+        // The geometry keeps the keys where they are drawn
+        assert_eq!(grid.keys[2].2.width, 1.5);
+        assert_eq!(grid.keys[3].2.rotation, 90.0);
+        let (dx, dy) = grid.keys[3].2.displacement();
+        assert!((dx - 0.25).abs() < 1e-9 && dy.abs() < 1e-9);
+
+        let annotations: Annotations = toml::from_str(
+            r#"
+            fingers = { LP = 70 }
+            keys = { Q = { finger = "LR", score = 80 } }
+            "#,
+        )
+        .unwrap();
+        let config = grid
+            .keymap_config(
+                |code, _| {
+                    annotations
+                        .key(code)
+                        .unwrap_or_else(|| parse_key_annotation("LP 50"))
+                },
+                |finger| Ok(annotations.finger(finger).unwrap_or(1.0)),
+            )
+            .unwrap();
+        assert_eq!(config.keys.keys()[0].score, 0.8);
+        assert_eq!(
+            config.fingers[0].finger,
+            Finger {
+                hand: Hand::Left,
+                finger: FingerKind::Pinky
+            }
+        );
+        assert_eq!(config.fingers[0].score, 0.7);
+        let layout = keymap_config_to_str(&config).unwrap();
+        let reparsed = crate::format::parse_keymap_config(&layout).unwrap();
+        assert_eq!(reparsed.keys.keys().len(), 9);
+    }
+
+    #[test]
+    fn test_kle_export() {
+        let config = crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::default();
+        stats.individual_key_counts.insert(Keycode::E, 10);
+        stats.individual_key_counts.insert(Keycode::T, 5);
+        let json = export_kle(&layout, &config, Some(&stats));
+        assert!(json.contains(r##"{"c":"#d73027"},"E""##));
+        assert!(json.contains(r#""!\n1""#));
+
+        // The export reads back as the same keyboard
+        let grid = KleGrid::new(&parse_kle(&json).unwrap()).unwrap();
+        assert!(grid.skipped.is_empty());
+        let physical = config.keys.keys();
+        assert_eq!(grid.keys.len(), physical.len());
+        for (code, position, geometry) in &grid.keys {
+            let key = physical.iter().find(|key| key.code == *code).unwrap();
+            assert_eq!(*position, key.position);
+            assert_eq!(*geometry, KeyGeometry::default());
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Support for typing in more than one language: corpora tagged with their
+//! language, metrics of a layout for each of them, and a small layer for
+//! the characters a language adds to the shared alphas, like `ñ` or `ü`.
+
+use std::collections::HashMap;
+
+use crate::{
+    char_table::CharTable,
+    error::{Error, Result},
+    layout::Layout,
+    nav::key_effort,
+    scoring::{hand_patterns, same_finger_bigrams, LayoutScorer},
+    stats::{char_to_key_event, process_text, Stats},
+    KeymapConfig,
+};
+
+/// Splits a corpus argument like `es=novel.txt` into its language and path.
+/// Untagged paths have no language.
+pub fn parse_tagged(arg: &str) -> (Option<&str>, &str) {
+    match arg.split_once('=') {
+        Some((language, path)) if !language.is_empty() && !language.contains('/') => {
+            (Some(language), path)
+        }
+        _ => (None, arg),
+    }
+}
+
+/// The text written in one language.
+pub struct LanguageCorpus {
+    pub language: String,
+    /// The key presses it would take to type the text on a US layout.
+    pub stats: Stats,
+    /// Lowercased characters of the text that a US layout can't type.
+    pub extra_char_counts: HashMap<char, u64>,
+}
+
+impl LanguageCorpus {
+    pub fn new(language: &str) -> Self {
+        Self {
+            language: language.to_string(),
+            stats: Stats::new(),
+            extra_char_counts: HashMap::new(),
+        }
+    }
+
+    /// Adds the text file at `path`.
+    pub fn add_text(&mut self, path: &str) -> Result<()> {
+        self.stats.merge(process_text(path, &CharTable::default())?);
+        let text =
+            std::fs::read_to_string(path).map_err(|error| Error::from(error).in_file(path))?;
+        for c in text.chars() {
+            if c.is_alphabetic() && char_to_key_event(c).is_none() {
+                let lower = c.to_lowercase().next().unwrap_or(c);
+                *self.extra_char_counts.entry(lower).or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the tagged corpus arguments, like `es=novel.txt`, into one corpus
+/// per language in the order they first appear.  Untagged paths are
+/// skipped.
+pub fn read_language_corpora(args: &[String]) -> Result<Vec<LanguageCorpus>> {
+    let mut corpora: Vec<LanguageCorpus> = vec![];
+    for arg in args {
+        let (Some(language), path) = parse_tagged(arg) else {
+            continue;
+        };
+        let i = match corpora.iter().position(|c| c.language == language) {
+            Some(i) => i,
+            None => {
+                corpora.push(LanguageCorpus::new(language));
+                corpora.len() - 1
+            }
+        };
+        corpora[i].add_text(path)?;
+    }
+    Ok(corpora)
+}
+
+/// How well a layout fits the text of one language.
+#[derive(Debug, Clone)]
+pub struct LanguageMetrics {
+    pub language: String,
+    /// The score of the layout normalized by the max possible score.
+    pub score: f64,
+    /// The share of bigrams typed with the same finger.
+    pub same_finger_rate: f64,
+    pub alternation_rate: f64,
+    /// The share of letters the layout can't type.
+    pub extra_char_rate: f64,
+}
+
+pub fn language_metrics(
+    layout: &Layout,
+    corpus: &LanguageCorpus,
+    keymap_config: &KeymapConfig,
+    scorer: &impl LayoutScorer,
+) -> LanguageMetrics {
+    let stats = &corpus.stats;
+    let score = scorer.score(layout, stats, keymap_config)
+        / scorer.max_possible_score(stats).max(f64::MIN_POSITIVE);
+    let bigrams: u64 = stats.consecutive_key_counts.values().sum();
+    let sfbs = same_finger_bigrams(layout, stats, keymap_config);
+    let extra_chars: u64 = corpus.extra_char_counts.values().sum();
+    let letters: u64 = stats
+        .char_counts
+        .iter()
+        .filter(|(c, _)| c.is_alphabetic())
+        .map(|(_, count)| count)
+        .sum::<u64>()
+        + extra_chars;
+    LanguageMetrics {
+        language: corpus.language.clone(),
+        score,
+        same_finger_rate: sfbs.count as f64 / bigrams.max(1) as f64,
+        alternation_rate: hand_patterns(layout, stats, keymap_config).alternation_rate(),
+        extra_char_rate: extra_chars as f64 / letters.max(1) as f64,
+    }
+}
+
+pub struct LanguageLayerConfig {
+    /// The most characters to put on the layer.
+    pub max_chars: usize,
+    /// Effort of holding the layer key, relative to pressing it, paid for
+    /// every character typed on the layer.
+    pub hold_cost: f64,
+    /// Effort added for every normal press of the hold key, since it
+    /// becomes a tap-hold key that can misfire.
+    pub tap_hold_penalty: f64,
+}
+
+impl Default for LanguageLayerConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: 8,
+            hold_cost: 0.5,
+            tap_hold_penalty: 0.1,
+        }
+    }
+}
+
+/// A layer for the extra characters of a language.
+#[derive(Debug, Clone)]
+pub struct LanguageLayer {
+    pub language: String,
+    /// The physical key held to activate the layer.
+    pub hold: usize,
+    /// The characters on the layer and their physical keys, most frequent
+    /// first.
+    pub chars: Vec<(char, usize)>,
+    /// Effort of typing the characters on the layer, including holding it.
+    pub cost: f64,
+}
+
+/// Places the most frequent extra characters of `corpus` on the most
+/// comfortable keys of the hand opposite the hold key, trying every key as
+/// the hold key.  Returns `None` if the language has no extra characters.
+pub fn propose_language_layer(
+    layout: &Layout,
+    corpus: &LanguageCorpus,
+    config: &KeymapConfig,
+    layer_config: &LanguageLayerConfig,
+) -> Option<LanguageLayer> {
+    let mut chars: Vec<(char, u64)> = corpus
+        .extra_char_counts
+        .iter()
+        .map(|(&c, &count)| (c, count))
+        .collect();
+    chars.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    chars.truncate(layer_config.max_chars);
+    if chars.is_empty() {
+        return None;
+    }
+    let total: u64 = chars.iter().map(|(_, count)| count).sum();
+
+    let physical = config.keys.keys();
+    let mut best: Option<LanguageLayer> = None;
+    for hold in 0..physical.len() {
+        let hand = physical[hold].finger.hand;
+        let mut free: Vec<usize> = (0..physical.len())
+            .filter(|&i| i != hold && physical[i].finger.hand != hand)
+            .collect();
+        if free.len() < chars.len() {
+            continue;
+        }
+        free.sort_by(|&a, &b| key_effort(config, a).total_cmp(&key_effort(config, b)));
+
+        let hold_key_presses = corpus
+            .stats
+            .individual_key_counts
+            .get(&layout.keys()[hold].keycode(false))
+            .copied()
+            .unwrap_or(0);
+        let mut cost = total as f64 * layer_config.hold_cost * key_effort(config, hold)
+            + hold_key_presses as f64 * layer_config.tap_hold_penalty;
+        let placed: Vec<(char, usize)> = chars
+            .iter()
+            .zip(free)
+            .map(|(&(c, count), i)| {
+                cost += count as f64 * key_effort(config, i);
+                (c, i)
+            })
+            .collect();
+
+        if best.as_ref().is_none_or(|b| cost < b.cost) {
+            best = Some(LanguageLayer {
+                language: corpus.language.clone(),
+                hold,
+                chars: placed,
+                cost,
+            });
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_propose_language_layer() {
+        assert_eq!(parse_tagged("es=libro.txt"), (Some("es"), "libro.txt"));
+        assert_eq!(parse_tagged("notes.md"), (None, "notes.md"));
+        assert_eq!(parse_tagged("./a=b.txt"), (None, "./a=b.txt"));
+
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut corpus = LanguageCorpus::new("es");
+        corpus.extra_char_counts.insert('ñ', 10);
+        corpus.extra_char_counts.insert('é', 30);
+
+        let layer =
+            propose_language_layer(&layout, &corpus, &config, &LanguageLayerConfig::default())
+                .unwrap();
+        assert_eq!(layer.chars.len(), 2);
+        assert_eq!(layer.chars[0].0, 'é');
+        let physical = config.keys.keys();
+        for (_, i) in &layer.chars {
+            assert_ne!(physical[*i].finger.hand, physical[layer.hold].finger.hand);
+        }
+        assert!(key_effort(&config, layer.chars[0].1) <= key_effort(&config, layer.chars[1].1));
+    }
+}
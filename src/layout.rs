@@ -0,0 +1,714 @@
+//! Logical keyboard layouts: which key goes on which physical key.
+
+use crate::{
+    char_table::built_in_keycode,
+    format::{map_keycode_to_str, GridItem},
+    KeymapConfig,
+};
+use ahash::{HashMap, HashMapExt};
+use device_query::Keycode;
+use genetic::{Crossover, Gen, Mutate};
+use rand::seq::SliceRandom;
+use std::{fmt, ops::Range, str::FromStr, sync::Arc};
+
+/// An assignment of logical [`Key`]s to the physical keys of a [`KeymapConfig`].
+///
+/// The key at index `i` is placed on the `i`th physical key of the keymap.
+#[derive(Debug)]
+pub struct Layout {
+    keys: Vec<Key>,
+    key_map: HashMap<Key, usize>,
+    /// Which physical keys [`Mutate`] leaves alone, shared by every layout
+    /// descended from the same [`Gen`].  Empty if nothing is pinned.
+    pinned: Arc<[bool]>,
+}
+
+impl Clone for Layout {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            key_map: self.key_map.clone(),
+            pinned: self.pinned.clone(),
+        }
+    }
+
+    /// Reuses the allocations of `self`, which matters when layouts are
+    /// copied millions of times while annealing.
+    fn clone_from(&mut self, source: &Self) {
+        self.keys.clone_from(&source.keys);
+        self.key_map.clone_from(&source.key_map);
+        self.pinned.clone_from(&source.pinned);
+    }
+}
+
+impl Layout {
+    pub fn new(keys: Vec<Key>) -> Self {
+        let mut key_map = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            key_map.insert(*key, i);
+        }
+        Self {
+            keys,
+            key_map,
+            pinned: Arc::from([]),
+        }
+    }
+
+    fn is_pinned(&self, i: usize) -> bool {
+        self.pinned.get(i).copied().unwrap_or(false)
+    }
+
+    /// The layout printed on the keycaps of the physical keyboard, i.e. every
+    /// physical key produces its own keycode.
+    pub fn from_keymap(config: &KeymapConfig) -> Self {
+        Self::new(
+            config
+                .keys
+                .keys()
+                .iter()
+                .map(|p| Key::from_keycode(p.code))
+                .collect(),
+        )
+    }
+
+    pub fn swap(&mut self, i: usize, j: usize) {
+        self.keys.swap(i, j);
+        self.key_map.insert(self.keys[i], i);
+        self.key_map.insert(self.keys[j], j);
+    }
+
+    /// The index of the physical key that `key` is placed on.
+    pub fn get(&self, key: &Key) -> usize {
+        *self.key_map.get(key).unwrap()
+    }
+
+    /// The index of the physical key whose unshifted keycode is `code`, if
+    /// any.
+    pub fn position_of_keycode(&self, code: Keycode) -> Option<usize> {
+        self.keys.iter().position(|key| key.keycode(false) == code)
+    }
+
+    /// The index of the physical key that types `c`, shifted or not.
+    pub fn position_of_char(&self, c: char) -> Option<usize> {
+        self.keys.iter().position(|key| match key {
+            Key::Normal { normal, shifted } => *normal == c || *shifted == c,
+            Key::Space => c == ' ',
+            _ => false,
+        })
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// Readies a layout made outside of [`Gen`], like a preset or a
+    /// previous best, to join a population for `config`: its pinned keys
+    /// are swapped into place and kept there.  Fails if the layout doesn't
+    /// have exactly the keys of the keyboard.
+    pub fn seed(mut self, config: &KeymapConfig) -> Result<Self, String> {
+        let expected = Self::from_keymap(config);
+        if self.keys.len() != expected.keys.len() || self.key_map.len() != self.keys.len() {
+            return Err(format!(
+                "it has {} distinct keys, the keyboard has {}",
+                self.key_map.len(),
+                expected.keys.len()
+            ));
+        }
+        if let Some(key) = expected
+            .keys
+            .iter()
+            .find(|key| !self.key_map.contains_key(key))
+        {
+            return Err(format!("it has no {:?} key", key));
+        }
+        self.apply_pins(config);
+        Ok(self)
+    }
+
+    /// Swaps the pinned keys of `config` into place and keeps [`Mutate`]
+    /// from moving them.
+    fn apply_pins(&mut self, config: &KeymapConfig) {
+        if config.pins.is_empty() {
+            return;
+        }
+        for (&i, key) in &config.pins {
+            let j = self.get(key);
+            self.swap(i, j);
+        }
+        self.pinned = (0..self.keys.len())
+            .map(|i| config.pins.contains_key(&i))
+            .collect();
+    }
+}
+
+/// Well known layouts to start optimizing from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Qwerty,
+    Colemak,
+    Dvorak,
+}
+
+impl Preset {
+    /// Where the preset puts the characters of QWERTY, as pairs of
+    /// QWERTY and preset rows.  Characters missing from them stay put.
+    fn rows(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::Qwerty => &[],
+            Preset::Colemak => &[
+                ("qwertyuiop", "qwfpgjluy;"),
+                ("asdfghjkl;", "arstdhneio"),
+                ("zxcvbnm", "zxcvbkm"),
+            ],
+            Preset::Dvorak => &[
+                ("-=", "[]"),
+                ("qwertyuiop[]", "',.pyfgcrl/="),
+                ("asdfghjkl;'", "aoeuidhtns-"),
+                ("zxcvbnm,./", ";qjkxbmwvz"),
+            ],
+        }
+    }
+
+    /// The preset on the keys of `config` that QWERTY would use.  Keys the
+    /// keyboard has in other places, like the thumb clusters, stay where
+    /// the keycaps say.
+    pub fn layout(&self, config: &KeymapConfig) -> Layout {
+        let mut moves = HashMap::new();
+        for (qwerty, preset) in self.rows() {
+            for (from, to) in qwerty.chars().zip(preset.chars()) {
+                moves.insert(from, to);
+            }
+        }
+        let keys = Layout::from_keymap(config)
+            .keys
+            .into_iter()
+            .map(|key| match key {
+                Key::Normal { normal, .. } => match moves.get(&normal) {
+                    Some(&c) => Key::from_char_default_shifted(c),
+                    None => key,
+                },
+                key => key,
+            })
+            .collect();
+        Layout::new(keys)
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "qwerty" => Ok(Preset::Qwerty),
+            "colemak" => Ok(Preset::Colemak),
+            "dvorak" => Ok(Preset::Dvorak),
+            _ => Err(format!("expected qwerty, colemak, or dvorak, got {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Preset::Qwerty => write!(f, "qwerty"),
+            Preset::Colemak => write!(f, "colemak"),
+            Preset::Dvorak => write!(f, "dvorak"),
+        }
+    }
+}
+
+/// Keys that are active while the physical key at index `hold` is held.
+/// Physical keys without a key on the layer fall through to the base
+/// layout.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub hold: usize,
+    pub keys: Vec<Option<Key>>,
+}
+
+/// The fraction of key positions (shifted and unshifted) that two layouts
+/// have in common.
+pub fn layout_similarity(l1: &Layout, l2: &Layout) -> f32 {
+    let mut score = 0.0;
+    for (key1, key2) in l1.keys().iter().zip(l2.keys().iter()) {
+        let key1_code = key1.keycode(false);
+        let key2_code = key2.keycode(false);
+        if key1_code == key2_code {
+            score += 1.0;
+        }
+        let shift_key1_code = key1.keycode(true);
+        let shift_key2_code = key2.keycode(true);
+        if shift_key1_code == shift_key2_code {
+            score += 1.0;
+        }
+    }
+    score / (l1.keys().len() * 2) as f32
+}
+
+impl Gen for Layout {
+    type Config = KeymapConfig;
+
+    fn gen<R: rand::Rng>(rng: &mut R, config: &Self::Config) -> Self {
+        let mut keys = Self::from_keymap(config).keys;
+        keys.shuffle(rng);
+        let mut layout = Self::new(keys);
+        layout.apply_pins(config);
+        layout
+    }
+}
+
+// Partially mapped crossover: a child takes a run of positions from one
+// parent and as many of the others as it can from the other parent, so
+// keys keep the places they had in either.  Keys both parents have in the
+// same place, like the pinned keys, stay there in both children.
+impl Crossover for Layout {
+    fn crossover<R: rand::Rng>(&self, rng: &mut R, other: &Self) -> (Self, Self) {
+        let n = self.keys().len();
+        let (a, b) = (rng.gen_range(0..=n), rng.gen_range(0..=n));
+        let segment = a.min(b)..a.max(b);
+        let mut child1 = Layout::new(partially_mapped(self, other, segment.clone()));
+        let mut child2 = Layout::new(partially_mapped(other, self, segment));
+        child1.pinned = self.pinned.clone();
+        child2.pinned = self.pinned.clone();
+        (child1, child2)
+    }
+}
+
+/// The keys of `p1` in `segment` and those of `p2` elsewhere.  A key of
+/// `p2` pushed out of the segment goes where `p2` has the key of `p1` that
+/// took its place, or if that's in the segment too, where `p2` has the key
+/// of `p1` there, and so on.  Both parents need the same keys.
+fn partially_mapped(p1: &Layout, p2: &Layout, segment: Range<usize>) -> Vec<Key> {
+    let mut child = p2.keys.clone();
+    child[segment.clone()].copy_from_slice(&p1.keys[segment.clone()]);
+    for i in segment.clone() {
+        let key = p2.keys[i];
+        if segment.contains(&p1.get(&key)) {
+            continue;
+        }
+        let mut j = i;
+        while segment.contains(&j) {
+            j = p2.get(&p1.keys[j]);
+        }
+        child[j] = key;
+    }
+    child
+}
+
+impl Mutate for Layout {
+    fn mutate<R: rand::Rng>(&mut self, rng: &mut R, rate: f32) {
+        for i in 0..self.keys().len() {
+            for j in (i + 1)..self.keys().len() {
+                if rng.gen_bool(rate as f64) && !self.is_pinned(i) && !self.is_pinned(j) {
+                    self.swap(i, j);
+                }
+            }
+        }
+    }
+}
+
+/// The mutation rate for a population whose diversity is `diversity`:
+/// `base` while it's at least `target`, rising linearly to `max` as the
+/// population collapses to copies of one layout.
+pub fn adaptive_mutation_rate(base: f64, max: f64, diversity: f64, target: f64) -> f64 {
+    if target <= 0.0 {
+        return base;
+    }
+    let collapse = ((target - diversity) / target).clamp(0.0, 1.0);
+    base + (max - base).max(0.0) * collapse
+}
+
+/// A logical key that can be placed anywhere on the physical keyboard.
+///
+/// Character keys carry both the character they produce and the character
+/// they produce while shift is held.  These can be any character, like `ü`,
+/// as long as a US layout or one of the built-in
+/// [`CharTable`](crate::char_table::CharTable)s has a key that types it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Normal { normal: char, shifted: char },
+    Backspace,
+    Tab,
+    Enter,
+    CapsLock,
+    LShift,
+    RShift,
+    LCtrl,
+    RCtrl,
+    LAlt,
+    RAlt,
+    LMeta,
+    RMeta,
+    Space,
+    Escape,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Left,
+    Right,
+    Up,
+    Down,
+    Delete,
+}
+
+impl Key {
+    pub fn from_keycode(code: Keycode) -> Self {
+        match code {
+            Keycode::Backspace => Key::Backspace,
+            Keycode::Tab => Key::Tab,
+            Keycode::Enter => Key::Enter,
+            Keycode::CapsLock => Key::CapsLock,
+            Keycode::LShift => Key::LShift,
+            Keycode::RShift => Key::RShift,
+            Keycode::LControl => Key::LCtrl,
+            Keycode::RControl => Key::RCtrl,
+            Keycode::LAlt => Key::LAlt,
+            Keycode::RAlt => Key::RAlt,
+            Keycode::LMeta => Key::LMeta,
+            Keycode::RMeta => Key::RMeta,
+            Keycode::Space => Key::Space,
+            Keycode::Escape => Key::Escape,
+            Keycode::Home => Key::Home,
+            Keycode::End => Key::End,
+            Keycode::PageUp => Key::PageUp,
+            Keycode::PageDown => Key::PageDown,
+            Keycode::Left => Key::Left,
+            Keycode::Right => Key::Right,
+            Keycode::Up => Key::Up,
+            Keycode::Down => Key::Down,
+            Keycode::Delete => Key::Delete,
+            _ => Key::Normal {
+                normal: keycode_to_char(code),
+                shifted: default_shifted(keycode_to_char(code)),
+            },
+        }
+    }
+
+    pub fn from_char_default_shifted(c: char) -> Self {
+        Key::Normal {
+            normal: c,
+            shifted: default_shifted(c),
+        }
+    }
+
+    pub fn keycode(&self, shift: bool) -> Keycode {
+        match self {
+            Key::Normal { normal, shifted } => {
+                if shift {
+                    char_to_keycode(*shifted)
+                } else {
+                    char_to_keycode(*normal)
+                }
+            }
+            Key::Backspace => Keycode::Backspace,
+            Key::Tab => Keycode::Tab,
+            Key::Enter => Keycode::Enter,
+            Key::CapsLock => Keycode::CapsLock,
+            Key::LShift => Keycode::LShift,
+            Key::RShift => Keycode::RShift,
+            Key::LCtrl => Keycode::LControl,
+            Key::RCtrl => Keycode::RControl,
+            Key::LAlt => Keycode::LAlt,
+            Key::RAlt => Keycode::RAlt,
+            Key::LMeta => Keycode::LMeta,
+            Key::RMeta => Keycode::RMeta,
+            Key::Space => Keycode::Space,
+            Key::Escape => Keycode::Escape,
+            Key::Home => Keycode::Home,
+            Key::End => Keycode::End,
+            Key::PageUp => Keycode::PageUp,
+            Key::PageDown => Keycode::PageDown,
+            Key::Left => Keycode::Left,
+            Key::Right => Keycode::Right,
+            Key::Up => Keycode::Up,
+            Key::Down => Keycode::Down,
+            Key::Delete => Keycode::Delete,
+        }
+    }
+}
+
+impl GridItem for Key {
+    fn num_items() -> usize {
+        2
+    }
+
+    fn get_item(&self, i: usize) -> Option<String> {
+        match i {
+            0 => {
+                let code = self.keycode(false);
+                Some(map_keycode_to_str(code).unwrap().to_string())
+            }
+            1 => {
+                let code = self.keycode(true);
+                Some(map_keycode_to_str(code).unwrap().to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The key that types `c` on a US layout, or else on the first built-in
+/// system layout that has it.
+pub fn char_to_keycode(c: char) -> Keycode {
+    match c {
+        'a' => Keycode::A,
+        'b' => Keycode::B,
+        'c' => Keycode::C,
+        'd' => Keycode::D,
+        'e' => Keycode::E,
+        'f' => Keycode::F,
+        'g' => Keycode::G,
+        'h' => Keycode::H,
+        'i' => Keycode::I,
+        'j' => Keycode::J,
+        'k' => Keycode::K,
+        'l' => Keycode::L,
+        'm' => Keycode::M,
+        'n' => Keycode::N,
+        'o' => Keycode::O,
+        'p' => Keycode::P,
+        'q' => Keycode::Q,
+        'r' => Keycode::R,
+        's' => Keycode::S,
+        't' => Keycode::T,
+        'u' => Keycode::U,
+        'v' => Keycode::V,
+        'w' => Keycode::W,
+        'x' => Keycode::X,
+        'y' => Keycode::Y,
+        'z' => Keycode::Z,
+        'A' => Keycode::A,
+        'B' => Keycode::B,
+        'C' => Keycode::C,
+        'D' => Keycode::D,
+        'E' => Keycode::E,
+        'F' => Keycode::F,
+        'G' => Keycode::G,
+        'H' => Keycode::H,
+        'I' => Keycode::I,
+        'J' => Keycode::J,
+        'K' => Keycode::K,
+        'L' => Keycode::L,
+        'M' => Keycode::M,
+        'N' => Keycode::N,
+        'O' => Keycode::O,
+        'P' => Keycode::P,
+        'Q' => Keycode::Q,
+        'R' => Keycode::R,
+        'S' => Keycode::S,
+        'T' => Keycode::T,
+        'U' => Keycode::U,
+        'V' => Keycode::V,
+        'W' => Keycode::W,
+        'X' => Keycode::X,
+        'Y' => Keycode::Y,
+        'Z' => Keycode::Z,
+        '0' => Keycode::Key0,
+        '1' => Keycode::Key1,
+        '2' => Keycode::Key2,
+        '3' => Keycode::Key3,
+        '4' => Keycode::Key4,
+        '5' => Keycode::Key5,
+        '6' => Keycode::Key6,
+        '7' => Keycode::Key7,
+        '8' => Keycode::Key8,
+        '9' => Keycode::Key9,
+        '!' => Keycode::Key1,
+        '@' => Keycode::Key2,
+        '#' => Keycode::Key3,
+        '$' => Keycode::Key4,
+        '%' => Keycode::Key5,
+        '^' => Keycode::Key6,
+        '&' => Keycode::Key7,
+        '*' => Keycode::Key8,
+        '(' => Keycode::Key9,
+        ')' => Keycode::Key0,
+        '-' => Keycode::Minus,
+        '_' => Keycode::Minus,
+        '=' => Keycode::Equal,
+        '+' => Keycode::Equal,
+        '[' => Keycode::LeftBracket,
+        '{' => Keycode::LeftBracket,
+        ']' => Keycode::RightBracket,
+        '}' => Keycode::RightBracket,
+        '\\' => Keycode::BackSlash,
+        '|' => Keycode::BackSlash,
+        ';' => Keycode::Semicolon,
+        ':' => Keycode::Semicolon,
+        '\'' => Keycode::Apostrophe,
+        '"' => Keycode::Apostrophe,
+        ',' => Keycode::Comma,
+        '<' => Keycode::Comma,
+        '.' => Keycode::Dot,
+        '>' => Keycode::Dot,
+        '/' => Keycode::Slash,
+        '?' => Keycode::Slash,
+        ' ' => Keycode::Space,
+        '`' => Keycode::Grave,
+        '~' => Keycode::Grave,
+        c => built_in_keycode(c).unwrap_or_else(|| panic!("no system layout types {c}")),
+    }
+}
+
+pub fn keycode_to_char(code: Keycode) -> char {
+    match code {
+        Keycode::A => 'a',
+        Keycode::B => 'b',
+        Keycode::C => 'c',
+        Keycode::D => 'd',
+        Keycode::E => 'e',
+        Keycode::F => 'f',
+        Keycode::G => 'g',
+        Keycode::H => 'h',
+        Keycode::I => 'i',
+        Keycode::J => 'j',
+        Keycode::K => 'k',
+        Keycode::L => 'l',
+        Keycode::M => 'm',
+        Keycode::N => 'n',
+        Keycode::O => 'o',
+        Keycode::P => 'p',
+        Keycode::Q => 'q',
+        Keycode::R => 'r',
+        Keycode::S => 's',
+        Keycode::T => 't',
+        Keycode::U => 'u',
+        Keycode::V => 'v',
+        Keycode::W => 'w',
+        Keycode::X => 'x',
+        Keycode::Y => 'y',
+        Keycode::Z => 'z',
+        Keycode::Key0 => '0',
+        Keycode::Key1 => '1',
+        Keycode::Key2 => '2',
+        Keycode::Key3 => '3',
+        Keycode::Key4 => '4',
+        Keycode::Key5 => '5',
+        Keycode::Key6 => '6',
+        Keycode::Key7 => '7',
+        Keycode::Key8 => '8',
+        Keycode::Key9 => '9',
+        Keycode::Minus => '-',
+        Keycode::Equal => '=',
+        Keycode::LeftBracket => '[',
+        Keycode::RightBracket => ']',
+        Keycode::BackSlash => '\\',
+        Keycode::Semicolon => ';',
+        Keycode::Apostrophe => '\'',
+        Keycode::Comma => ',',
+        Keycode::Dot => '.',
+        Keycode::Slash => '/',
+        Keycode::Grave => '`',
+        Keycode::Space => ' ',
+        code => unimplemented!("{:?}", code),
+    }
+}
+
+/// What the key typing `c` types shifted on a US layout, or the uppercase
+/// of other letters.
+pub fn default_shifted(c: char) -> char {
+    match c {
+        'a' => 'A',
+        'b' => 'B',
+        'c' => 'C',
+        'd' => 'D',
+        'e' => 'E',
+        'f' => 'F',
+        'g' => 'G',
+        'h' => 'H',
+        'i' => 'I',
+        'j' => 'J',
+        'k' => 'K',
+        'l' => 'L',
+        'm' => 'M',
+        'n' => 'N',
+        'o' => 'O',
+        'p' => 'P',
+        'q' => 'Q',
+        'r' => 'R',
+        's' => 'S',
+        't' => 'T',
+        'u' => 'U',
+        'v' => 'V',
+        'w' => 'W',
+        'x' => 'X',
+        'y' => 'Y',
+        'z' => 'Z',
+        '0' => ')',
+        '1' => '!',
+        '2' => '@',
+        '3' => '#',
+        '4' => '$',
+        '5' => '%',
+        '6' => '^',
+        '7' => '&',
+        '8' => '*',
+        '9' => '(',
+        '-' => '_',
+        '=' => '+',
+        '[' => '{',
+        ']' => '}',
+        '\\' => '|',
+        ';' => ':',
+        '\'' => '"',
+        ',' => '<',
+        '.' => '>',
+        '/' => '?',
+        '`' => '~',
+        c if !c.is_ascii() => {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(upper), None) => upper,
+                // Like ß, whose uppercase is SS
+                _ => c,
+            }
+        }
+        _ => unimplemented!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_crossover() {
+        let mut config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let keycaps = Layout::from_keymap(&config);
+        config.pin(0, keycaps.keys()[0]).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            let p1 = Layout::gen(&mut rng, &config);
+            let p2 = Layout::gen(&mut rng, &config);
+            let (child1, child2) = p1.crossover(&mut rng, &p2);
+            for child in [child1, child2] {
+                assert_eq!(child.key_map.len(), keycaps.keys().len());
+                assert!(keycaps
+                    .keys()
+                    .iter()
+                    .all(|key| child.key_map.contains_key(key)));
+                for (i, key) in child.keys().iter().enumerate() {
+                    if p1.keys()[i] == p2.keys()[i] {
+                        assert_eq!(*key, p1.keys()[i]);
+                    }
+                }
+                assert_eq!(child.keys()[0], keycaps.keys()[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adaptive_mutation_rate() {
+        assert_eq!(adaptive_mutation_rate(0.001, 0.01, 0.5, 0.2), 0.001);
+        assert_eq!(adaptive_mutation_rate(0.001, 0.01, 0.2, 0.2), 0.001);
+        assert!((adaptive_mutation_rate(0.0, 0.01, 0.1, 0.2) - 0.005).abs() < 1e-12);
+        assert_eq!(adaptive_mutation_rate(0.0, 0.01, 0.0, 0.2), 0.01);
+        // A max below the base never lowers the rate
+        assert_eq!(adaptive_mutation_rate(0.01, 0.0, 0.0, 0.2), 0.01);
+        assert_eq!(adaptive_mutation_rate(0.01, 0.05, 0.0, 0.0), 0.01);
+    }
+}
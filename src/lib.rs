@@ -1,16 +1,241 @@
-pub mod layout_format;
+//! Tools for collecting typing statistics and generating keyboard layouts
+//! optimized for them.
+//!
+//! The crate is split into a few modules that mirror the pipeline of the
+//! binaries:
+//!
+//! - [`capture`]: the key event log written by the `keylogger`.
+//! - [`binlog`]: a compact binary format for the key event log.
+//! - [`diagnostics`]: checking that the `keylogger` can see key presses.
+//! - [`stats`]: aggregating a key log into [`Stats`](stats::Stats).
+//! - [`stats_export`]: the frequency tables as JSON or CSV for other tools.
+//! - [`sessions`]: splitting a key log into typing sessions.
+//! - [`char_table`]: what the keys type on the system layout of a key log.
+//! - [`format`]: the `.layout` file describing a physical keyboard.
+//! - [`kle`]: importing a physical keyboard from keyboard-layout-editor.com.
+//! - [`layout`]: logical layouts to place on a physical keyboard.
+//! - [`scoring`]: the effort model used to rate layouts.
+//! - [`cost_model`]: the effort model as costs for external optimizers.
+//! - [`qap`]: optimal placements and bounds from the cost model.
+//! - [`scenarios`]: rating layouts against several sets of stats at once.
+//! - [`pareto`]: trading off several objectives instead of one score.
+//! - [`search`]: refining a layout by swapping its keys.
+//! - [`delta`]: scoring those swaps without rescoring the whole layout.
+//! - [`dashboard`]: watching the search in the terminal.
+//! - [`language`]: typing in more than one language.
+//! - [`privacy`]: anonymizing stats before sharing them.
+//! - [`remap`] and [`trial`]: trying a layout live and measuring the result.
+//! - [`ab_test`]: choosing between two layouts from trial sessions.
+//! - [`nav`]: proposing a navigation layer.
+//! - [`numeric`]: recommending how to enter numbers.
+//! - [`combos`]: proposing key combos for frequent sequences.
+//! - [`qmk`]: exporting a layout as QMK firmware.
+//! - [`kanata`]: exporting a layout as a kanata or kmonad config.
+//! - [`os_layout`]: exporting a layout for Windows or macOS to install.
+//! - [`report`]: tables for eyeballing a layout against the stats.
+//! - [`heatmap`]: drawing how often every key is pressed as an SVG image.
+//! - [`cache`]: remembering scores between runs.
+//! - [`editor`]: weighting the command keys of Vim and Emacs.
+//! - [`config`]: settings shared by every binary, like number formatting.
+//! - [`run_file`]: everything a run of `explore_layouts` needs in one file.
+//! - [`retention`]: keeping the history of long runs from piling up.
+//! - [`constraints`]: restricting where keys may be placed.
+//! - [`error`]: what went wrong reading a log or layout file.
+//!
+//! The [`prelude`] re-exports the commonly used items:
+//!
+//! ```no_run
+//! use keyboard_layout_generator::prelude::*;
+//!
+//! # fn main() -> keyboard_layout_generator::error::Result<()> {
+//! let mut stats = process_log("keys.log")?;
+//! let keymap = parse_keymap_config(&std::fs::read_to_string("kinesis.layout")?)?;
+//! let mut scoring = ScoringConfig::default();
+//! prepare(&mut stats, &mut scoring, &keymap, &PrepareOptions::default());
+//! let layout = Layout::from_keymap(&keymap);
+//! println!("{}", scoring.score(&layout, &stats, &keymap));
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod ab_test;
+pub mod binlog;
+pub mod cache;
+pub mod capture;
+pub mod char_table;
+pub mod combos;
+pub mod config;
+pub mod constraints;
+pub mod cost_model;
+pub mod dashboard;
+pub mod delta;
+pub mod diagnostics;
+pub mod editor;
+pub mod error;
+pub mod format;
+pub mod heatmap;
+pub mod kanata;
+pub mod kle;
+pub mod language;
+pub mod layout;
+pub mod nav;
+pub mod numeric;
+pub mod os_layout;
+pub mod pareto;
+pub mod privacy;
+pub mod qap;
+pub mod qmk;
+pub mod remap;
+pub mod report;
+pub mod retention;
+pub mod run_file;
+pub mod scenarios;
+pub mod scoring;
+pub mod search;
+mod serde_util;
+pub mod sessions;
 pub mod stats;
+pub mod stats_export;
+pub mod trial;
+
+pub mod prelude {
+    pub use crate::{
+        capture::{parse_event, write_event, KeyStateTracker},
+        format::{keymap_config_to_str, parse_keymap_config},
+        layout::{Key, Layout},
+        scoring::{layout_score, max_possible_score, LayoutScorer, ScoringConfig},
+        stats::{prepare, process_log, KeyProcessor, PrepareOptions, Stats},
+        Finger, FingerConfig, FingerKind, Hand, KeymapConfig, PhysicalKey, PhysicalKeyboard,
+    };
+}
+
+use std::collections::HashMap;
 
 use device_query::Keycode;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    error::{Error, Result},
+    layout::Key,
+    scoring::{KeyPairs, PairMatrix, Weights},
+};
+
+/// A physical keyboard and how comfortable each of its keys is to press.
 #[derive(Debug)]
 pub struct KeymapConfig {
     pub fingers: Vec<FingerConfig>,
     pub keys: PhysicalKeyboard,
+    /// Keys that stay on one physical key while optimizing, by the index
+    /// of the physical key.
+    pub pins: HashMap<usize, Key>,
+    /// How whole columns of the grid are moved, set with
+    /// [`set_columns`](Self::set_columns).
+    pub columns: ColumnGeometry,
+    /// The key every finger rests on, by the index of the physical key, as
+    /// declared in the keymap config.  See [`home_keys`](Self::home_keys).
+    pub home: HashMap<Finger, usize>,
+    /// Computed from the keys when first scored, see
+    /// [`distances`](Self::distances).
+    pub(crate) pairs: KeyPairs,
 }
 
-#[derive(Debug)]
+impl KeymapConfig {
+    /// Keeps `key` on the physical key at `position` while optimizing.
+    /// Fails if the keyboard doesn't have the position or the key, or if
+    /// either is already pinned to something else.
+    pub fn pin(&mut self, position: usize, key: Key) -> Result<()> {
+        let Some(code) = self.keys.keys().get(position).map(|p| p.code) else {
+            return Err(Error::Invalid(format!(
+                "the keyboard has no key {} to pin {:?} to, only {}",
+                position,
+                key.keycode(false),
+                self.keys.keys().len()
+            )));
+        };
+        if !self
+            .keys
+            .keys()
+            .iter()
+            .any(|p| Key::from_keycode(p.code) == key)
+        {
+            return Err(Error::Invalid(format!(
+                "{:?} isn't on the keyboard",
+                key.keycode(false)
+            )));
+        }
+        if let Some(pinned) = self.pins.get(&position).filter(|pinned| **pinned != key) {
+            return Err(Error::Invalid(format!(
+                "{:?} and {:?} are both pinned to {:?}",
+                pinned.keycode(false),
+                key.keycode(false),
+                code
+            )));
+        }
+        if let Some((&other, _)) = self
+            .pins
+            .iter()
+            .find(|&(&i, pinned)| *pinned == key && i != position)
+        {
+            return Err(Error::Invalid(format!(
+                "{:?} is pinned to both {:?} and {:?}",
+                key.keycode(false),
+                self.keys.keys()[other].code,
+                code
+            )));
+        }
+        self.pins.insert(position, key);
+        Ok(())
+    }
+
+    /// Moves the columns of the keyboard by `columns`, on top of the
+    /// geometry of every key.
+    pub fn set_columns(&mut self, columns: ColumnGeometry) {
+        for key in &mut self.keys.0 {
+            key.column_offset = columns.offset(key.position.0 as usize);
+        }
+        self.columns = columns;
+        self.pairs = KeyPairs::default();
+    }
+
+    /// How far apart every pair of keys is, see [`scoring::distance`].
+    /// Computed once, so the keys mustn't change after scoring a layout.
+    pub fn distances(&self) -> PairMatrix<'_> {
+        self.pairs.distances(self.keys.keys())
+    }
+
+    /// The [transition score](scoring::Weights::transition_score) of every
+    /// pair of keys with `weights`, computed once like
+    /// [`distances`](Self::distances).
+    pub fn transition_scores(&self, weights: &Weights) -> PairMatrix<'_> {
+        self.pairs.transitions(self.keys.keys(), weights)
+    }
+
+    /// The key every finger rests on and returns to, by the index of the
+    /// physical key.  Fingers without a declared home key rest on their
+    /// most comfortable key, the first one on ties.
+    pub fn home_keys(&self) -> HashMap<Finger, usize> {
+        let mut home = self.home.clone();
+        for (i, key) in self.keys.keys().iter().enumerate() {
+            if self.home.contains_key(&key.finger) {
+                continue;
+            }
+            let best = home.entry(key.finger).or_insert(i);
+            if key.score > self.keys.keys()[*best].score {
+                *best = i;
+            }
+        }
+        home
+    }
+
+    /// The physical keys whose keys may move, for choosing swaps.
+    pub fn unpinned(&self) -> Vec<usize> {
+        (0..self.keys.keys().len())
+            .filter(|i| !self.pins.contains_key(i))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct PhysicalKeyboard(Vec<PhysicalKey>);
 
 impl PhysicalKeyboard {
@@ -32,7 +257,113 @@ pub struct PhysicalKey {
     pub code: Keycode,
     pub finger: Finger,
     pub score: f64,
+    /// The column and row of the key in the grid of the `.layout` file.
     pub position: (f64, f64),
+    /// Where the key really is, relative to its cell of the grid.
+    pub geometry: KeyGeometry,
+    /// How far the column of the key is moved by the
+    /// [`ColumnGeometry`] of the keyboard.
+    pub column_offset: (f64, f64),
+}
+
+impl PhysicalKey {
+    /// The center of the key on the keyboard, in key widths, with the grid
+    /// cell of `position` moved by its column and the key's geometry.
+    pub fn center(&self) -> (f64, f64) {
+        let (dx, dy) = self.geometry.displacement();
+        (
+            self.position.0 + self.column_offset.0 + dx,
+            self.position.1 + self.column_offset.1 + dy,
+        )
+    }
+}
+
+/// Offsets of whole columns of the grid, for column-staggered and split
+/// keyboards like the Corne, in key widths.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnGeometry {
+    /// How far every column is moved down, starting with the leftmost one.
+    /// Columns past the end aren't moved.
+    pub stagger: Vec<f64>,
+    /// Where the keyboard splits into two halves.
+    pub split: Option<Split>,
+}
+
+/// The gap between the halves of a split keyboard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Split {
+    /// How many columns of the grid the left half has.
+    pub after: usize,
+    /// How far the right half is moved right, in key widths.
+    pub gap: f64,
+}
+
+impl ColumnGeometry {
+    /// How far the column at `column` is moved, right and down.
+    pub fn offset(&self, column: usize) -> (f64, f64) {
+        let x = match self.split {
+            Some(split) if column >= split.after => split.gap,
+            _ => 0.0,
+        };
+        let y = self.stagger.get(column).copied().unwrap_or(0.0);
+        (x, y)
+    }
+}
+
+/// The shape of a key and how far it is from the center of its grid cell,
+/// for row-staggered keyboards and tilted thumb clusters that don't fit a
+/// grid of square keys.  Distances are in key widths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyGeometry {
+    /// How far the key is moved from the center of its cell, right and
+    /// down, along the key's own axes.
+    pub offset: (f64, f64),
+    /// The width of the key, growing to the right of its cell.
+    pub width: f64,
+    /// How many degrees the key is turned clockwise about the center of its
+    /// cell, so an offset moves it along the tilt.
+    pub rotation: f64,
+    /// How far the key sits below the keys around it, in key widths, for
+    /// keywells like the Kinesis Advantage's where fingers reach down to
+    /// the outer keys.
+    pub depth: f64,
+}
+
+impl Default for KeyGeometry {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            width: 1.0,
+            rotation: 0.0,
+            depth: 0.0,
+        }
+    }
+}
+
+impl KeyGeometry {
+    /// How far the center of the key is from the center of its cell, right
+    /// and down.
+    pub fn displacement(&self) -> (f64, f64) {
+        let x = self.offset.0 + (self.width - 1.0) / 2.0;
+        let y = self.offset.1;
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (x * cos - y * sin, x * sin + y * cos)
+    }
+
+    /// The geometry of a key `width` wide and turned by `rotation` whose
+    /// center is `displacement` away from the center of its cell.
+    pub fn displaced(displacement: (f64, f64), width: f64, rotation: f64) -> Self {
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let (dx, dy) = displacement;
+        let x = dx * cos + dy * sin;
+        let y = -dx * sin + dy * cos;
+        Self {
+            offset: (x - (width - 1.0) / 2.0, y),
+            width,
+            rotation,
+            depth: 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -0,0 +1,194 @@
+//! Proposing a navigation layer so the arrow and editing keys can be
+//! reached from the home position instead of a separate cluster.
+
+use device_query::Keycode;
+
+use crate::{
+    layout::{Key, Layer, Layout},
+    stats::Stats,
+    KeymapConfig,
+};
+
+/// The keys moved onto a navigation layer.  The first four are placed as a
+/// row in vim order when possible.
+pub const NAV_KEYS: [Key; 9] = [
+    Key::Left,
+    Key::Down,
+    Key::Up,
+    Key::Right,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Delete,
+];
+
+pub struct NavLayerConfig {
+    /// Effort of holding the layer key, relative to pressing it, paid for
+    /// every navigation key press.
+    pub hold_cost: f64,
+    /// Effort added for every normal press of the hold key, since it
+    /// becomes a tap-hold key that can misfire.
+    pub tap_hold_penalty: f64,
+}
+
+impl Default for NavLayerConfig {
+    fn default() -> Self {
+        Self {
+            hold_cost: 0.5,
+            tap_hold_penalty: 0.1,
+        }
+    }
+}
+
+pub struct NavLayerProposal {
+    pub layer: Layer,
+    /// Effort of the navigation key presses on the base layout.
+    pub base_cost: f64,
+    /// Effort of the same presses on the layer, including holding it.
+    pub layer_cost: f64,
+}
+
+impl NavLayerProposal {
+    /// The fraction of navigation effort saved by the layer.
+    pub fn savings(&self) -> f64 {
+        if self.base_cost == 0.0 {
+            return 0.0;
+        }
+        1.0 - self.layer_cost / self.base_cost
+    }
+}
+
+/// The effort of pressing the physical key at `index`, from 0 (the most
+/// comfortable key on the strongest finger) to 1.
+pub fn key_effort(config: &KeymapConfig, index: usize) -> f64 {
+    let key = &config.keys.keys()[index];
+    let finger_score = config
+        .fingers
+        .iter()
+        .find(|c| c.finger == key.finger)
+        .map(|c| c.score)
+        .unwrap_or(0.0);
+    1.0 - key.score * finger_score
+}
+
+fn nav_count(stats: &Stats, key: Key) -> u64 {
+    stats
+        .individual_key_counts
+        .get(&key.keycode(false))
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Tries every physical key as the layer's hold key and returns the layer
+/// with the least navigation effort.  Returns `None` if no navigation keys
+/// were used.
+pub fn propose_nav_layer(
+    layout: &Layout,
+    stats: &Stats,
+    config: &KeymapConfig,
+    nav_config: &NavLayerConfig,
+) -> Option<NavLayerProposal> {
+    let base_cost: f64 = NAV_KEYS
+        .iter()
+        .filter_map(|key| {
+            let i = layout.position_of_keycode(key.keycode(false))?;
+            Some(nav_count(stats, *key) as f64 * key_effort(config, i))
+        })
+        .sum();
+    let total_presses: u64 = NAV_KEYS.iter().map(|key| nav_count(stats, *key)).sum();
+    if total_presses == 0 {
+        return None;
+    }
+
+    let mut best: Option<NavLayerProposal> = None;
+    for hold in 0..config.keys.keys().len() {
+        let Some(keys) = place_nav_keys(stats, config, hold) else {
+            continue;
+        };
+        let hold_key_presses = stats
+            .individual_key_counts
+            .get(&layout.keys()[hold].keycode(false))
+            .copied()
+            .unwrap_or(0);
+        let mut layer_cost = total_presses as f64 * nav_config.hold_cost * key_effort(config, hold)
+            + hold_key_presses as f64 * nav_config.tap_hold_penalty;
+        for (i, key) in keys.iter().enumerate() {
+            if let Some(key) = key {
+                layer_cost += nav_count(stats, *key) as f64 * key_effort(config, i);
+            }
+        }
+
+        if best.as_ref().is_none_or(|b| layer_cost < b.layer_cost) {
+            best = Some(NavLayerProposal {
+                layer: Layer {
+                    name: "nav".to_string(),
+                    hold,
+                    keys,
+                },
+                base_cost,
+                layer_cost,
+            });
+        }
+    }
+    best
+}
+
+/// Places the navigation keys on the hand opposite `hold`: the arrows on
+/// the most comfortable row of four adjacent keys, the rest on the most
+/// comfortable remaining keys by how often they're used.
+fn place_nav_keys(stats: &Stats, config: &KeymapConfig, hold: usize) -> Option<Vec<Option<Key>>> {
+    let physical = config.keys.keys();
+    let hand = physical[hold].finger.hand;
+    let mut free: Vec<usize> = (0..physical.len())
+        .filter(|&i| i != hold && physical[i].finger.hand != hand)
+        .collect();
+    if free.len() < NAV_KEYS.len() {
+        return None;
+    }
+    let mut keys = vec![None; physical.len()];
+
+    let find = |x: f64, y: f64| {
+        free.iter()
+            .copied()
+            .find(|&i| physical[i].position == (x, y))
+    };
+    let arrow_row = free
+        .iter()
+        .filter_map(|&start| {
+            let (x, y) = physical[start].position;
+            let row: Option<Vec<usize>> = (0..4).map(|dx| find(x + dx as f64, y)).collect();
+            row
+        })
+        .min_by(|a, b| {
+            let effort = |row: &Vec<usize>| row.iter().map(|&i| key_effort(config, i)).sum::<f64>();
+            effort(a).partial_cmp(&effort(b)).unwrap()
+        });
+    let mut remaining: Vec<Key> = NAV_KEYS.to_vec();
+    if let Some(row) = arrow_row {
+        for (&i, key) in row.iter().zip(&NAV_KEYS[..4]) {
+            keys[i] = Some(*key);
+        }
+        free.retain(|i| !row.contains(i));
+        remaining.drain(..4);
+    }
+
+    free.sort_by(|&a, &b| {
+        key_effort(config, a)
+            .partial_cmp(&key_effort(config, b))
+            .unwrap()
+    });
+    remaining.sort_by_key(|key| std::cmp::Reverse(nav_count(stats, *key)));
+    for (i, key) in free.into_iter().zip(remaining) {
+        keys[i] = Some(key);
+    }
+    Some(keys)
+}
+
+/// How often each navigation key was pressed.
+pub fn nav_key_counts(stats: &Stats) -> Vec<(Keycode, u64)> {
+    NAV_KEYS
+        .iter()
+        .map(|key| (key.keycode(false), nav_count(stats, *key)))
+        .collect()
+}
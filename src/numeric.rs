@@ -0,0 +1,316 @@
+//! Estimating the effort of entering numbers on the number row, a numpad
+//! layer, or a separate numpad, to recommend the best fit for how numbers
+//! are actually typed.
+
+use device_query::Keycode;
+
+use crate::{
+    layout::{Key, Layer, Layout},
+    nav::key_effort,
+    scoring::transition_score,
+    stats::Stats,
+    KeymapConfig,
+};
+
+const DIGITS: [char; 10] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
+
+/// How numbers were typed.
+#[derive(Debug, Default)]
+pub struct NumericUsage {
+    pub digit_presses: u64,
+    /// Digits typed as part of a numeric burst.
+    pub burst_digits: u64,
+    pub bursts: u64,
+}
+
+impl NumericUsage {
+    pub fn from_stats(stats: &Stats) -> Self {
+        let digit_presses = DIGITS
+            .iter()
+            .map(|c| stats.char_counts.get(c).copied().unwrap_or(0))
+            .sum();
+        let mut usage = Self {
+            digit_presses,
+            ..Default::default()
+        };
+        for (length, count) in &stats.numeric_burst_lengths {
+            usage.bursts += count;
+            usage.burst_digits += length * count;
+        }
+        usage
+    }
+
+    /// Digits typed on their own, outside of a burst.
+    pub fn isolated_digits(&self) -> u64 {
+        self.digit_presses.saturating_sub(self.burst_digits)
+    }
+}
+
+pub struct NumberEntryConfig {
+    /// Effort of holding the layer key relative to pressing it, paid once
+    /// per burst and once per isolated digit.
+    pub hold_cost: f64,
+    /// Effort of moving a hand to a separate numpad and back.
+    pub numpad_homing_cost: f64,
+    /// Effort of pressing a key on a separate numpad once the hand is on it.
+    pub numpad_key_effort: f64,
+}
+
+impl Default for NumberEntryConfig {
+    fn default() -> Self {
+        Self {
+            hold_cost: 0.5,
+            numpad_homing_cost: 2.0,
+            numpad_key_effort: 0.2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NumberEntryOption {
+    NumberRow,
+    NumpadLayer(Layer),
+    NumpadBlock,
+}
+
+#[derive(Debug)]
+pub struct NumberEntryEstimate {
+    pub option: NumberEntryOption,
+    pub effort: f64,
+}
+
+/// Estimates every way of entering numbers, cheapest first.
+pub fn recommend_number_entry(
+    layout: &Layout,
+    stats: &Stats,
+    config: &KeymapConfig,
+    number_config: &NumberEntryConfig,
+) -> Vec<NumberEntryEstimate> {
+    let usage = NumericUsage::from_stats(stats);
+    let mut estimates = vec![];
+
+    let row_positions: Option<Vec<usize>> = DIGITS
+        .iter()
+        .map(|&c| layout.position_of_keycode(Key::from_char_default_shifted(c).keycode(false)))
+        .collect();
+    if let Some(positions) = row_positions {
+        estimates.push(NumberEntryEstimate {
+            option: NumberEntryOption::NumberRow,
+            effort: digit_effort(stats, config, &positions),
+        });
+    }
+
+    if let Some((layer, effort)) = best_numpad_layer(stats, config, &usage, number_config) {
+        estimates.push(NumberEntryEstimate {
+            option: NumberEntryOption::NumpadLayer(layer),
+            effort,
+        });
+    }
+
+    estimates.push(NumberEntryEstimate {
+        option: NumberEntryOption::NumpadBlock,
+        effort: usage.digit_presses as f64 * number_config.numpad_key_effort
+            + (usage.bursts + usage.isolated_digits()) as f64 * number_config.numpad_homing_cost,
+    });
+
+    estimates.sort_by(|a, b| a.effort.total_cmp(&b.effort));
+    estimates
+}
+
+/// The effort of typing the digits with digit `i` on physical key
+/// `positions[i]`: pressing each key, plus how awkward each consecutive
+/// pair of digits is.
+fn digit_effort(stats: &Stats, config: &KeymapConfig, positions: &[usize]) -> f64 {
+    let physical = config.keys.keys();
+    let mut effort = 0.0;
+    for (d, &i) in positions.iter().enumerate() {
+        let count = stats.char_counts.get(&DIGITS[d]).copied().unwrap_or(0);
+        effort += count as f64 * key_effort(config, i);
+    }
+    for (d1, &i) in positions.iter().enumerate() {
+        for (d2, &j) in positions.iter().enumerate() {
+            let Some(count) = stats.consecutive_char_counts.get(&(DIGITS[d1], DIGITS[d2])) else {
+                continue;
+            };
+            let (k1, k2) = (&physical[i], &physical[j]);
+            let comfort = transition_score(k1, k2);
+            effort += *count as f64 * (1.0 - comfort);
+        }
+    }
+    effort
+}
+
+/// Tries every physical key as the hold key, placing the digits on the ten
+/// most comfortable keys of the other hand in the best order found.
+fn best_numpad_layer(
+    stats: &Stats,
+    config: &KeymapConfig,
+    usage: &NumericUsage,
+    number_config: &NumberEntryConfig,
+) -> Option<(Layer, f64)> {
+    let physical = config.keys.keys();
+    let mut best: Option<(Layer, f64)> = None;
+    for hold in 0..physical.len() {
+        let hand = physical[hold].finger.hand;
+        let mut free: Vec<usize> = (0..physical.len())
+            .filter(|&i| i != hold && physical[i].finger.hand != hand)
+            .collect();
+        if free.len() < DIGITS.len() {
+            continue;
+        }
+        free.sort_by(|&a, &b| key_effort(config, a).total_cmp(&key_effort(config, b)));
+        free.truncate(DIGITS.len());
+
+        let positions = optimize_digit_order(stats, config, free);
+        let effort = digit_effort(stats, config, &positions)
+            + (usage.bursts + usage.isolated_digits()) as f64
+                * number_config.hold_cost
+                * key_effort(config, hold);
+        if best.as_ref().is_none_or(|(_, e)| effort < *e) {
+            let mut keys = vec![None; physical.len()];
+            for (d, &i) in positions.iter().enumerate() {
+                keys[i] = Some(Key::from_char_default_shifted(DIGITS[d]));
+            }
+            let layer = Layer {
+                name: "numpad".to_string(),
+                hold,
+                keys,
+            };
+            best = Some((layer, effort));
+        }
+    }
+    best
+}
+
+/// Starts with the most common digits on the most comfortable keys and
+/// swaps digits while that lowers the effort.
+fn optimize_digit_order(stats: &Stats, config: &KeymapConfig, keys: Vec<usize>) -> Vec<usize> {
+    let mut by_frequency: Vec<usize> = (0..DIGITS.len()).collect();
+    by_frequency.sort_by_key(|&d| {
+        std::cmp::Reverse(stats.char_counts.get(&DIGITS[d]).copied().unwrap_or(0))
+    });
+    let mut positions = vec![0; DIGITS.len()];
+    for (&d, &i) in by_frequency.iter().zip(&keys) {
+        positions[d] = i;
+    }
+
+    let mut effort = digit_effort(stats, config, &positions);
+    loop {
+        let mut improved = false;
+        for a in 0..positions.len() {
+            for b in (a + 1)..positions.len() {
+                positions.swap(a, b);
+                let new_effort = digit_effort(stats, config, &positions);
+                if new_effort < effort {
+                    effort = new_effort;
+                    improved = true;
+                } else {
+                    positions.swap(a, b);
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    positions
+}
+
+/// How often each digit was typed.
+pub fn digit_counts(stats: &Stats) -> Vec<(Keycode, u64)> {
+    DIGITS
+        .iter()
+        .map(|&c| {
+            (
+                Key::from_char_default_shifted(c).keycode(false),
+                stats.char_counts.get(&c).copied().unwrap_or(0),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        char_table::CharTable,
+        format::parse_keymap_config,
+        stats::{typing_events, KeyProcessor},
+    };
+
+    fn typed(text: &str) -> Stats {
+        let mut processor = KeyProcessor::new();
+        for (key, press) in typing_events(text, &CharTable::default()) {
+            processor.push_event(key, press);
+        }
+        processor.finish()
+    }
+
+    #[test]
+    fn test_numeric_bursts() {
+        // Separators inside a number don't end its burst, other characters
+        // do, and runs of fewer than three digits aren't bursts
+        let stats = typed("call 555-1234 now, pi is 3.14159, 1,000 or 42 and 7.");
+        let mut lengths: Vec<(u64, u64)> = stats
+            .numeric_burst_lengths
+            .iter()
+            .map(|(&length, &count)| (length, count))
+            .collect();
+        lengths.sort();
+        assert_eq!(lengths, [(3, 1), (4, 2), (6, 1)]);
+
+        let usage = NumericUsage::from_stats(&stats);
+        assert_eq!(usage.digit_presses, 20);
+        assert_eq!(usage.bursts, 4);
+        assert_eq!(usage.burst_digits, 17);
+        assert_eq!(usage.isolated_digits(), 3);
+    }
+
+    #[test]
+    fn test_recommend_number_entry() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let number_config = NumberEntryConfig::default();
+        let stats = typed("1 2 3 4 5 6 7 8 9 0 12 34 56 78 90 and 2024");
+        let usage = NumericUsage::from_stats(&stats);
+
+        let estimates = recommend_number_entry(&layout, &stats, &config, &number_config);
+        assert_eq!(estimates.len(), 3);
+        assert!(estimates
+            .windows(2)
+            .all(|pair| pair[0].effort <= pair[1].effort));
+
+        let block = estimates
+            .iter()
+            .find(|estimate| matches!(estimate.option, NumberEntryOption::NumpadBlock))
+            .unwrap();
+        let homings = usage.bursts + usage.isolated_digits();
+        assert_eq!(homings, 21);
+        assert_eq!(
+            block.effort,
+            usage.digit_presses as f64 * number_config.numpad_key_effort
+                + homings as f64 * number_config.numpad_homing_cost
+        );
+        // Reaching for a separate numpad for every short number is the
+        // worst way to type them
+        assert!(matches!(
+            estimates.last().unwrap().option,
+            NumberEntryOption::NumpadBlock
+        ));
+
+        let layer = estimates
+            .iter()
+            .find_map(|estimate| match &estimate.option {
+                NumberEntryOption::NumpadLayer(layer) => Some(layer),
+                _ => None,
+            })
+            .unwrap();
+        let physical = config.keys.keys();
+        let digits: Vec<usize> = (0..physical.len())
+            .filter(|&i| layer.keys[i].is_some())
+            .collect();
+        assert_eq!(digits.len(), DIGITS.len());
+        let hold_hand = physical[layer.hold].finger.hand;
+        assert!(digits.iter().all(|&i| physical[i].finger.hand != hold_hand));
+    }
+}
@@ -0,0 +1,451 @@
+//! Exporting a layout as a keyboard layout for the operating system, so it
+//! can be installed on Windows with MSKLC or on macOS without any remapper
+//! running.
+//!
+//! OS layouts only change what the character keys type, so keys that put a
+//! character on a modifier, a thumb key, or an arrow, or move those keys
+//! onto character keys, can't be expressed.  The exports leave them out
+//! and count them, for `apply` or a kanata config to handle instead.
+
+use std::{fmt::Write, path::Path};
+
+use device_query::Keycode;
+
+use crate::{
+    layout::{Key, Layout},
+    stats::char_to_key_event,
+    KeymapConfig,
+};
+
+/// The contents of an OS layout file, and how many keys it left out.
+pub struct OsLayout {
+    pub contents: String,
+    /// Physical keys whose key in the layout the OS layout can't place.
+    pub unmapped: usize,
+}
+
+/// The characters each character key types, unshifted and shifted, by
+/// its key on a US layout.  Keys the layout moves elsewhere type nothing.
+fn character_keys(layout: &Layout, config: &KeymapConfig) -> (Vec<(Keycode, char, char)>, usize) {
+    let mut keys = vec![];
+    let mut unmapped = 0;
+    for (key, physical) in layout.keys().iter().zip(config.keys.keys()) {
+        let character_key = codes(physical.code).is_some();
+        match key {
+            Key::Normal { normal, shifted } if character_key => {
+                keys.push((physical.code, *normal, *shifted))
+            }
+            Key::Normal { .. } => unmapped += 1,
+            _ if character_key => unmapped += 1,
+            // A non-character key where the keyboard has one stays put
+            _ => {}
+        }
+    }
+    (keys, unmapped)
+}
+
+/// Builds an MSKLC source file named `name`, which MSKLC turns into an
+/// installer.  Write it with [`utf16_le`], the encoding MSKLC expects.
+pub fn export_klc(layout: &Layout, config: &KeymapConfig, name: &str) -> OsLayout {
+    let (keys, unmapped) = character_keys(layout, config);
+    let id: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(8)
+        .collect();
+    let id = if id.is_empty() {
+        "layout".to_string()
+    } else {
+        id
+    };
+
+    let mut s = String::new();
+    writeln!(s, "KBD\t{id}\t\"{name}\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "COPYRIGHT\t\"Generated by keyboard_layout_generator\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "COMPANY\t\"keyboard_layout_generator\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "LOCALENAME\t\"en-US\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "LOCALEID\t\"00000409\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "VERSION\t1.0").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "SHIFTSTATE").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "0\t//Column 4").unwrap();
+    writeln!(s, "1\t//Column 5 : Shft").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "LAYOUT\t\t;an extra '@' at the end is a dead key").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "//SC\tVK_\t\tCap\t0\t1").unwrap();
+    writeln!(s, "//--\t----\t\t----\t----\t----").unwrap();
+    writeln!(s).unwrap();
+    let mut rows: Vec<(u8, String)> = keys
+        .iter()
+        .map(|&(code, normal, shifted)| {
+            let (scancode, physical_vk, _) = codes(code).unwrap();
+            // Shortcuts like Ctrl+C follow the character, as long as it's
+            // on a US layout at all
+            let vk = char_to_key_event(normal)
+                .and_then(|(code, _)| codes(code))
+                .map_or(physical_vk, |(_, vk, _)| vk);
+            let caps = (normal.to_uppercase().eq([shifted]) && normal != shifted) as u8;
+            (
+                scancode,
+                format!(
+                    "{scancode:02x}\t{vk}\t\t{caps}\t{}\t{}",
+                    klc_char(normal),
+                    klc_char(shifted)
+                ),
+            )
+        })
+        .collect();
+    rows.push((0x39, "39\tSPACE\t\t0\t0020\t0020".to_string()));
+    rows.sort();
+    rows.dedup_by_key(|(scancode, _)| *scancode);
+    for (_, row) in rows {
+        writeln!(s, "{row}").unwrap();
+    }
+    writeln!(s).unwrap();
+    for (section, names) in [("KEYNAME", KEYNAMES), ("KEYNAME_EXT", KEYNAMES_EXT)] {
+        writeln!(s, "{section}").unwrap();
+        writeln!(s).unwrap();
+        for (scancode, name) in names {
+            writeln!(s, "{scancode:02x}\t{name}").unwrap();
+        }
+        writeln!(s).unwrap();
+    }
+    writeln!(s, "DESCRIPTIONS").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "0409\t{name}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "LANGUAGENAMES").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "0409\tEnglish (United States)").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "ENDKBD").unwrap();
+
+    OsLayout {
+        contents: s.replace('\n', "\r\n"),
+        unmapped,
+    }
+}
+
+/// Letters and digits as themselves, anything else as its code point, like
+/// MSKLC writes them.
+fn klc_char(c: char) -> String {
+    if c.is_ascii_alphanumeric() {
+        c.to_string()
+    } else {
+        format!("{:04x}", c as u32)
+    }
+}
+
+/// `s` as UTF-16 with a byte order mark, the encoding of `.klc` files.
+pub fn utf16_le(s: &str) -> Vec<u8> {
+    std::iter::once(0xfeff)
+        .chain(s.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+const KEYNAMES: &[(u8, &str)] = &[
+    (0x01, "Esc"),
+    (0x0e, "Backspace"),
+    (0x0f, "Tab"),
+    (0x1c, "Enter"),
+    (0x1d, "Ctrl"),
+    (0x2a, "Shift"),
+    (0x36, "\"Right Shift\""),
+    (0x38, "Alt"),
+    (0x39, "Space"),
+    (0x3a, "\"Caps Lock\""),
+];
+
+const KEYNAMES_EXT: &[(u8, &str)] = &[
+    (0x1c, "\"Num Enter\""),
+    (0x1d, "\"Right Ctrl\""),
+    (0x38, "\"Right Alt\""),
+    (0x47, "Home"),
+    (0x48, "Up"),
+    (0x49, "\"Page Up\""),
+    (0x4b, "Left"),
+    (0x4d, "Right"),
+    (0x4f, "End"),
+    (0x50, "Down"),
+    (0x51, "\"Page Down\""),
+    (0x53, "Delete"),
+    (0x5b, "\"Left Windows\""),
+    (0x5c, "\"Right Windows\""),
+];
+
+/// Builds a macOS keyboard layout named `name`.  Caps lock capitalizes
+/// letters, and shortcuts with command follow the characters.
+pub fn export_keylayout(layout: &Layout, config: &KeymapConfig, name: &str) -> OsLayout {
+    let (keys, unmapped) = character_keys(layout, config);
+    // Custom layouts have negative ids, which only need to differ from the
+    // other installed layouts
+    let id = -1 - (name.bytes().map(u32::from).sum::<u32>() % 30000) as i32;
+
+    let mut s = String::new();
+    writeln!(s, r#"<?xml version="1.1" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        s,
+        r#"<!DOCTYPE keyboard SYSTEM "file://localhost/System/Library/DTDs/KeyboardLayout.dtd">"#
+    )
+    .unwrap();
+    writeln!(
+        s,
+        r#"<keyboard group="126" id="{id}" name="{}" maxout="1">"#,
+        xml_escape(name)
+    )
+    .unwrap();
+    writeln!(s, "  <layouts>").unwrap();
+    writeln!(
+        s,
+        r#"    <layout first="0" last="17" modifiers="Modifiers" mapSet="ANSI"/>"#
+    )
+    .unwrap();
+    writeln!(s, "  </layouts>").unwrap();
+    writeln!(s, r#"  <modifierMap id="Modifiers" defaultIndex="0">"#).unwrap();
+    for (index, modifiers) in [
+        "",
+        "anyShift caps?",
+        "caps",
+        "anyShift? caps? anyOption? anyControl? command",
+    ]
+    .iter()
+    .enumerate()
+    {
+        writeln!(s, r#"    <keyMapSelect mapIndex="{index}">"#).unwrap();
+        writeln!(s, r#"      <modifier keys="{modifiers}"/>"#).unwrap();
+        writeln!(s, "    </keyMapSelect>").unwrap();
+    }
+    writeln!(s, "  </modifierMap>").unwrap();
+    writeln!(s, r#"  <keyMapSet id="ANSI">"#).unwrap();
+    let outputs: [fn(char, char) -> String; 4] = [
+        |normal, _| normal.to_string(),
+        |_, shifted| shifted.to_string(),
+        |normal, _| normal.to_uppercase().collect(),
+        |normal, _| normal.to_string(),
+    ];
+    for (index, output) in outputs.iter().enumerate() {
+        writeln!(s, r#"    <keyMap index="{index}">"#).unwrap();
+        let mut map: Vec<(u16, String)> = keys
+            .iter()
+            .map(|&(code, normal, shifted)| (codes(code).unwrap().2, output(normal, shifted)))
+            .chain(
+                MAC_CONTROL_KEYS
+                    .iter()
+                    .map(|&(code, c)| (code, c.to_string())),
+            )
+            .collect();
+        map.sort();
+        map.dedup_by_key(|(code, _)| *code);
+        for (code, output) in map {
+            writeln!(
+                s,
+                r#"      <key code="{code}" output="{}"/>"#,
+                xml_escape(&output)
+            )
+            .unwrap();
+        }
+        writeln!(s, "    </keyMap>").unwrap();
+    }
+    writeln!(s, "  </keyMapSet>").unwrap();
+    writeln!(s, "</keyboard>").unwrap();
+
+    OsLayout {
+        contents: s,
+        unmapped,
+    }
+}
+
+/// Writes `keylayout` as the bundle `<name>.bundle` in `dir`, which macOS
+/// installs when copied into `~/Library/Keyboard Layouts`.
+pub fn write_keylayout_bundle(dir: &Path, name: &str, keylayout: &str) -> std::io::Result<()> {
+    let id: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let contents = dir.join(format!("{name}.bundle")).join("Contents");
+    let resources = contents.join("Resources");
+    std::fs::create_dir_all(&resources)?;
+    std::fs::write(resources.join(format!("{name}.keylayout")), keylayout)?;
+
+    let name = xml_escape(name);
+    let mut plist = String::new();
+    writeln!(plist, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        plist,
+        r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+    )
+    .unwrap();
+    writeln!(plist, r#"<plist version="1.0">"#).unwrap();
+    writeln!(plist, "<dict>").unwrap();
+    writeln!(plist, "  <key>CFBundleIdentifier</key>").unwrap();
+    writeln!(
+        plist,
+        "  <string>org.keyboard-layout-generator.keyboardlayout.{id}</string>"
+    )
+    .unwrap();
+    writeln!(plist, "  <key>CFBundleName</key>").unwrap();
+    writeln!(plist, "  <string>{name}</string>").unwrap();
+    writeln!(plist, "  <key>CFBundleVersion</key>").unwrap();
+    writeln!(plist, "  <string>1.0</string>").unwrap();
+    writeln!(plist, "  <key>KLInfo_{name}</key>").unwrap();
+    writeln!(plist, "  <dict>").unwrap();
+    writeln!(plist, "    <key>TISInputSourceID</key>").unwrap();
+    writeln!(
+        plist,
+        "    <string>org.keyboard-layout-generator.keyboardlayout.{id}.{id}</string>"
+    )
+    .unwrap();
+    writeln!(plist, "    <key>TISIntendedLanguage</key>").unwrap();
+    writeln!(plist, "    <string>en</string>").unwrap();
+    writeln!(plist, "  </dict>").unwrap();
+    writeln!(plist, "</dict>").unwrap();
+    writeln!(plist, "</plist>").unwrap();
+    std::fs::write(contents.join("Info.plist"), plist)
+}
+
+/// Escapes `s` for an XML attribute, with control characters as character
+/// references like keylayout files write them.
+pub(crate) fn xml_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if c.is_control() => write!(escaped, "&#x{:04X};", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Keys every macOS key map needs to list for them to keep working, with
+/// the control characters they send.
+const MAC_CONTROL_KEYS: &[(u16, char)] = &[
+    (36, '\r'),
+    (48, '\t'),
+    (49, ' '),
+    (51, '\u{8}'),
+    (53, '\u{1b}'),
+    (115, '\u{1}'),
+    (116, '\u{b}'),
+    (117, '\u{7f}'),
+    (119, '\u{4}'),
+    (121, '\u{c}'),
+    (123, '\u{1c}'),
+    (124, '\u{1d}'),
+    (125, '\u{1f}'),
+    (126, '\u{1e}'),
+];
+
+/// The Windows scancode, Windows virtual key, and macOS key code of the
+/// character keys, which are the only keys OS layouts can change.
+fn codes(code: Keycode) -> Option<(u8, &'static str, u16)> {
+    Some(match code {
+        Keycode::Grave => (0x29, "OEM_3", 50),
+        Keycode::Key1 => (0x02, "1", 18),
+        Keycode::Key2 => (0x03, "2", 19),
+        Keycode::Key3 => (0x04, "3", 20),
+        Keycode::Key4 => (0x05, "4", 21),
+        Keycode::Key5 => (0x06, "5", 23),
+        Keycode::Key6 => (0x07, "6", 22),
+        Keycode::Key7 => (0x08, "7", 26),
+        Keycode::Key8 => (0x09, "8", 28),
+        Keycode::Key9 => (0x0a, "9", 25),
+        Keycode::Key0 => (0x0b, "0", 29),
+        Keycode::Minus => (0x0c, "OEM_MINUS", 27),
+        Keycode::Equal => (0x0d, "OEM_PLUS", 24),
+        Keycode::Q => (0x10, "Q", 12),
+        Keycode::W => (0x11, "W", 13),
+        Keycode::E => (0x12, "E", 14),
+        Keycode::R => (0x13, "R", 15),
+        Keycode::T => (0x14, "T", 17),
+        Keycode::Y => (0x15, "Y", 16),
+        Keycode::U => (0x16, "U", 32),
+        Keycode::I => (0x17, "I", 34),
+        Keycode::O => (0x18, "O", 31),
+        Keycode::P => (0x19, "P", 35),
+        Keycode::LeftBracket => (0x1a, "OEM_4", 33),
+        Keycode::RightBracket => (0x1b, "OEM_6", 30),
+        Keycode::A => (0x1e, "A", 0),
+        Keycode::S => (0x1f, "S", 1),
+        Keycode::D => (0x20, "D", 2),
+        Keycode::F => (0x21, "F", 3),
+        Keycode::G => (0x22, "G", 5),
+        Keycode::H => (0x23, "H", 4),
+        Keycode::J => (0x24, "J", 38),
+        Keycode::K => (0x25, "K", 40),
+        Keycode::L => (0x26, "L", 37),
+        Keycode::Semicolon => (0x27, "OEM_1", 41),
+        Keycode::Apostrophe => (0x28, "OEM_7", 39),
+        Keycode::BackSlash => (0x2b, "OEM_5", 42),
+        Keycode::Z => (0x2c, "Z", 6),
+        Keycode::X => (0x2d, "X", 7),
+        Keycode::C => (0x2e, "C", 8),
+        Keycode::V => (0x2f, "V", 9),
+        Keycode::B => (0x30, "B", 11),
+        Keycode::N => (0x31, "N", 45),
+        Keycode::M => (0x32, "M", 46),
+        Keycode::Comma => (0x33, "OEM_COMMA", 43),
+        Keycode::Dot => (0x34, "OEM_PERIOD", 47),
+        Keycode::Slash => (0x35, "OEM_2", 44),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_os_layouts() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut keys = Layout::from_keymap(&config).keys().to_vec();
+        let position = |code| {
+            keys.iter()
+                .position(|key| *key == Key::from_keycode(code))
+                .unwrap()
+        };
+        let (q, a, tab) = (
+            position(Keycode::Q),
+            position(Keycode::A),
+            position(Keycode::Tab),
+        );
+        keys[q] = Key::Normal {
+            normal: 'q',
+            shifted: '!',
+        };
+        // A letter on a key that isn't a character key can't be placed
+        keys.swap(a, tab);
+        let layout = Layout::new(keys);
+
+        let klc = export_klc(&layout, &config, "My Layout");
+        assert_eq!(klc.unmapped, 2);
+        assert!(klc.contents.starts_with("KBD\tMyLayout\t\"My Layout\"\r\n"));
+        assert!(klc.contents.contains("\r\n10\tQ\t\t0\tq\t0021\r\n"));
+        assert!(klc.contents.contains("\r\n11\tW\t\t1\tw\tW\r\n"));
+        assert!(!klc.contents.contains("\r\n1e\t"));
+        assert_eq!(&utf16_le("K")[..], &[0xff, 0xfe, b'K', 0]);
+
+        let keylayout = export_keylayout(&layout, &config, "My Layout");
+        assert_eq!(keylayout.unmapped, 2);
+        assert!(keylayout
+            .contents
+            .contains(r#"<key code="12" output="!"/>"#));
+        assert!(keylayout
+            .contents
+            .contains(r#"<key code="51" output="&#x0008;"/>"#));
+        assert_eq!(keylayout.contents.matches("<keyMap ").count(), 4);
+    }
+}
@@ -0,0 +1,355 @@
+//! Optimizing several objectives at once, like effort, same finger bigrams
+//! and staying close to QWERTY, and keeping the layouts that trade them off
+//! best, the Pareto front, instead of a single best layout.
+//!
+//! The front is saved as JSON to continue it in a later run, and as an HTML
+//! page with sliders to pick a trade-off and export the layout it leads to.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{self, Error},
+    format::{layout_to_str, parse_layout},
+    layout::{layout_similarity, Layout},
+    scoring::same_finger_bigrams,
+    stats::Stats,
+    KeymapConfig,
+};
+
+/// Something to optimize a layout for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    /// The score of the effort model, normalized by its max.
+    Score,
+    /// The share of bigrams not typed with the same finger.
+    SameFinger,
+    /// The share of keys in the same place as on the keycaps, which are
+    /// usually QWERTY.
+    QwertySimilarity,
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(Objective::Score),
+            "sfb" => Ok(Objective::SameFinger),
+            "qwerty" => Ok(Objective::QwertySimilarity),
+            _ => Err(format!("expected score, sfb, or qwerty, got {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Objective::Score => write!(f, "score"),
+            Objective::SameFinger => write!(f, "sfb"),
+            Objective::QwertySimilarity => write!(f, "qwerty"),
+        }
+    }
+}
+
+/// Rates layouts by several objectives, each between 0 and 1 where higher
+/// is better.
+pub struct Objectives {
+    pub objectives: Vec<Objective>,
+    stats: Arc<Stats>,
+    keymap_config: Arc<KeymapConfig>,
+    keycaps: Layout,
+    total_bigrams: u64,
+}
+
+impl Objectives {
+    pub fn new(
+        objectives: Vec<Objective>,
+        stats: Arc<Stats>,
+        keymap_config: Arc<KeymapConfig>,
+    ) -> Self {
+        Self {
+            objectives,
+            keycaps: Layout::from_keymap(&keymap_config),
+            total_bigrams: stats.consecutive_key_counts.values().sum(),
+            stats,
+            keymap_config,
+        }
+    }
+
+    /// The value of every objective for `layout`, in order.  The score
+    /// is passed in, already normalized, since it can come from several
+    /// scenarios.
+    pub fn evaluate(&self, layout: &Layout, normalized_score: f64) -> Vec<f64> {
+        self.objectives
+            .iter()
+            .map(|objective| match objective {
+                Objective::Score => normalized_score,
+                Objective::SameFinger => {
+                    let sfbs = same_finger_bigrams(layout, &self.stats, &self.keymap_config);
+                    1.0 - sfbs.count as f64 / self.total_bigrams.max(1) as f64
+                }
+                Objective::QwertySimilarity => layout_similarity(layout, &self.keycaps) as f64,
+            })
+            .collect()
+    }
+}
+
+/// Whether `a` is at least as good as `b` in every objective and better in
+/// at least one.
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a >= b) && a.iter().zip(b).any(|(a, b)| a > b)
+}
+
+/// How many layers of non-dominated points lie above each point: 0 for the
+/// Pareto front, 1 for the front of what's left without it, and so on.
+pub fn pareto_ranks(points: &[Vec<f64>]) -> Vec<usize> {
+    let mut ranks = vec![usize::MAX; points.len()];
+    let mut rank = 0;
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    while !remaining.is_empty() {
+        let front: Vec<usize> = remaining
+            .iter()
+            .copied()
+            .filter(|&i| !remaining.iter().any(|&j| dominates(&points[j], &points[i])))
+            .collect();
+        for &i in &front {
+            ranks[i] = rank;
+        }
+        remaining.retain(|i| !front.contains(i));
+        rank += 1;
+    }
+    ranks
+}
+
+/// Weights for the objectives drawn evenly from all the ways to split 1
+/// between them, so scalarizing with many of them covers the whole front.
+pub fn random_weights<R: Rng>(objectives: usize, rng: &mut R) -> Vec<f64> {
+    let weights: Vec<f64> = (0..objectives)
+        .map(|_| -rng.gen_range(f64::MIN_POSITIVE..1.0).ln())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+/// Combines the values of the objectives into one by `weights`.
+pub fn scalarize(values: &[f64], weights: &[f64]) -> f64 {
+    values.iter().zip(weights).map(|(v, w)| v * w).sum()
+}
+
+/// The best trade-offs seen so far: layouts that no other layout seen beats
+/// in every objective.
+#[derive(Default)]
+pub struct ParetoFront {
+    pub members: Vec<(Layout, Vec<f64>)>,
+}
+
+impl ParetoFront {
+    /// Adds `layout` unless a member dominates it or has the same values,
+    /// dropping the members it dominates.  Returns whether it was added.
+    pub fn insert(&mut self, layout: &Layout, values: Vec<f64>) -> bool {
+        if self
+            .members
+            .iter()
+            .any(|(_, member)| *member == values || dominates(member, &values))
+        {
+            return false;
+        }
+        self.members
+            .retain(|(_, member)| !dominates(&values, member));
+        self.members.push((layout.clone(), values));
+        true
+    }
+
+    /// The members sorted by their first objective, best first.
+    pub fn sorted(&self) -> Vec<&(Layout, Vec<f64>)> {
+        let mut members: Vec<_> = self.members.iter().collect();
+        members.sort_by(|a, b| b.1[0].total_cmp(&a.1[0]));
+        members
+    }
+
+    /// The front as JSON, with the layouts in the format of `best.txt`.
+    pub fn to_json(&self, objectives: &[Objective], keymap_config: &KeymapConfig) -> String {
+        let saved = SavedFront {
+            objectives: objectives.iter().map(|o| o.to_string()).collect(),
+            members: self
+                .sorted()
+                .into_iter()
+                .map(|(layout, values)| SavedMember {
+                    layout: layout_to_str(layout, keymap_config).unwrap(),
+                    values: values.clone(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&saved).unwrap()
+    }
+
+    /// Reads a front written by [`ParetoFront::to_json`].  Fails if it was
+    /// made for other objectives.  Its layouts are valued again with
+    /// `evaluate` rather than by the values saved with them, which may
+    /// have come from other stats or scoring, so they only stay on the
+    /// front if they still earn their place.
+    pub fn from_json(
+        s: &str,
+        objectives: &[Objective],
+        keymap_config: &KeymapConfig,
+        evaluate: impl Fn(&Layout) -> Vec<f64>,
+    ) -> error::Result<Self> {
+        let saved: SavedFront =
+            serde_json::from_str(s).map_err(|error| Error::Invalid(error.to_string()))?;
+        let names: Vec<String> = objectives.iter().map(|o| o.to_string()).collect();
+        if saved.objectives != names {
+            return Err(Error::Invalid(format!(
+                "the front is for the objectives {}, not {}",
+                saved.objectives.join(","),
+                names.join(",")
+            )));
+        }
+        let mut front = Self::default();
+        for member in saved.members {
+            let layout = parse_layout(&member.layout, keymap_config)?;
+            front.insert(&layout, evaluate(&layout));
+        }
+        Ok(front)
+    }
+
+    /// A self-contained page with a slider for every objective, showing
+    /// the layout that fits the weights they're set to best, with a button
+    /// to download it.
+    pub fn to_html(&self, objectives: &[Objective], keymap_config: &KeymapConfig) -> String {
+        // Keeps a layout from ending the script it's embedded in
+        let data = self
+            .to_json(objectives, keymap_config)
+            .replace("</", "<\\/");
+        PICKER_HTML.replace("/*FRONT*/", &data)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedFront {
+    objectives: Vec<String>,
+    members: Vec<SavedMember>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedMember {
+    layout: String,
+    values: Vec<f64>,
+}
+
+/// The page of [`ParetoFront::to_html`], with the front in place of
+/// `/*FRONT*/`.  Values are scaled to the range of the front, so the
+/// sliders weigh the objectives evenly.
+const PICKER_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Pareto front</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+label { display: block; margin: 0.5em 0; }
+pre { font-size: 14px; background: #f4f4f4; padding: 1em; }
+td { padding: 0 1em 0 0; }
+</style>
+</head>
+<body>
+<h1>Pareto front</h1>
+<p>Slide how much every objective matters to pick the layout that trades them off best.</p>
+<div id="sliders"></div>
+<h2 id="title"></h2>
+<table id="metrics"></table>
+<pre id="layout"></pre>
+<button id="export">Download layout</button>
+<script>
+const front = /*FRONT*/;
+const n = front.objectives.length;
+const min = front.objectives.map((_, i) => Math.min(...front.members.map(m => m.values[i])));
+const max = front.objectives.map((_, i) => Math.max(...front.members.map(m => m.values[i])));
+const scaled = (m, i) => max[i] > min[i] ? (m.values[i] - min[i]) / (max[i] - min[i]) : 1;
+const sliders = front.objectives.map(name => {
+  const label = document.createElement("label");
+  const input = document.createElement("input");
+  input.type = "range";
+  input.min = 0;
+  input.max = 100;
+  input.value = 50;
+  input.oninput = update;
+  label.append(input, " " + name);
+  document.getElementById("sliders").append(label);
+  return input;
+});
+let chosen = 0;
+function update() {
+  const weights = sliders.map(s => Number(s.value));
+  const rate = m => weights.reduce((sum, w, i) => sum + w * scaled(m, i), 0);
+  chosen = front.members.reduce((best, m, i) => rate(m) > rate(front.members[best]) ? i : best, 0);
+  const member = front.members[chosen];
+  document.getElementById("title").textContent = "Layout " + chosen + " of " + front.members.length;
+  document.getElementById("metrics").innerHTML = front.objectives
+    .map((name, i) => "<tr><td>" + name + "</td><td>" + member.values[i].toFixed(4) + "</td></tr>")
+    .join("");
+  document.getElementById("layout").textContent = member.layout;
+}
+document.getElementById("export").onclick = () => {
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(new Blob([front.members[chosen].layout], { type: "text/plain" }));
+  link.download = "pareto_" + chosen + ".txt";
+  link.click();
+};
+if (front.members.length > 0) update();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_pareto_front() {
+        let points = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.5, 0.5],
+            vec![0.4, 0.4],
+            vec![0.1, 0.1],
+        ];
+        assert_eq!(pareto_ranks(&points), [0, 0, 0, 1, 2]);
+
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut front = ParetoFront::default();
+        assert!(front.insert(&layout, vec![0.5, 0.5]));
+        assert!(front.insert(&layout, vec![1.0, 0.0]));
+        assert!(!front.insert(&layout, vec![0.4, 0.4]));
+        assert!(!front.insert(&layout, vec![0.5, 0.5]));
+        assert!(front.insert(&layout, vec![0.6, 0.6]));
+        assert_eq!(front.members.len(), 2);
+        assert_eq!(front.sorted()[0].1, [1.0, 0.0]);
+
+        let objectives = [Objective::Score, Objective::SameFinger];
+        let json = front.to_json(&objectives, &config);
+        // Valued again, both layouts are the same point
+        let loaded =
+            ParetoFront::from_json(&json, &objectives, &config, |_| vec![0.2, 0.3]).unwrap();
+        assert_eq!(loaded.members.len(), 1);
+        assert_eq!(loaded.members[0].1, [0.2, 0.3]);
+        assert!(ParetoFront::from_json(&json, &[Objective::Score], &config, |_| vec![]).is_err());
+        assert!(front
+            .to_html(&objectives, &config)
+            .contains("\"objectives\": [\n    \"score\""));
+
+        let weights = random_weights(3, &mut rand::thread_rng());
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let objectives = Objectives::new(
+            vec![Objective::QwertySimilarity, Objective::Score],
+            Arc::new(Stats::new()),
+            Arc::new(config),
+        );
+        assert_eq!(objectives.evaluate(&layout, 0.25), [1.0, 0.25]);
+    }
+}
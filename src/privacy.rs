@@ -0,0 +1,185 @@
+//! Anonymizing [`Stats`] before they are shared.
+//!
+//! Rare n-grams are the ones that identify what was typed (names, passwords
+//! typed by hand), so counts below a threshold are perturbed with Laplace
+//! noise and anything that still ends up below the minimum count is dropped.
+//!
+//! Some typing shouldn't be logged in the first place, so the keylogger
+//! pauses while a window matching [`WindowExclusions`] is focused.
+
+use std::{collections::HashMap, hash::Hash};
+
+use rand::Rng;
+
+use crate::{capture::ActiveWindow, stats::Stats};
+
+/// Patterns of windows not to log, matched against the application and the
+/// title of the focused window, ignoring case.  `*` matches anything, so
+/// `keepassxc` only matches that application while `*ssh*` matches any
+/// title containing `ssh`.
+#[derive(Debug, Clone, Default)]
+pub struct WindowExclusions {
+    patterns: Vec<String>,
+}
+
+impl WindowExclusions {
+    pub fn new(patterns: &[String]) -> Self {
+        Self {
+            patterns: patterns.iter().map(|p| p.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The first pattern that matches `window`.
+    pub fn matching(&self, window: &ActiveWindow) -> Option<&str> {
+        let app = window.app.to_lowercase();
+        let title = window.title.to_lowercase();
+        self.patterns
+            .iter()
+            .find(|pattern| wildcard_match(pattern, &app) || wildcard_match(pattern, &title))
+            .map(|pattern| pattern.as_str())
+    }
+}
+
+/// Whether all of `text` matches `pattern`, where `*` matches any run of
+/// characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+pub struct PrivacyConfig {
+    /// Counts below this are considered identifying and get noise added.
+    pub noise_threshold: u64,
+    /// Scale of the Laplace noise added to identifying counts.
+    pub noise_scale: f64,
+    /// Counts below this after adding noise are dropped entirely.
+    pub min_count: u64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            noise_threshold: 100,
+            noise_scale: 2.0,
+            min_count: 5,
+        }
+    }
+}
+
+/// Adds noise to and drops rare counts from every frequency table in `stats`.
+pub fn anonymize<R: Rng>(stats: &mut Stats, config: &PrivacyConfig, rng: &mut R) {
+    anonymize_counts(&mut stats.char_counts, config, rng);
+    anonymize_counts(&mut stats.consecutive_char_counts, config, rng);
+    anonymize_counts(&mut stats.shifted_char_counts, config, rng);
+    anonymize_counts(&mut stats.individual_key_counts, config, rng);
+    anonymize_counts(&mut stats.consecutive_key_counts, config, rng);
+    anonymize_counts(&mut stats.trigram_char_counts, config, rng);
+    anonymize_counts(&mut stats.trigram_key_counts, config, rng);
+    anonymize_counts(&mut stats.simultaneous_key_counts, config, rng);
+    anonymize_counts(&mut stats.rollover_key_counts, config, rng);
+    anonymize_counts(&mut stats.word_counts, config, rng);
+    anonymize_counts(&mut stats.error_key_counts, config, rng);
+    anonymize_counts(&mut stats.confusion_counts, config, rng);
+    anonymize_counts(&mut stats.corrected_char_counts, config, rng);
+    anonymize_counts(&mut stats.correction_bigram_counts, config, rng);
+    anonymize_counts(&mut stats.ignored_key_counts, config, rng);
+    // Hostnames and device names identify whoever shares the stats
+    stats.log_headers.clear();
+    // Latencies would give away the pairs dropped above
+    let bigrams = &stats.consecutive_key_counts;
+    stats
+        .latencies
+        .bigrams
+        .retain(|pair, _| bigrams.contains_key(pair));
+}
+
+fn anonymize_counts<K: Eq + Hash, R: Rng>(
+    counts: &mut HashMap<K, u64>,
+    config: &PrivacyConfig,
+    rng: &mut R,
+) {
+    counts.retain(|_, count| {
+        if *count < config.noise_threshold {
+            let noisy = *count as f64 + laplace(rng, config.noise_scale);
+            *count = noisy.round().max(0.0) as u64;
+        }
+        *count >= config.min_count
+    });
+}
+
+fn laplace<R: Rng>(rng: &mut R, scale: f64) -> f64 {
+    if scale <= 0.0 {
+        return 0.0;
+    }
+    let u: f64 = rng.gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drops_rare_counts() {
+        let mut stats = Stats::new();
+        stats.char_counts.insert('a', 1000);
+        stats.char_counts.insert('q', 3);
+        let config = PrivacyConfig {
+            noise_threshold: 100,
+            noise_scale: 0.0,
+            min_count: 5,
+        };
+        anonymize(&mut stats, &config, &mut rand::thread_rng());
+        assert_eq!(stats.char_counts.get(&'a'), Some(&1000));
+        assert_eq!(stats.char_counts.get(&'q'), None);
+    }
+
+    #[test]
+    fn test_window_exclusions() {
+        let exclusions = WindowExclusions::new(&[
+            "KeePassXC".to_string(),
+            "*ssh *".to_string(),
+            "*bank*login*".to_string(),
+        ]);
+        let window = |app: &str, title: &str| ActiveWindow {
+            app: app.to_string(),
+            title: title.to_string(),
+        };
+        assert_eq!(
+            exclusions.matching(&window("keepassxc", "Passwords.kdbx")),
+            Some("keepassxc")
+        );
+        assert_eq!(exclusions.matching(&window("KeePassXC-Browser", "")), None);
+        assert!(exclusions
+            .matching(&window("gnome-terminal", "ssh me@server"))
+            .is_some());
+        assert!(exclusions
+            .matching(&window("firefox", "My Bank - Login"))
+            .is_some());
+        assert_eq!(
+            exclusions.matching(&window("firefox", "Login - Bank")),
+            None
+        );
+        assert!(!wildcard_match("a*a", "a"));
+        assert!(wildcard_match("*", ""));
+    }
+}
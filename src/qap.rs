@@ -0,0 +1,432 @@
+//! Placing keys as a quadratic assignment problem, to find provably optimal
+//! placements of a few keys or bounds on how good any placement can be.
+//!
+//! Some keys of a layout are freed and placed among the physical keys they
+//! occupy, with the rest staying put.  The [`CostModel`] turns the presses
+//! and bigrams of every placement into a cost, which is either minimized
+//! exactly by [`Qap::branch_and_bound`] or handed to an external solver in
+//! the format of QAPLIB.
+
+use std::io::{self, Write};
+
+use crate::{cost_model::CostModel, layout::Key, layout::Layout, stats::Stats};
+
+/// Differences in cost smaller than this are rounding errors.
+const EPSILON: f64 = 1e-9;
+
+/// Keys to place on physical keys, at the cost of `linear[k][l]` for key
+/// `k` on location `l`, plus `flow[k][m] * distance[l][o]` for every pair
+/// of keys `k` and `m` on locations `l` and `o`.
+#[derive(Debug, Clone)]
+pub struct Qap {
+    /// The keys to place.
+    pub keys: Vec<Key>,
+    /// The indices of the physical keys they can go on.
+    pub locations: Vec<usize>,
+    /// The presses of every key and the transitions with the keys that
+    /// stay put, by key and location.
+    pub linear: Vec<Vec<f64>>,
+    /// The bigrams between the keys to place.
+    pub flow: Vec<Vec<f64>>,
+    /// The transitions between the locations.
+    pub distance: Vec<Vec<f64>>,
+    /// The cost of the keys that stay put among themselves.
+    pub constant: f64,
+}
+
+/// The best placement found by [`Qap::branch_and_bound`].
+#[derive(Debug, Clone)]
+pub struct QapSolution {
+    /// The location of every key.
+    pub assignment: Vec<usize>,
+    pub cost: f64,
+    /// No placement costs less than this.  Equal to `cost` if the search
+    /// finished, otherwise the least bound of what's left to search.
+    pub lower_bound: f64,
+    /// Partial placements searched.
+    pub nodes: u64,
+}
+
+impl QapSolution {
+    /// Whether no placement costs less.
+    pub fn optimal(&self) -> bool {
+        self.lower_bound >= self.cost - EPSILON
+    }
+}
+
+impl Qap {
+    /// Frees the keys of `layout` on the physical keys at `free` and keeps
+    /// the others where they are.
+    pub fn new(layout: &Layout, free: &[usize], stats: &Stats, model: &CostModel) -> Self {
+        let keys = layout.keys();
+        let presses = |key: &Key| {
+            stats
+                .individual_key_counts
+                .get(&key.keycode(false))
+                .copied()
+                .unwrap_or(0) as f64
+        };
+        let bigrams =
+            |a: &Key, b: &Key| stats.key_bigram_weight((a.keycode(false), b.keycode(false)));
+        let fixed: Vec<usize> = (0..keys.len()).filter(|i| !free.contains(i)).collect();
+        let trans = &model.transition;
+
+        let linear = free
+            .iter()
+            .map(|&k| {
+                let key = &keys[k];
+                free.iter()
+                    .map(|&l| {
+                        let mut cost =
+                            presses(key) * model.effort[l] + bigrams(key, key) * trans[l][l];
+                        for &f in &fixed {
+                            cost += bigrams(key, &keys[f]) * trans[l][f]
+                                + bigrams(&keys[f], key) * trans[f][l];
+                        }
+                        cost
+                    })
+                    .collect()
+            })
+            .collect();
+        let flow = free
+            .iter()
+            .map(|&k| {
+                free.iter()
+                    .map(|&m| {
+                        if k == m {
+                            0.0
+                        } else {
+                            bigrams(&keys[k], &keys[m])
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let distance = free
+            .iter()
+            .map(|&l| free.iter().map(|&o| trans[l][o]).collect())
+            .collect();
+        let mut constant = 0.0;
+        for &f in &fixed {
+            constant += presses(&keys[f]) * model.effort[f];
+            for &g in &fixed {
+                constant += bigrams(&keys[f], &keys[g]) * trans[f][g];
+            }
+        }
+        Self {
+            keys: free.iter().map(|&k| keys[k]).collect(),
+            locations: free.to_vec(),
+            linear,
+            flow,
+            distance,
+            constant,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The cost of placing every key `k` on location `assignment[k]`,
+    /// including the keys that stay put.
+    pub fn cost(&self, assignment: &[usize]) -> f64 {
+        let mut cost = self.constant;
+        for (k, &l) in assignment.iter().enumerate() {
+            cost += self.linear[k][l];
+            for (m, &o) in assignment.iter().enumerate() {
+                cost += self.flow[k][m] * self.distance[l][o];
+            }
+        }
+        cost
+    }
+
+    /// The placement the keys start out in: every key on its own location.
+    pub fn identity(&self) -> Vec<usize> {
+        (0..self.len()).collect()
+    }
+
+    /// `layout` with the keys placed by `assignment`.
+    pub fn apply(&self, layout: &Layout, assignment: &[usize]) -> Layout {
+        let mut keys = layout.keys().to_vec();
+        for (k, &l) in assignment.iter().enumerate() {
+            keys[self.locations[l]] = self.keys[k];
+        }
+        Layout::new(keys)
+    }
+
+    /// A bound no placement costs less than, without searching any.
+    pub fn lower_bound(&self) -> f64 {
+        let search = Search::new(self, 0);
+        self.constant + search.bound(&self.linear, &vec![false; self.len()], 0)
+    }
+
+    /// Searches placements for the cheapest, starting from `incumbent`,
+    /// and skipping those whose bound shows they can't be cheaper.  Stops
+    /// after `max_nodes`, with the lower bound of what was left.  The
+    /// search grows exponentially, so this is only practical for about 20
+    /// keys.
+    pub fn branch_and_bound(&self, incumbent: Vec<usize>, max_nodes: u64) -> QapSolution {
+        let mut search = Search::new(self, max_nodes);
+        search.best_cost = self.cost(&incumbent);
+        search.best = incumbent;
+        let mut assignment = vec![usize::MAX; self.len()];
+        let mut used = vec![false; self.len()];
+        search.visit(&self.linear, &mut assignment, &mut used, self.constant, 0);
+        QapSolution {
+            cost: search.best_cost,
+            lower_bound: search.open_bound.min(search.best_cost),
+            assignment: search.best,
+            nodes: search.nodes,
+        }
+    }
+
+    /// Writes the instance in the format of QAPLIB: the number of keys,
+    /// the flow, and the distances, followed by the linear costs as a
+    /// third matrix.  Solvers take integers, so distances and linear costs
+    /// are in thousandths.
+    pub fn write_qaplib(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "{}", self.len())?;
+        for (matrix, scale) in [
+            (&self.flow, 1.0),
+            (&self.distance, 1000.0),
+            (&self.linear, 1000.0),
+        ] {
+            writeln!(w)?;
+            for row in matrix {
+                let row: Vec<String> = row
+                    .iter()
+                    .map(|value| ((value * scale).round() as i64).to_string())
+                    .collect();
+                writeln!(w, "{}", row.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a placement in the format of a QAPLIB solution: the size and
+    /// the cost, followed by the 1-based location of every key.  Anything
+    /// before the last numbers is ignored, so solvers can log freely.
+    pub fn parse_solution(&self, s: &str) -> Result<Vec<usize>, String> {
+        let numbers: Vec<usize> = s
+            .split_whitespace()
+            .filter_map(|token| token.parse().ok())
+            .collect();
+        if numbers.len() < self.len() {
+            return Err(format!(
+                "expected the locations of {} keys, got {} numbers",
+                self.len(),
+                numbers.len()
+            ));
+        }
+        let assignment: Vec<usize> = numbers[numbers.len() - self.len()..]
+            .iter()
+            .map(|&l| l.wrapping_sub(1))
+            .collect();
+        let mut seen = vec![false; self.len()];
+        for &l in &assignment {
+            if l >= self.len() || std::mem::replace(&mut seen[l], true) {
+                return Err(format!(
+                    "expected a permutation of 1 to {}, got {:?}",
+                    self.len(),
+                    numbers
+                ));
+            }
+        }
+        Ok(assignment)
+    }
+}
+
+/// The state of [`Qap::branch_and_bound`].
+struct Search<'a> {
+    qap: &'a Qap,
+    /// The cheapest transition between two different locations, which no
+    /// pair of keys yet to place can beat.
+    min_distance: f64,
+    /// Keys in the order they're placed, heaviest flow first, so bad
+    /// branches are cut early.
+    order: Vec<usize>,
+    best: Vec<usize>,
+    best_cost: f64,
+    /// The least bound of the branches left unsearched at `max_nodes`.
+    open_bound: f64,
+    nodes: u64,
+    max_nodes: u64,
+}
+
+impl<'a> Search<'a> {
+    fn new(qap: &'a Qap, max_nodes: u64) -> Self {
+        let n = qap.len();
+        let min_distance = (0..n)
+            .flat_map(|l| (0..n).filter(move |&o| o != l).map(move |o| (l, o)))
+            .map(|(l, o)| qap.distance[l][o])
+            .fold(f64::INFINITY, f64::min);
+        let weight = |k: usize| (0..n).map(|m| qap.flow[k][m] + qap.flow[m][k]).sum::<f64>();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| weight(b).total_cmp(&weight(a)));
+        Self {
+            qap,
+            min_distance: if min_distance.is_finite() {
+                min_distance
+            } else {
+                0.0
+            },
+            order,
+            best: vec![],
+            best_cost: f64::INFINITY,
+            open_bound: f64::INFINITY,
+            nodes: 0,
+            max_nodes,
+        }
+    }
+
+    /// The least cost of placing the keys after the first `depth` of
+    /// `order`, given the costs of every key and location with the keys
+    /// placed so far in `partial`.  Pairs of keys yet to place are bounded
+    /// by the cheapest transition, and every key by its cheapest location.
+    fn bound(&self, partial: &[Vec<f64>], used: &[bool], depth: usize) -> f64 {
+        let rest = &self.order[depth..];
+        let mut bound = 0.0;
+        for &k in rest {
+            bound += (0..self.qap.len())
+                .filter(|&l| !used[l])
+                .map(|l| partial[k][l])
+                .fold(f64::INFINITY, f64::min);
+            for &m in rest {
+                bound += self.qap.flow[k][m] * self.min_distance;
+            }
+        }
+        bound
+    }
+
+    fn visit(
+        &mut self,
+        partial: &[Vec<f64>],
+        assignment: &mut [usize],
+        used: &mut [bool],
+        cost: f64,
+        depth: usize,
+    ) {
+        self.nodes += 1;
+        let n = self.qap.len();
+        if depth == n {
+            if cost < self.best_cost - EPSILON {
+                self.best_cost = cost;
+                self.best = assignment.to_vec();
+            }
+            return;
+        }
+        let k = self.order[depth];
+        let mut children = vec![];
+        let free: Vec<usize> = (0..n).filter(|&l| !used[l]).collect();
+        for l in free {
+            // Placing k on l adds its transitions to every key yet to place
+            let mut next = partial.to_vec();
+            for &m in &self.order[depth + 1..] {
+                for (o, cost) in next[m].iter_mut().enumerate() {
+                    *cost += self.qap.flow[k][m] * self.qap.distance[l][o]
+                        + self.qap.flow[m][k] * self.qap.distance[o][l];
+                }
+            }
+            used[l] = true;
+            let child_cost = cost + partial[k][l];
+            let bound = child_cost + self.bound(&next, used, depth + 1);
+            used[l] = false;
+            children.push((bound, l, child_cost, next));
+        }
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (bound, l, child_cost, next) in children {
+            if bound >= self.best_cost - EPSILON {
+                continue;
+            }
+            if self.nodes >= self.max_nodes {
+                self.open_bound = self.open_bound.min(bound);
+                continue;
+            }
+            assignment[k] = l;
+            used[l] = true;
+            self.visit(&next, assignment, used, child_cost, depth + 1);
+            used[l] = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format::parse_keymap_config, scoring::ScoringConfig};
+    use device_query::Keycode;
+
+    #[test]
+    fn test_branch_and_bound() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let model = CostModel::new(&config, &ScoringConfig::default());
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        let letters = [Keycode::E, Keycode::T, Keycode::A, Keycode::O, Keycode::Z];
+        for (i, &code) in letters.iter().enumerate() {
+            stats
+                .individual_key_counts
+                .insert(code, 100 - 20 * i as u64);
+            stats
+                .consecutive_key_counts
+                .insert((code, letters[(i + 1) % letters.len()]), 10);
+        }
+        stats.individual_key_counts.insert(Keycode::J, 5);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::J, Keycode::E), 30);
+
+        let free: Vec<usize> = [
+            Keycode::E,
+            Keycode::T,
+            Keycode::A,
+            Keycode::O,
+            Keycode::Z,
+            Keycode::Q,
+        ]
+        .iter()
+        .map(|&code| layout.position_of_keycode(code).unwrap())
+        .collect();
+        let qap = Qap::new(&layout, &free, &stats, &model);
+        let identity = qap.identity();
+        let solution = qap.branch_and_bound(identity.clone(), u64::MAX);
+        assert!(solution.optimal());
+        assert!(solution.cost <= qap.cost(&identity) + EPSILON);
+        assert!(qap.lower_bound() <= solution.cost + EPSILON);
+
+        // Nothing beats the solution, checked by trying every placement
+        let mut best = f64::INFINITY;
+        let mut assignment = identity;
+        permutations(&mut assignment, 0, &mut |a| best = best.min(qap.cost(a)));
+        assert!((best - solution.cost).abs() < 1e-6);
+
+        let mut dat = vec![];
+        qap.write_qaplib(&mut dat).unwrap();
+        let sln: String = solution
+            .assignment
+            .iter()
+            .map(|l| (l + 1).to_string() + " ")
+            .collect();
+        assert_eq!(
+            qap.parse_solution(&format!("6 123\n{sln}")).unwrap(),
+            solution.assignment
+        );
+        assert!(qap.parse_solution("6 0\n1 1 2 3 4 5").is_err());
+    }
+
+    fn permutations(a: &mut [usize], i: usize, f: &mut impl FnMut(&[usize])) {
+        if i == a.len() {
+            f(a);
+            return;
+        }
+        for j in i..a.len() {
+            a.swap(i, j);
+            permutations(a, i + 1, f);
+            a.swap(i, j);
+        }
+    }
+}
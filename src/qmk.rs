@@ -0,0 +1,458 @@
+//! Exporting a layout as a QMK keymap, so a generated layout can be
+//! flashed onto keyboards running QMK firmware.
+//!
+//! The keys are passed to the keyboard's `LAYOUT` macro in the order of
+//! the physical keys in the `.layout` file, which has to match the order
+//! the macro expects.  Layers become `LT` tap-hold keys, shifted
+//! characters that differ from a US layout become key overrides, and
+//! combos send their output with `SEND_STRING`.
+//!
+//! Keymaps can also be exported as a `keymap.json`, which QMK Configurator
+//! reads too, but which has no key overrides or combos.
+
+use std::{fmt::Write, path::Path};
+
+use device_query::Keycode;
+
+use crate::{
+    combos::Combo,
+    layout::{default_shifted, Key, Layer, Layout},
+    stats::char_to_key_event,
+    KeymapConfig,
+};
+
+/// The files of a QMK keymap directory.
+pub struct QmkKeymap {
+    pub keymap_c: String,
+    pub config_h: String,
+    pub rules_mk: String,
+}
+
+impl QmkKeymap {
+    /// Writes the files into `dir`, creating it if needed.
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("keymap.c"), &self.keymap_c)?;
+        std::fs::write(dir.join("config.h"), &self.config_h)?;
+        std::fs::write(dir.join("rules.mk"), &self.rules_mk)?;
+        Ok(())
+    }
+}
+
+/// Builds a keymap with `base` as the default layer followed by `layers`,
+/// plus `combos` on the base layer.  `tapping_term_ms` is how long a
+/// layer's hold key has to be held to activate the layer instead of typing
+/// its key.
+pub fn export_qmk(
+    base: &Layout,
+    layers: &[Layer],
+    combos: &[Combo],
+    config: &KeymapConfig,
+    tapping_term_ms: u32,
+) -> QmkKeymap {
+    let codes = layer_codes(base, layers, |_, layer| layer_name(&layer.name), "_______");
+    let base_codes = &codes[0];
+    let overrides = key_overrides(base);
+
+    let mut keymap_c = String::new();
+    writeln!(keymap_c, "#include QMK_KEYBOARD_H").unwrap();
+    writeln!(keymap_c).unwrap();
+    writeln!(keymap_c, "enum layers {{").unwrap();
+    writeln!(keymap_c, "    _BASE,").unwrap();
+    for layer in layers {
+        writeln!(keymap_c, "    {},", layer_name(&layer.name)).unwrap();
+    }
+    writeln!(keymap_c, "}};").unwrap();
+    writeln!(keymap_c).unwrap();
+    writeln!(
+        keymap_c,
+        "const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{"
+    )
+    .unwrap();
+    let names = std::iter::once("_BASE".to_string())
+        .chain(layers.iter().map(|layer| layer_name(&layer.name)));
+    for (name, codes) in names.zip(&codes) {
+        write_layer(&mut keymap_c, &name, codes, config);
+    }
+    writeln!(keymap_c, "}};").unwrap();
+
+    if !overrides.is_empty() {
+        writeln!(keymap_c).unwrap();
+        for (i, (normal, shifted)) in overrides.iter().enumerate() {
+            writeln!(
+                keymap_c,
+                "const key_override_t override_{i} = ko_make_basic(MOD_MASK_SHIFT, {normal}, {shifted});"
+            )
+            .unwrap();
+        }
+        writeln!(keymap_c).unwrap();
+        writeln!(keymap_c, "const key_override_t *key_overrides[] = {{").unwrap();
+        for i in 0..overrides.len() {
+            writeln!(keymap_c, "    &override_{i},").unwrap();
+        }
+        writeln!(keymap_c, "}};").unwrap();
+    }
+
+    if !combos.is_empty() {
+        writeln!(keymap_c).unwrap();
+        for (i, combo) in combos.iter().enumerate() {
+            writeln!(
+                keymap_c,
+                "const uint16_t PROGMEM combo_{i}[] = {{{}, {}, COMBO_END}};",
+                base_codes[combo.keys.0], base_codes[combo.keys.1]
+            )
+            .unwrap();
+        }
+        writeln!(keymap_c).unwrap();
+        writeln!(keymap_c, "combo_t key_combos[] = {{").unwrap();
+        for i in 0..combos.len() {
+            writeln!(keymap_c, "    COMBO_ACTION(combo_{i}),").unwrap();
+        }
+        writeln!(keymap_c, "}};").unwrap();
+        writeln!(keymap_c).unwrap();
+        writeln!(
+            keymap_c,
+            "void process_combo_event(uint16_t combo_index, bool pressed) {{"
+        )
+        .unwrap();
+        writeln!(keymap_c, "    if (!pressed) {{").unwrap();
+        writeln!(keymap_c, "        return;").unwrap();
+        writeln!(keymap_c, "    }}").unwrap();
+        writeln!(keymap_c, "    switch (combo_index) {{").unwrap();
+        for (i, combo) in combos.iter().enumerate() {
+            writeln!(
+                keymap_c,
+                "        case {i}: SEND_STRING({}); break;",
+                c_string(&combo.output)
+            )
+            .unwrap();
+        }
+        writeln!(keymap_c, "    }}").unwrap();
+        writeln!(keymap_c, "}}").unwrap();
+    }
+
+    let mut config_h = String::new();
+    writeln!(config_h, "#pragma once").unwrap();
+    if !layers.is_empty() {
+        writeln!(config_h).unwrap();
+        writeln!(
+            config_h,
+            "// Layers are held on keys that still type when tapped"
+        )
+        .unwrap();
+        writeln!(config_h, "#define TAPPING_TERM {tapping_term_ms}").unwrap();
+        writeln!(config_h, "#define PERMISSIVE_HOLD").unwrap();
+        writeln!(config_h, "#define QUICK_TAP_TERM 0").unwrap();
+    }
+
+    let mut rules_mk = String::new();
+    if !overrides.is_empty() {
+        writeln!(rules_mk, "KEY_OVERRIDE_ENABLE = yes").unwrap();
+    }
+    if !combos.is_empty() {
+        writeln!(rules_mk, "COMBO_ENABLE = yes").unwrap();
+    }
+
+    QmkKeymap {
+        keymap_c,
+        config_h,
+        rules_mk,
+    }
+}
+
+/// A `keymap.json`, and what it had to leave out.
+pub struct QmkJsonKeymap {
+    pub keymap_json: serde_json::Value,
+    /// Keys whose shifted character differs from a US layout, which would
+    /// need key overrides and type the US shifted character instead.
+    pub dropped_overrides: usize,
+}
+
+/// Builds a `keymap.json` for the QMK `keyboard` with `base` as the default
+/// layer followed by `layers`, named `keymap`, like [`export_qmk`].
+pub fn export_qmk_json(
+    base: &Layout,
+    layers: &[Layer],
+    keyboard: &str,
+    keymap: &str,
+    tapping_term_ms: u32,
+) -> QmkJsonKeymap {
+    // JSON keymaps refer to layers by their index, after the base layer
+    let codes = layer_codes(base, layers, |i, _| (i + 1).to_string(), "KC_TRNS");
+    let mut keymap_json = serde_json::json!({
+        "version": 1,
+        "keyboard": keyboard,
+        "keymap": keymap,
+        "layout": "LAYOUT",
+        "layers": codes,
+    });
+    if !layers.is_empty() {
+        keymap_json["config"] = serde_json::json!({
+            "tapping": {
+                "term": tapping_term_ms,
+                "permissive_hold": true,
+            },
+        });
+    }
+    QmkJsonKeymap {
+        keymap_json,
+        dropped_overrides: key_overrides(base).len(),
+    }
+}
+
+/// The keycodes of `base` and every layer, with `LT` hold keys on the base
+/// layer referring to the layers by `layer_ref` and keys a layer leaves
+/// alone as `transparent`.
+fn layer_codes(
+    base: &Layout,
+    layers: &[Layer],
+    layer_ref: impl Fn(usize, &Layer) -> String,
+    transparent: &str,
+) -> Vec<Vec<String>> {
+    let mut base_codes: Vec<String> = base.keys().iter().map(key_to_qmk).collect();
+    for (i, layer) in layers.iter().enumerate() {
+        // Only basic keycodes can be tapped, so the hold key types its
+        // unshifted keycode.
+        let tap = keycode_to_qmk(base.keys()[layer.hold].keycode(false)).unwrap_or("KC_NO");
+        base_codes[layer.hold] = format!("LT({}, {tap})", layer_ref(i, layer));
+    }
+    let mut codes = vec![base_codes];
+    for layer in layers {
+        codes.push(
+            layer
+                .keys
+                .iter()
+                .map(|key| key.as_ref().map_or(transparent.to_string(), key_to_qmk))
+                .collect(),
+        );
+    }
+    codes
+}
+
+/// The unshifted and shifted keycodes of the keys of `base` whose shifted
+/// character differs from a US layout.
+fn key_overrides(base: &Layout) -> Vec<(String, String)> {
+    base.keys()
+        .iter()
+        .filter_map(|key| match key {
+            Key::Normal { normal, shifted } if *shifted != default_shifted(*normal) => {
+                Some((char_to_qmk(*normal)?, char_to_qmk(*shifted)?))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes one layer, breaking the lines where the rows of the physical
+/// keyboard end.
+fn write_layer(s: &mut String, name: &str, codes: &[String], config: &KeymapConfig) {
+    writeln!(s, "    [{name}] = LAYOUT(").unwrap();
+    let keys = config.keys.keys();
+    for (i, code) in codes.iter().enumerate() {
+        let row_start = i == 0 || keys[i - 1].position.1 != keys[i].position.1;
+        let last = i + 1 == codes.len();
+        if row_start {
+            write!(s, "        ").unwrap();
+        }
+        write!(s, "{code}").unwrap();
+        if !last {
+            write!(s, ",").unwrap();
+        }
+        if last || keys[i + 1].position.1 != keys[i].position.1 {
+            writeln!(s).unwrap();
+        } else {
+            write!(s, " ").unwrap();
+        }
+    }
+    writeln!(s, "    ),").unwrap();
+}
+
+/// The name of the layer's entry in the `layers` enum.
+fn layer_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("_{name}")
+}
+
+/// `s` as a C string literal.
+fn c_string(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            literal.push('\\');
+        }
+        literal.push(c);
+    }
+    literal.push('"');
+    literal
+}
+
+fn key_to_qmk(key: &Key) -> String {
+    match key {
+        Key::Normal { normal, .. } => char_to_qmk(*normal).unwrap_or("KC_NO".to_string()),
+        key => keycode_to_qmk(key.keycode(false))
+            .unwrap_or("KC_NO")
+            .to_string(),
+    }
+}
+
+/// The QMK keycode typing `c` on a US system layout, shifted if needed.
+fn char_to_qmk(c: char) -> Option<String> {
+    let (code, shift) = match c {
+        ' ' => (Keycode::Space, false),
+        c => char_to_key_event(c)?,
+    };
+    let code = keycode_to_qmk(code)?;
+    Some(if shift {
+        format!("S({code})")
+    } else {
+        code.to_string()
+    })
+}
+
+fn keycode_to_qmk(code: Keycode) -> Option<&'static str> {
+    Some(match code {
+        Keycode::A => "KC_A",
+        Keycode::B => "KC_B",
+        Keycode::C => "KC_C",
+        Keycode::D => "KC_D",
+        Keycode::E => "KC_E",
+        Keycode::F => "KC_F",
+        Keycode::G => "KC_G",
+        Keycode::H => "KC_H",
+        Keycode::I => "KC_I",
+        Keycode::J => "KC_J",
+        Keycode::K => "KC_K",
+        Keycode::L => "KC_L",
+        Keycode::M => "KC_M",
+        Keycode::N => "KC_N",
+        Keycode::O => "KC_O",
+        Keycode::P => "KC_P",
+        Keycode::Q => "KC_Q",
+        Keycode::R => "KC_R",
+        Keycode::S => "KC_S",
+        Keycode::T => "KC_T",
+        Keycode::U => "KC_U",
+        Keycode::V => "KC_V",
+        Keycode::W => "KC_W",
+        Keycode::X => "KC_X",
+        Keycode::Y => "KC_Y",
+        Keycode::Z => "KC_Z",
+        Keycode::Key1 => "KC_1",
+        Keycode::Key2 => "KC_2",
+        Keycode::Key3 => "KC_3",
+        Keycode::Key4 => "KC_4",
+        Keycode::Key5 => "KC_5",
+        Keycode::Key6 => "KC_6",
+        Keycode::Key7 => "KC_7",
+        Keycode::Key8 => "KC_8",
+        Keycode::Key9 => "KC_9",
+        Keycode::Key0 => "KC_0",
+        Keycode::Grave => "KC_GRV",
+        Keycode::Minus => "KC_MINS",
+        Keycode::Equal => "KC_EQL",
+        Keycode::LeftBracket => "KC_LBRC",
+        Keycode::RightBracket => "KC_RBRC",
+        Keycode::BackSlash => "KC_BSLS",
+        Keycode::Semicolon => "KC_SCLN",
+        Keycode::Apostrophe => "KC_QUOT",
+        Keycode::Comma => "KC_COMM",
+        Keycode::Dot => "KC_DOT",
+        Keycode::Slash => "KC_SLSH",
+        Keycode::Space => "KC_SPC",
+        Keycode::Enter => "KC_ENT",
+        Keycode::Tab => "KC_TAB",
+        Keycode::Backspace => "KC_BSPC",
+        Keycode::Delete => "KC_DEL",
+        Keycode::Escape => "KC_ESC",
+        Keycode::CapsLock => "KC_CAPS",
+        Keycode::LShift => "KC_LSFT",
+        Keycode::RShift => "KC_RSFT",
+        Keycode::LControl => "KC_LCTL",
+        Keycode::RControl => "KC_RCTL",
+        Keycode::LAlt => "KC_LALT",
+        Keycode::RAlt => "KC_RALT",
+        Keycode::LMeta => "KC_LGUI",
+        Keycode::RMeta => "KC_RGUI",
+        Keycode::Home => "KC_HOME",
+        Keycode::End => "KC_END",
+        Keycode::PageUp => "KC_PGUP",
+        Keycode::PageDown => "KC_PGDN",
+        Keycode::Left => "KC_LEFT",
+        Keycode::Right => "KC_RGHT",
+        Keycode::Up => "KC_UP",
+        Keycode::Down => "KC_DOWN",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_export_qmk() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut base = Layout::from_keymap(&config);
+        let q = base.get(&Key::from_keycode(Keycode::Q));
+        let mut keys = base.keys().to_vec();
+        keys[q] = Key::Normal {
+            normal: 'q',
+            shifted: '!',
+        };
+        base = Layout::new(keys);
+        let mut layer_keys = vec![None; config.keys.keys().len()];
+        layer_keys[0] = Some(Key::Left);
+        let layer = Layer {
+            name: "nav".to_string(),
+            hold: 5,
+            keys: layer_keys,
+        };
+
+        let combo = Combo {
+            keys: (0, 1),
+            output: "th".to_string(),
+            count: 1,
+            benefit: 1.0,
+        };
+
+        let layers = [layer];
+        let keymap = export_qmk(&base, &layers, &[combo], &config, 200);
+        assert!(keymap
+            .keymap_c
+            .contains("[_NAV] = LAYOUT(\n        KC_LEFT, _______,"));
+        assert!(keymap.keymap_c.contains("LT(_NAV, KC_5)"));
+        assert!(keymap
+            .keymap_c
+            .contains("ko_make_basic(MOD_MASK_SHIFT, KC_Q, S(KC_1))"));
+        assert!(keymap
+            .keymap_c
+            .contains("combo_0[] = {KC_EQL, KC_1, COMBO_END};"));
+        assert!(keymap
+            .keymap_c
+            .contains("case 0: SEND_STRING(\"th\"); break;"));
+        assert!(keymap.config_h.contains("#define TAPPING_TERM 200"));
+        assert_eq!(
+            keymap.rules_mk,
+            "KEY_OVERRIDE_ENABLE = yes\nCOMBO_ENABLE = yes\n"
+        );
+
+        let json = export_qmk_json(&base, &layers, "kinesis/kint36", "mine", 200);
+        let json_layers = json.keymap_json["layers"].as_array().unwrap();
+        assert_eq!(json_layers.len(), 2);
+        assert_eq!(json_layers[0][5], "LT(1, KC_5)");
+        assert_eq!(json_layers[1][0], "KC_LEFT");
+        assert_eq!(json_layers[1][1], "KC_TRNS");
+        assert_eq!(json.keymap_json["config"]["tapping"]["term"], 200);
+        assert_eq!(json.dropped_overrides, 1);
+    }
+}
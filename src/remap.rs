@@ -0,0 +1,34 @@
+//! Applying a [`Layout`] to a live keyboard by remapping key events, so a
+//! generated layout can be tried without flashing firmware.
+
+use std::collections::HashMap;
+
+use device_query::Keycode;
+
+use crate::{layout::Layout, KeymapConfig};
+
+#[cfg(target_os = "linux")]
+pub mod uinput;
+
+/// Maps the keycode a physical key sends to the keycode the layout places
+/// on it.
+pub struct RemapTable(HashMap<Keycode, Keycode>);
+
+impl RemapTable {
+    pub fn new(layout: &Layout, config: &KeymapConfig) -> Self {
+        let mut table = HashMap::new();
+        for (key, physical) in layout.keys().iter().zip(config.keys.keys()) {
+            table.insert(physical.code, key.keycode(false));
+        }
+        Self(table)
+    }
+
+    /// Keys that aren't part of the layout are passed through unchanged.
+    pub fn map(&self, code: Keycode) -> Keycode {
+        *self.0.get(&code).unwrap_or(&code)
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = Keycode> + '_ {
+        self.0.values().copied()
+    }
+}
@@ -0,0 +1,213 @@
+//! A Linux remapping backend that grabs a keyboard through evdev and
+//! re-emits remapped events from a uinput virtual keyboard.
+//!
+//! Opening input devices and creating uinput devices usually requires root
+//! or membership in the `input` group.
+
+use std::{collections::HashSet, io};
+
+use device_query::Keycode;
+use evdev::{uinput::VirtualDevice, AttributeSet, Device, EventType, InputEvent, InputEventKind};
+
+use super::RemapTable;
+
+macro_rules! evdev_keys {
+    ($($variant:ident:$key:ident),*) => {
+        pub fn keycode_to_evdev(code: Keycode) -> Option<evdev::Key> {
+            match code {
+                $(Keycode::$variant => Some(evdev::Key::$key),)*
+                _ => None,
+            }
+        }
+
+        pub fn evdev_to_keycode(key: evdev::Key) -> Option<Keycode> {
+            match key {
+                $(evdev::Key::$key => Some(Keycode::$variant),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+evdev_keys! {
+    Key0: KEY_0,
+    Key1: KEY_1,
+    Key2: KEY_2,
+    Key3: KEY_3,
+    Key4: KEY_4,
+    Key5: KEY_5,
+    Key6: KEY_6,
+    Key7: KEY_7,
+    Key8: KEY_8,
+    Key9: KEY_9,
+    A: KEY_A,
+    B: KEY_B,
+    C: KEY_C,
+    D: KEY_D,
+    E: KEY_E,
+    F: KEY_F,
+    G: KEY_G,
+    H: KEY_H,
+    I: KEY_I,
+    J: KEY_J,
+    K: KEY_K,
+    L: KEY_L,
+    M: KEY_M,
+    N: KEY_N,
+    O: KEY_O,
+    P: KEY_P,
+    Q: KEY_Q,
+    R: KEY_R,
+    S: KEY_S,
+    T: KEY_T,
+    U: KEY_U,
+    V: KEY_V,
+    W: KEY_W,
+    X: KEY_X,
+    Y: KEY_Y,
+    Z: KEY_Z,
+    Escape: KEY_ESC,
+    Space: KEY_SPACE,
+    LControl: KEY_LEFTCTRL,
+    RControl: KEY_RIGHTCTRL,
+    LShift: KEY_LEFTSHIFT,
+    RShift: KEY_RIGHTSHIFT,
+    LAlt: KEY_LEFTALT,
+    RAlt: KEY_RIGHTALT,
+    LMeta: KEY_LEFTMETA,
+    RMeta: KEY_RIGHTMETA,
+    Enter: KEY_ENTER,
+    Up: KEY_UP,
+    Down: KEY_DOWN,
+    Left: KEY_LEFT,
+    Right: KEY_RIGHT,
+    Backspace: KEY_BACKSPACE,
+    CapsLock: KEY_CAPSLOCK,
+    Tab: KEY_TAB,
+    Home: KEY_HOME,
+    End: KEY_END,
+    PageUp: KEY_PAGEUP,
+    PageDown: KEY_PAGEDOWN,
+    Insert: KEY_INSERT,
+    Delete: KEY_DELETE,
+    Grave: KEY_GRAVE,
+    Minus: KEY_MINUS,
+    Equal: KEY_EQUAL,
+    LeftBracket: KEY_LEFTBRACE,
+    RightBracket: KEY_RIGHTBRACE,
+    BackSlash: KEY_BACKSLASH,
+    Semicolon: KEY_SEMICOLON,
+    Apostrophe: KEY_APOSTROPHE,
+    Comma: KEY_COMMA,
+    Dot: KEY_DOT,
+    Slash: KEY_SLASH
+}
+
+/// The first input device that looks like a full keyboard.
+pub fn find_keyboard() -> Option<Device> {
+    evdev::enumerate()
+        .map(|(_, device)| device)
+        .find(is_keyboard)
+}
+
+/// Whether `device` looks like a full keyboard rather than, say, the power
+/// button.
+pub fn is_keyboard(device: &Device) -> bool {
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(evdev::Key::KEY_A) && keys.contains(evdev::Key::KEY_SPACE)
+    })
+}
+
+/// Grabs a keyboard so its events only reach the rest of the system after
+/// being remapped.  The grab is released when this is dropped.
+pub struct UinputRemapper {
+    device: Device,
+    output: VirtualDevice,
+    table: RemapTable,
+    /// Remapped keys that are pressed, to release them when the remap
+    /// ends instead of leaving them stuck.
+    held: HashSet<evdev::Key>,
+}
+
+impl UinputRemapper {
+    pub fn new(mut device: Device, table: RemapTable) -> io::Result<Self> {
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        if let Some(supported) = device.supported_keys() {
+            for key in supported.iter() {
+                keys.insert(key);
+            }
+        }
+        for key in table.targets().filter_map(keycode_to_evdev) {
+            keys.insert(key);
+        }
+        let output = evdev::uinput::VirtualDeviceBuilder::new()?
+            .name("keyboard_layout_generator remap")
+            .with_keys(&keys)?
+            .build()?;
+        device.grab()?;
+        Ok(Self {
+            device,
+            output,
+            table,
+            held: HashSet::new(),
+        })
+    }
+
+    /// Blocks until the keyboard produces events, re-emits them remapped and
+    /// reports each remapped press (`true`) or release (`false`) to
+    /// `on_event`.  Key repeats are forwarded but not reported.
+    pub fn process(&mut self, mut on_event: impl FnMut(Keycode, bool)) -> io::Result<()> {
+        self.process_physical(|_, mapped, press| on_event(mapped, press))
+    }
+
+    /// Like [`process`](Self::process), but also reports the physical key
+    /// before remapping, as `(physical, remapped, press)`.
+    pub fn process_physical(
+        &mut self,
+        mut on_event: impl FnMut(Keycode, Keycode, bool),
+    ) -> io::Result<()> {
+        let events: Vec<_> = self.device.fetch_events()?.collect();
+        for event in events {
+            let InputEventKind::Key(key) = event.kind() else {
+                continue;
+            };
+            let mapped = match evdev_to_keycode(key) {
+                Some(code) => {
+                    let mapped = self.table.map(code);
+                    if event.value() != 2 {
+                        on_event(code, mapped, event.value() == 1);
+                    }
+                    keycode_to_evdev(mapped).unwrap_or(key)
+                }
+                None => key,
+            };
+            match event.value() {
+                0 => {
+                    self.held.remove(&mapped);
+                }
+                1 => {
+                    self.held.insert(mapped);
+                }
+                _ => {}
+            }
+            self.output.emit(&[InputEvent::new(
+                EventType::KEY,
+                mapped.code(),
+                event.value(),
+            )])?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UinputRemapper {
+    fn drop(&mut self) {
+        let releases: Vec<_> = self
+            .held
+            .drain()
+            .map(|key| InputEvent::new(EventType::KEY, key.code(), 0))
+            .collect();
+        let _ = self.output.emit(&releases);
+        let _ = self.device.ungrab();
+    }
+}
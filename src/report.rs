@@ -0,0 +1,689 @@
+//! Tables for eyeballing a layout against the stats.
+
+use crate::{
+    config::NumberFormat,
+    kle::finger_name,
+    layout::{layout_similarity, Key, Layout},
+    scoring::{
+        confusable_separation_score, correction_effort, finger_travel, hand_patterns,
+        home_row_mods, layout_confusion_score, layout_consecutive_key_score,
+        layout_individual_key_score, layout_score_with, same_finger_bigrams, thumb_load,
+        weighted_hand_patterns, HandPatterns, Intuition, LayoutScorer, SameFingerBigrams,
+        ScoringConfig, ThumbLoad,
+    },
+    stats::Stats,
+    Finger, Hand, KeymapConfig,
+};
+
+/// How a bigram is typed on a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigramKind {
+    /// Both characters are typed by the same finger.  Repeating a key
+    /// counts too.
+    SameFinger,
+    SameHand,
+    Alternating,
+}
+
+impl BigramKind {
+    /// A one letter flag for when color isn't available.
+    fn flag(&self) -> char {
+        match self {
+            BigramKind::SameFinger => 'F',
+            BigramKind::SameHand => 'H',
+            BigramKind::Alternating => ' ',
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            BigramKind::SameFinger => "\x1b[31m",
+            BigramKind::SameHand => "\x1b[33m",
+            BigramKind::Alternating => "\x1b[32m",
+        }
+    }
+}
+
+/// Returns `None` if either character isn't on the layout.
+pub fn bigram_kind(
+    layout: &Layout,
+    config: &KeymapConfig,
+    first: char,
+    second: char,
+) -> Option<BigramKind> {
+    let physical = config.keys.keys();
+    let finger1 = physical[layout.position_of_char(first)?].finger;
+    let finger2 = physical[layout.position_of_char(second)?].finger;
+    let kind = if finger1 == finger2 {
+        BigramKind::SameFinger
+    } else if finger1.hand == finger2.hand {
+        BigramKind::SameHand
+    } else {
+        BigramKind::Alternating
+    };
+    Some(kind)
+}
+
+/// Counts of the bigrams between the most typed characters.
+pub struct BigramMatrix {
+    /// The most typed characters, most typed first.
+    pub chars: Vec<char>,
+    /// `counts[i][j]` is how often `chars[i]` was followed by `chars[j]`.
+    pub counts: Vec<Vec<u64>>,
+}
+
+impl BigramMatrix {
+    /// The matrix of the `k` most typed characters.  Whitespace is left out
+    /// since it can't be told apart in a table.
+    pub fn top(stats: &Stats, k: usize) -> Self {
+        let mut chars: Vec<(char, u64)> = stats
+            .char_counts
+            .iter()
+            .filter(|(c, _)| !c.is_whitespace())
+            .map(|(c, count)| (*c, *count))
+            .collect();
+        chars.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        chars.truncate(k);
+        let chars: Vec<char> = chars.into_iter().map(|(c, _)| c).collect();
+
+        let counts = chars
+            .iter()
+            .map(|&first| {
+                chars
+                    .iter()
+                    .map(|&second| {
+                        stats
+                            .consecutive_char_counts
+                            .get(&(first, second))
+                            .copied()
+                            .unwrap_or(0)
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { chars, counts }
+    }
+
+    /// Renders the matrix with rows as the first character and columns as
+    /// the second.  Each cell is colored by its [`BigramKind`] on `layout`
+    /// if `color` is set, or flagged with `F` (same finger) or `H` (same
+    /// hand) otherwise.
+    pub fn render(
+        &self,
+        layout: &Layout,
+        config: &KeymapConfig,
+        fmt: &NumberFormat,
+        color: bool,
+    ) -> String {
+        let width = self
+            .counts
+            .iter()
+            .flatten()
+            .map(|count| fmt.count(*count).chars().count())
+            .max()
+            .unwrap_or(1)
+            + 1;
+
+        let mut s = String::new();
+        s.push_str("  ");
+        for c in &self.chars {
+            s.push_str(&format!(" {:>width$}", c, width = width));
+        }
+        s.push('\n');
+        for (i, &first) in self.chars.iter().enumerate() {
+            s.push_str(&format!("{} ", first));
+            for (j, &second) in self.chars.iter().enumerate() {
+                let count = self.counts[i][j];
+                let count_str = fmt.count(count);
+                let kind = bigram_kind(layout, config, first, second);
+                match kind {
+                    Some(kind) if color && count > 0 => s.push_str(&format!(
+                        " {}{:>width$}\x1b[0m",
+                        kind.color(),
+                        count_str,
+                        width = width
+                    )),
+                    Some(kind) => s.push_str(&format!(
+                        " {:>width$}{}",
+                        count_str,
+                        kind.flag(),
+                        width = width - 1
+                    )),
+                    None => s.push_str(&format!(" {:>width$}?", count_str, width = width - 1)),
+                }
+            }
+            s.push('\n');
+        }
+        if color {
+            s.push_str(&format!(
+                "{}same finger\x1b[0m {}same hand\x1b[0m {}alternating\x1b[0m\n",
+                BigramKind::SameFinger.color(),
+                BigramKind::SameHand.color(),
+                BigramKind::Alternating.color(),
+            ));
+        } else {
+            s.push_str("F: same finger, H: same hand, ?: not on the layout\n");
+        }
+        s
+    }
+}
+
+/// A warning listing the keys skipped while reading the logs of `stats`,
+/// most pressed first, or `None` if every key in the logs was known.
+pub fn ignored_keys_summary(stats: &Stats, fmt: &NumberFormat) -> Option<String> {
+    if stats.ignored_key_counts.is_empty() {
+        return None;
+    }
+    let mut ignored: Vec<(&String, &u64)> = stats.ignored_key_counts.iter().collect();
+    ignored.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let total: u64 = stats.ignored_key_counts.values().sum();
+    let keys = ignored
+        .iter()
+        .map(|(name, count)| format!("{} ({})", name, fmt.count(**count)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "{} presses of keys without a keycode were ignored: {}\nMap them to keycodes with key_aliases in config.json to count them",
+        fmt.count(total),
+        keys
+    ))
+}
+
+/// The metrics of a layout typing some stats, to compare layouts side by
+/// side.
+#[derive(Debug, Clone)]
+pub struct LayoutMetrics {
+    pub score: f64,
+    pub max_possible_score: f64,
+    pub same_finger: SameFingerBigrams,
+    pub patterns: HandPatterns,
+    pub thumbs: ThumbLoad,
+    /// Presses of every finger, in the order of the keymap config's
+    /// fingers.
+    pub finger_presses: Vec<(Finger, u64)>,
+    /// The share of keys in the same place as on the keycaps.
+    pub keycaps_similarity: f64,
+    /// How far the fingers travel from their home keys, see
+    /// [`finger_travel`].
+    pub travel: f64,
+}
+
+impl LayoutMetrics {
+    pub fn new(
+        layout: &Layout,
+        stats: &Stats,
+        keymap_config: &KeymapConfig,
+        scorer: &impl LayoutScorer,
+    ) -> Self {
+        let finger_presses = keymap_config
+            .fingers
+            .iter()
+            .map(|finger| {
+                let presses = layout
+                    .keys()
+                    .iter()
+                    .zip(keymap_config.keys.keys())
+                    .filter(|(_, pkey)| pkey.finger == finger.finger)
+                    .filter_map(|(key, _)| stats.individual_key_counts.get(&key.keycode(false)))
+                    .sum();
+                (finger.finger, presses)
+            })
+            .collect();
+        Self {
+            score: scorer.score(layout, stats, keymap_config),
+            max_possible_score: scorer.max_possible_score(stats),
+            same_finger: same_finger_bigrams(layout, stats, keymap_config),
+            patterns: hand_patterns(layout, stats, keymap_config),
+            thumbs: thumb_load(layout, stats, keymap_config),
+            finger_presses,
+            keycaps_similarity: layout_similarity(layout, &Layout::from_keymap(keymap_config))
+                as f64,
+            travel: finger_travel(layout, stats, keymap_config),
+        }
+    }
+
+    /// The metrics formatted with `fmt`, labeled.
+    pub fn rows(&self, fmt: &NumberFormat) -> Vec<(String, String)> {
+        let of_bigrams =
+            |count: u64| fmt.percent(count as f64 / self.patterns.bigrams.max(1) as f64);
+        let presses: u64 = self.finger_presses.iter().map(|(_, presses)| presses).sum();
+        let of_presses = |count: u64| fmt.percent(count as f64 / presses.max(1) as f64);
+        let left: u64 = self
+            .finger_presses
+            .iter()
+            .filter(|(finger, _)| finger.hand == Hand::Left)
+            .map(|(_, presses)| presses)
+            .sum();
+        let mut rows = vec![
+            ("Score".to_string(), fmt.decimal(self.score)),
+            (
+                "Of max".to_string(),
+                fmt.percent(self.score / self.max_possible_score),
+            ),
+            (
+                "Same finger bigrams".to_string(),
+                of_bigrams(self.same_finger.count),
+            ),
+            (
+                "Same finger travel".to_string(),
+                fmt.decimal(self.same_finger.weighted / self.same_finger.count.max(1) as f64),
+            ),
+            (
+                "Travel per bigram".to_string(),
+                fmt.decimal(self.travel / self.patterns.bigrams.max(1) as f64),
+            ),
+            (
+                "Hand alternation".to_string(),
+                of_bigrams(self.patterns.alternations),
+            ),
+            (
+                "Inward rolls".to_string(),
+                of_bigrams(self.patterns.inward_rolls),
+            ),
+            (
+                "Outward rolls".to_string(),
+                of_bigrams(self.patterns.outward_rolls),
+            ),
+            ("Left hand".to_string(), of_presses(left)),
+            ("Thumbs".to_string(), fmt.percent(self.thumbs.thumb_share())),
+            (
+                "Thumb hold conflicts".to_string(),
+                fmt.count(self.thumbs.hold_conflicts),
+            ),
+        ];
+        for (finger, count) in &self.finger_presses {
+            rows.push((finger_name(*finger), of_presses(*count)));
+        }
+        rows.push((
+            "Same as keycaps".to_string(),
+            fmt.percent(self.keycaps_similarity),
+        ));
+        rows
+    }
+}
+
+/// A table with a column of metrics for every named layout.
+pub fn metrics_table(layouts: &[(String, LayoutMetrics)], fmt: &NumberFormat) -> String {
+    let columns: Vec<Vec<(String, String)>> = layouts
+        .iter()
+        .map(|(_, metrics)| metrics.rows(fmt))
+        .collect();
+    let Some(first) = columns.first() else {
+        return String::new();
+    };
+    let label_width = first
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    let widths: Vec<usize> = layouts
+        .iter()
+        .zip(&columns)
+        .map(|((name, _), rows)| {
+            rows.iter()
+                .map(|(_, value)| value.chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let mut s = format!("{:label_width$}", "");
+    for ((name, _), width) in layouts.iter().zip(&widths) {
+        s.push_str(&format!("  {name:>width$}"));
+    }
+    s.push('\n');
+    for (i, (label, _)) in first.iter().enumerate() {
+        s.push_str(&format!("{label:label_width$}"));
+        for (rows, width) in columns.iter().zip(&widths) {
+            s.push_str(&format!("  {:>width$}", rows[i].1));
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// Every term that adds up to the score of `layout` with `scoring`, labeled,
+/// followed by whether each intuition holds, the same finger bigrams and the
+/// load of every finger, to see why a layout scores the way it does.
+pub fn score_breakdown(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    scoring: &ScoringConfig,
+    fmt: &NumberFormat,
+) -> String {
+    let weights = &scoring.weights;
+    let intuitions = scoring.intuitions();
+    let kept: Vec<bool> = intuitions
+        .iter()
+        .map(|intuition| intuition.satisfied(layout, keymap_config))
+        .collect();
+    let same_finger = same_finger_bigrams(layout, stats, keymap_config);
+    let patterns = weighted_hand_patterns(layout, stats, keymap_config);
+    let mut terms = vec![
+        (
+            "Individual keys",
+            layout_individual_key_score(layout, stats, keymap_config),
+        ),
+        (
+            "Consecutive keys",
+            layout_consecutive_key_score(layout, stats, keymap_config, weights),
+        ),
+        (
+            "Confused keys apart",
+            layout_confusion_score(layout, stats, keymap_config),
+        ),
+        (
+            "Intuitions",
+            weights.intuition_weight * kept.iter().filter(|kept| **kept).count() as f64,
+        ),
+        (
+            "Same finger bigrams",
+            -scoring.sfb_weight * same_finger.weighted,
+        ),
+        (
+            "Hand alternation",
+            scoring.alternation_bonus * patterns.alternations,
+        ),
+        (
+            "Inward rolls",
+            scoring.inward_roll_bonus * patterns.inward_rolls,
+        ),
+        (
+            "Outward rolls",
+            scoring.outward_roll_bonus * patterns.outward_rolls,
+        ),
+        (
+            "Thumb hold conflicts",
+            -scoring.thumb_conflict_weight
+                * thumb_load(layout, stats, keymap_config).hold_conflicts as f64,
+        ),
+    ];
+    if !scoring.confusable_pairs.is_empty() {
+        terms.push((
+            "Confusable pairs apart",
+            scoring.confusable_weight
+                * confusable_separation_score(layout, keymap_config, &scoring.confusable_pairs),
+        ));
+    }
+    if scoring.travel_weight != 0.0 {
+        terms.push((
+            "Finger travel",
+            -scoring.travel_weight * finger_travel(layout, stats, keymap_config),
+        ));
+    }
+    if !scoring.home_row_mods.is_empty() {
+        terms.push((
+            "Home row mods",
+            -scoring.home_row_mod_weight
+                * home_row_mods(layout, stats, keymap_config, &scoring.home_row_mods).cost,
+        ));
+    }
+    if scoring.correction_weight != 0.0 {
+        terms.push((
+            "Corrections",
+            -scoring.correction_weight * correction_effort(layout, stats, keymap_config, weights),
+        ));
+    }
+
+    let mut s = String::new();
+    let width = terms
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    for (label, value) in &terms {
+        s.push_str(&format!("{label:width$}  {}\n", fmt.decimal(*value)));
+    }
+    s.push_str(&format!(
+        "{:width$}  {}\n",
+        "Score",
+        fmt.decimal(layout_score_with(layout, stats, keymap_config, scoring))
+    ));
+
+    s.push_str("\nIntuitions:\n");
+    for (intuition, kept) in intuitions.iter().zip(&kept) {
+        let mark = if *kept { "kept" } else { "broken" };
+        s.push_str(&format!("{mark:6}  {intuition}\n"));
+    }
+
+    s.push_str(&format!(
+        "\nSame finger bigrams: {}, {} key widths of travel\n",
+        fmt.count(same_finger.count),
+        fmt.decimal(same_finger.weighted)
+    ));
+    let metrics = LayoutMetrics::new(layout, stats, keymap_config, scoring);
+    let presses: u64 = metrics
+        .finger_presses
+        .iter()
+        .map(|(_, presses)| presses)
+        .sum();
+    s.push_str("\nFinger load:\n");
+    for (finger, count) in &metrics.finger_presses {
+        s.push_str(&format!(
+            "{}: {} ({})\n",
+            finger_name(*finger),
+            fmt.count(*count),
+            fmt.percent(*count as f64 / presses.max(1) as f64)
+        ));
+    }
+    s
+}
+
+/// A key that ended up on a different physical key between two layouts.
+#[derive(Debug)]
+pub struct KeyMove {
+    pub key: Key,
+    pub from: usize,
+    pub to: usize,
+    /// The score change of making only this move, by swapping the key with
+    /// whatever was at its new place.  The deltas of all moves don't add up
+    /// to the total change since moves interact.
+    pub score_delta: f64,
+}
+
+/// The keys that moved from `before` to `after`, biggest score gain first.
+pub fn key_moves(before: &Layout, after: &Layout, score: impl Fn(&Layout) -> f64) -> Vec<KeyMove> {
+    let before_score = score(before);
+    let mut moves: Vec<KeyMove> = after
+        .keys()
+        .iter()
+        .enumerate()
+        .filter_map(|(to, key)| {
+            let from = before.get(key);
+            if from == to {
+                return None;
+            }
+            let mut moved = before.clone();
+            moved.swap(from, to);
+            Some(KeyMove {
+                key: *key,
+                from,
+                to,
+                score_delta: score(&moved) - before_score,
+            })
+        })
+        .collect();
+    moves.sort_by(|a, b| b.score_delta.partial_cmp(&a.score_delta).unwrap());
+    moves
+}
+
+/// Follows which intuitions the best layout satisfies from generation to
+/// generation, and which pairs of intuitions no layout has satisfied
+/// together.
+pub struct IntuitionTracker {
+    intuitions: Vec<Intuition>,
+    best_satisfied: Option<Vec<bool>>,
+    /// Whether each intuition has been satisfied by any layout.
+    ever: Vec<bool>,
+    /// Whether each pair of intuitions has been satisfied by the same
+    /// layout, indexed `[i][j]` with `i < j`.
+    ever_together: Vec<Vec<bool>>,
+    generations: usize,
+    warned: Vec<(usize, usize)>,
+}
+
+/// A change in whether the best layout satisfies an intuition.
+pub struct IntuitionFlip<'a> {
+    pub intuition: &'a Intuition,
+    pub satisfied: bool,
+}
+
+impl IntuitionTracker {
+    pub fn new(intuitions: Vec<Intuition>) -> Self {
+        let n = intuitions.len();
+        Self {
+            intuitions,
+            best_satisfied: None,
+            ever: vec![false; n],
+            ever_together: vec![vec![false; n]; n],
+            generations: 0,
+            warned: vec![],
+        }
+    }
+
+    /// Records a generation and returns the intuitions that flipped for the
+    /// best layout since the last one.
+    pub fn record<'a>(
+        &'a mut self,
+        best: &Layout,
+        population: &[Layout],
+        config: &KeymapConfig,
+    ) -> Vec<IntuitionFlip<'a>> {
+        self.generations += 1;
+        for layout in population {
+            let satisfied = self.satisfied(layout, config);
+            let indices: Vec<usize> = (0..satisfied.len()).filter(|&i| satisfied[i]).collect();
+            for (n, &i) in indices.iter().enumerate() {
+                self.ever[i] = true;
+                for &j in &indices[n + 1..] {
+                    self.ever_together[i][j] = true;
+                }
+            }
+        }
+
+        let satisfied = self.satisfied(best, config);
+        let previous = self.best_satisfied.replace(satisfied.clone());
+        let Some(previous) = previous else {
+            return vec![];
+        };
+        satisfied
+            .iter()
+            .zip(&previous)
+            .enumerate()
+            .filter(|(_, (now, before))| now != before)
+            .map(|(i, (now, _))| IntuitionFlip {
+                intuition: &self.intuitions[i],
+                satisfied: *now,
+            })
+            .collect()
+    }
+
+    /// Pairs of intuitions that have each been satisfied, but never by the
+    /// same layout after `min_generations`, which likely can't be satisfied
+    /// together.  Every pair is only returned once.
+    pub fn conflicts(&mut self, min_generations: usize) -> Vec<(&Intuition, &Intuition)> {
+        if self.generations < min_generations {
+            return vec![];
+        }
+        let n = self.intuitions.len();
+        let mut new = vec![];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                if self.ever[i]
+                    && self.ever[j]
+                    && !self.ever_together[i][j]
+                    && !self.warned.contains(&(i, j))
+                {
+                    new.push((i, j));
+                }
+            }
+        }
+        self.warned.extend(&new);
+        new.into_iter()
+            .map(|(i, j)| (&self.intuitions[i], &self.intuitions[j]))
+            .collect()
+    }
+
+    fn satisfied(&self, layout: &Layout, config: &KeymapConfig) -> Vec<bool> {
+        self.intuitions
+            .iter()
+            .map(|intuition| intuition.satisfied(layout, config))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+    use device_query::Keycode;
+
+    #[test]
+    fn test_bigram_matrix() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let kind = |first, second| bigram_kind(&layout, &config, first, second);
+        assert_eq!(kind('e', 'd'), Some(BigramKind::SameFinger));
+        assert_eq!(kind('e', 'e'), Some(BigramKind::SameFinger));
+        assert_eq!(kind('e', 'r'), Some(BigramKind::SameHand));
+        assert_eq!(kind('e', 'j'), Some(BigramKind::Alternating));
+        assert_eq!(kind('e', 'é'), None);
+
+        let mut stats = Stats::new();
+        for (c, count) in [('e', 10), ('j', 6), ('d', 4), ('r', 1), (' ', 20)] {
+            stats.char_counts.insert(c, count);
+        }
+        for (pair, count) in [(('e', 'd'), 4), (('e', 'j'), 6), (('d', 'e'), 2)] {
+            stats.consecutive_char_counts.insert(pair, count);
+        }
+        // Spaces are left out, and only the three most typed are kept
+        let matrix = BigramMatrix::top(&stats, 3);
+        assert_eq!(matrix.chars, ['e', 'j', 'd']);
+        assert_eq!(matrix.counts, [[0, 6, 4], [0, 0, 0], [2, 0, 0]]);
+
+        let rendered = matrix.render(&layout, &config, &NumberFormat::default(), false);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[1], "e  0F 6  4F");
+        assert_eq!(lines[2], "j  0  0F 0 ");
+        assert_eq!(lines[3], "d  2F 0  0F");
+    }
+
+    #[test]
+    fn test_score_breakdown() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        for (key, count) in [(Keycode::E, 10), (Keycode::D, 4), (Keycode::J, 6)] {
+            stats.individual_key_counts.insert(key, count);
+        }
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::D), 4);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::J), 6);
+        let scoring = ScoringConfig {
+            sfb_weight: 1.0,
+            travel_weight: 0.5,
+            ..ScoringConfig::default()
+        };
+
+        let breakdown =
+            score_breakdown(&layout, &stats, &config, &scoring, &NumberFormat::default());
+        let (terms, rest) = breakdown.split_once("\nScore").unwrap();
+        let value =
+            |line: &str| -> f64 { line.split_whitespace().last().unwrap().parse().unwrap() };
+        let sum: f64 = terms.lines().map(value).sum();
+        let score = value(rest.lines().next().unwrap());
+        assert!((sum - score).abs() < 0.1, "{breakdown}");
+        assert!(terms.contains("Finger travel"));
+        assert!(!terms.contains("Corrections"));
+        assert!(breakdown.contains("Same finger bigrams: 4,"));
+        assert_eq!(
+            breakdown.matches("kept").count() + breakdown.matches("broken").count(),
+            scoring.intuitions().len()
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Keeping the `--history` of `explore_layouts` from growing without bound.
+//!
+//! The history gets a line for every generation, which adds up over a
+//! week long run.  A [`Retention`] keeps the last few generations, the best
+//! generation of every day, and always the last one, which is as far as
+//! the run got.  [`HistoryWriter`] applies it while the run is going, and
+//! `clean_run` to histories written before.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+};
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Which lines of a history to keep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retention {
+    /// How many of the last generations to keep.
+    pub keep_last: usize,
+    /// Also keep the generation with the best score of every UTC day, by
+    /// its `timestamp`.  Lines written before histories had timestamps
+    /// don't count towards any day.
+    pub daily_bests: bool,
+}
+
+impl Retention {
+    /// The lines of `history` to keep, in their order.
+    pub fn apply<'a>(&self, history: &[&'a str]) -> Result<Vec<&'a str>> {
+        let mut keep = vec![false; history.len()];
+        let recent = history.len().saturating_sub(self.keep_last.max(1));
+        keep[recent..].fill(true);
+        if self.daily_bests {
+            // The index and score of the best generation of every day
+            let mut bests: HashMap<u64, (usize, f64)> = HashMap::new();
+            for (i, line) in history.iter().enumerate() {
+                let value: Value = serde_json::from_str(line)
+                    .map_err(|error| Error::parse(line, error.to_string()).at_line(i))?;
+                let (Some(timestamp), Some(score)) =
+                    (value["timestamp"].as_u64(), value["best_score"].as_f64())
+                else {
+                    continue;
+                };
+                let best = bests.entry(timestamp / DAY_MS).or_insert((i, score));
+                if score > best.1 {
+                    *best = (i, score);
+                }
+            }
+            for (i, _) in bests.into_values() {
+                keep[i] = true;
+            }
+        }
+        Ok(history
+            .iter()
+            .zip(keep)
+            .filter_map(|(line, keep)| keep.then_some(*line))
+            .collect())
+    }
+}
+
+/// Rewrites the history at `path` with only the lines `retention` keeps,
+/// and returns how many it removed.  The history is replaced at once, so
+/// it's never left half written.
+pub fn prune_history(path: &str, retention: &Retention) -> Result<usize> {
+    let content = std::fs::read_to_string(path)?;
+    let lines: Vec<&str> = content.lines().filter(|line| !line.is_empty()).collect();
+    let kept = retention.apply(&lines)?;
+    let removed = lines.len() - kept.len();
+    if removed > 0 {
+        let pruned = format!("{path}.pruned");
+        let mut writer = BufWriter::new(File::create(&pruned)?);
+        for line in kept {
+            writeln!(writer, "{line}")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&pruned, path)?;
+    }
+    Ok(removed)
+}
+
+/// Writes the history of a run a line at a time, pruning it with a
+/// [`Retention`] whenever it has grown by `keep_last` lines.
+pub struct HistoryWriter {
+    path: String,
+    writer: BufWriter<File>,
+    retention: Option<Retention>,
+    unpruned: usize,
+}
+
+impl HistoryWriter {
+    /// Starts a new history at `path`, replacing any there.
+    pub fn create(path: &str, retention: Option<Retention>) -> Result<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            writer: BufWriter::new(File::create(path)?),
+            retention,
+            unpruned: 0,
+        })
+    }
+
+    /// Appends `line`, flushed so the run can be plotted while it's still
+    /// going.
+    pub fn write(&mut self, line: &Value) -> Result<()> {
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()?;
+        self.unpruned += 1;
+        if let Some(retention) = &self.retention {
+            if self.unpruned >= retention.keep_last.max(1) {
+                prune_history(&self.path, retention)?;
+                self.writer = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+                self.unpruned = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention() {
+        let line = |day: u64, score: f64| {
+            serde_json::json!({ "timestamp": day * DAY_MS + 1000, "best_score": score }).to_string()
+        };
+        let history = [
+            line(0, 1.0),
+            line(0, 3.0),
+            line(0, 2.0),
+            line(1, 4.0),
+            line(1, 4.0),
+            line(1, 5.0),
+            line(1, 5.0),
+        ];
+        let history: Vec<&str> = history.iter().map(String::as_str).collect();
+
+        let retention = Retention {
+            keep_last: 1,
+            daily_bests: true,
+        };
+        assert_eq!(
+            retention.apply(&history).unwrap(),
+            [history[1], history[5], history[6]]
+        );
+        let retention = Retention {
+            keep_last: 2,
+            daily_bests: false,
+        };
+        assert_eq!(retention.apply(&history).unwrap(), [history[5], history[6]]);
+        // The last generation stays even when keeping none
+        let retention = Retention {
+            keep_last: 0,
+            daily_bests: false,
+        };
+        assert_eq!(retention.apply(&history).unwrap(), [history[6]]);
+
+        let retention = Retention {
+            keep_last: 1,
+            daily_bests: true,
+        };
+        let error = retention.apply(&["{}", "not json"]).unwrap_err();
+        assert!(error.to_string().starts_with("line 2: "));
+    }
+}
@@ -0,0 +1,170 @@
+//! `optimize.toml`: everything a run of `explore_layouts` needs in one
+//! file, so a long run can be repeated or continued from it alone.
+//!
+//! Top level keys are the flags of the binary, with `_` or `-`, and its
+//! positional arguments by name.  Flags without a value are `true` or
+//! `false`, and flags given more than once are arrays.  `[weights]` holds
+//! what `weights.toml` would, and `intuitions` the lines of an intuitions
+//! file:
+//!
+//! ```toml
+//! log_file = "log.txt"
+//! keymap_config = "kinesis.layout"
+//! population = 500
+//! max_generations = 200
+//! pin = ["Z", "X", "C", "V"]
+//! intuitions = ["close(. ,)", "same_row(Left Right)"]
+//!
+//! [weights]
+//! intuition_weight = 2.0
+//! ```
+
+use std::str::FromStr;
+
+use crate::{
+    error::{Error, Result},
+    scoring::{Intuition, Weights},
+};
+
+/// A parsed `optimize.toml`.
+#[derive(Debug)]
+pub struct RunFile {
+    /// The command line the top level keys stand for, without the name of
+    /// the binary.
+    pub args: Vec<String>,
+    pub weights: Option<Weights>,
+    pub intuitions: Vec<Intuition>,
+}
+
+impl RunFile {
+    /// Parses a run file whose keys named in `positional` are positional
+    /// arguments, in that order, rather than flags.
+    pub fn parse(s: &str, positional: &[&str]) -> Result<Self> {
+        let mut table: toml::Table =
+            toml::from_str(s).map_err(|error| Error::Invalid(error.to_string()))?;
+        let weights = match table.remove("weights") {
+            Some(weights) => Some(
+                weights
+                    .try_into()
+                    .map_err(|error: toml::de::Error| Error::Invalid(error.to_string()))?,
+            ),
+            None => None,
+        };
+        let intuitions = match table.remove("intuitions") {
+            Some(toml::Value::Array(lines)) => lines
+                .iter()
+                .map(|line| {
+                    let line = line.as_str().ok_or_else(|| {
+                        Error::Invalid(format!("intuitions: expected strings, got {line}"))
+                    })?;
+                    Intuition::from_str(line)
+                        .map_err(|message| Error::Invalid(format!("intuition {line:?}: {message}")))
+                })
+                .collect::<Result<_>>()?,
+            Some(other) => {
+                return Err(Error::Invalid(format!(
+                    "intuitions: expected an array of lines, got {other}"
+                )))
+            }
+            None => vec![],
+        };
+
+        let mut args = vec![];
+        for name in positional {
+            match table.remove(*name) {
+                Some(value) => args.push(scalar(name, &value)?),
+                None => return Err(Error::Invalid(format!("missing {name}"))),
+            }
+        }
+        for (key, value) in &table {
+            let flag = format!("--{}", key.replace('_', "-"));
+            match value {
+                toml::Value::Boolean(true) => args.push(flag),
+                toml::Value::Boolean(false) => {}
+                toml::Value::Array(values) => {
+                    for value in values {
+                        args.push(flag.clone());
+                        args.push(scalar(key, value)?);
+                    }
+                }
+                value => {
+                    args.push(flag);
+                    args.push(scalar(key, value)?);
+                }
+            }
+        }
+        Ok(Self {
+            args,
+            weights,
+            intuitions,
+        })
+    }
+
+    /// [`RunFile::parse`] of the file at `path`.
+    pub fn load(path: &str, positional: &[&str]) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?, positional)
+    }
+}
+
+/// `value` as it's written on the command line.
+fn scalar(key: &str, value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(x) => Ok(x.to_string()),
+        value => Err(Error::Invalid(format!(
+            "{key}: expected a string or a number, got {value}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_file() {
+        let run = RunFile::parse(
+            r#"
+            log_file = "log.txt"
+            keymap_config = "kinesis.layout"
+            population = 500
+            min-temperature = 0.001
+            show_moves = true
+            bounded_memory = false
+            pin = ["Z", "X"]
+            intuitions = ["close(. ,)", "same_row(Left Right)"]
+
+            [weights]
+            intuition_weight = 2.0
+            "#,
+            &["log_file", "keymap_config"],
+        )
+        .unwrap();
+        assert_eq!(
+            run.args,
+            [
+                "log.txt",
+                "kinesis.layout",
+                "--min-temperature",
+                "0.001",
+                "--pin",
+                "Z",
+                "--pin",
+                "X",
+                "--population",
+                "500",
+                "--show-moves",
+            ]
+        );
+        assert_eq!(run.weights.unwrap().intuition_weight, 2.0);
+        assert_eq!(run.intuitions.len(), 2);
+
+        let missing = RunFile::parse("population = 500", &["log_file"]).unwrap_err();
+        assert!(
+            missing.to_string().contains("missing log_file"),
+            "{missing}"
+        );
+        assert!(RunFile::parse("log_file = [[1]]", &[]).is_err());
+    }
+}
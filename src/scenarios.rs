@@ -0,0 +1,206 @@
+//! Scoring a layout against several sets of stats at once, like writing,
+//! coding and a second language, to find a layout that's good for all of
+//! them rather than just on average.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{layout::Layout, scoring::LayoutScorer, stats::Stats, KeymapConfig};
+
+/// How the scores of the scenarios are combined into one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// The mean score.
+    Mean,
+    /// The score of the worst scenario.
+    Min,
+    /// The mean score of the worst fraction of the scenarios, the
+    /// conditional value at risk.  `Cvar(1.0)` is the mean, and small
+    /// fractions approach the minimum.
+    Cvar(f64),
+}
+
+impl Aggregation {
+    /// Combines `scores`, which have to be on the same scale.
+    pub fn aggregate(&self, scores: &[f64]) -> f64 {
+        if scores.is_empty() {
+            return 0.0;
+        }
+        match *self {
+            Aggregation::Mean => scores.iter().sum::<f64>() / scores.len() as f64,
+            Aggregation::Min => scores.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Cvar(fraction) => {
+                let mut sorted = scores.to_vec();
+                sorted.sort_by(f64::total_cmp);
+                // The worst `fraction` of the scenarios, counting the last
+                // one partially if it doesn't fit whole
+                let tail = fraction.clamp(f64::MIN_POSITIVE, 1.0) * sorted.len() as f64;
+                let mut remaining = tail;
+                let mut sum = 0.0;
+                for score in sorted {
+                    let weight = remaining.min(1.0);
+                    sum += weight * score;
+                    remaining -= weight;
+                    if remaining <= 0.0 {
+                        break;
+                    }
+                }
+                sum / tail
+            }
+        }
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    /// Parses `mean`, `min`, or `cvar:<fraction>` like `cvar:0.25`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "mean" => Ok(Aggregation::Mean),
+            None if s == "min" => Ok(Aggregation::Min),
+            Some(("cvar", fraction)) => match fraction.parse::<f64>() {
+                Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => {
+                    Ok(Aggregation::Cvar(fraction))
+                }
+                _ => Err(format!(
+                    "the fraction of cvar has to be in (0, 1], got {fraction}"
+                )),
+            },
+            _ => Err(format!("expected mean, min, or cvar:<fraction>, got {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregation::Mean => write!(f, "mean"),
+            Aggregation::Min => write!(f, "min"),
+            Aggregation::Cvar(fraction) => write!(f, "cvar:{fraction}"),
+        }
+    }
+}
+
+/// One set of stats to score layouts against, shared with the scorers that
+/// only score against it.
+pub struct Scenario {
+    pub name: String,
+    pub stats: Arc<Stats>,
+    max_possible_score: f64,
+}
+
+/// Several scenarios whose scores are normalized by their max possible
+/// score, so a large log doesn't outweigh a small one, and then aggregated.
+pub struct Scenarios {
+    pub scenarios: Vec<Scenario>,
+    pub aggregation: Aggregation,
+}
+
+impl Scenarios {
+    pub fn new(aggregation: Aggregation) -> Self {
+        Self {
+            scenarios: vec![],
+            aggregation,
+        }
+    }
+
+    pub fn push(&mut self, name: String, stats: Stats, scorer: &impl LayoutScorer) {
+        let max_possible_score = scorer.max_possible_score(&stats);
+        self.scenarios.push(Scenario {
+            name,
+            stats: Arc::new(stats),
+            max_possible_score,
+        });
+    }
+
+    /// The normalized score of `layout` in every scenario, in order.
+    pub fn scores(
+        &self,
+        layout: &Layout,
+        keymap_config: &KeymapConfig,
+        scorer: &impl LayoutScorer,
+    ) -> Vec<f64> {
+        self.scenarios
+            .iter()
+            .map(|scenario| {
+                scorer.score(layout, &scenario.stats, keymap_config)
+                    / scenario.max_possible_score.max(f64::MIN_POSITIVE)
+            })
+            .collect()
+    }
+
+    /// The aggregated score of `layout`, at most 1.
+    pub fn score(
+        &self,
+        layout: &Layout,
+        keymap_config: &KeymapConfig,
+        scorer: &impl LayoutScorer,
+    ) -> f64 {
+        self.aggregation
+            .aggregate(&self.scores(layout, keymap_config, scorer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    /// Rates layouts by how often the key in the first position was typed.
+    struct FirstKey;
+
+    impl LayoutScorer for FirstKey {
+        fn score(&self, layout: &Layout, stats: &Stats, _: &KeymapConfig) -> f64 {
+            let code = layout.keys()[0].keycode(false);
+            stats.individual_key_counts.get(&code).copied().unwrap_or(0) as f64
+        }
+
+        fn max_possible_score(&self, stats: &Stats) -> f64 {
+            stats
+                .individual_key_counts
+                .values()
+                .copied()
+                .max()
+                .unwrap_or(0) as f64
+        }
+    }
+
+    #[test]
+    fn test_custom_scorer() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let first = layout.keys()[0].keycode(false);
+        let other = layout.keys()[1].keycode(false);
+
+        let mut scenarios = Scenarios::new(Aggregation::Min);
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(first, 10);
+        scenarios.push("first".to_string(), stats, &FirstKey);
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(first, 1);
+        stats.individual_key_counts.insert(other, 4);
+        scenarios.push("other".to_string(), stats, &FirstKey);
+
+        assert_eq!(scenarios.scores(&layout, &config, &FirstKey), [1.0, 0.25]);
+        assert_eq!(scenarios.score(&layout, &config, &FirstKey), 0.25);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let scores = [0.9, 0.5, 0.7, 0.3];
+        let close = |aggregation: Aggregation, expected: f64| {
+            (aggregation.aggregate(&scores) - expected).abs() < 1e-9
+        };
+        assert!(close(Aggregation::Mean, 0.6));
+        assert!(close(Aggregation::Min, 0.3));
+        assert!(close(Aggregation::Cvar(0.5), 0.4));
+        assert!(close(Aggregation::Cvar(1.0), 0.6));
+        // Three eighths are the worst scenario and half of the second worst
+        assert!(close(Aggregation::Cvar(0.375), (0.3 + 0.25) / 1.5));
+
+        assert_eq!("cvar:0.25".parse(), Ok(Aggregation::Cvar(0.25)));
+        assert_eq!("min".parse(), Ok(Aggregation::Min));
+        assert!("cvar:0".parse::<Aggregation>().is_err());
+        assert!("max".parse::<Aggregation>().is_err());
+    }
+}
@@ -0,0 +1,1670 @@
+//! The effort model used to rate how well a [`Layout`] fits a set of [`Stats`].
+
+use std::{borrow::Cow, collections::HashMap, fmt, str::FromStr, sync::OnceLock};
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    char_table::built_in_keycode,
+    editor::EditorProfile,
+    error::{self, Error},
+    layout::{char_to_keycode, Key, Layout},
+    serde_util::keycode_list,
+    stats::Stats,
+    Finger, FingerKind, Hand, KeymapConfig, PhysicalKey,
+};
+
+/// Settings for how stats are turned into scores, read from the `scoring`
+/// section of `config.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    pub smoothing: Smoothing,
+    /// Characters that are easily mixed up, like `b` and `v`, which should
+    /// neither mirror each other nor sit next to each other under the same
+    /// finger while learning a layout.
+    pub confusable_pairs: Vec<(char, char)>,
+    /// Score for every confusable pair kept apart.
+    pub confusable_weight: f64,
+    /// Adds the intuitions of an editor.  [`EditorProfile::Auto`] has to be
+    /// resolved before scoring, otherwise it adds nothing.
+    pub editor_profile: EditorProfile,
+    /// How much more the editor's command keys count than other keys.
+    pub editor_weight: f64,
+    /// Penalty for every same finger bigram per key of distance the finger
+    /// travels.
+    pub sfb_weight: f64,
+    /// Bonus for every bigram typed with alternating hands.
+    pub alternation_bonus: f64,
+    /// Bonus for every bigram rolled inward on one hand, from the pinky
+    /// towards the index finger.
+    pub inward_roll_bonus: f64,
+    /// Bonus for every bigram rolled outward on one hand, from the index
+    /// finger towards the pinky.
+    pub outward_roll_bonus: f64,
+    /// Penalty for every chord that holds two keys with the same thumb,
+    /// like a modifier on a thumb key held while the thumb taps Space.
+    pub thumb_conflict_weight: f64,
+    /// Penalty for every key width the fingers travel from their home keys
+    /// and back, see [`finger_travel`].
+    pub travel_weight: f64,
+    /// Modifiers held on home row keys that type their own key when tapped,
+    /// like `["LShift", "LControl"]`.  Every layout gets them on the home
+    /// keys where holding them costs least, see [`home_row_mods`].
+    #[serde(with = "keycode_list")]
+    pub home_row_mods: Vec<Keycode>,
+    /// Penalty for every chord a home row modifier is held in, by how
+    /// awkward holding it is while pressing the other keys.
+    pub home_row_mod_weight: f64,
+    /// Intuitions to rate layouts against besides the built in ones, read
+    /// from a file with [`load_intuitions`] rather than `config.json`.
+    #[serde(skip)]
+    pub custom_intuitions: Vec<Intuition>,
+    /// Penalty for the bigrams typed right before a correction, by how
+    /// awkward they are on the layout, see [`correction_effort`].
+    pub correction_weight: f64,
+    /// The constants of the effort model, read from `weights.toml` rather
+    /// than `config.json`.
+    #[serde(skip)]
+    pub weights: Weights,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            smoothing: Smoothing::default(),
+            confusable_pairs: vec![],
+            confusable_weight: 100.0,
+            editor_profile: EditorProfile::None,
+            editor_weight: 2.0,
+            sfb_weight: 1.0,
+            alternation_bonus: 0.2,
+            inward_roll_bonus: 0.3,
+            outward_roll_bonus: 0.1,
+            thumb_conflict_weight: 1.0,
+            travel_weight: 0.0,
+            home_row_mods: vec![],
+            home_row_mod_weight: 1.0,
+            custom_intuitions: vec![],
+            correction_weight: 0.0,
+            weights: Weights::default(),
+        }
+    }
+}
+
+/// The constants of the effort model.  The defaults are written out in
+/// `weights.toml` to be tuned without recompiling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    /// Score for every satisfied intuition.
+    pub intuition_weight: f64,
+    /// How comfortable it is to press two keys in a row with a pair of
+    /// fingers of the same hand, from 0 (awful) to 1 (effortless).
+    pub synergy: FingerPairs,
+    /// How much the distance between two keys pressed in a row with a pair
+    /// of fingers of the same hand matters, from 0 (not at all) to 1.
+    pub distance_importance: FingerPairs,
+    /// Synergies for pressing a key with the second finger of a pair right
+    /// after one with the first, in that order, replacing `synergy` for
+    /// pairs that feel different one way than the other.
+    pub directional_synergy: Vec<DirectionalSynergy>,
+    /// How moving up or down a row changes the synergy.
+    pub vertical: Vertical,
+}
+
+/// The synergy of pressing a key with `to` right after one with `from`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectionalSynergy {
+    pub from: FingerKind,
+    pub to: FingerKind,
+    pub synergy: f64,
+}
+
+/// Factors for the synergy of two keys pressed in a row with one hand when
+/// the second is on a row above or below the first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Vertical {
+    pub upward: f64,
+    pub downward: f64,
+}
+
+impl Default for Vertical {
+    fn default() -> Self {
+        Self {
+            upward: 1.0,
+            downward: 1.0,
+        }
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            intuition_weight: 100.0,
+            synergy: FingerPairs {
+                pinky_pinky: 0.1,
+                pinky_ring: 0.2,
+                pinky_middle: 0.2,
+                pinky_index: 0.5,
+                pinky_thumb: 0.6,
+                ring_ring: 0.1,
+                ring_middle: 0.3,
+                ring_index: 0.3,
+                ring_thumb: 0.2,
+                middle_middle: 0.2,
+                middle_index: 0.7,
+                middle_thumb: 0.7,
+                index_index: 0.3,
+                index_thumb: 0.9,
+                thumb_thumb: 0.3,
+            },
+            distance_importance: FingerPairs {
+                pinky_pinky: 1.0,
+                pinky_ring: 0.9,
+                pinky_middle: 0.8,
+                pinky_index: 0.2,
+                pinky_thumb: 0.1,
+                ring_ring: 1.0,
+                ring_middle: 0.9,
+                ring_index: 0.5,
+                ring_thumb: 0.1,
+                middle_middle: 1.0,
+                middle_index: 0.7,
+                middle_thumb: 0.1,
+                index_index: 1.0,
+                index_thumb: 0.2,
+                thumb_thumb: 1.0,
+            },
+            directional_synergy: vec![],
+            vertical: Vertical::default(),
+        }
+    }
+}
+
+impl Weights {
+    pub fn load(path: &str) -> error::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        toml::from_str(&s).map_err(|error| Error::Invalid(error.to_string()))
+    }
+
+    /// How comfortable it is to press a key with `f2` right after pressing
+    /// one `distance` away with `f1`, from 0 (awful) to 1 (effortless).
+    pub fn consecutive_finger_score(&self, f1: Finger, f2: Finger, distance: f64) -> f64 {
+        if distance == 0.0 {
+            return 1.0;
+        }
+
+        if f1.hand != f2.hand {
+            return 1.0;
+        }
+
+        let synergy = self.synergy.get(f1.finger, f2.finger);
+        self.distance_score(f1, f2, distance) * synergy
+    }
+
+    /// [`Weights::consecutive_finger_score`] of pressing `k2` right after
+    /// `k1`, which also tells the order of the fingers apart and whether
+    /// `k2` is above or below `k1`.
+    pub fn transition_score(&self, k1: &PhysicalKey, k2: &PhysicalKey) -> f64 {
+        let (f1, f2) = (k1.finger, k2.finger);
+        let distance = distance(k1, k2);
+        if distance == 0.0 || f1.hand != f2.hand {
+            return 1.0;
+        }
+
+        let synergy = self
+            .directional_synergy
+            .iter()
+            .find(|d| d.from == f1.finger && d.to == f2.finger)
+            .map_or_else(|| self.synergy.get(f1.finger, f2.finger), |d| d.synergy);
+        // Rows count down from the top
+        let vertical = match k2.position.1.total_cmp(&k1.position.1) {
+            std::cmp::Ordering::Less => self.vertical.upward,
+            std::cmp::Ordering::Greater => self.vertical.downward,
+            std::cmp::Ordering::Equal => 1.0,
+        };
+        (self.distance_score(f1, f2, distance) * synergy * vertical).min(1.0)
+    }
+
+    fn distance_score(&self, f1: Finger, f2: Finger, distance: f64) -> f64 {
+        let distance_importance = self.distance_importance.get(f1.finger, f2.finger);
+        let raw_score = 1.0 / (distance + 1.0);
+        1.0 * (1.0 - distance_importance) + raw_score * distance_importance
+    }
+}
+
+/// A value for every pair of fingers on one hand, in either order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FingerPairs {
+    pub pinky_pinky: f64,
+    pub pinky_ring: f64,
+    pub pinky_middle: f64,
+    pub pinky_index: f64,
+    pub pinky_thumb: f64,
+    pub ring_ring: f64,
+    pub ring_middle: f64,
+    pub ring_index: f64,
+    pub ring_thumb: f64,
+    pub middle_middle: f64,
+    pub middle_index: f64,
+    pub middle_thumb: f64,
+    pub index_index: f64,
+    pub index_thumb: f64,
+    pub thumb_thumb: f64,
+}
+
+impl FingerPairs {
+    pub fn get(&self, f1: FingerKind, f2: FingerKind) -> f64 {
+        match (f1, f2) {
+            (FingerKind::Pinky, FingerKind::Pinky) => self.pinky_pinky,
+            (FingerKind::Pinky, FingerKind::Ring) | (FingerKind::Ring, FingerKind::Pinky) => {
+                self.pinky_ring
+            }
+            (FingerKind::Pinky, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Pinky) => {
+                self.pinky_middle
+            }
+            (FingerKind::Pinky, FingerKind::Index) | (FingerKind::Index, FingerKind::Pinky) => {
+                self.pinky_index
+            }
+            (FingerKind::Pinky, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Pinky) => {
+                self.pinky_thumb
+            }
+            (FingerKind::Ring, FingerKind::Ring) => self.ring_ring,
+            (FingerKind::Ring, FingerKind::Middle) | (FingerKind::Middle, FingerKind::Ring) => {
+                self.ring_middle
+            }
+            (FingerKind::Ring, FingerKind::Index) | (FingerKind::Index, FingerKind::Ring) => {
+                self.ring_index
+            }
+            (FingerKind::Ring, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Ring) => {
+                self.ring_thumb
+            }
+            (FingerKind::Middle, FingerKind::Middle) => self.middle_middle,
+            (FingerKind::Middle, FingerKind::Index) | (FingerKind::Index, FingerKind::Middle) => {
+                self.middle_index
+            }
+            (FingerKind::Middle, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Middle) => {
+                self.middle_thumb
+            }
+            (FingerKind::Index, FingerKind::Index) => self.index_index,
+            (FingerKind::Index, FingerKind::Thumb) | (FingerKind::Thumb, FingerKind::Index) => {
+                self.index_thumb
+            }
+            (FingerKind::Thumb, FingerKind::Thumb) => self.thumb_thumb,
+        }
+    }
+}
+
+/// How consecutive key counts are smoothed before scoring, so that bigrams
+/// that just didn't happen during a short log don't count as never
+/// happening.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Smoothing {
+    #[default]
+    None,
+    /// Adds `alpha` presses to every pair of keys on the keyboard.
+    Laplace { alpha: f64 },
+    /// Takes `discount` presses from every seen pair and spreads them over
+    /// all pairs starting with the same key, favoring keys that follow
+    /// many different keys, like Kneser-Ney smoothing.
+    KneserNey { discount: f64 },
+}
+
+impl Smoothing {
+    /// Smooths the consecutive key counts of `stats` between the keys of
+    /// `config` into [`Stats::smoothed_key_counts`], leaving the counts of
+    /// what was typed as they are.  Pairs with keys that aren't on the
+    /// keyboard keep their counts.
+    pub fn apply(&self, stats: &mut Stats, config: &KeymapConfig) {
+        let codes: Vec<Keycode> = config.keys.keys().iter().map(|key| key.code).collect();
+        let counts = &stats.consecutive_key_counts;
+        let mut smoothed: HashMap<(Keycode, Keycode), f64> = counts
+            .iter()
+            .map(|(pair, count)| (*pair, *count as f64))
+            .collect();
+        match *self {
+            Smoothing::None => return,
+            Smoothing::Laplace { alpha } => {
+                for &a in &codes {
+                    for &b in &codes {
+                        *smoothed.entry((a, b)).or_insert(0.0) += alpha;
+                    }
+                }
+            }
+            Smoothing::KneserNey { discount } => {
+                // How many different keys each key follows and is followed by
+                let mut followers: HashMap<Keycode, u64> = HashMap::new();
+                let mut predecessors: HashMap<Keycode, u64> = HashMap::new();
+                for ((a, b), count) in counts.iter() {
+                    if *count > 0 {
+                        *followers.entry(*a).or_insert(0) += 1;
+                        *predecessors.entry(*b).or_insert(0) += 1;
+                    }
+                }
+                let distinct_pairs: u64 = predecessors.values().sum();
+                if distinct_pairs > 0 {
+                    for &a in &codes {
+                        let reserved = discount * followers.get(&a).copied().unwrap_or(0) as f64;
+                        for &b in &codes {
+                            let count = counts.get(&(a, b)).copied().unwrap_or(0) as f64;
+                            let continuation = predecessors.get(&b).copied().unwrap_or(0) as f64
+                                / distinct_pairs as f64;
+                            smoothed.insert(
+                                (a, b),
+                                (count - discount).max(0.0) + reserved * continuation,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        smoothed.retain(|_, weight| *weight > 0.0);
+        stats.smoothed_key_counts = Some(smoothed);
+    }
+}
+
+/// An upper bound for [`layout_score`] given `stats`, used to normalize scores.
+pub fn max_possible_score(stats: &Stats) -> f64 {
+    max_possible_score_weighted(stats, &Weights::default())
+}
+
+fn max_possible_score_weighted(stats: &Stats, weights: &Weights) -> f64 {
+    let mut score = 0.0;
+    for count in stats.individual_key_counts.values() {
+        score += *count as f64;
+    }
+    for (_, weight) in stats.key_bigram_weights() {
+        score += weight;
+    }
+    for count in stats.confusion_counts.values() {
+        score += *count as f64;
+    }
+    let n_intuitions = intuitions().len() as f64;
+    score += n_intuitions * weights.intuition_weight;
+    score
+}
+
+/// Rates `layout` for the typing recorded in `stats`.  Higher is better.
+pub fn layout_score(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
+    layout_score_weighted(layout, stats, keymap_config, &Weights::default())
+}
+
+fn layout_score_weighted(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    weights: &Weights,
+) -> f64 {
+    let individual_key_score = layout_individual_key_score(layout, stats, keymap_config);
+    let consecutive_key_score = layout_consecutive_key_score(layout, stats, keymap_config, weights);
+    let intuition_score = intuition_score(layout, keymap_config, &intuitions());
+    let confusion_score = layout_confusion_score(layout, stats, keymap_config);
+
+    individual_key_score
+        + consecutive_key_score
+        + weights.intuition_weight * intuition_score
+        + confusion_score
+}
+
+impl ScoringConfig {
+    /// The built in intuitions plus those of the editor profile and the
+    /// custom ones.
+    pub fn intuitions(&self) -> Vec<Intuition> {
+        let mut all = intuitions();
+        all.extend(self.editor_profile.intuitions());
+        all.extend(self.custom_intuitions.iter().cloned());
+        all
+    }
+}
+
+/// [`max_possible_score`] including the optional terms of `scoring`.
+pub fn max_possible_score_with(stats: &Stats, scoring: &ScoringConfig) -> f64 {
+    // Every bigram gets at most one of the hand pattern bonuses
+    let bigrams: f64 = stats.key_bigram_weights().map(|(_, weight)| weight).sum();
+    let hand_pattern_bonus = scoring
+        .alternation_bonus
+        .max(scoring.inward_roll_bonus)
+        .max(scoring.outward_roll_bonus)
+        .max(0.0);
+    max_possible_score_weighted(stats, &scoring.weights)
+        + scoring.confusable_pairs.len() as f64 * scoring.confusable_weight
+        + (scoring.editor_profile.intuitions().len() + scoring.custom_intuitions.len()) as f64
+            * scoring.weights.intuition_weight
+        + bigrams * hand_pattern_bonus
+}
+
+/// [`layout_score`] plus the optional terms of `scoring`.
+pub fn layout_score_with(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    scoring: &ScoringConfig,
+) -> f64 {
+    let mut score = layout_score_weighted(layout, stats, keymap_config, &scoring.weights);
+    if !scoring.confusable_pairs.is_empty() {
+        score += scoring.confusable_weight
+            * confusable_separation_score(layout, keymap_config, &scoring.confusable_pairs);
+    }
+    let mut extra_intuitions = scoring.editor_profile.intuitions();
+    extra_intuitions.extend(scoring.custom_intuitions.iter().cloned());
+    if !extra_intuitions.is_empty() {
+        score += scoring.weights.intuition_weight
+            * intuition_score(layout, keymap_config, &extra_intuitions);
+    }
+    if scoring.sfb_weight != 0.0 {
+        score -= scoring.sfb_weight * same_finger_bigrams(layout, stats, keymap_config).weighted;
+    }
+    if scoring.alternation_bonus != 0.0
+        || scoring.inward_roll_bonus != 0.0
+        || scoring.outward_roll_bonus != 0.0
+    {
+        let patterns = weighted_hand_patterns(layout, stats, keymap_config);
+        score += scoring.alternation_bonus * patterns.alternations
+            + scoring.inward_roll_bonus * patterns.inward_rolls
+            + scoring.outward_roll_bonus * patterns.outward_rolls;
+    }
+    if scoring.thumb_conflict_weight != 0.0 {
+        score -= scoring.thumb_conflict_weight
+            * thumb_load(layout, stats, keymap_config).hold_conflicts as f64;
+    }
+    if scoring.travel_weight != 0.0 {
+        score -= scoring.travel_weight * finger_travel(layout, stats, keymap_config);
+    }
+    if !scoring.home_row_mods.is_empty() {
+        score -= scoring.home_row_mod_weight
+            * home_row_mods(layout, stats, keymap_config, &scoring.home_row_mods).cost;
+    }
+    if scoring.correction_weight != 0.0 {
+        score -= scoring.correction_weight
+            * correction_effort(layout, stats, keymap_config, &scoring.weights);
+    }
+    score
+}
+
+/// An effort model that rates how well a layout fits a set of stats, so
+/// tools and tests can swap in their own.  [`ScoringConfig`] is the one
+/// used by the binaries.
+pub trait LayoutScorer {
+    /// Rates `layout` for the typing recorded in `stats`.  Higher is better.
+    fn score(&self, layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64;
+
+    /// An upper bound for [`LayoutScorer::score`] given `stats`, used to
+    /// normalize scores.
+    fn max_possible_score(&self, stats: &Stats) -> f64;
+}
+
+impl LayoutScorer for ScoringConfig {
+    fn score(&self, layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
+        layout_score_with(layout, stats, keymap_config, self)
+    }
+
+    fn max_possible_score(&self, stats: &Stats) -> f64 {
+        max_possible_score_with(stats, self)
+    }
+}
+
+/// How the bigrams typed on a layout move between hands and fingers, in
+/// presses, or in [weights](Stats::key_bigram_weights) for `HandPatterns<f64>`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HandPatterns<T = u64> {
+    /// Bigrams of keys on the layout.
+    pub bigrams: T,
+    /// Bigrams typed with one hand and then the other.
+    pub alternations: T,
+    /// Bigrams on one hand moving from the pinky towards the index finger.
+    pub inward_rolls: T,
+    /// Bigrams on one hand moving from the index finger towards the pinky.
+    pub outward_rolls: T,
+}
+
+impl HandPatterns {
+    pub fn alternation_rate(&self) -> f64 {
+        if self.bigrams == 0 {
+            return 0.0;
+        }
+        self.alternations as f64 / self.bigrams as f64
+    }
+}
+
+/// Counts the hand alternations and rolls `layout` would have typing
+/// `stats`.  Bigrams involving a thumb alternate if the other key is on
+/// the other hand, but never roll.
+pub fn hand_patterns(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> HandPatterns {
+    let bigrams = stats
+        .consecutive_key_counts
+        .iter()
+        .map(|(pair, count)| (*pair, *count));
+    count_hand_patterns(layout, keymap_config, bigrams)
+}
+
+/// [`hand_patterns`] by the [weights](Stats::key_bigram_weights) of the
+/// bigrams, as they're scored.
+pub fn weighted_hand_patterns(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+) -> HandPatterns<f64> {
+    count_hand_patterns(layout, keymap_config, stats.key_bigram_weights())
+}
+
+fn count_hand_patterns<T: Copy + Default + std::ops::AddAssign>(
+    layout: &Layout,
+    keymap_config: &KeymapConfig,
+    bigrams: impl Iterator<Item = ((Keycode, Keycode), T)>,
+) -> HandPatterns<T> {
+    let physical = keymap_config.keys.keys();
+    let fingers: HashMap<Keycode, Finger> = layout
+        .keys()
+        .iter()
+        .zip(physical)
+        .map(|(key, pkey)| (key.keycode(false), pkey.finger))
+        .collect();
+    let mut patterns = HandPatterns::default();
+    for ((code1, code2), count) in bigrams {
+        let (Some(f1), Some(f2)) = (fingers.get(&code1), fingers.get(&code2)) else {
+            continue;
+        };
+        patterns.bigrams += count;
+        if f1.hand != f2.hand {
+            patterns.alternations += count;
+        } else if f1.finger != FingerKind::Thumb && f2.finger != FingerKind::Thumb {
+            match roll_order(f1.finger).cmp(&roll_order(f2.finger)) {
+                std::cmp::Ordering::Less => patterns.inward_rolls += count,
+                std::cmp::Ordering::Greater => patterns.outward_rolls += count,
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+    }
+    patterns
+}
+
+/// The position of a finger counting inward from the pinky.
+pub(crate) fn roll_order(finger: FingerKind) -> u8 {
+    match finger {
+        FingerKind::Pinky => 0,
+        FingerKind::Ring => 1,
+        FingerKind::Middle => 2,
+        FingerKind::Index => 3,
+        FingerKind::Thumb => 4,
+    }
+}
+
+/// Consecutive presses of two different keys with the same finger.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SameFingerBigrams {
+    pub count: u64,
+    /// The [weights](Stats::key_bigram_weights) of the bigrams multiplied
+    /// by how far the finger travels between the keys.
+    pub weighted: f64,
+}
+
+/// Counts the same finger bigrams `layout` would have typing `stats`.
+pub fn same_finger_bigrams(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+) -> SameFingerBigrams {
+    let physical = keymap_config.keys.keys();
+    let distances = keymap_config.distances();
+    let mut sfbs = SameFingerBigrams::default();
+    for (i, pkey1) in physical.iter().enumerate() {
+        let code1 = layout.keys()[i].keycode(false);
+        for (j, pkey2) in physical.iter().enumerate() {
+            if i == j || pkey1.finger != pkey2.finger {
+                continue;
+            }
+            let code2 = layout.keys()[j].keycode(false);
+            sfbs.count += stats
+                .consecutive_key_counts
+                .get(&(code1, code2))
+                .copied()
+                .unwrap_or(0);
+            sfbs.weighted += stats.key_bigram_weight((code1, code2)) * distances.get(i, j);
+        }
+    }
+    sfbs
+}
+
+/// How awkward the bigrams that came right before corrections are on
+/// `layout`: every one counts by how far its
+/// [transition score](Weights::transition_score) is from effortless.
+pub fn correction_effort(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    weights: &Weights,
+) -> f64 {
+    let transitions = keymap_config.transition_scores(weights);
+    let mut effort = 0.0;
+    for ((key1, key2), count) in &stats.correction_bigram_counts {
+        let (Some(i), Some(j)) = (
+            layout.position_of_keycode(*key1),
+            layout.position_of_keycode(*key2),
+        ) else {
+            continue;
+        };
+        effort += *count as f64 * (1.0 - transitions.get(i, j));
+    }
+    effort
+}
+
+/// How far the fingers travel typing `stats` on `layout`, in key widths.
+///
+/// Only bigrams are recorded, so every press is followed from the one
+/// before it: a finger pressing twice in a row moves straight between the
+/// keys, while a finger taking over from another one comes from its home
+/// key and the other one goes back to its own.
+pub fn finger_travel(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
+    let physical = keymap_config.keys.keys();
+    let home = keymap_config.home_keys();
+    let distances = keymap_config.distances();
+    let mut positions = HashMap::new();
+    for (i, key) in layout.keys().iter().enumerate() {
+        positions.entry(key.keycode(false)).or_insert(i);
+    }
+    let from_home = |i: usize| distances.get(home[&physical[i].finger], i);
+    let mut travel = 0.0;
+    for ((code1, code2), weight) in stats.key_bigram_weights() {
+        let (Some(&i), Some(&j)) = (positions.get(&code1), positions.get(&code2)) else {
+            continue;
+        };
+        let moved = if physical[i].finger == physical[j].finger {
+            distances.get(i, j)
+        } else {
+            from_home(i) + from_home(j)
+        };
+        travel += weight * moved;
+    }
+    travel
+}
+
+/// Where the home row modifiers go on a layout, and what holding them
+/// costs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HomeRowMods {
+    /// Every modifier placed and the physical key it's held on.
+    pub placement: Vec<(Keycode, usize)>,
+    /// The chords the modifiers are held in, weighted by how awkward
+    /// holding them is.
+    pub cost: f64,
+}
+
+/// Cost of holding a modifier while the same finger presses another key,
+/// which means letting go of the modifier.
+const HOLD_SAME_FINGER: f64 = 1.0;
+/// Cost of holding a modifier while another finger of the same hand
+/// presses a key.
+const HOLD_SAME_HAND: f64 = 0.3;
+
+/// Places `mods` on the home keys of the fingers, not the thumbs, so that
+/// holding them in the chords of `stats` costs least on `layout`.  A
+/// modifier held while the other hand types costs nothing.  Modifiers that
+/// don't fit on the home keys are left out.
+pub fn home_row_mods(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    mods: &[Keycode],
+) -> HomeRowMods {
+    let physical = keymap_config.keys.keys();
+    let mut homes: Vec<usize> = keymap_config
+        .home_keys()
+        .into_iter()
+        .filter(|(finger, _)| finger.finger != FingerKind::Thumb)
+        .map(|(_, i)| i)
+        .collect();
+    homes.sort();
+    let mods = &mods[..mods.len().min(homes.len())];
+    let mut positions = HashMap::new();
+    for (i, key) in layout.keys().iter().enumerate() {
+        positions.entry(key.keycode(false)).or_insert(i);
+    }
+
+    // What holding every modifier on every home key costs, which doesn't
+    // depend on where the other modifiers go
+    let mut costs = vec![vec![0.0; homes.len()]; mods.len()];
+    for (chord, &count) in &stats.simultaneous_key_counts {
+        for (m, modifier) in mods.iter().enumerate() {
+            if !chord.contains(modifier) {
+                continue;
+            }
+            let pressed = chord
+                .iter()
+                .filter(|code| !mods.contains(code))
+                .filter_map(|code| positions.get(code));
+            for &i in pressed {
+                for (h, &home) in homes.iter().enumerate() {
+                    let (held, other) = (physical[home].finger, physical[i].finger);
+                    costs[m][h] += count as f64
+                        * if held == other {
+                            HOLD_SAME_FINGER
+                        } else if held.hand == other.hand {
+                            HOLD_SAME_HAND
+                        } else {
+                            0.0
+                        };
+                }
+            }
+        }
+    }
+
+    // The cheapest placement of the first modifiers on every set of home
+    // keys, adding one modifier at a time
+    let mut best: Vec<Option<(f64, Vec<usize>)>> = vec![None; 1 << homes.len()];
+    best[0] = Some((0.0, vec![]));
+    for cost in &costs {
+        let mut next = vec![None; best.len()];
+        for (used, entry) in best.iter().enumerate() {
+            let Some((total, placed)) = entry else {
+                continue;
+            };
+            for (h, &home_cost) in cost.iter().enumerate() {
+                if used & (1 << h) != 0 {
+                    continue;
+                }
+                let total = total + home_cost;
+                let slot: &mut Option<(f64, Vec<usize>)> = &mut next[used | (1 << h)];
+                if slot.as_ref().is_none_or(|(cheapest, _)| total < *cheapest) {
+                    let mut placed = placed.clone();
+                    placed.push(h);
+                    *slot = Some((total, placed));
+                }
+            }
+        }
+        best = next;
+    }
+    let Some((cost, placed)) = best
+        .into_iter()
+        .flatten()
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+    else {
+        return HomeRowMods::default();
+    };
+    HomeRowMods {
+        placement: mods
+            .iter()
+            .zip(placed)
+            .map(|(&modifier, h)| (modifier, homes[h]))
+            .collect(),
+        cost,
+    }
+}
+
+/// How much of the typing on a layout falls on the thumbs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThumbLoad {
+    pub left_thumb: u64,
+    pub right_thumb: u64,
+    /// Presses of the other fingers.
+    pub fingers: u64,
+    /// Chords holding two keys with the same thumb, which has to hold one
+    /// while it taps the other.
+    pub hold_conflicts: u64,
+}
+
+impl ThumbLoad {
+    /// The share of all presses made with a thumb.
+    pub fn thumb_share(&self) -> f64 {
+        let thumbs = self.left_thumb + self.right_thumb;
+        let total = thumbs + self.fingers;
+        if total == 0 {
+            return 0.0;
+        }
+        thumbs as f64 / total as f64
+    }
+}
+
+/// Counts the presses `layout` puts on each thumb typing `stats`, and the
+/// chords that make a thumb hold one key while pressing another.
+pub fn thumb_load(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> ThumbLoad {
+    let physical = keymap_config.keys.keys();
+    let mut load = ThumbLoad::default();
+    let mut thumbs = HashMap::new();
+    for (key, pkey) in layout.keys().iter().zip(physical) {
+        let code = key.keycode(false);
+        let count = stats.individual_key_counts.get(&code).copied().unwrap_or(0);
+        match (pkey.finger.finger, pkey.finger.hand) {
+            (FingerKind::Thumb, Hand::Left) => load.left_thumb += count,
+            (FingerKind::Thumb, Hand::Right) => load.right_thumb += count,
+            _ => load.fingers += count,
+        }
+        if pkey.finger.finger == FingerKind::Thumb {
+            thumbs.insert(code, pkey.finger.hand);
+        }
+    }
+
+    for (chord, count) in &stats.simultaneous_key_counts {
+        let hands: Vec<Hand> = chord
+            .iter()
+            .filter_map(|code| thumbs.get(code))
+            .copied()
+            .collect();
+        let conflict = [Hand::Left, Hand::Right]
+            .iter()
+            .any(|hand| hands.iter().filter(|h| *h == hand).count() > 1);
+        if conflict {
+            load.hold_conflicts += count;
+        }
+    }
+    load
+}
+
+/// The number of `pairs` that are neither mirrored nor next to each other
+/// under the same finger.  Pairs typed by the same key, like `;` and `:`,
+/// always move together, and pairs missing from the layout can't be
+/// confused, so both count as kept apart.
+pub fn confusable_separation_score(
+    layout: &Layout,
+    keymap_config: &KeymapConfig,
+    pairs: &[(char, char)],
+) -> f64 {
+    let mut score = 0.0;
+    for &(a, b) in pairs {
+        let (Some(i), Some(j)) = (layout.position_of_char(a), layout.position_of_char(b)) else {
+            score += 1.0;
+            continue;
+        };
+        if i == j {
+            score += 1.0;
+            continue;
+        }
+        let pkey1 = &keymap_config.keys.keys()[i];
+        let pkey2 = &keymap_config.keys.keys()[j];
+        let adjacent = pkey1.finger == pkey2.finger && distance(pkey1, pkey2) < 1.1;
+        let mirrored = are_symmetric(keymap_config, pkey1.position, pkey2.position);
+        if !adjacent && !mirrored {
+            score += 1.0;
+        }
+    }
+    score
+}
+
+/// Rewards keeping keys that get mistyped for each other apart: a confused
+/// pair only scores if its keys aren't next to each other under the same
+/// finger.
+pub fn layout_confusion_score(layout: &Layout, stats: &Stats, keymap_config: &KeymapConfig) -> f64 {
+    let physical = keymap_config.keys.keys();
+    let distances = keymap_config.distances();
+    let mut score = 0.0;
+    for ((typed, corrected), count) in &stats.confusion_counts {
+        let (Some(i), Some(j)) = (
+            layout.position_of_keycode(*typed),
+            layout.position_of_keycode(*corrected),
+        ) else {
+            score += *count as f64;
+            continue;
+        };
+        if physical[i].finger != physical[j].finger || distances.get(i, j) >= 1.1 {
+            score += *count as f64;
+        }
+    }
+    score
+}
+
+#[derive(Debug, Clone)]
+pub struct IntuitionPair(pub Key, pub Key);
+
+impl IntuitionPair {
+    fn physical_keys<'a>(
+        &self,
+        layout: &Layout,
+        keymap_config: &'a KeymapConfig,
+    ) -> (&'a PhysicalKey, &'a PhysicalKey) {
+        (
+            get_physical_key_for_key(layout, keymap_config, &self.0),
+            get_physical_key_for_key(layout, keymap_config, &self.1),
+        )
+    }
+}
+
+/// A rule of thumb about where keys should be placed relative to each other,
+/// e.g. that the arrow keys should be close together.
+#[derive(Debug, Clone)]
+pub enum Intuition {
+    Close(IntuitionPair),
+    Symmetric(IntuitionPair),
+    SameRow(IntuitionPair),
+    SameColumn(IntuitionPair),
+    LeftOf(IntuitionPair),
+    RightOf(IntuitionPair),
+    Above(IntuitionPair),
+    Below(IntuitionPair),
+    SameHand(IntuitionPair),
+    Or(Box<Intuition>, Box<Intuition>),
+    And(Box<Intuition>, Box<Intuition>),
+}
+
+impl Intuition {
+    /// Every key the intuition is about.
+    pub fn keys(&self) -> Vec<Key> {
+        match self {
+            Intuition::Close(pair)
+            | Intuition::Symmetric(pair)
+            | Intuition::SameRow(pair)
+            | Intuition::SameColumn(pair)
+            | Intuition::LeftOf(pair)
+            | Intuition::RightOf(pair)
+            | Intuition::Above(pair)
+            | Intuition::Below(pair)
+            | Intuition::SameHand(pair) => vec![pair.0, pair.1],
+            Intuition::Or(a, b) | Intuition::And(a, b) => {
+                let mut keys = a.keys();
+                keys.extend(b.keys());
+                keys
+            }
+        }
+    }
+
+    pub fn satisfied(&self, layout: &Layout, keymap_config: &KeymapConfig) -> bool {
+        match self {
+            Intuition::Close(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                distance(key1, key2) < 1.1
+            }
+            Intuition::Symmetric(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                are_symmetric(keymap_config, key1.position, key2.position)
+            }
+            Intuition::SameRow(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.1 == key2.position.1
+            }
+            Intuition::SameColumn(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.0 == key2.position.0
+            }
+            Intuition::LeftOf(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.0 < key2.position.0
+            }
+            Intuition::RightOf(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.0 > key2.position.0
+            }
+            Intuition::Above(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.1 < key2.position.1
+            }
+            Intuition::Below(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.position.1 > key2.position.1
+            }
+            Intuition::SameHand(pair) => {
+                let (key1, key2) = pair.physical_keys(layout, keymap_config);
+                key1.finger.hand == key2.finger.hand
+            }
+            Intuition::Or(a, b) => {
+                a.satisfied(layout, keymap_config) || b.satisfied(layout, keymap_config)
+            }
+            Intuition::And(a, b) => {
+                a.satisfied(layout, keymap_config) && b.satisfied(layout, keymap_config)
+            }
+        }
+    }
+}
+
+impl fmt::Display for IntuitionPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |key: &Key| match key {
+            Key::Normal { normal, .. } => normal.to_string(),
+            key => format!("{:?}", key),
+        };
+        write!(f, "{} {}", name(&self.0), name(&self.1))
+    }
+}
+
+impl fmt::Display for Intuition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Intuition::Close(pair) => write!(f, "close({pair})"),
+            Intuition::Symmetric(pair) => write!(f, "symmetric({pair})"),
+            Intuition::SameRow(pair) => write!(f, "same_row({pair})"),
+            Intuition::SameColumn(pair) => write!(f, "same_column({pair})"),
+            Intuition::LeftOf(pair) => write!(f, "left_of({pair})"),
+            Intuition::RightOf(pair) => write!(f, "right_of({pair})"),
+            Intuition::Above(pair) => write!(f, "above({pair})"),
+            Intuition::Below(pair) => write!(f, "below({pair})"),
+            Intuition::SameHand(pair) => write!(f, "same_hand({pair})"),
+            Intuition::Or(a, b) => write!(f, "({a} or {b})"),
+            Intuition::And(a, b) => write!(f, "({a} and {b})"),
+        }
+    }
+}
+
+pub fn intuition_score(
+    layout: &Layout,
+    keymap_config: &KeymapConfig,
+    intuitions: &[Intuition],
+) -> f64 {
+    let mut score = 0.0;
+    for intuition in intuitions {
+        if intuition.satisfied(layout, keymap_config) {
+            score += 1.0;
+        }
+    }
+    score
+}
+
+pub fn close(key1: Key, key2: Key) -> Intuition {
+    Intuition::Close(IntuitionPair(key1, key2))
+}
+
+pub fn symmetric(key1: Key, key2: Key) -> Intuition {
+    Intuition::Symmetric(IntuitionPair(key1, key2))
+}
+
+pub fn same_row(key1: Key, key2: Key) -> Intuition {
+    Intuition::SameRow(IntuitionPair(key1, key2))
+}
+
+pub fn same_column(key1: Key, key2: Key) -> Intuition {
+    Intuition::SameColumn(IntuitionPair(key1, key2))
+}
+
+pub fn left_of(key1: Key, key2: Key) -> Intuition {
+    Intuition::LeftOf(IntuitionPair(key1, key2))
+}
+
+pub fn right_of(key1: Key, key2: Key) -> Intuition {
+    Intuition::RightOf(IntuitionPair(key1, key2))
+}
+
+pub fn above(key1: Key, key2: Key) -> Intuition {
+    Intuition::Above(IntuitionPair(key1, key2))
+}
+
+pub fn below(key1: Key, key2: Key) -> Intuition {
+    Intuition::Below(IntuitionPair(key1, key2))
+}
+
+pub fn same_hand(key1: Key, key2: Key) -> Intuition {
+    Intuition::SameHand(IntuitionPair(key1, key2))
+}
+
+pub fn or(a: Intuition, b: Intuition) -> Intuition {
+    Intuition::Or(Box::new(a), Box::new(b))
+}
+
+pub fn and(a: Intuition, b: Intuition) -> Intuition {
+    Intuition::And(Box::new(a), Box::new(b))
+}
+
+pub fn key(c: char) -> Key {
+    Key::from_char_default_shifted(c)
+}
+
+/// The intuitions every layout is rated against.
+pub fn intuitions() -> Vec<Intuition> {
+    use Key::*;
+    vec![
+        and(same_row(Left, Right), left_of(Left, Right)),
+        and(same_column(Up, Down), above(Up, Down)),
+        or(close(Left, Right), symmetric(Left, Right)),
+        or(close(Up, Down), symmetric(Up, Down)),
+        or(close(PageUp, PageDown), symmetric(PageUp, PageDown)),
+        or(close(key('['), key(']')), symmetric(key('['), key(']'))),
+        symmetric(LShift, RShift),
+        symmetric(LCtrl, RCtrl),
+        close(key('1'), key('2')),
+        close(key('2'), key('3')),
+        close(key('4'), key('5')),
+        close(key('5'), key('6')),
+        close(key('7'), key('8')),
+        close(key('8'), key('9')),
+        close(key('1'), key('4')),
+        close(key('2'), key('5')),
+        close(key('3'), key('6')),
+        close(key('4'), key('7')),
+        close(key('5'), key('8')),
+        close(key('6'), key('9')),
+    ]
+}
+
+/// Reads intuitions written the way they're printed, one per line, like
+/// `close(. ,)` or `(close(Left Right) or symmetric(Left Right))`.  Keys
+/// are characters or the names of other keys, like `LShift`, `PageUp` or
+/// `Space`.  Blank lines and lines starting with `#` are skipped.
+pub fn parse_intuitions(s: &str) -> error::Result<Vec<Intuition>> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            line.trim()
+                .parse()
+                .map_err(|message: String| Error::parse(line, message).at_line(i))
+        })
+        .collect()
+}
+
+/// [`parse_intuitions`] of the file at `path`.
+pub fn load_intuitions(path: &str) -> error::Result<Vec<Intuition>> {
+    parse_intuitions(&std::fs::read_to_string(path)?)
+}
+
+impl FromStr for Intuition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        let intuition = parse_intuition(&mut rest)?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected {:?} after the intuition", rest.trim()));
+        }
+        Ok(intuition)
+    }
+}
+
+/// Parses the intuition at the start of `rest` and moves past it.
+fn parse_intuition(rest: &mut &str) -> Result<Intuition, String> {
+    *rest = rest.trim_start();
+    if let Some(inner) = rest.strip_prefix('(') {
+        *rest = inner;
+        let a = parse_intuition(rest)?;
+        *rest = rest.trim_start();
+        let combine = if let Some(after) = rest.strip_prefix("and ") {
+            *rest = after;
+            and
+        } else if let Some(after) = rest.strip_prefix("or ") {
+            *rest = after;
+            or
+        } else {
+            return Err("expected `and` or `or` between two intuitions".to_string());
+        };
+        let b = parse_intuition(rest)?;
+        *rest = rest.trim_start();
+        *rest = rest
+            .strip_prefix(')')
+            .ok_or("expected `)` after the second intuition")?;
+        return Ok(combine(a, b));
+    }
+
+    let (name, after) = rest
+        .split_once('(')
+        .ok_or("expected an intuition like close(a b)")?;
+    let rule = match name.trim() {
+        "close" => close,
+        "symmetric" => symmetric,
+        "same_row" => same_row,
+        "same_column" => same_column,
+        "left_of" => left_of,
+        "right_of" => right_of,
+        "above" => above,
+        "below" => below,
+        "same_hand" => same_hand,
+        name => return Err(format!("unknown intuition {name}")),
+    };
+    // The second key runs up to the closing parenthesis, which it can
+    // start with itself
+    let (first, after) = after
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .ok_or("expected two keys separated by a space")?;
+    let after = after.trim_start();
+    let second_len = after
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == ')')
+        .map(|(i, _)| i)
+        .ok_or("expected `)` after the keys")?;
+    let second = after[..second_len].trim_end();
+    *rest = &after[second_len + 1..];
+    Ok(rule(parse_key_name(first)?, parse_key_name(second)?))
+}
+
+/// The key typing a character, shifted or not, or another key by its name
+/// like `LShift`.
+fn parse_key_name(name: &str) -> Result<Key, String> {
+    use Key::*;
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_graphic() || built_in_keycode(c).is_some() {
+            return Ok(Key::from_keycode(char_to_keycode(c)));
+        }
+    }
+    [
+        Backspace, Tab, Enter, CapsLock, LShift, RShift, LCtrl, RCtrl, LAlt, RAlt, LMeta, RMeta,
+        Space, Escape, Home, End, PageUp, PageDown, Left, Right, Up, Down, Delete,
+    ]
+    .into_iter()
+    .find(|key| format!("{key:?}") == name)
+    .ok_or_else(|| format!("unknown key {name}"))
+}
+
+/// Whether two positions mirror each other across the middle of the keyboard.
+pub fn are_symmetric(config: &KeymapConfig, pos1: (f64, f64), pos2: (f64, f64)) -> bool {
+    if pos1.1 != pos2.1 {
+        return false;
+    }
+
+    let max = config
+        .keys
+        .keys()
+        .iter()
+        .map(|k| k.position.0)
+        .max_by(|a, b| a.partial_cmp(b).unwrap())
+        .unwrap();
+    let smaller = pos1.0.min(pos2.0);
+    let larger = pos1.0.max(pos2.0);
+
+    (max - larger - smaller).abs() < 0.01
+}
+
+pub fn get_physical_key_for_key<'a>(
+    layout: &Layout,
+    config: &'a KeymapConfig,
+    key: &Key,
+) -> &'a PhysicalKey {
+    &config.keys.keys()[layout.get(key)]
+}
+
+/// How easily `layout` types the bigrams of `stats`: every bigram counts
+/// by the [transition score](Weights::transition_score) between the
+/// physical keys the layout puts its keys on.  Bigrams with keys that
+/// aren't on the layout don't count.
+pub fn layout_consecutive_key_score(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+    weights: &Weights,
+) -> f64 {
+    let transitions = keymap_config.transition_scores(weights);
+    let positions: HashMap<Keycode, usize> = layout
+        .keys()
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (key.keycode(false), i))
+        .collect();
+    let mut score = 0.0;
+    for ((code1, code2), weight) in stats.key_bigram_weights() {
+        if let (Some(&i), Some(&j)) = (positions.get(&code1), positions.get(&code2)) {
+            score += weight * transitions.get(i, j);
+        }
+    }
+    score
+}
+
+/// How far the fingers travel between the centers of two keys, including
+/// the depth of keys in a keywell.
+pub fn distance(key1: &PhysicalKey, key2: &PhysicalKey) -> f64 {
+    let key1_pos = key1.center();
+    let key2_pos = key2.center();
+    let depth = key1.geometry.depth - key2.geometry.depth;
+    ((key1_pos.0 - key2_pos.0).powi(2) + (key1_pos.1 - key2_pos.1).powi(2) + depth.powi(2)).sqrt()
+}
+
+/// A value for every ordered pair of physical keys, by their positions.
+#[derive(Debug)]
+pub struct PairMatrix<'a> {
+    n: usize,
+    values: Cow<'a, [f64]>,
+}
+
+impl PairMatrix<'_> {
+    fn new(physical: &[PhysicalKey], value: impl Fn(&PhysicalKey, &PhysicalKey) -> f64) -> Self {
+        let values = physical
+            .iter()
+            .flat_map(|pkey1| physical.iter().map(|pkey2| value(pkey1, pkey2)))
+            .collect();
+        Self {
+            n: physical.len(),
+            values: Cow::Owned(values),
+        }
+    }
+
+    /// The value of the keys at positions `i` and `j`, in that order.
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        self.values[i * self.n + j]
+    }
+}
+
+/// The [`distance`] and [`Weights::transition_score`] of every pair of keys
+/// of a keyboard, computed the first time a layout is scored rather than
+/// for every bigram of every layout.  See [`KeymapConfig::distances`].
+#[derive(Debug, Default)]
+pub struct KeyPairs {
+    distances: OnceLock<Vec<f64>>,
+    /// With the weights they were computed with.
+    transitions: OnceLock<(Weights, Vec<f64>)>,
+}
+
+impl KeyPairs {
+    pub(crate) fn distances<'a>(&'a self, physical: &[PhysicalKey]) -> PairMatrix<'a> {
+        let n = physical.len();
+        let values = self
+            .distances
+            .get_or_init(|| PairMatrix::new(physical, distance).values.into_owned());
+        PairMatrix {
+            n,
+            values: Cow::Borrowed(values),
+        }
+    }
+
+    /// Computed anew with other weights than the first ones, which only
+    /// happens when comparing weights.
+    pub(crate) fn transitions<'a>(
+        &'a self,
+        physical: &[PhysicalKey],
+        weights: &Weights,
+    ) -> PairMatrix<'a> {
+        let transition =
+            |pkey1: &PhysicalKey, pkey2: &PhysicalKey| weights.transition_score(pkey1, pkey2);
+        let (cached, values) = self.transitions.get_or_init(|| {
+            let values = PairMatrix::new(physical, transition).values.into_owned();
+            (weights.clone(), values)
+        });
+        if cached != weights {
+            return PairMatrix::new(physical, transition);
+        }
+        PairMatrix {
+            n: physical.len(),
+            values: Cow::Borrowed(values),
+        }
+    }
+}
+
+pub fn layout_individual_key_score(
+    layout: &Layout,
+    stats: &Stats,
+    keymap_config: &KeymapConfig,
+) -> f64 {
+    let mut score = 0.0;
+    for (key, config) in layout.keys().iter().zip(keymap_config.keys.keys().iter()) {
+        let count = stats
+            .individual_key_counts
+            .get(&key.keycode(false))
+            .unwrap_or(&0);
+        score += config.score
+            * keymap_config
+                .fingers
+                .iter()
+                .find(|c| c.finger == config.finger)
+                .unwrap()
+                .score
+            * *count as f64;
+    }
+    score
+}
+
+/// [`Weights::consecutive_finger_score`] with the default weights.
+pub fn consecutive_finger_score(f1: Finger, f2: Finger, distance: f64) -> f64 {
+    Weights::default().consecutive_finger_score(f1, f2, distance)
+}
+
+/// [`Weights::transition_score`] with the default weights.
+pub fn transition_score(k1: &PhysicalKey, k2: &PhysicalKey) -> f64 {
+    Weights::default().transition_score(k1, k2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::parse_keymap_config;
+
+    #[test]
+    fn test_default_weights() {
+        let weights: Weights = toml::from_str(include_str!("../weights.toml")).unwrap();
+        assert_eq!(weights, Weights::default());
+    }
+
+    #[test]
+    fn test_same_finger_bigrams() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        // E and D are both typed with the left middle finger, E and R aren't
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::D), 10);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::R), 5);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::E), 3);
+        let sfbs = same_finger_bigrams(&layout, &stats, &config);
+        assert_eq!(sfbs.count, 10);
+        assert_eq!(sfbs.weighted, 10.0);
+    }
+
+    #[test]
+    fn test_key_pairs() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let physical = config.keys.keys();
+        let (i, j) = (3, 20);
+        let weights = Weights::default();
+        assert_eq!(
+            config.distances().get(i, j),
+            distance(&physical[i], &physical[j])
+        );
+        assert_eq!(
+            config.transition_scores(&weights).get(i, j),
+            weights.transition_score(&physical[i], &physical[j])
+        );
+        // Other weights than the cached ones aren't looked up in the cache
+        let other = Weights {
+            vertical: Vertical {
+                upward: 0.5,
+                downward: 0.5,
+            },
+            ..Weights::default()
+        };
+        assert_eq!(
+            config.transition_scores(&other).get(i, j),
+            other.transition_score(&physical[i], &physical[j])
+        );
+    }
+
+    #[test]
+    fn test_parse_intuitions() {
+        let parsed = parse_intuitions(
+            "# Punctuation\nclose(. ,)\n\n(close(Left Right) or symmetric(Left Right))\nsame_row(( ))\n",
+        )
+        .unwrap();
+        let printed: Vec<_> = parsed.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            printed,
+            [
+                "close(. ,)",
+                "(close(Left Right) or symmetric(Left Right))",
+                "same_row(9 0)"
+            ]
+        );
+        for intuition in intuitions().iter().map(|i| i.to_string()) {
+            assert_eq!(
+                intuition.parse::<Intuition>().unwrap().to_string(),
+                intuition
+            );
+        }
+        assert!(matches!(
+            parse_intuitions("close(a b)\nnear(a b)"),
+            Err(Error::Parse { line: Some(2), .. })
+        ));
+        assert!(parse_intuitions("close(a Nope)").is_err());
+        assert!(parse_intuitions("(close(a b) xor close(c d))").is_err());
+
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let scoring = ScoringConfig {
+            custom_intuitions: parse_intuitions("close(. ,)\nsame_row(q p)").unwrap(),
+            ..ScoringConfig::default()
+        };
+        let stats = Stats::new();
+        let without = ScoringConfig::default();
+        assert_eq!(
+            scoring.score(&layout, &stats, &config) - without.score(&layout, &stats, &config),
+            2.0 * scoring.weights.intuition_weight
+        );
+    }
+
+    #[test]
+    fn test_hand_patterns() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::A, Keycode::F), 4);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::F, Keycode::S), 2);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::F, Keycode::J), 6);
+        let patterns = hand_patterns(&layout, &stats, &config);
+        assert_eq!(patterns.bigrams, 12);
+        assert_eq!(patterns.inward_rolls, 4);
+        assert_eq!(patterns.outward_rolls, 2);
+        assert_eq!(patterns.alternations, 6);
+        assert_eq!(patterns.alternation_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut stats = Stats::new();
+        for (pair, count) in [
+            ((Keycode::A, Keycode::B), 1),
+            ((Keycode::A, Keycode::C), 3),
+            ((Keycode::D, Keycode::B), 2),
+        ] {
+            stats.consecutive_key_counts.insert(pair, count);
+        }
+        let counts = stats.consecutive_key_counts.clone();
+
+        let mut laplace = stats.clone();
+        Smoothing::Laplace { alpha: 0.3 }.apply(&mut laplace, &config);
+        assert_eq!(laplace.consecutive_key_counts, counts);
+        assert_eq!(laplace.key_bigram_weight((Keycode::A, Keycode::B)), 1.3);
+        // An unseen pair gets a fraction of a press instead of none
+        assert_eq!(laplace.key_bigram_weight((Keycode::B, Keycode::A)), 0.3);
+
+        let mut kneser_ney = stats.clone();
+        Smoothing::KneserNey { discount: 0.75 }.apply(&mut kneser_ney, &config);
+        assert_eq!(kneser_ney.consecutive_key_counts, counts);
+        // A follows two keys and B follows two of the three pairs, so the
+        // singleton A B keeps 1 - 0.75 and gets 2 * 0.75 * 2 / 3 back
+        assert_eq!(kneser_ney.key_bigram_weight((Keycode::A, Keycode::B)), 1.25);
+        // D is followed by one key and C follows one of the pairs
+        assert_eq!(kneser_ney.key_bigram_weight((Keycode::D, Keycode::C)), 0.25);
+        // Nothing follows B, so it has nothing to spread
+        assert_eq!(kneser_ney.key_bigram_weight((Keycode::B, Keycode::C)), 0.0);
+
+        // The score of an unseen pair counts
+        let layout = Layout::from_keymap(&config);
+        let weights = Weights::default();
+        let score = |stats: &Stats| layout_consecutive_key_score(&layout, stats, &config, &weights);
+        assert_ne!(score(&kneser_ney), score(&stats));
+        Smoothing::None.apply(&mut stats, &config);
+        assert_eq!(stats.smoothed_key_counts, None);
+    }
+
+    #[test]
+    fn test_directional_synergy() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let key = |code| &config.keys.keys()[layout.position_of_keycode(code).unwrap()];
+        let mut weights = Weights::default();
+        // D and F are typed with the middle and the index finger
+        let (d, f) = (key(Keycode::D), key(Keycode::F));
+        assert_eq!(
+            weights.transition_score(d, f),
+            weights.transition_score(f, d)
+        );
+        weights.directional_synergy.push(DirectionalSynergy {
+            from: FingerKind::Middle,
+            to: FingerKind::Index,
+            synergy: 0.9,
+        });
+        assert!(weights.transition_score(d, f) > weights.transition_score(f, d));
+
+        // E is above D
+        let e = key(Keycode::E);
+        weights.vertical.upward = 0.5;
+        assert!(weights.transition_score(d, e) < weights.transition_score(e, d));
+    }
+
+    #[test]
+    fn test_thumb_load() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::Space, 10);
+        stats.individual_key_counts.insert(Keycode::LControl, 3);
+        stats.individual_key_counts.insert(Keycode::A, 7);
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::Backspace, Keycode::LControl], 2);
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::A, Keycode::LControl], 5);
+        let load = thumb_load(&layout, &stats, &config);
+        assert_eq!(load.left_thumb, 3);
+        assert_eq!(load.right_thumb, 10);
+        assert_eq!(load.fingers, 7);
+        assert_eq!(load.hold_conflicts, 2);
+        assert_eq!(load.thumb_share(), 0.65);
+    }
+
+    #[test]
+    fn test_finger_travel() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let mut stats = Stats::new();
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::D), 10);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::R), 5);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::E, Keycode::E), 3);
+        // D and F are the best keys of their fingers, so typing R after E
+        // moves the middle finger back down from E and the index finger up
+        assert_eq!(finger_travel(&layout, &stats, &config), 20.0);
+
+        let s = format!("{}\nHome\nE\n", include_str!("../kinesis.layout"));
+        let config = parse_keymap_config(&s).unwrap();
+        assert_eq!(crate::format::keymap_config_to_str(&config).unwrap(), s);
+        assert_eq!(finger_travel(&layout, &stats, &config), 15.0);
+        let s = format!("{}\nHome\nE D\n", include_str!("../kinesis.layout"));
+        assert!(parse_keymap_config(&s).is_err());
+    }
+
+    #[test]
+    fn test_home_row_mods() {
+        let config = parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let layout = Layout::from_keymap(&config);
+        let code = |i: usize| config.keys.keys()[i].code;
+        let mut stats = Stats::new();
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LShift, Keycode::A], 10);
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LControl, Keycode::J], 5);
+        // Both are held on the other hand, where they cost nothing
+        let mods = home_row_mods(
+            &layout,
+            &stats,
+            &config,
+            &[Keycode::LShift, Keycode::LControl],
+        );
+        assert_eq!(mods.cost, 0.0);
+        let hand = |i: usize| config.keys.keys()[i].finger.hand;
+        assert_eq!(hand(mods.placement[0].1), Hand::Right);
+        assert_eq!(hand(mods.placement[1].1), Hand::Left);
+
+        // Shift is held on the right hand, but not by the finger on J
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::LShift, Keycode::J], 5);
+        let mods = home_row_mods(&layout, &stats, &config, &[Keycode::LShift]);
+        assert_eq!(mods.cost, 5.0 * HOLD_SAME_HAND);
+        assert_eq!(code(mods.placement[0].1), Keycode::K);
+    }
+}
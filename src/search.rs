@@ -0,0 +1,453 @@
+//! Local searches that refine a layout by swapping its keys, run on every
+//! layout of the population between generations of `explore_layouts`.
+//!
+//! They take the random number generator to use, so a search seeded the
+//! same way finds the same layout.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::layout::Layout;
+
+/// What the searches maximize.  Any function of a layout is one, which
+/// rescores the whole layout after every swap.
+pub trait SwapObjective {
+    fn score(&self, layout: &Layout) -> f64;
+
+    /// Swaps the keys at `i` and `j` of `layout`, whose score is `score`,
+    /// and returns the new score.
+    fn score_swap(&self, layout: &mut Layout, score: f64, i: usize, j: usize) -> f64 {
+        let _ = score;
+        layout.swap(i, j);
+        self.score(layout)
+    }
+}
+
+impl<F: Fn(&Layout) -> f64> SwapObjective for F {
+    fn score(&self, layout: &Layout) -> f64 {
+        self(layout)
+    }
+}
+
+/// How annealing cools down, the `annealing` section of `config.json`.
+/// Temperatures are relative to the max possible score, so they mean the
+/// same for any stats.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    /// The temperature annealing starts at, and reheats to.
+    pub initial_temperature: f64,
+    /// What the temperature is multiplied by after every step, in (0, 1).
+    pub cooling: f64,
+    /// Annealing stops once the temperature drops below this.
+    pub min_temperature: f64,
+    /// How many swaps are tried at every temperature.
+    pub swaps_per_step: usize,
+    /// Reheat after this many steps without a new best layout, or never
+    /// if 0.
+    pub reheat_after: usize,
+    /// Reheat when less than this share of the last [`ACCEPTANCE_WINDOW`]
+    /// swaps was accepted, or never if 0.  Acceptance drops as annealing
+    /// cools, so this reheats once it has mostly stopped moving.
+    pub min_acceptance: f64,
+    /// How many times annealing reheats at most, so it ends.
+    pub max_reheats: usize,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            initial_temperature: 1.0,
+            cooling: 0.9999,
+            min_temperature: 0.0001,
+            swaps_per_step: 1,
+            reheat_after: 0,
+            min_acceptance: 0.0,
+            max_reheats: 3,
+        }
+    }
+}
+
+/// The swaps [`Schedule::min_acceptance`] is measured over.
+pub const ACCEPTANCE_WINDOW: usize = 1000;
+
+impl Schedule {
+    /// What's wrong with the schedule, if it would never end.
+    pub fn check(&self) -> Result<(), String> {
+        if self.cooling <= 0.0 || self.cooling >= 1.0 {
+            Err(format!(
+                "the cooling factor has to be in (0, 1), got {}",
+                self.cooling
+            ))
+        } else if self.swaps_per_step == 0 {
+            Err("annealing needs at least 1 swap per step".to_string())
+        } else if self.min_temperature <= 0.0 || self.initial_temperature < self.min_temperature {
+            Err(format!(
+                "the temperatures have to be above 0 and start above the minimum, got {} and {}",
+                self.initial_temperature, self.min_temperature
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The swaps annealing tries before it cools down, without reheating.
+    pub fn evaluations(&self) -> usize {
+        let steps = (self.min_temperature / self.initial_temperature).ln() / self.cooling.ln();
+        steps.ceil().max(1.0) as usize * self.swaps_per_step
+    }
+}
+
+/// Where annealing is in its [`Schedule`].
+struct Cooling<'a> {
+    schedule: &'a Schedule,
+    temperature: f64,
+    /// Swaps tried at the current temperature.
+    swaps: usize,
+    steps_since_best: usize,
+    /// Swaps tried and accepted towards the next acceptance rate.
+    window: (usize, usize),
+    reheats: usize,
+}
+
+impl<'a> Cooling<'a> {
+    fn new(schedule: &'a Schedule) -> Self {
+        Self {
+            schedule,
+            temperature: schedule.initial_temperature,
+            swaps: 0,
+            steps_since_best: 0,
+            window: (0, 0),
+            reheats: 0,
+        }
+    }
+
+    /// Records a swap, whether it was `accepted` and made a `new_best`
+    /// layout, and returns whether annealing goes on.
+    fn record(&mut self, accepted: bool, new_best: bool) -> bool {
+        let schedule = self.schedule;
+        if new_best {
+            self.steps_since_best = 0;
+        }
+        let mut reheat = false;
+        if schedule.min_acceptance > 0.0 {
+            self.window.0 += 1;
+            self.window.1 += accepted as usize;
+            if self.window.0 == ACCEPTANCE_WINDOW {
+                let rate = self.window.1 as f64 / ACCEPTANCE_WINDOW as f64;
+                reheat |= rate < schedule.min_acceptance;
+                self.window = (0, 0);
+            }
+        }
+        self.swaps += 1;
+        if self.swaps == schedule.swaps_per_step {
+            self.swaps = 0;
+            self.temperature *= schedule.cooling;
+            self.steps_since_best += 1;
+            reheat |= schedule.reheat_after > 0 && self.steps_since_best >= schedule.reheat_after;
+        }
+        if reheat && self.reheats < schedule.max_reheats {
+            self.reheats += 1;
+            self.temperature = schedule.initial_temperature;
+            self.steps_since_best = 0;
+        }
+        self.temperature >= schedule.min_temperature
+    }
+}
+
+/// Anneals `initial_layout` by swapping the keys at random `movable`
+/// positions, cooling down by `schedule`, and returns the best layout
+/// seen.  Scores are normalized by `max_possible_score` to compare them to
+/// the temperature.
+pub fn simmulated_annealing(
+    objective: &impl SwapObjective,
+    movable: &[usize],
+    max_possible_score: f64,
+    schedule: &Schedule,
+    initial_layout: Layout,
+    rng: &mut impl Rng,
+) -> Layout {
+    let mut layout = initial_layout;
+    let mut score = objective.score(&layout);
+    let mut cooling = Cooling::new(schedule);
+    let mut best_layout = layout.clone();
+    let mut best_score = score;
+    loop {
+        let mut new_layout = layout.clone();
+        let i = movable[rng.gen_range(0..movable.len())];
+        let j = movable[rng.gen_range(0..movable.len())];
+        let new_score = objective.score_swap(&mut new_layout, score, i, j);
+        let new_best = new_score > best_score;
+        if new_best {
+            best_layout = new_layout.clone();
+            best_score = new_score;
+        }
+        let delta = new_score - score;
+        let normalized_delta = delta / max_possible_score;
+        let accepted = delta > 0.0 || rng.gen_bool((normalized_delta / cooling.temperature).exp());
+        if accepted {
+            layout = new_layout;
+            score = new_score;
+        }
+        if !cooling.record(accepted, new_best) {
+            break;
+        }
+    }
+    best_layout
+}
+
+/// Like [`simmulated_annealing`], but undoes rejected swaps instead of
+/// copying the layout for every step, and copies the best layout into a
+/// single reused buffer.
+pub fn simmulated_annealing_in_place(
+    objective: &impl SwapObjective,
+    movable: &[usize],
+    max_possible_score: f64,
+    schedule: &Schedule,
+    layout: &mut Layout,
+    rng: &mut impl Rng,
+) {
+    let mut score = objective.score(layout);
+    let mut cooling = Cooling::new(schedule);
+    let mut best_layout = layout.clone();
+    let mut best_score = score;
+    loop {
+        let i = movable[rng.gen_range(0..movable.len())];
+        let j = movable[rng.gen_range(0..movable.len())];
+        let new_score = objective.score_swap(layout, score, i, j);
+        let new_best = new_score > best_score;
+        if new_best {
+            best_layout.clone_from(layout);
+            best_score = new_score;
+        }
+        let delta = new_score - score;
+        let normalized_delta = delta / max_possible_score;
+        let accepted = delta > 0.0 || rng.gen_bool((normalized_delta / cooling.temperature).exp());
+        if accepted {
+            score = new_score;
+        } else {
+            layout.swap(i, j);
+        }
+        if !cooling.record(accepted, new_best) {
+            break;
+        }
+    }
+    layout.clone_from(&best_layout);
+}
+
+/// Swaps sampled for every step of [`tabu_search`].
+const TABU_CANDIDATES: usize = 32;
+
+/// Moves to the best of a sample of swaps every step, even when it's
+/// worse, to walk out of local optima.  Swapping back the same keys is
+/// tabu for a while, unless it makes a new best layout, so the search
+/// doesn't cycle.  Scores about `evaluations` layouts in place, and leaves
+/// the best one found in `layout`.
+pub fn tabu_search(
+    objective: &impl SwapObjective,
+    movable: &[usize],
+    evaluations: usize,
+    layout: &mut Layout,
+    rng: &mut impl Rng,
+) {
+    let tenure = (movable.len() / 4).max(1);
+    let mut tabu_until: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut best_layout = layout.clone();
+    let mut score = objective.score(layout);
+    let mut best_score = score;
+    for step in 0..evaluations / TABU_CANDIDATES {
+        let mut chosen: Option<((usize, usize), f64)> = None;
+        for _ in 0..TABU_CANDIDATES {
+            let i = movable[rng.gen_range(0..movable.len())];
+            let j = movable[rng.gen_range(0..movable.len())];
+            if i == j {
+                continue;
+            }
+            let swap = (i.min(j), i.max(j));
+            let new_score = objective.score_swap(layout, score, i, j);
+            layout.swap(i, j);
+            let tabu = matches!(tabu_until.get(&swap), Some(&until) if until > step);
+            if (!tabu || new_score > best_score)
+                && chosen.is_none_or(|(_, chosen_score)| new_score > chosen_score)
+            {
+                chosen = Some((swap, new_score));
+            }
+        }
+        let Some(((i, j), new_score)) = chosen else {
+            continue;
+        };
+        layout.swap(i, j);
+        score = new_score;
+        tabu_until.insert((i, j), step + tenure);
+        if new_score > best_score {
+            best_layout.clone_from(layout);
+            best_score = new_score;
+        }
+    }
+    layout.clone_from(&best_layout);
+}
+
+/// Keeps every random swap that improves the score until none has in a
+/// while, then restarts from a shuffle of the movable keys.  Scores
+/// `evaluations` layouts in place, and leaves the best one found in
+/// `layout`.
+pub fn hill_climbing(
+    objective: &impl SwapObjective,
+    movable: &[usize],
+    evaluations: usize,
+    layout: &mut Layout,
+    rng: &mut impl Rng,
+) {
+    // Roughly every swap has been tried once without improving
+    let patience = movable.len() * movable.len() / 2;
+    let mut score = objective.score(layout);
+    let mut best_layout = layout.clone();
+    let mut best_score = score;
+    let mut failures = 0;
+    for _ in 0..evaluations {
+        if failures >= patience {
+            for (n, &i) in movable.iter().enumerate() {
+                layout.swap(i, movable[rng.gen_range(n..movable.len())]);
+            }
+            score = objective.score(layout);
+            failures = 0;
+        }
+        let i = movable[rng.gen_range(0..movable.len())];
+        let j = movable[rng.gen_range(0..movable.len())];
+        let new_score = objective.score_swap(layout, score, i, j);
+        if new_score > score {
+            score = new_score;
+            failures = 0;
+            if score > best_score {
+                best_layout.clone_from(layout);
+                best_score = score;
+            }
+        } else {
+            layout.swap(i, j);
+            failures += 1;
+        }
+    }
+    layout.clone_from(&best_layout);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        char_table::CharTable,
+        format::parse_keymap_config,
+        scoring::{LayoutScorer, ScoringConfig},
+        stats::{typing_events, KeyProcessor},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// The swaps `schedule` tries when every swap is `accepted` and none
+    /// makes a new best layout.
+    fn swaps(schedule: &Schedule, accepted: bool) -> usize {
+        let mut cooling = Cooling::new(schedule);
+        let mut swaps = 1;
+        while cooling.record(accepted, false) {
+            swaps += 1;
+        }
+        swaps
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = Schedule {
+            cooling: 0.99,
+            min_temperature: 0.01,
+            ..Schedule::default()
+        };
+        assert_eq!(swaps(&schedule, true), schedule.evaluations());
+        let slower = Schedule {
+            swaps_per_step: 3,
+            ..schedule.clone()
+        };
+        assert_eq!(swaps(&slower, true), 3 * schedule.evaluations());
+
+        let reheating = Schedule {
+            reheat_after: 100,
+            max_reheats: 2,
+            ..schedule.clone()
+        };
+        assert_eq!(swaps(&reheating, true), 2 * 100 + schedule.evaluations());
+        let adaptive = Schedule {
+            cooling: 0.9999,
+            min_acceptance: 0.5,
+            max_reheats: 1,
+            ..schedule.clone()
+        };
+        // Nothing is accepted, so it reheats after the first window
+        assert_eq!(
+            swaps(&adaptive, false),
+            ACCEPTANCE_WINDOW + adaptive.evaluations()
+        );
+        assert!(Schedule {
+            cooling: 1.0,
+            ..Schedule::default()
+        }
+        .check()
+        .is_err());
+    }
+
+    #[test]
+    fn test_local_search_never_worsens() {
+        let config = parse_keymap_config(include_str!("../tests/data/tiny.layout")).unwrap();
+        let mut processor = KeyProcessor::new();
+        let text = "the quick brown fox jumps over the lazy dog, 1995 times";
+        for (key, press) in typing_events(text, &CharTable::default()) {
+            processor.push_event(key, press);
+        }
+        let stats = processor.finish();
+        let scoring = ScoringConfig::default();
+        let objective = |layout: &Layout| scoring.score(layout, &stats, &config);
+        // Few keys, so hill climbing soon restarts from shuffles that are
+        // worse than the layout it started from
+        let movable = &config.unpinned()[..10];
+        let sorted_keys = |layout: &Layout| {
+            let mut keys: Vec<String> =
+                layout.keys().iter().map(|key| format!("{key:?}")).collect();
+            keys.sort();
+            keys
+        };
+
+        type Search = fn(&dyn Fn(&Layout) -> f64, &[usize], usize, &mut Layout, &mut StdRng);
+        let searches: [(&str, Search); 2] = [
+            ("tabu", |objective, movable, evaluations, layout, rng| {
+                tabu_search(&objective, movable, evaluations, layout, rng)
+            }),
+            (
+                "hill climbing",
+                |objective, movable, evaluations, layout, rng| {
+                    hill_climbing(&objective, movable, evaluations, layout, rng)
+                },
+            ),
+        ];
+        for seed in 0..2 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            // Starting from the keycaps and from a shuffle of them
+            let mut starts = vec![Layout::from_keymap(&config)];
+            let mut shuffled = starts[0].clone();
+            for (n, &i) in movable.iter().enumerate() {
+                shuffled.swap(i, movable[rng.gen_range(n..movable.len())]);
+            }
+            starts.push(shuffled);
+            for start in starts {
+                for evaluations in [60, 300] {
+                    for (name, search) in searches {
+                        let mut layout = start.clone();
+                        search(&objective, movable, evaluations, &mut layout, &mut rng);
+                        assert!(
+                            objective(&layout) >= objective(&start),
+                            "{name} with seed {seed} made the layout worse"
+                        );
+                        assert_eq!(sorted_keys(&layout), sorted_keys(&start));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Serde helpers for [`Keycode`], which doesn't implement serde's traits
+//! itself.  Keycodes are written with the same names as the key log.
+
+use std::{collections::HashMap, hash::Hash, str::FromStr};
+
+use device_query::Keycode;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+fn parse_keycode<E: Error>(s: &str) -> Result<Keycode, E> {
+    Keycode::from_str(s).map_err(E::custom)
+}
+
+/// For `HashMap<Keycode, V>`, written as a map keyed by keycode name.
+pub mod keycode_map {
+    use super::*;
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        map: &HashMap<Keycode, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Keycode, V>, D::Error> {
+        let map = HashMap::<String, V>::deserialize(deserializer)?;
+        map.into_iter()
+            .map(|(k, v)| Ok((parse_keycode(&k)?, v)))
+            .collect()
+    }
+}
+
+/// For `HashMap<(Keycode, Keycode), V>`, written as a list of
+/// `[first, second, value]` entries.
+pub mod keycode_pair_map {
+    use super::*;
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        map: &HashMap<(Keycode, Keycode), V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            map.iter()
+                .map(|((a, b), v)| (a.to_string(), b.to_string(), v)),
+        )
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<(Keycode, Keycode), V>, D::Error> {
+        let entries = Vec::<(String, String, V)>::deserialize(deserializer)?;
+        entries
+            .into_iter()
+            .map(|(a, b, v)| Ok(((parse_keycode(&a)?, parse_keycode(&b)?), v)))
+            .collect()
+    }
+}
+
+/// For `HashMap<(Keycode, Keycode, Keycode), V>`, written as a list of
+/// `[first, second, third, value]` entries.
+pub mod keycode_triple_map {
+    use super::*;
+
+    type Triple = (Keycode, Keycode, Keycode);
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        map: &HashMap<Triple, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            map.iter()
+                .map(|((a, b, c), v)| (a.to_string(), b.to_string(), c.to_string(), v)),
+        )
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Triple, V>, D::Error> {
+        let entries = Vec::<(String, String, String, V)>::deserialize(deserializer)?;
+        entries
+            .into_iter()
+            .map(|(a, b, c, v)| {
+                Ok((
+                    (parse_keycode(&a)?, parse_keycode(&b)?, parse_keycode(&c)?),
+                    v,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// For `Vec<Keycode>`, written as a list of keycode names.
+pub mod keycode_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(keys: &[Keycode], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(keys.iter().map(|k| k.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Keycode>, D::Error> {
+        let keys = Vec::<String>::deserialize(deserializer)?;
+        keys.iter().map(|k| parse_keycode(k)).collect()
+    }
+}
+
+/// For `HashMap<Vec<Keycode>, V>`, written as a list of `[keys, value]`
+/// entries.
+pub mod keycode_list_map {
+    use super::*;
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        map: &HashMap<Vec<Keycode>, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter().map(|(keys, v)| {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            (keys, v)
+        }))
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Vec<Keycode>, V>, D::Error> {
+        let entries = Vec::<(Vec<String>, V)>::deserialize(deserializer)?;
+        entries
+            .into_iter()
+            .map(|(keys, v)| {
+                let keys = keys
+                    .iter()
+                    .map(|k| parse_keycode(k))
+                    .collect::<Result<_, _>>()?;
+                Ok((keys, v))
+            })
+            .collect()
+    }
+}
+
+/// For maps whose keys can't be JSON object keys, like tuples, written as a
+/// list of `[key, value]` entries.
+pub mod entries {
+    use super::*;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let entries = Vec::<(K, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
+}
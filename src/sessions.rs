@@ -0,0 +1,172 @@
+//! Splitting key logs into typing sessions at the idle gaps between them,
+//! like a morning of work and an evening of chatting, so the stats can be
+//! limited to recent typing.
+//!
+//! Sessions need timestamps: saved stats and logs from before the
+//! keylogger recorded them have none.
+
+use crate::{
+    capture::{read_log, LogLine},
+    error::Result,
+    stats::LogOptions,
+};
+
+/// How long typing has to pause for the next key press to start a new
+/// session, unless `session_gap_minutes` is set in `config.json`.
+pub const DEFAULT_GAP_MINUTES: u64 = 30;
+
+/// A stretch of typing without an idle gap.  Times are in milliseconds
+/// since the Unix epoch, like in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypingSession {
+    /// When the first key was pressed.
+    pub start: u64,
+    /// When the last key was pressed.
+    pub end: u64,
+    pub key_presses: u64,
+}
+
+impl TypingSession {
+    pub fn duration_ms(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// Splits the key presses in the logs at `paths` into sessions wherever
+/// typing paused for at least `gap_ms`, oldest first.  Sessions of several
+/// logs, like those of two machines, are joined where they're less than
+/// `gap_ms` apart.  Only presses in the period of `options` count, and
+/// saved stats are skipped.
+pub fn find_sessions<S: AsRef<str>>(
+    paths: &[S],
+    gap_ms: u64,
+    options: &LogOptions,
+) -> Result<Vec<TypingSession>> {
+    let mut sessions: Vec<TypingSession> = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        if path.ends_with(".json") {
+            continue;
+        }
+        for line in read_log(path).map_err(|error| error.in_file(path))? {
+            let LogLine::Event(_, true, Some(timestamp)) =
+                line.map_err(|error| error.in_file(path))?
+            else {
+                continue;
+            };
+            if options.since.is_some_and(|since| timestamp < since)
+                || options.until.is_some_and(|until| timestamp >= until)
+            {
+                continue;
+            }
+            add_press(&mut sessions, timestamp, 1, gap_ms);
+        }
+    }
+
+    // The logs may overlap in time
+    sessions.sort_by_key(|session| session.start);
+    let mut joined = vec![];
+    for session in sessions {
+        add_press(&mut joined, session.start, session.key_presses, gap_ms);
+        let last = joined.last_mut().unwrap();
+        last.end = last.end.max(session.end);
+    }
+    Ok(joined)
+}
+
+/// Adds `key_presses` at `timestamp` to the last of `sessions`, or starts a
+/// new one after a gap or if the clock went back.
+fn add_press(sessions: &mut Vec<TypingSession>, timestamp: u64, key_presses: u64, gap_ms: u64) {
+    match sessions.last_mut() {
+        Some(last) if timestamp >= last.start && timestamp < last.end + gap_ms => {
+            last.end = last.end.max(timestamp);
+            last.key_presses += key_presses;
+        }
+        _ => sessions.push(TypingSession {
+            start: timestamp,
+            end: timestamp,
+            key_presses,
+        }),
+    }
+}
+
+/// When the last `n` of `sessions` started, to only count the events since,
+/// see [`LogOptions::since`](crate::stats::LogOptions::since).
+pub fn last_sessions_start(sessions: &[TypingSession], n: usize) -> Option<u64> {
+    if n == 0 {
+        return None;
+    }
+    sessions
+        .get(sessions.len().saturating_sub(n))
+        .map(|session| session.start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{capture::parse_date, stats::process_log_with};
+    use device_query::Keycode;
+
+    #[test]
+    fn test_sessions() {
+        let minute = 60_000;
+        let mut log = String::new();
+        for (key, timestamp) in [
+            ("A", 0),
+            ("B", 1000),
+            ("C", 40 * minute),
+            ("D", 41 * minute),
+        ] {
+            log += &format!("{key} 1 {timestamp}\n{key} 0 {}\n", timestamp + 50);
+        }
+        let path = std::env::temp_dir().join("keyboard_layout_generator_sessions.log");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, log).unwrap();
+
+        let gap = DEFAULT_GAP_MINUTES * minute;
+        let sessions = find_sessions(&[path], gap, &LogOptions::default()).unwrap();
+        assert_eq!(
+            sessions,
+            [
+                TypingSession {
+                    start: 0,
+                    end: 1000,
+                    key_presses: 2
+                },
+                TypingSession {
+                    start: 40 * minute,
+                    end: 41 * minute,
+                    key_presses: 2
+                },
+            ]
+        );
+        // The same log twice overlaps itself
+        assert_eq!(
+            find_sessions(&[path, path], gap, &LogOptions::default()).unwrap()[1].key_presses,
+            4
+        );
+
+        let options = LogOptions {
+            since: last_sessions_start(&sessions, 1),
+            ..LogOptions::default()
+        };
+        let stats = process_log_with(path, &options).unwrap();
+        assert_eq!(stats.individual_key_counts.get(&Keycode::A), None);
+        assert_eq!(stats.individual_key_counts.get(&Keycode::C), Some(&1));
+        assert_eq!(last_sessions_start(&sessions, 5), Some(0));
+
+        let until = LogOptions {
+            until: Some(40 * minute),
+            ..LogOptions::default()
+        };
+        assert_eq!(find_sessions(&[path], gap, &until).unwrap().len(), 1);
+        let stats = process_log_with(path, &until).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(stats.individual_key_counts.get(&Keycode::B), Some(&1));
+        assert_eq!(stats.individual_key_counts.get(&Keycode::C), None);
+        assert_eq!(parse_date("1970-01-02T00:01"), Ok(86_460_000));
+        assert_eq!(parse_date("2024-05-01"), Ok(1_714_521_600_000));
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+}
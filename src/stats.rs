@@ -1,110 +1,1134 @@
 use std::{
     collections::{HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader},
-    str::FromStr,
+    hash::Hash,
+    io::BufReader,
 };
 
 use device_query::Keycode;
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    capture::{read_log, LogHeader, LogLine},
+    char_table::CharTable,
+    error::{Error, Result},
+    layout::{Key, Layout},
+    scoring::ScoringConfig,
+    serde_util::{entries, keycode_list_map, keycode_map, keycode_pair_map, keycode_triple_map},
+    trial::LatencyStats,
+    Hand, KeymapConfig,
+};
+
+/// Aggregated counts of everything typed in a key log.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Stats {
     pub total_log_lines: u64,
     pub char_counts: HashMap<char, u64>,
+    #[serde(with = "entries")]
     pub consecutive_char_counts: HashMap<(char, char), u64>,
+    /// Characters typed with Shift held, by the hand of the Shift key,
+    /// the left one if both were held.
+    #[serde(with = "entries")]
+    pub shifted_char_counts: HashMap<(char, Hand), u64>,
+    #[serde(with = "keycode_map")]
     pub individual_key_counts: HashMap<Keycode, u64>,
-    pub consectutive_key_counts: HashMap<(Keycode, Keycode), u64>,
+    #[serde(with = "keycode_pair_map")]
+    pub consecutive_key_counts: HashMap<(Keycode, Keycode), u64>,
+    /// The consecutive key counts after
+    /// [smoothing](crate::scoring::Smoothing), which aren't whole presses,
+    /// if the stats were smoothed.  Read them with
+    /// [`Stats::key_bigram_weights`].
+    #[serde(skip)]
+    pub smoothed_key_counts: Option<HashMap<(Keycode, Keycode), f64>>,
+    /// Runs of three characters, for scoring rolls and redirects.
+    #[serde(with = "entries")]
+    pub trigram_char_counts: HashMap<(char, char, char), u64>,
+    /// Runs of three key presses, regardless of which keys were held.
+    #[serde(with = "keycode_triple_map")]
+    pub trigram_key_counts: HashMap<(Keycode, Keycode, Keycode), u64>,
+    /// Keys held down together on purpose, like shortcuts, counted once
+    /// every time they're pressed together.  See [`MIN_CHORD_OVERLAP_MS`].
+    #[serde(with = "keycode_list_map")]
     pub simultaneous_key_counts: HashMap<Vec<Keycode>, u64>,
+    /// Keys that were only down together because the next one was pressed
+    /// before the previous one was released, like rolling `th`.
+    #[serde(with = "keycode_list_map")]
+    pub rollover_key_counts: HashMap<Vec<Keycode>, u64>,
+    /// Lowercased runs of letters, with backspaced letters removed.
+    pub word_counts: HashMap<String, u64>,
+    /// How often each key was deleted with backspace right after typing it.
+    #[serde(with = "keycode_map")]
+    pub error_key_counts: HashMap<Keycode, u64>,
+    /// Keys typed by mistake and the key that replaced them after a single
+    /// backspace, as `(typed, corrected)`.
+    #[serde(with = "keycode_pair_map")]
+    pub confusion_counts: HashMap<(Keycode, Keycode), u64>,
+    /// How many runs of backspaces of each length were typed.
+    pub correction_lengths: HashMap<u64, u64>,
+    /// The character typed right before each run of backspaces.
+    pub corrected_char_counts: HashMap<char, u64>,
+    /// The last two keys typed before each run of backspaces, the bigrams
+    /// most likely to go wrong.
+    #[serde(with = "keycode_pair_map")]
+    pub correction_bigram_counts: HashMap<(Keycode, Keycode), u64>,
+    /// How many runs of digits of each length were typed, counting only
+    /// runs of at least [`MIN_NUMERIC_BURST`] digits.
+    pub numeric_burst_lengths: HashMap<u64, u64>,
+    /// How many times consecutive key presses were apart by each interval,
+    /// in buckets of [`INTERVAL_BUCKET_MS`] keyed by their lower bound.
+    /// Only logs with timestamps have intervals.
+    pub interval_histogram: HashMap<u64, u64>,
+    /// The intervals before each key and between each pair of keys.
+    pub latencies: LatencyStats,
+    /// The typing speed over every [`WPM_WINDOW_CHARS`] characters in a
+    /// row, in words of five characters per minute, oldest first.  Pauses
+    /// longer than [`MAX_INTERVAL_MS`] don't count.  Only logs with
+    /// timestamps have it.
+    pub rolling_wpm: Vec<f64>,
+    /// Presses of keys in the log that [`Keycode`] has no name for and
+    /// that have no alias, by the name in the log.
+    pub ignored_key_counts: HashMap<String, u64>,
+    /// The headers of the log segments, describing where each was recorded,
+    /// oldest first.  Logs from before headers were written have none.
+    pub log_headers: Vec<LogHeader>,
 }
 
+/// The width of the buckets of [`Stats::interval_histogram`].
+pub const INTERVAL_BUCKET_MS: u64 = 10;
+
+/// Intervals longer than this are pauses rather than typing and aren't
+/// counted.
+pub const MAX_INTERVAL_MS: u64 = 2000;
+
+/// How many characters [`Stats::rolling_wpm`] measures the speed over.
+pub const WPM_WINDOW_CHARS: u64 = 100;
+
+/// The number of consecutive digits that counts as entering a number rather
+/// than typing a digit in passing.
+pub const MIN_NUMERIC_BURST: u64 = 3;
+
+/// Keys held down together are a chord if the key pressed last is released
+/// first, like the C of Ctrl-C, and rollover if one of the keys pressed
+/// before it is, like the T of rolling `th`.  With timestamps, they also
+/// have to be down together for this long to be a chord, which is shorter
+/// than any press on purpose and only leaves out keys brushed on the way.
+pub const MIN_CHORD_OVERLAP_MS: u64 = 15;
+
 impl Stats {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             total_log_lines: 0,
             char_counts: HashMap::new(),
             consecutive_char_counts: HashMap::new(),
+            shifted_char_counts: HashMap::new(),
             individual_key_counts: HashMap::new(),
-            consectutive_key_counts: HashMap::new(),
+            consecutive_key_counts: HashMap::new(),
+            smoothed_key_counts: None,
+            trigram_char_counts: HashMap::new(),
+            trigram_key_counts: HashMap::new(),
             simultaneous_key_counts: HashMap::new(),
+            rollover_key_counts: HashMap::new(),
+            word_counts: HashMap::new(),
+            error_key_counts: HashMap::new(),
+            confusion_counts: HashMap::new(),
+            correction_lengths: HashMap::new(),
+            corrected_char_counts: HashMap::new(),
+            correction_bigram_counts: HashMap::new(),
+            numeric_burst_lengths: HashMap::new(),
+            interval_histogram: HashMap::new(),
+            latencies: LatencyStats::default(),
+            rolling_wpm: vec![],
+            ignored_key_counts: HashMap::new(),
+            log_headers: vec![],
+        }
+    }
+
+    /// The fraction of presses of `code` that were backspaced right away.
+    pub fn error_rate(&self, code: Keycode) -> f64 {
+        let presses = self.individual_key_counts.get(&code).copied().unwrap_or(0);
+        if presses == 0 {
+            return 0.0;
+        }
+        self.error_key_counts.get(&code).copied().unwrap_or(0) as f64 / presses as f64
+    }
+
+    /// The number of runs of backspaces per character typed.
+    pub fn correction_rate(&self) -> f64 {
+        let chars: u64 = self.char_counts.values().sum();
+        if chars == 0 {
+            return 0.0;
+        }
+        self.correction_lengths.values().sum::<u64>() as f64 / chars as f64
+    }
+
+    /// How much every pair of consecutive keys weighs in scores: the
+    /// smoothed counts if the stats were smoothed, otherwise the counts.
+    pub fn key_bigram_weights(&self) -> impl Iterator<Item = ((Keycode, Keycode), f64)> + '_ {
+        let smoothed = self.smoothed_key_counts.iter().flatten();
+        let counts = self
+            .consecutive_key_counts
+            .iter()
+            .filter(|_| self.smoothed_key_counts.is_none());
+        smoothed
+            .map(|(pair, weight)| (*pair, *weight))
+            .chain(counts.map(|(pair, count)| (*pair, *count as f64)))
+    }
+
+    /// The weight of `pair` in [`Stats::key_bigram_weights`].
+    pub fn key_bigram_weight(&self, pair: (Keycode, Keycode)) -> f64 {
+        match &self.smoothed_key_counts {
+            Some(smoothed) => smoothed.get(&pair).copied().unwrap_or(0.0),
+            None => self.consecutive_key_counts.get(&pair).copied().unwrap_or(0) as f64,
+        }
+    }
+
+    /// Adds the counts of `other`, e.g. from a log of another machine.
+    /// Smoothed counts are dropped, they have to be smoothed again.
+    pub fn merge(&mut self, other: Stats) {
+        self.smoothed_key_counts = None;
+        self.total_log_lines += other.total_log_lines;
+        merge_counts(&mut self.char_counts, other.char_counts);
+        merge_counts(
+            &mut self.consecutive_char_counts,
+            other.consecutive_char_counts,
+        );
+        merge_counts(&mut self.shifted_char_counts, other.shifted_char_counts);
+        merge_counts(&mut self.individual_key_counts, other.individual_key_counts);
+        merge_counts(
+            &mut self.consecutive_key_counts,
+            other.consecutive_key_counts,
+        );
+        merge_counts(&mut self.trigram_char_counts, other.trigram_char_counts);
+        merge_counts(&mut self.trigram_key_counts, other.trigram_key_counts);
+        merge_counts(
+            &mut self.simultaneous_key_counts,
+            other.simultaneous_key_counts,
+        );
+        merge_counts(&mut self.rollover_key_counts, other.rollover_key_counts);
+        merge_counts(&mut self.word_counts, other.word_counts);
+        merge_counts(&mut self.error_key_counts, other.error_key_counts);
+        merge_counts(&mut self.confusion_counts, other.confusion_counts);
+        merge_counts(&mut self.correction_lengths, other.correction_lengths);
+        merge_counts(&mut self.corrected_char_counts, other.corrected_char_counts);
+        merge_counts(
+            &mut self.correction_bigram_counts,
+            other.correction_bigram_counts,
+        );
+        merge_counts(&mut self.numeric_burst_lengths, other.numeric_burst_lengths);
+        merge_counts(&mut self.interval_histogram, other.interval_histogram);
+        self.latencies.merge(other.latencies);
+        self.rolling_wpm.extend(other.rolling_wpm);
+        merge_counts(&mut self.ignored_key_counts, other.ignored_key_counts);
+        self.log_headers.extend(other.log_headers);
+    }
+
+    /// Writes the stats as JSON so they can be reused without processing the
+    /// log again.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Keys of the physical keyboard that were never pressed in the log.
+    /// Their placement doesn't change the score, so the optimizer puts
+    /// them anywhere.
+    pub fn unseen_keys(&self, config: &KeymapConfig) -> Vec<Keycode> {
+        config
+            .keys
+            .keys()
+            .iter()
+            .map(|key| key.code)
+            .filter(|code| !self.individual_key_counts.contains_key(code))
+            .collect()
+    }
+
+    /// Pretends every unseen key was pressed `prior_count` times so it
+    /// still gets a reasonable spot, except for the `dont_care` keys whose
+    /// placement really doesn't matter.  Returns the keys that got the
+    /// prior.
+    pub fn impute_unseen_keys(
+        &mut self,
+        config: &KeymapConfig,
+        prior_count: u64,
+        dont_care: &[Keycode],
+    ) -> Vec<Keycode> {
+        let imputed: Vec<Keycode> = self
+            .unseen_keys(config)
+            .into_iter()
+            .filter(|code| !dont_care.contains(code))
+            .collect();
+        if prior_count > 0 {
+            for code in &imputed {
+                self.individual_key_counts.insert(*code, prior_count);
+            }
+        }
+        imputed
+    }
+
+    /// Recounts the presses and bigrams of the keys of `config` that type
+    /// characters from the characters typed, so layouts are scored by what
+    /// was typed rather than by the keys that typed it on the system layout
+    /// of the log.  A character counts for the key that types it, shifted
+    /// or not, under the keycode layouts know that key by.  Keys that
+    /// don't type characters, like Backspace, keep their counts, and so do
+    /// stats without character counts.
+    pub fn count_keys_by_char(&mut self, config: &KeymapConfig) {
+        let mut by_char: HashMap<char, Keycode> = HashMap::new();
+        for key in Layout::from_keymap(config).keys() {
+            match *key {
+                Key::Normal { normal, shifted } => {
+                    by_char.insert(normal, key.keycode(false));
+                    by_char.entry(shifted).or_insert(key.keycode(false));
+                }
+                Key::Space => {
+                    by_char.insert(' ', Keycode::Space);
+                }
+                _ => {}
+            }
+        }
+        let char_codes: HashSet<Keycode> = by_char.values().copied().collect();
+
+        if !self.char_counts.is_empty() {
+            self.individual_key_counts
+                .retain(|code, _| !char_codes.contains(code));
+            for (c, count) in &self.char_counts {
+                if let Some(code) = by_char.get(c) {
+                    *self.individual_key_counts.entry(*code).or_insert(0) += count;
+                }
+            }
+        }
+        if !self.consecutive_char_counts.is_empty() {
+            self.consecutive_key_counts
+                .retain(|(a, b), _| !char_codes.contains(a) || !char_codes.contains(b));
+            for ((c1, c2), count) in &self.consecutive_char_counts {
+                if let (Some(a), Some(b)) = (by_char.get(c1), by_char.get(c2)) {
+                    *self.consecutive_key_counts.entry((*a, *b)).or_insert(0) += count;
+                }
+            }
+        }
+    }
+
+    #[deprecated(
+        since = "0.1.0",
+        note = "use the `consecutive_key_counts` field instead"
+    )]
+    pub fn consectutive_key_counts(&self) -> &HashMap<(Keycode, Keycode), u64> {
+        &self.consecutive_key_counts
+    }
+}
+
+fn merge_counts<K: Eq + Hash>(into: &mut HashMap<K, u64>, from: HashMap<K, u64>) {
+    for (key, count) in from {
+        *into.entry(key).or_insert(0) += count;
+    }
+}
+
+/// How [`prepare`] gets stats ready for scoring, besides the scoring
+/// settings.
+#[derive(Debug, Default, Clone)]
+pub struct PrepareOptions {
+    /// Latencies measured by `trial --typing-test`, to weight consecutive
+    /// keys by how slow they were to type.
+    pub latencies: Option<LatencyStats>,
+    /// Weight consecutive keys by how slow they were to type according to
+    /// the timestamps in the log.
+    pub weight_by_timing: bool,
+    /// Presses assumed for keys that never appear, by default the count of
+    /// the least pressed key.
+    pub prior_count: Option<u64>,
+    /// Keys that never appear and whose placement doesn't matter, so no
+    /// presses are assumed for them.
+    pub dont_care: Vec<Keycode>,
+}
+
+/// What [`prepare`] assumed about keys that never appear.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prepared {
+    /// The keys assumed to be pressed `prior_count` times each.
+    pub imputed: Vec<Keycode>,
+    pub prior_count: u64,
+}
+
+/// Gets `stats` ready to score layouts of `keymap_config` with `scoring`,
+/// the same way in every tool.  An [`EditorProfile::Auto`] is detected
+/// from the stats as they were read and replaced in `scoring`.  Then the
+/// keys are counted by the characters they typed, consecutive keys are
+/// weighted by their latencies, presses are assumed for keys that never
+/// appear, consecutive keys are smoothed, and the editor's command keys
+/// weighted, in that order.
+///
+/// [`EditorProfile::Auto`]: crate::editor::EditorProfile::Auto
+pub fn prepare(
+    stats: &mut Stats,
+    scoring: &mut ScoringConfig,
+    keymap_config: &KeymapConfig,
+    options: &PrepareOptions,
+) -> Prepared {
+    scoring.editor_profile = scoring.editor_profile.resolve(stats);
+    stats.count_keys_by_char(keymap_config);
+    if let Some(latencies) = &options.latencies {
+        latencies.weight_stats(stats);
+    }
+    if options.weight_by_timing {
+        let latencies = std::mem::take(&mut stats.latencies);
+        latencies.weight_stats(stats);
+        stats.latencies = latencies;
+    }
+    let prior_count = options.prior_count.unwrap_or_else(|| {
+        stats
+            .individual_key_counts
+            .values()
+            .min()
+            .copied()
+            .unwrap_or(0)
+    });
+    let imputed = stats.impute_unseen_keys(keymap_config, prior_count, &options.dont_care);
+    scoring.smoothing.apply(stats, keymap_config);
+    scoring
+        .editor_profile
+        .weight_stats(stats, scoring.editor_weight);
+    Prepared {
+        imputed,
+        prior_count,
+    }
+}
+
+/// How key logs are read.
+#[derive(Debug, Default, Clone)]
+pub struct LogOptions {
+    /// Leave out sessions tagged with any of these, e.g. gaming sessions
+    /// that would drown out everything else with WASD presses.
+    pub exclude_tagged: Vec<String>,
+    /// Keys the OS remapped while logging, e.g. CapsLock acting as
+    /// LControl.  Their events are counted as the key they're remapped to.
+    pub remaps: HashMap<Keycode, Keycode>,
+    /// Keys that [`Keycode`] has no name for, like media keys, by their
+    /// name in the log, and the key to count them as.  Other unknown keys
+    /// are skipped and counted in [`Stats::ignored_key_counts`].
+    pub aliases: HashMap<String, Keycode>,
+    /// Only count the log segments typed on this keyboard, as named with
+    /// `keylogger --device`.
+    pub device: Option<String>,
+    /// What the keys typed on the system layout the log was recorded on.
+    pub chars: CharTable,
+    /// Only count events from this many milliseconds since the Unix epoch
+    /// on, like the start of a [session](crate::sessions).
+    pub since: Option<u64>,
+    /// Only count events from before this many milliseconds since the Unix
+    /// epoch.  Events without a timestamp are left out if either is set.
+    pub until: Option<u64>,
+    /// Leave modifiers out of the key bigrams and trigrams, as if they
+    /// weren't pressed, so `Ab` counts as the bigram `A B` rather than
+    /// `LShift A`, `LShift B` and `A B`.
+    pub transparent_modifiers: bool,
+}
+
+/// Reads every path with [`read_stats`] and merges the results, reading
+/// logs with `options`.  Saved stats can't be filtered by device or
+/// remapped anymore and are used as they are.
+pub fn read_merged_stats<S: AsRef<str>>(paths: &[S], options: &LogOptions) -> Result<Stats> {
+    let mut stats = Stats::new();
+    for path in paths {
+        let path = path.as_ref();
+        if path.ends_with(".json") {
+            let saved = Stats::load(path).map_err(|error| Error::from(error).in_file(path))?;
+            stats.merge(saved);
+        } else {
+            stats.merge(process_log_with(path, options)?);
+        }
+    }
+    Ok(stats)
+}
+
+/// Like [`read_merged_stats`], but keeps the stats of every device apart
+/// with [`process_log_by_device`].  Saved stats don't know their device and
+/// count under `None`.
+pub fn read_stats_by_device<S: AsRef<str>>(
+    paths: &[S],
+    options: &LogOptions,
+) -> Result<Vec<(Option<String>, Stats)>> {
+    let mut devices: Vec<(Option<String>, Stats)> = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        let read = if path.ends_with(".json") {
+            let saved = Stats::load(path).map_err(|error| Error::from(error).in_file(path))?;
+            vec![(None, saved)]
+        } else {
+            process_log_by_device(path, options)?
+        };
+        for (device, stats) in read {
+            match devices.iter_mut().find(|(known, _)| *known == device) {
+                Some((_, known)) => known.merge(stats),
+                None => devices.push((device, stats)),
+            }
         }
     }
+    Ok(devices)
+}
+
+/// Loads stats saved with [`Stats::save`] if `path` ends in `.json`, and
+/// processes it as a key log otherwise.
+pub fn read_stats(path: &str) -> Result<Stats> {
+    read_merged_stats(&[path], &LogOptions::default())
 }
 
-pub fn process_log(path: &str) -> Stats {
+/// Reads a log written by the `keylogger` binary and aggregates it into [`Stats`].
+pub fn process_log(path: &str) -> Result<Stats> {
+    process_log_with(path, &LogOptions::default())
+}
+
+/// Like [`process_log`], but leaves out tagged sessions and the segments of
+/// other devices, and applies the remaps of `options`.
+///
+/// Fails on the first line that isn't a valid event or session marker,
+/// reporting the path and line number.  Events of unknown keys aren't
+/// errors, they're skipped and counted.
+pub fn process_log_with(path: &str, options: &LogOptions) -> Result<Stats> {
     let mut stats = Stats::new();
-    let mut key_processor = KeyProcessor::new();
+    for (device, device_stats) in process_log_by_device(path, options)? {
+        if options.device.is_none() || device == options.device {
+            stats.merge(device_stats);
+        }
+    }
+    Ok(stats)
+}
+
+/// Like [`process_log_with`], but keeps the stats of every device apart, in
+/// the order the devices first appear.  Segments without a device, like
+/// those of logs from before devices were named, are under `None`.
+pub fn process_log_by_device(
+    path: &str,
+    options: &LogOptions,
+) -> Result<Vec<(Option<String>, Stats)>> {
+    let mut processor = LogProcessor::new(options);
+    for line in read_log(path).map_err(|error| error.in_file(path))? {
+        processor.push_line(line.map_err(|error| error.in_file(path))?);
+    }
+    Ok(processor.finish())
+}
+
+/// Aggregates the lines of a key log into [`Stats`] for every device, one
+/// line at a time, like [`KeyProcessor`] does for events.  Leaves out
+/// tagged sessions and applies the remaps of the options.
+pub struct LogProcessor<'a> {
+    options: &'a LogOptions,
+    /// Every device has its own processor, so keys typed on one don't
+    /// form bigrams with keys typed on another.  The stats only hold what
+    /// the processor doesn't count: lines, ignored keys, and headers.
+    devices: Vec<(Option<String>, KeyProcessor, Stats)>,
+    current: usize,
+    active_tags: HashSet<String>,
+}
+
+impl<'a> LogProcessor<'a> {
+    pub fn new(options: &'a LogOptions) -> Self {
+        let mut processor = Self {
+            options,
+            devices: vec![],
+            current: 0,
+            active_tags: HashSet::new(),
+        };
+        processor
+            .devices
+            .push((None, processor.key_processor(), Stats::new()));
+        processor
+    }
+
+    fn key_processor(&self) -> KeyProcessor {
+        KeyProcessor::with_remaps(self.options.remaps.clone())
+            .with_chars(self.options.chars.clone())
+            .with_transparent_modifiers(self.options.transparent_modifiers)
+    }
+
+    pub fn push_line(&mut self, line: LogLine) {
+        let options = self.options;
+        let switch_to = match &line {
+            LogLine::Header(header) => Some(header.device.clone()),
+            LogLine::Device(device) => Some(Some(device.clone())),
+            _ => None,
+        };
+        if let Some(switch_to) = switch_to {
+            self.current = match self
+                .devices
+                .iter()
+                .position(|(device, ..)| *device == switch_to)
+            {
+                Some(i) => i,
+                None => {
+                    let key_processor = self.key_processor();
+                    self.devices.push((switch_to, key_processor, Stats::new()));
+                    self.devices.len() - 1
+                }
+            };
+        }
+        let (_, key_processor, device_stats) = &mut self.devices[self.current];
+        // Session markers and excluded events count as lines too
+        device_stats.total_log_lines += 1;
+        let excluded = self
+            .active_tags
+            .iter()
+            .any(|tag| options.exclude_tagged.contains(tag));
+        let (key_code, press, timestamp) = match line {
+            LogLine::Event(key_code, press, timestamp) => (key_code, press, timestamp),
+            LogLine::UnknownKey(name, press, timestamp) => match options.aliases.get(&name) {
+                Some(&key_code) => (key_code, press, timestamp),
+                None => {
+                    if press && !excluded {
+                        *device_stats.ignored_key_counts.entry(name).or_insert(0) += 1;
+                    }
+                    return;
+                }
+            },
+            LogLine::Tag(tag) => {
+                self.active_tags.insert(tag);
+                return;
+            }
+            LogLine::Untag(tag) => {
+                self.active_tags.remove(&tag);
+                return;
+            }
+            // A new run of the keylogger starts outside of any session
+            LogLine::Header(header) => {
+                device_stats.log_headers.push(header);
+                self.active_tags.clear();
+                return;
+            }
+            LogLine::Device(_) => return,
+        };
+        let in_period = match timestamp {
+            Some(timestamp) => {
+                options.since.is_none_or(|since| timestamp >= since)
+                    && options.until.is_none_or(|until| timestamp < until)
+            }
+            None => options.since.is_none() && options.until.is_none(),
+        };
+        if !excluded && in_period {
+            match timestamp {
+                Some(timestamp) => key_processor.push_timed_event(key_code, press, timestamp),
+                None => key_processor.push_event(key_code, press),
+            }
+        }
+    }
+
+    /// The stats of the lines so far, merged for the device of the
+    /// options or for all of them, like [`process_log_with`].  Processing
+    /// continues unaffected.
+    pub fn snapshot(&self) -> Stats {
+        let mut stats = Stats::new();
+        for (device, key_processor, counted) in &self.devices {
+            if self.options.device.is_none() || *device == self.options.device {
+                stats.merge(Self::device_stats(
+                    key_processor.snapshot(),
+                    counted.clone(),
+                ));
+            }
+        }
+        stats
+    }
 
-    let reader = BufReader::new(File::open(path).unwrap());
+    /// The stats of every device that has any lines, once there are no
+    /// more.
+    pub fn finish(self) -> Vec<(Option<String>, Stats)> {
+        self.devices
+            .into_iter()
+            .filter(|(_, _, counted)| counted.total_log_lines > 0)
+            .map(|(device, key_processor, counted)| {
+                (device, Self::device_stats(key_processor.finish(), counted))
+            })
+            .collect()
+    }
 
-    for line in reader.lines() {
-        stats.total_log_lines += 1;
-        let line = line.unwrap();
-        let (key_code, press) = line.split_once(" ").unwrap();
-        let key_code = Keycode::from_str(key_code).unwrap();
-        let press = press == "1";
-        key_processor.process_key(key_code, press, &mut stats);
+    fn device_stats(mut stats: Stats, counted: Stats) -> Stats {
+        stats.total_log_lines = counted.total_log_lines;
+        stats.ignored_key_counts = counted.ignored_key_counts;
+        stats.log_headers = counted.log_headers;
+        stats
+    }
+}
+
+/// Aggregates the key presses it would take to type out the text file at
+/// `path` on the system layout of `chars`, for when there's no key log yet.
+/// Characters that can't be typed that way are skipped.
+pub fn process_text(path: &str, chars: &CharTable) -> Result<Stats> {
+    let mut key_processor = KeyProcessor::new().with_chars(chars.clone());
+    let text = std::fs::read_to_string(path).map_err(|error| Error::from(error).in_file(path))?;
+    for (key_code, press) in typing_events(&text, chars) {
+        key_processor.push_event(key_code, press);
+    }
+    Ok(key_processor.finish())
+}
+
+/// The key presses and releases that type out `text` on the system layout
+/// of `chars`, holding shift where needed and going through dead keys for
+/// characters like `é`.  Characters that can't be typed that way are
+/// skipped.
+pub fn typing_events(text: &str, chars: &CharTable) -> Vec<(Keycode, bool)> {
+    let mut events = vec![];
+    for c in text.chars() {
+        let mut presses = match c {
+            '\n' => vec![(Keycode::Enter, false)],
+            '\t' => vec![(Keycode::Tab, false)],
+            c => match chars.key_events(c) {
+                Some(presses) => presses,
+                None => continue,
+            },
+        };
+        // A dead key on its own is typed by following it with a space
+        if chars.is_dead(c) {
+            presses.push((Keycode::Space, false));
+        }
+        for (key_code, shift) in presses {
+            if shift {
+                events.push((Keycode::LShift, true));
+            }
+            events.extend([(key_code, true), (key_code, false)]);
+            if shift {
+                events.push((Keycode::LShift, false));
+            }
+        }
+    }
+    events
+}
+
+/// Every key that [`translate_key_to_char`] knows a character for.
+pub(crate) const CHAR_KEYCODES: [Keycode; 48] = [
+    Keycode::A,
+    Keycode::B,
+    Keycode::C,
+    Keycode::D,
+    Keycode::E,
+    Keycode::F,
+    Keycode::G,
+    Keycode::H,
+    Keycode::I,
+    Keycode::J,
+    Keycode::K,
+    Keycode::L,
+    Keycode::M,
+    Keycode::N,
+    Keycode::O,
+    Keycode::P,
+    Keycode::Q,
+    Keycode::R,
+    Keycode::S,
+    Keycode::T,
+    Keycode::U,
+    Keycode::V,
+    Keycode::W,
+    Keycode::X,
+    Keycode::Y,
+    Keycode::Z,
+    Keycode::Key0,
+    Keycode::Key1,
+    Keycode::Key2,
+    Keycode::Key3,
+    Keycode::Key4,
+    Keycode::Key5,
+    Keycode::Key6,
+    Keycode::Key7,
+    Keycode::Key8,
+    Keycode::Key9,
+    Keycode::Space,
+    Keycode::Comma,
+    Keycode::Dot,
+    Keycode::Slash,
+    Keycode::Semicolon,
+    Keycode::Apostrophe,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::BackSlash,
+    Keycode::Minus,
+    Keycode::Equal,
+    Keycode::Grave,
+];
+
+/// The key and whether shift is needed to type `c`, the inverse of
+/// [`translate_key_to_char`].
+pub(crate) fn char_to_key_event(c: char) -> Option<(Keycode, bool)> {
+    for key_code in CHAR_KEYCODES {
+        for shift in [false, true] {
+            if translate_key_to_char(&key_code, shift) == Some(c) {
+                return Some((key_code, shift));
+            }
+        }
     }
+    None
+}
 
-    stats
+/// Aggregates key events into [`Stats`] as they happen, for tools that see
+/// events live instead of reading them from a log.
+#[derive(Default)]
+pub struct KeyProcessor {
+    state: KeyState,
+    stats: Stats,
+    remaps: HashMap<Keycode, Keycode>,
+    chars: CharTable,
 }
 
-struct KeyProcessor {
+impl KeyProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts events of the keys in `remaps` as the key they're remapped
+    /// to, so e.g. CapsLock acting as LControl makes chords with it Ctrl
+    /// shortcuts.
+    pub fn with_remaps(remaps: HashMap<Keycode, Keycode>) -> Self {
+        Self {
+            remaps,
+            ..Self::default()
+        }
+    }
+
+    /// Counts the characters the keys type on the system layout of
+    /// `chars` instead of a US layout.
+    pub fn with_chars(self, chars: CharTable) -> Self {
+        Self { chars, ..self }
+    }
+
+    /// Leaves modifiers out of the key bigrams and trigrams if
+    /// `transparent`, see [`LogOptions::transparent_modifiers`].
+    pub fn with_transparent_modifiers(mut self, transparent: bool) -> Self {
+        self.state.transparent_modifiers = transparent;
+        self
+    }
+
+    fn remap(&self, key_code: Keycode) -> Keycode {
+        self.remaps.get(&key_code).copied().unwrap_or(key_code)
+    }
+
+    /// Counts a key press or release.
+    pub fn push_event(&mut self, key_code: Keycode, press: bool) {
+        self.push(key_code, press, None);
+    }
+
+    /// Like [`push_event`](Self::push_event), but also measures the time
+    /// since the previous press.  `timestamp` is in milliseconds, e.g.
+    /// since the Unix epoch like in the log.
+    pub fn push_timed_event(&mut self, key_code: Keycode, press: bool, timestamp: u64) {
+        let effective = self.remap(key_code);
+        self.state
+            .process_timing(effective, press, timestamp, &mut self.stats);
+        self.push(key_code, press, Some(timestamp));
+    }
+
+    fn push(&mut self, key_code: Keycode, press: bool, timestamp: Option<u64>) {
+        let key_code = self.remap(key_code);
+        self.stats.total_log_lines += 1;
+        self.state
+            .process_key(key_code, press, timestamp, &self.chars, &mut self.stats);
+    }
+
+    /// The stats of the events so far, counting the word or number being
+    /// typed as if it had ended.  Processing continues unaffected.
+    pub fn snapshot(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        self.state.clone().finish(&mut stats);
+        stats
+    }
+
+    /// The stats of all events, once there are no more.
+    pub fn finish(mut self) -> Stats {
+        self.state.finish(&mut self.stats);
+        self.stats
+    }
+}
+
+/// What [`KeyProcessor`] remembers about the previous events.
+#[derive(Clone, Default)]
+struct KeyState {
     prev_keys: HashSet<Keycode>,
     keys: HashSet<Keycode>,
     prev_char: Option<char>,
+    prev_prev_char: Option<char>,
+    /// The last two key presses, oldest first.
+    prev_presses: (Option<Keycode>, Option<Keycode>),
+    word: String,
+    typed: Vec<Keycode>,
+    corrected: Option<Keycode>,
+    backspaces_in_a_row: u32,
+    numeric_run: u64,
+    last_timed_press: Option<(Keycode, u64)>,
+    /// A dead key waiting for the character it changes, and its key.
+    dead_key: Option<(Keycode, char)>,
+    /// The characters typed and the time spent typing them towards the
+    /// next [`Stats::rolling_wpm`].
+    wpm_window: (u64, f64),
+    /// The keys held down together since the last press, the key pressed
+    /// last, and when, until a release tells a chord from rollover.
+    held_together: Option<(Vec<Keycode>, Keycode, Option<u64>)>,
+    /// See [`LogOptions::transparent_modifiers`].
+    transparent_modifiers: bool,
 }
 
-impl KeyProcessor {
-    fn new() -> Self {
-        Self {
-            prev_keys: HashSet::new(),
-            keys: HashSet::new(),
-            prev_char: None,
+/// Keys that change what other keys do rather than doing something.
+const MODIFIERS: [Keycode; 8] = [
+    Keycode::LShift,
+    Keycode::RShift,
+    Keycode::LControl,
+    Keycode::RControl,
+    Keycode::LAlt,
+    Keycode::RAlt,
+    Keycode::LMeta,
+    Keycode::RMeta,
+];
+
+/// How many char keys are remembered for attributing backspaces.
+const TYPED_HISTORY: usize = 64;
+
+impl KeyState {
+    fn finish(&mut self, stats: &mut Stats) {
+        self.end_word(stats);
+        self.end_numeric_run(stats);
+        self.end_correction(stats);
+    }
+
+    fn end_correction(&mut self, stats: &mut Stats) {
+        let run = std::mem::take(&mut self.backspaces_in_a_row);
+        if run > 0 {
+            *stats.correction_lengths.entry(run as u64).or_insert(0) += 1;
+        }
+    }
+
+    fn end_numeric_run(&mut self, stats: &mut Stats) {
+        let run = std::mem::take(&mut self.numeric_run);
+        if run >= MIN_NUMERIC_BURST {
+            *stats.numeric_burst_lengths.entry(run).or_insert(0) += 1;
         }
     }
 
-    fn process_key(&mut self, key_code: Keycode, press: bool, stats: &mut Stats) {
-        {}
+    fn end_word(&mut self, stats: &mut Stats) {
+        if !self.word.is_empty() {
+            let word = std::mem::take(&mut self.word);
+            *stats.word_counts.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    fn process_timing(
+        &mut self,
+        key_code: Keycode,
+        press: bool,
+        timestamp: u64,
+        stats: &mut Stats,
+    ) {
+        if !press {
+            return;
+        }
+        if let Some((last_code, last_timestamp)) = self.last_timed_press {
+            let interval = timestamp.saturating_sub(last_timestamp);
+            if interval <= MAX_INTERVAL_MS {
+                self.wpm_window.1 += interval as f64;
+                let bucket = interval / INTERVAL_BUCKET_MS * INTERVAL_BUCKET_MS;
+                *stats.interval_histogram.entry(bucket).or_insert(0) += 1;
+                let ms = interval as f64;
+                stats.latencies.keys.entry(key_code).or_default().add(ms);
+                stats
+                    .latencies
+                    .bigrams
+                    .entry((last_code, key_code))
+                    .or_default()
+                    .add(ms);
+            }
+        }
+        self.last_timed_press = Some((key_code, timestamp));
+    }
+
+    fn process_key(
+        &mut self,
+        key_code: Keycode,
+        press: bool,
+        timestamp: Option<u64>,
+        chars: &CharTable,
+        stats: &mut Stats,
+    ) {
         if press {
             self.keys.insert(key_code);
             let count = stats.individual_key_counts.entry(key_code).or_insert(0);
             *count += 1;
-            let prev_keys = std::mem::replace(&mut self.prev_keys, self.keys.clone());
-            for prev in prev_keys {
-                let count = stats
-                    .consectutive_key_counts
-                    .entry((prev, key_code))
-                    .or_insert(0);
-                *count += 1;
-            }
-            let shift_held =
-                self.keys.contains(&Keycode::LShift) || self.keys.contains(&Keycode::RShift);
-            if let Some(c) = translate_key_to_char(&key_code, shift_held) {
-                let count = stats.char_counts.entry(c).or_insert(0);
-                *count += 1;
-
-                if let Some(prev_char) = self.prev_char {
-                    let count = stats
-                        .consecutive_char_counts
-                        .entry((prev_char, c))
-                        .or_insert(0);
-                    *count += 1;
+            if !(self.transparent_modifiers && MODIFIERS.contains(&key_code)) {
+                self.count_key_ngrams(key_code, stats);
+            }
+            let shift = if self.keys.contains(&Keycode::LShift) {
+                Some(Hand::Left)
+            } else if self.keys.contains(&Keycode::RShift) {
+                Some(Hand::Right)
+            } else {
+                None
+            };
+            if let Some(c) = chars.char(key_code, shift.is_some()) {
+                if let Some(hand) = shift {
+                    *stats.shifted_char_counts.entry((c, hand)).or_insert(0) += 1;
                 }
-                self.prev_char = Some(c);
+                match self.dead_key.take() {
+                    Some((dead_code, dead)) => match chars.compose(dead, c) {
+                        Some(composed) => self.type_char(key_code, composed, stats),
+                        None => {
+                            // What doesn't combine with a dead key follows
+                            // it, except for a space which only ends it
+                            self.type_char(dead_code, dead, stats);
+                            if c != ' ' {
+                                self.type_char(key_code, c, stats);
+                            }
+                        }
+                    },
+                    None if chars.is_dead(c) => self.dead_key = Some((key_code, c)),
+                    None => self.type_char(key_code, c, stats),
+                }
+            } else if key_code == Keycode::Backspace && self.dead_key.take().is_some() {
+                // Backspace only cancelled the dead key
+            } else if key_code == Keycode::Backspace {
+                if self.backspaces_in_a_row == 0 {
+                    if let Some(c) = self.prev_char {
+                        *stats.corrected_char_counts.entry(c).or_insert(0) += 1;
+                    }
+                    if let [.., first, second] = self.typed[..] {
+                        *stats
+                            .correction_bigram_counts
+                            .entry((first, second))
+                            .or_insert(0) += 1;
+                    }
+                }
+                self.word.pop();
+
+                let deleted = self.typed.pop();
+                if let Some(deleted) = deleted {
+                    *stats.error_key_counts.entry(deleted).or_insert(0) += 1;
+                }
+                // Only a single backspace tells us which key replaced which
+                self.corrected = deleted.filter(|_| self.backspaces_in_a_row == 0);
+                self.backspaces_in_a_row += 1;
             }
         } else {
             self.keys.remove(&key_code);
         }
 
-        if self.keys.len() > 1 {
+        if press && self.keys.len() > 1 {
+            // A key pressed on top of others grows the keys held before it
+            // rather than ending them, so Ctrl-Shift on the way to
+            // Ctrl-Shift-T only counts as part of Ctrl-Shift-T
             let mut held: Vec<_> = self.keys.iter().cloned().collect();
             held.sort_by_key(|x| x.to_string());
-            let count = stats.simultaneous_key_counts.entry(held).or_insert(0);
+            self.held_together = Some((held, key_code, timestamp));
+        } else if !press {
+            self.release_held_together(key_code, timestamp, stats);
+        }
+    }
+
+    /// Counts the bigrams and the trigram that end with pressing
+    /// `key_code`.
+    fn count_key_ngrams(&mut self, key_code: Keycode, stats: &mut Stats) {
+        let mut held = self.keys.clone();
+        if self.transparent_modifiers {
+            held.retain(|code| !MODIFIERS.contains(code));
+        }
+        let prev_keys = std::mem::replace(&mut self.prev_keys, held);
+        for prev in prev_keys {
+            let count = stats
+                .consecutive_key_counts
+                .entry((prev, key_code))
+                .or_insert(0);
             *count += 1;
         }
+        if let (Some(first), Some(second)) = self.prev_presses {
+            *stats
+                .trigram_key_counts
+                .entry((first, second, key_code))
+                .or_insert(0) += 1;
+        }
+        self.prev_presses = (self.prev_presses.1, Some(key_code));
+    }
+
+    /// Counts the keys held down together as a chord or as rollover once
+    /// the first of them is released, as `released` at `timestamp`, see
+    /// [`MIN_CHORD_OVERLAP_MS`].
+    fn release_held_together(
+        &mut self,
+        released: Keycode,
+        timestamp: Option<u64>,
+        stats: &mut Stats,
+    ) {
+        let Some((held, last, since)) = self
+            .held_together
+            .take_if(|(held, _, _)| held.contains(&released))
+        else {
+            return;
+        };
+        let overlap = match (since, timestamp) {
+            (Some(since), Some(until)) => until.saturating_sub(since),
+            _ => MIN_CHORD_OVERLAP_MS,
+        };
+        let counts = if released == last && overlap >= MIN_CHORD_OVERLAP_MS {
+            &mut stats.simultaneous_key_counts
+        } else {
+            &mut stats.rollover_key_counts
+        };
+        *counts.entry(held).or_insert(0) += 1;
+    }
+
+    /// Counts `c` typed with `key_code`.
+    fn type_char(&mut self, key_code: Keycode, c: char, stats: &mut Stats) {
+        let count = stats.char_counts.entry(c).or_insert(0);
+        *count += 1;
+
+        if let Some(prev_char) = self.prev_char {
+            let count = stats
+                .consecutive_char_counts
+                .entry((prev_char, c))
+                .or_insert(0);
+            *count += 1;
+
+            if let Some(prev_prev_char) = self.prev_prev_char {
+                *stats
+                    .trigram_char_counts
+                    .entry((prev_prev_char, prev_char, c))
+                    .or_insert(0) += 1;
+            }
+        }
+        self.prev_prev_char = self.prev_char;
+        self.prev_char = Some(c);
+
+        if c.is_alphabetic() {
+            self.word.extend(c.to_lowercase());
+        } else {
+            self.end_word(stats);
+        }
+
+        if c.is_ascii_digit() {
+            self.numeric_run += 1;
+        } else if !(self.numeric_run > 0 && matches!(c, '.' | ',')) {
+            // Decimal points and thousands separators don't end a number
+            self.end_numeric_run(stats);
+        }
+
+        if let Some(wrong) = self.corrected.take() {
+            if wrong != key_code {
+                *stats.confusion_counts.entry((wrong, key_code)).or_insert(0) += 1;
+            }
+        }
+        self.end_correction(stats);
+        self.wpm_window.0 += 1;
+        if self.wpm_window.0 == WPM_WINDOW_CHARS {
+            let (chars, ms) = std::mem::take(&mut self.wpm_window);
+            if ms > 0.0 {
+                stats.rolling_wpm.push(chars as f64 / 5.0 / (ms / 60_000.0));
+            }
+        }
+        if self.typed.len() == TYPED_HISTORY {
+            self.typed.remove(0);
+        }
+        self.typed.push(key_code);
     }
 }
 
-fn translate_key_to_char(key: &Keycode, shift_held: bool) -> Option<char> {
+/// The character a key produces on a US QWERTY system layout.
+pub fn translate_key_to_char(key: &Keycode, shift_held: bool) -> Option<char> {
     let c = match (key, shift_held) {
         (Keycode::A, true) => 'A',
         (Keycode::B, true) => 'B',
@@ -205,3 +1229,689 @@ fn translate_key_to_char(key: &Keycode, shift_held: bool) -> Option<char> {
     };
     Some(c)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{editor::EditorProfile, scoring::Smoothing};
+
+    fn type_keys(stats: &mut Stats, processor: &mut KeyState, keys: &[Keycode]) {
+        for &key in keys {
+            let chars = CharTable::default();
+            processor.process_key(key, true, None, &chars, stats);
+            processor.process_key(key, false, None, &chars, stats);
+        }
+    }
+
+    #[test]
+    fn test_corrections() {
+        let mut stats = Stats::new();
+        let mut processor = KeyState::default();
+        type_keys(
+            &mut stats,
+            &mut processor,
+            &[Keycode::T, Keycode::R, Keycode::Backspace, Keycode::E],
+        );
+        processor.finish(&mut stats);
+        assert_eq!(stats.error_key_counts.get(&Keycode::R), Some(&1));
+        assert_eq!(
+            stats.confusion_counts.get(&(Keycode::R, Keycode::E)),
+            Some(&1)
+        );
+        assert_eq!(stats.word_counts.get("te"), Some(&1));
+
+        // A run of two backspaces at the end of the log
+        type_keys(
+            &mut stats,
+            &mut processor,
+            &[
+                Keycode::A,
+                Keycode::S,
+                Keycode::Backspace,
+                Keycode::Backspace,
+            ],
+        );
+        processor.finish(&mut stats);
+        assert_eq!(stats.correction_lengths.get(&1), Some(&1));
+        assert_eq!(stats.correction_lengths.get(&2), Some(&1));
+        assert_eq!(stats.corrected_char_counts.get(&'r'), Some(&1));
+        assert_eq!(stats.corrected_char_counts.get(&'s'), Some(&1));
+        assert_eq!(
+            stats
+                .correction_bigram_counts
+                .get(&(Keycode::T, Keycode::R)),
+            Some(&1)
+        );
+        assert_eq!(
+            stats
+                .correction_bigram_counts
+                .get(&(Keycode::A, Keycode::S)),
+            Some(&1)
+        );
+        assert_eq!(stats.correction_rate(), 2.0 / 5.0);
+    }
+
+    #[test]
+    fn test_backspace_analysis() {
+        let chars = CharTable::default();
+        let mut processor = KeyProcessor::new();
+        let mut type_text = |text: &str, backspaces: usize| {
+            for (key, press) in typing_events(text, &chars) {
+                processor.push_event(key, press);
+            }
+            for _ in 0..backspaces {
+                processor.push_event(Keycode::Backspace, true);
+                processor.push_event(Keycode::Backspace, false);
+            }
+        };
+        type_text("teh", 2);
+        type_text("he the a", 1);
+        type_text("s", 0);
+        let stats = processor.finish();
+
+        assert_eq!(stats.correction_lengths, HashMap::from([(2, 1), (1, 1)]));
+        assert_eq!(stats.correction_rate(), 2.0 / 12.0);
+        // The character before a run of backspaces and the bigram ending
+        // with it are what got corrected
+        assert_eq!(
+            stats.corrected_char_counts,
+            HashMap::from([('h', 1), ('a', 1)])
+        );
+        assert_eq!(
+            stats.correction_bigram_counts,
+            HashMap::from([
+                ((Keycode::E, Keycode::H), 1),
+                ((Keycode::Space, Keycode::A), 1)
+            ])
+        );
+        assert_eq!(
+            stats.error_key_counts,
+            HashMap::from([(Keycode::H, 1), (Keycode::E, 1), (Keycode::A, 1)])
+        );
+        // Only a single backspace tells which key was typed instead
+        assert_eq!(
+            stats.confusion_counts,
+            HashMap::from([((Keycode::A, Keycode::S), 1)])
+        );
+        assert_eq!(
+            stats.word_counts,
+            HashMap::from([("the".to_string(), 2), ("s".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_count_keys_by_char() {
+        let config = crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        // Typed on a German layout, where the key of Y types z
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::Y, 5);
+        stats.individual_key_counts.insert(Keycode::Backspace, 2);
+        stats.char_counts.insert('z', 4);
+        stats.char_counts.insert('Z', 1);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::Y, Keycode::U), 3);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::U, Keycode::Backspace), 2);
+        stats.consecutive_char_counts.insert(('z', 'u'), 3);
+        stats.consecutive_char_counts.insert(('\n', 'u'), 1);
+
+        stats.count_keys_by_char(&config);
+        assert_eq!(stats.individual_key_counts.get(&Keycode::Z), Some(&5));
+        assert_eq!(stats.individual_key_counts.get(&Keycode::Y), None);
+        assert_eq!(
+            stats.individual_key_counts.get(&Keycode::Backspace),
+            Some(&2)
+        );
+        assert_eq!(
+            stats.consecutive_key_counts,
+            HashMap::from([
+                ((Keycode::Z, Keycode::U), 3),
+                ((Keycode::U, Keycode::Backspace), 2),
+            ])
+        );
+
+        // Without characters, the keys are all there is
+        let mut keys_only = Stats::new();
+        keys_only
+            .consecutive_key_counts
+            .insert((Keycode::Y, Keycode::U), 3);
+        keys_only.count_keys_by_char(&config);
+        assert_eq!(keys_only.consecutive_key_counts.len(), 1);
+    }
+
+    #[test]
+    fn test_prepare() {
+        let config = crate::format::parse_keymap_config(include_str!("../kinesis.layout")).unwrap();
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::Escape, 30);
+        stats.individual_key_counts.insert(Keycode::H, 3);
+        stats.char_counts.insert('h', 3);
+        stats.consecutive_char_counts.insert(('h', 'h'), 2);
+        let mut scoring = ScoringConfig {
+            editor_profile: EditorProfile::Auto,
+            editor_weight: 2.0,
+            smoothing: Smoothing::Laplace { alpha: 0.5 },
+            ..ScoringConfig::default()
+        };
+        let options = PrepareOptions {
+            dont_care: vec![Keycode::Tab],
+            ..PrepareOptions::default()
+        };
+        let prepared = prepare(&mut stats, &mut scoring, &config, &options);
+
+        assert_eq!(scoring.editor_profile, EditorProfile::Vim);
+        assert_eq!(prepared.prior_count, 3);
+        assert!(prepared.imputed.contains(&Keycode::A));
+        assert!(!prepared.imputed.contains(&Keycode::H));
+        assert!(!prepared.imputed.contains(&Keycode::Tab));
+        // H is a Vim command key, counted by the h typed
+        assert_eq!(stats.individual_key_counts[&Keycode::H], 6);
+        assert_eq!(stats.individual_key_counts[&Keycode::A], 3);
+        assert_eq!(stats.key_bigram_weight((Keycode::H, Keycode::H)), 2.5);
+    }
+
+    #[test]
+    fn test_dead_keys() {
+        let chars = CharTable::parse("[dead]\n\"`\" = { e = \"è\", a = \"à\" }").unwrap();
+        let mut processor = KeyProcessor::new().with_chars(chars);
+        let keys = [
+            // Composes
+            Keycode::Grave,
+            Keycode::E,
+            // Doesn't combine, so both are typed
+            Keycode::Grave,
+            Keycode::X,
+            // A space types the dead key alone
+            Keycode::Grave,
+            Keycode::Space,
+            // Backspace cancels the dead key without correcting anything
+            Keycode::Grave,
+            Keycode::Backspace,
+            Keycode::A,
+            Keycode::Grave,
+            Keycode::A,
+        ];
+        for key in keys {
+            processor.push_event(key, true);
+            processor.push_event(key, false);
+        }
+        let stats = processor.finish();
+
+        let mut char_counts: Vec<(char, u64)> = stats
+            .char_counts
+            .iter()
+            .map(|(&c, &count)| (c, count))
+            .collect();
+        char_counts.sort();
+        assert_eq!(
+            char_counts,
+            [('`', 2), ('a', 1), ('x', 1), ('à', 1), ('è', 1)]
+        );
+        let mut bigrams: Vec<(char, char)> =
+            stats.consecutive_char_counts.keys().copied().collect();
+        bigrams.sort();
+        assert_eq!(
+            bigrams,
+            [('`', 'a'), ('`', 'x'), ('a', 'à'), ('x', '`'), ('è', '`')]
+        );
+        // Every press still counts for its key
+        assert_eq!(stats.individual_key_counts[&Keycode::Grave], 5);
+        assert!(stats.corrected_char_counts.is_empty());
+        assert!(stats.correction_lengths.is_empty());
+    }
+
+    #[test]
+    fn test_impute_unseen_keys() {
+        let config =
+            crate::format::parse_keymap_config(include_str!("../tests/data/tiny.layout")).unwrap();
+        let unseen = [Keycode::Key7, Keycode::Key8, Keycode::Key9];
+        let mut stats = Stats::new();
+        for key in config.keys.keys() {
+            if !unseen.contains(&key.code) {
+                stats.individual_key_counts.insert(key.code, 10);
+            }
+        }
+        assert_eq!(stats.unseen_keys(&config), unseen);
+
+        // Without a prior, the keys are only listed
+        let mut without_prior = stats.clone();
+        assert_eq!(
+            without_prior.impute_unseen_keys(&config, 0, &[Keycode::Key8]),
+            [Keycode::Key7, Keycode::Key9]
+        );
+        assert_eq!(
+            without_prior.individual_key_counts,
+            stats.individual_key_counts
+        );
+
+        let seen = stats.individual_key_counts.clone();
+        let imputed = stats.impute_unseen_keys(&config, 4, &[Keycode::Key8]);
+        assert_eq!(imputed, [Keycode::Key7, Keycode::Key9]);
+        assert_eq!(stats.individual_key_counts[&Keycode::Key7], 4);
+        assert_eq!(stats.individual_key_counts[&Keycode::Key9], 4);
+        assert!(!stats.individual_key_counts.contains_key(&Keycode::Key8));
+        for (code, count) in seen {
+            assert_eq!(stats.individual_key_counts[&code], count);
+        }
+        assert_eq!(stats.unseen_keys(&config), [Keycode::Key8]);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut stats = Stats::new();
+        let mut processor = KeyState::default();
+        type_keys(
+            &mut stats,
+            &mut processor,
+            &[Keycode::A, Keycode::B, Keycode::C],
+        );
+        let path = std::env::temp_dir().join("keyboard_layout_generator_stats.json");
+        let path = path.to_str().unwrap();
+        stats.save(path).unwrap();
+        let loaded = Stats::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.individual_key_counts, stats.individual_key_counts);
+        assert_eq!(
+            loaded.consecutive_char_counts,
+            stats.consecutive_char_counts
+        );
+        assert_eq!(loaded.trigram_key_counts, stats.trigram_key_counts);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut stats = Stats::new();
+        stats.char_counts.insert('a', 2);
+        let mut other = Stats::new();
+        other.char_counts.insert('a', 3);
+        other.char_counts.insert('b', 1);
+        other.total_log_lines = 10;
+        stats.merge(other);
+        assert_eq!(stats.char_counts.get(&'a'), Some(&5));
+        assert_eq!(stats.char_counts.get(&'b'), Some(&1));
+        assert_eq!(stats.total_log_lines, 10);
+    }
+
+    #[test]
+    fn test_transparent_modifiers() {
+        // `Ab`, with Shift released before B
+        let events = [
+            (Keycode::LShift, true),
+            (Keycode::A, true),
+            (Keycode::A, false),
+            (Keycode::LShift, false),
+            (Keycode::B, true),
+            (Keycode::B, false),
+        ];
+        let count = |transparent: bool| {
+            let mut processor = KeyProcessor::new().with_transparent_modifiers(transparent);
+            for (key, press) in events {
+                processor.push_event(key, press);
+            }
+            processor.finish()
+        };
+
+        let stats = count(false);
+        assert_eq!(stats.consecutive_key_counts.len(), 3);
+        assert_eq!(
+            stats
+                .consecutive_key_counts
+                .get(&(Keycode::LShift, Keycode::B)),
+            Some(&1)
+        );
+
+        let stats = count(true);
+        assert_eq!(
+            stats.consecutive_key_counts,
+            HashMap::from([((Keycode::A, Keycode::B), 1)])
+        );
+        assert_eq!(stats.individual_key_counts.get(&Keycode::LShift), Some(&1));
+        assert_eq!(
+            stats.consecutive_char_counts,
+            HashMap::from([(('A', 'b'), 1)])
+        );
+        assert_eq!(
+            stats.shifted_char_counts,
+            HashMap::from([(('A', Hand::Left), 1)])
+        );
+    }
+
+    #[test]
+    fn test_chords_and_rollover() {
+        let mut processor = KeyProcessor::new();
+        // Ctrl-C, a roll from T to H, and Ctrl-Shift-T
+        for (key, press) in [
+            (Keycode::LControl, true),
+            (Keycode::C, true),
+            (Keycode::C, false),
+            (Keycode::LControl, false),
+            (Keycode::T, true),
+            (Keycode::H, true),
+            (Keycode::T, false),
+            (Keycode::H, false),
+            (Keycode::LControl, true),
+            (Keycode::LShift, true),
+            (Keycode::T, true),
+            (Keycode::T, false),
+            (Keycode::LShift, false),
+            (Keycode::LControl, false),
+        ] {
+            processor.push_event(key, press);
+        }
+        // The same with timestamps, with a quick Ctrl-C and a slow roll that
+        // holds T with H for a while, and then Shift with A brushed on the
+        // way
+        for (key, press, timestamp) in [
+            (Keycode::LControl, true, 0),
+            (Keycode::C, true, 100),
+            (Keycode::C, false, 120),
+            (Keycode::LControl, false, 200),
+            (Keycode::T, true, 1000),
+            (Keycode::H, true, 1100),
+            (Keycode::T, false, 1200),
+            (Keycode::H, false, 1250),
+            (Keycode::LShift, true, 2000),
+            (Keycode::A, true, 2100),
+            (Keycode::A, false, 2105),
+            (Keycode::LShift, false, 2200),
+        ] {
+            processor.push_timed_event(key, press, timestamp);
+        }
+        let stats = processor.finish();
+        let ctrl_c = vec![Keycode::C, Keycode::LControl];
+        let th = vec![Keycode::H, Keycode::T];
+        let ctrl_shift_t = vec![Keycode::LControl, Keycode::LShift, Keycode::T];
+        let shift_a = vec![Keycode::A, Keycode::LShift];
+        assert_eq!(
+            stats.simultaneous_key_counts,
+            HashMap::from([(ctrl_c, 2), (ctrl_shift_t, 1)])
+        );
+        assert_eq!(
+            stats.rollover_key_counts,
+            HashMap::from([(th, 2), (shift_a, 1)])
+        );
+    }
+
+    #[test]
+    fn test_char_to_key_event() {
+        assert_eq!(char_to_key_event('a'), Some((Keycode::A, false)));
+        assert_eq!(char_to_key_event('A'), Some((Keycode::A, true)));
+        assert_eq!(char_to_key_event('~'), Some((Keycode::Grave, true)));
+        assert_eq!(char_to_key_event('é'), None);
+    }
+
+    #[test]
+    fn test_intervals() {
+        let mut stats = Stats::new();
+        let mut processor = KeyState::default();
+        for (key, timestamp) in [(Keycode::A, 1000), (Keycode::B, 1125), (Keycode::C, 9000)] {
+            processor.process_timing(key, true, timestamp, &mut stats);
+        }
+        assert_eq!(stats.interval_histogram.get(&120), Some(&1));
+        assert_eq!(stats.interval_histogram.len(), 1);
+        let latency = &stats.latencies.bigrams[&(Keycode::A, Keycode::B)];
+        assert_eq!(latency.mean_ms(), 125.0);
+        assert_eq!(latency.median_ms(), 125.0);
+
+        // A character every 120 ms is 100 words per minute
+        let mut processor = KeyProcessor::new();
+        for i in 0..2 * WPM_WINDOW_CHARS {
+            processor.push_timed_event(Keycode::A, true, i * 120);
+            processor.push_timed_event(Keycode::A, false, i * 120 + 50);
+        }
+        let stats = processor.finish();
+        assert_eq!(stats.rolling_wpm.len(), 2);
+        assert_eq!(stats.rolling_wpm[1], 100.0);
+    }
+
+    #[test]
+    fn test_typing_speed() {
+        let mut processor = KeyProcessor::new();
+        let mut timestamp = 0;
+        // A window of A and B, slower from B to A, then after a pause that
+        // isn't timed, a window of C and D typed fast
+        for i in 0..WPM_WINDOW_CHARS {
+            let key = if i % 2 == 0 { Keycode::A } else { Keycode::B };
+            processor.push_timed_event(key, true, timestamp);
+            processor.push_timed_event(key, false, timestamp + 30);
+            timestamp += if i % 2 == 0 { 100 } else { 200 };
+        }
+        timestamp += 5000;
+        for i in 0..WPM_WINDOW_CHARS {
+            let key = if i % 2 == 0 { Keycode::C } else { Keycode::D };
+            processor.push_timed_event(key, true, timestamp);
+            processor.push_timed_event(key, false, timestamp + 30);
+            timestamp += 60;
+        }
+        let mut stats = processor.finish();
+
+        let wpm = |ms: f64| WPM_WINDOW_CHARS as f64 / 5.0 / (ms / 60_000.0);
+        assert_eq!(
+            stats.rolling_wpm,
+            [wpm(50.0 * 100.0 + 49.0 * 200.0), wpm(99.0 * 60.0)]
+        );
+
+        let latencies = &stats.latencies;
+        assert!(!latencies.bigrams.contains_key(&(Keycode::B, Keycode::C)));
+        assert_eq!(latencies.bigrams[&(Keycode::A, Keycode::B)].count, 50);
+        assert_eq!(
+            latencies.bigrams[&(Keycode::B, Keycode::A)].median_ms(),
+            205.0
+        );
+        assert_eq!(latencies.keys[&Keycode::B].mean_ms(), 100.0);
+        assert_eq!(
+            latencies.transitions_by_speed(1),
+            [
+                ((Keycode::C, Keycode::D), 65.0),
+                ((Keycode::D, Keycode::C), 65.0),
+                ((Keycode::A, Keycode::B), 105.0),
+                ((Keycode::B, Keycode::A), 205.0),
+            ]
+        );
+
+        // Weighted by how slow they are against the median of 65 ms, and
+        // the pair that wasn't timed keeps its count
+        let latencies = std::mem::take(&mut stats.latencies);
+        latencies.weight_stats(&mut stats);
+        let count = |pair| stats.consecutive_key_counts[&pair];
+        assert_eq!(count((Keycode::A, Keycode::B)), 81);
+        assert_eq!(count((Keycode::B, Keycode::A)), 155);
+        assert_eq!(count((Keycode::C, Keycode::D)), 50);
+        assert_eq!(count((Keycode::B, Keycode::C)), 1);
+    }
+
+    #[test]
+    fn test_trigrams() {
+        let mut stats = Stats::new();
+        let mut processor = KeyState::default();
+        type_keys(
+            &mut stats,
+            &mut processor,
+            &[Keycode::T, Keycode::H, Keycode::E, Keycode::N],
+        );
+        assert_eq!(stats.trigram_char_counts.get(&('t', 'h', 'e')), Some(&1));
+        assert_eq!(stats.trigram_char_counts.get(&('h', 'e', 'n')), Some(&1));
+        assert_eq!(
+            stats
+                .trigram_key_counts
+                .get(&(Keycode::T, Keycode::H, Keycode::E)),
+            Some(&1)
+        );
+        assert_eq!(stats.trigram_key_counts.len(), 2);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut processor = KeyProcessor::new();
+        for key in [Keycode::H, Keycode::I] {
+            processor.push_event(key, true);
+            processor.push_event(key, false);
+        }
+        let snapshot = processor.snapshot();
+        assert_eq!(snapshot.word_counts.get("hi"), Some(&1));
+        assert_eq!(snapshot.total_log_lines, 4);
+
+        processor.push_event(Keycode::N, true);
+        let stats = processor.finish();
+        assert_eq!(stats.word_counts.get("hin"), Some(&1));
+        assert_eq!(stats.word_counts.get("hi"), None);
+    }
+
+    #[test]
+    fn test_remaps() {
+        let remaps = HashMap::from([(Keycode::CapsLock, Keycode::LShift)]);
+        let mut processor = KeyProcessor::with_remaps(remaps);
+        processor.push_event(Keycode::CapsLock, true);
+        processor.push_event(Keycode::A, true);
+        processor.push_event(Keycode::A, false);
+        processor.push_event(Keycode::CapsLock, false);
+        let stats = processor.finish();
+        assert_eq!(stats.char_counts.get(&'A'), Some(&1));
+        assert_eq!(stats.individual_key_counts.get(&Keycode::LShift), Some(&1));
+        assert_eq!(stats.individual_key_counts.get(&Keycode::CapsLock), None);
+    }
+
+    #[test]
+    fn test_log_headers() {
+        let header = LogHeader {
+            hostname: Some("desk".to_string()),
+            os: "linux x86_64".to_string(),
+            os_layout: Some("de".to_string()),
+            ..LogHeader::default()
+        };
+        let mut log = vec![];
+        crate::capture::write_header(&mut log, &header).unwrap();
+        let log = format!(
+            "# tag gaming\nA 1\nA 0\n{}B 1\nB 0\n",
+            String::from_utf8(log).unwrap()
+        );
+        let path = std::env::temp_dir().join("keyboard_layout_generator_headers.log");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, log).unwrap();
+        let options = LogOptions {
+            exclude_tagged: vec!["gaming".to_string()],
+            ..LogOptions::default()
+        };
+        let stats = process_log_with(path, &options).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(stats.log_headers, [header]);
+        // The header ends the gaming session of the previous run
+        assert_eq!(stats.individual_key_counts.get(&Keycode::A), None);
+        assert_eq!(stats.individual_key_counts.get(&Keycode::B), Some(&1));
+    }
+
+    #[test]
+    fn test_devices() {
+        let mut log = vec![];
+        for (device, key) in [("laptop", "A"), ("split", "B"), ("laptop", "C")] {
+            let header = LogHeader {
+                device: Some(device.to_string()),
+                ..LogHeader::default()
+            };
+            crate::capture::write_header(&mut log, &header).unwrap();
+            log.extend(format!("{key} 1\n{key} 0\n").bytes());
+        }
+        // Typed on another keyboard during the last run
+        log.extend(b"# device split\nD 1\nD 0\n");
+        let path = std::env::temp_dir().join("keyboard_layout_generator_devices.log");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, log).unwrap();
+        let devices = process_log_by_device(path, &LogOptions::default()).unwrap();
+        let options = LogOptions {
+            device: Some("laptop".to_string()),
+            ..LogOptions::default()
+        };
+        let laptop = process_log_with(path, &options).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let names: Vec<_> = devices
+            .iter()
+            .map(|(device, _)| device.as_deref())
+            .collect();
+        assert_eq!(names, [Some("laptop"), Some("split")]);
+        assert_eq!(devices[0].1.total_log_lines, 6);
+        assert_eq!(laptop.individual_key_counts.len(), 2);
+        assert_eq!(laptop.individual_key_counts.get(&Keycode::B), None);
+        assert_eq!(laptop.log_headers.len(), 2);
+        assert_eq!(devices[1].1.individual_key_counts.len(), 2);
+        assert_eq!(laptop.individual_key_counts.get(&Keycode::D), None);
+    }
+
+    #[test]
+    fn test_date_range() {
+        let lines = || {
+            let mut lines = vec![];
+            for (key, timestamp) in [
+                (Keycode::A, 999),
+                (Keycode::B, 1000),
+                (Keycode::C, 1500),
+                (Keycode::D, 2000),
+            ] {
+                lines.push(LogLine::Event(key, true, Some(timestamp)));
+                lines.push(LogLine::Event(key, false, Some(timestamp + 40)));
+            }
+            // Lines of logs from before timestamps
+            lines.push(LogLine::Event(Keycode::E, true, None));
+            lines.push(LogLine::Event(Keycode::E, false, None));
+            lines
+        };
+        let stats = |since, until| {
+            let options = LogOptions {
+                since,
+                until,
+                ..LogOptions::default()
+            };
+            let mut processor = LogProcessor::new(&options);
+            for line in lines() {
+                processor.push_line(line);
+            }
+            let stats = processor.snapshot();
+            let mut keys: Vec<Keycode> = stats.individual_key_counts.keys().copied().collect();
+            keys.sort_by_key(|key| key.to_string());
+            (keys, stats)
+        };
+
+        // From the start of the range up to but not including its end, and
+        // untimed lines only without a range
+        let (keys, in_range) = stats(Some(1000), Some(2000));
+        assert_eq!(keys, [Keycode::B, Keycode::C]);
+        assert_eq!(
+            in_range.consecutive_key_counts,
+            HashMap::from([((Keycode::B, Keycode::C), 1)])
+        );
+        // Every line still counts towards the lines read
+        assert_eq!(in_range.total_log_lines, 10);
+        assert_eq!(
+            stats(Some(1000), None).0,
+            [Keycode::B, Keycode::C, Keycode::D]
+        );
+        assert_eq!(stats(None, Some(1000)).0, [Keycode::A]);
+        assert_eq!(
+            stats(None, None).0,
+            [Keycode::A, Keycode::B, Keycode::C, Keycode::D, Keycode::E]
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let log = "A 1\nA 0\nVolumeUp 1\nVolumeUp 0\nMute 1\nMute 0\nVolumeUp 1\nVolumeUp 0\n";
+        let path = std::env::temp_dir().join("keyboard_layout_generator_unknown.log");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, log).unwrap();
+        let options = LogOptions {
+            aliases: HashMap::from([("Mute".to_string(), Keycode::F13)]),
+            ..LogOptions::default()
+        };
+        let stats = process_log_with(path, &options).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(stats.total_log_lines, 8);
+        assert_eq!(stats.individual_key_counts.get(&Keycode::A), Some(&1));
+        assert_eq!(stats.individual_key_counts.get(&Keycode::F13), Some(&1));
+        assert_eq!(
+            stats.ignored_key_counts,
+            HashMap::from([("VolumeUp".to_string(), 2)])
+        );
+    }
+}
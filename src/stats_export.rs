@@ -0,0 +1,203 @@
+//! The frequency tables of [`Stats`] as JSON or CSV, to load them into a
+//! spreadsheet, pandas, or another analyzer instead of reading them off
+//! the output of `collect_stats`.
+//!
+//! Every row of a table is what was counted, like the two keys of a
+//! bigram, and how often, most frequent first.  The CSV has a row for every
+//! row of every table, with as many item columns as the longest one needs:
+//!
+//! ```text
+//! table,item1,item2,item3,count
+//! individual_keys,E,,,1402
+//! key_bigrams,T,H,,311
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::stats::Stats;
+
+/// One frequency table of [`Stats`], like the key bigrams.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyTable {
+    pub name: &'static str,
+    /// What was counted and how often, most frequent first.
+    pub rows: Vec<(Vec<String>, u64)>,
+}
+
+/// `counts` as a table named `name`, with `items` naming what every count
+/// is of.  Ties are ordered by their items so the output is the same every
+/// time.
+fn table<K>(
+    name: &'static str,
+    counts: &HashMap<K, u64>,
+    items: impl Fn(&K) -> Vec<String>,
+) -> FrequencyTable {
+    let mut rows: Vec<(Vec<String>, u64)> = counts
+        .iter()
+        .map(|(key, count)| (items(key), *count))
+        .collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    FrequencyTable { name, rows }
+}
+
+/// Every frequency table of `stats`.  Keys are named like in the key log.
+pub fn frequency_tables(stats: &Stats) -> Vec<FrequencyTable> {
+    let one = |item: &dyn ToString| vec![item.to_string()];
+    vec![
+        table("individual_keys", &stats.individual_key_counts, |code| {
+            one(code)
+        }),
+        table("key_bigrams", &stats.consecutive_key_counts, |(a, b)| {
+            vec![a.to_string(), b.to_string()]
+        }),
+        table("key_trigrams", &stats.trigram_key_counts, |(a, b, c)| {
+            vec![a.to_string(), b.to_string(), c.to_string()]
+        }),
+        table("chords", &stats.simultaneous_key_counts, |codes| {
+            codes.iter().map(|code| code.to_string()).collect()
+        }),
+        table("rollover", &stats.rollover_key_counts, |codes| {
+            codes.iter().map(|code| code.to_string()).collect()
+        }),
+        table("chars", &stats.char_counts, |c| one(c)),
+        table("shifted_chars", &stats.shifted_char_counts, |(c, hand)| {
+            vec![c.to_string(), format!("{:?}", hand)]
+        }),
+        table("char_bigrams", &stats.consecutive_char_counts, |(a, b)| {
+            vec![a.to_string(), b.to_string()]
+        }),
+        table("char_trigrams", &stats.trigram_char_counts, |(a, b, c)| {
+            vec![a.to_string(), b.to_string(), c.to_string()]
+        }),
+        table("words", &stats.word_counts, |word| one(word)),
+        table("mistyped_keys", &stats.error_key_counts, |code| one(code)),
+        table(
+            "confused_keys",
+            &stats.confusion_counts,
+            |(typed, meant)| vec![typed.to_string(), meant.to_string()],
+        ),
+        table("corrected_chars", &stats.corrected_char_counts, |c| one(c)),
+        table(
+            "correction_bigrams",
+            &stats.correction_bigram_counts,
+            |(a, b)| vec![a.to_string(), b.to_string()],
+        ),
+        table("correction_lengths", &stats.correction_lengths, |length| {
+            one(length)
+        }),
+        table("numeric_bursts", &stats.numeric_burst_lengths, |length| {
+            one(length)
+        }),
+        table("interval_ms", &stats.interval_histogram, |bucket| {
+            one(bucket)
+        }),
+        table("ignored_keys", &stats.ignored_key_counts, |name| one(name)),
+    ]
+}
+
+/// Writes `tables` as a JSON object with an array of
+/// `{"items": [...], "count": n}` rows for every table.
+pub fn write_json(tables: &[FrequencyTable], w: &mut impl Write) -> io::Result<()> {
+    let object: Map<String, Value> = tables
+        .iter()
+        .map(|table| {
+            let rows = table
+                .rows
+                .iter()
+                .map(|(items, count)| json!({ "items": items, "count": count }))
+                .collect();
+            (table.name.to_string(), Value::Array(rows))
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *w, &object)?;
+    writeln!(w)
+}
+
+/// Writes `tables` as a single CSV table, see the [module](self).
+pub fn write_csv(tables: &[FrequencyTable], w: &mut impl Write) -> io::Result<()> {
+    let width = tables
+        .iter()
+        .flat_map(|table| &table.rows)
+        .map(|(items, _)| items.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let items: Vec<String> = (1..=width).map(|i| format!("item{i}")).collect();
+    writeln!(w, "table,{},count", items.join(","))?;
+    for table in tables {
+        for (items, count) in &table.rows {
+            let mut fields = vec![table.name.to_string()];
+            fields.extend(items.iter().map(|item| csv_field(item)));
+            fields.resize(width + 1, String::new());
+            fields.push(count.to_string());
+            writeln!(w, "{}", fields.join(","))?;
+        }
+    }
+    Ok(())
+}
+
+/// Quotes `field` if it has a comma, a quote or a line break, or spaces
+/// that would otherwise get trimmed, like the space character.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device_query::Keycode;
+
+    #[test]
+    fn test_export() {
+        let mut stats = Stats::new();
+        stats.individual_key_counts.insert(Keycode::A, 3);
+        stats.individual_key_counts.insert(Keycode::B, 5);
+        stats
+            .consecutive_key_counts
+            .insert((Keycode::A, Keycode::B), 2);
+        stats
+            .simultaneous_key_counts
+            .insert(vec![Keycode::C, Keycode::LControl, Keycode::LShift], 1);
+        stats.consecutive_char_counts.insert((',', ' '), 4);
+
+        let tables = frequency_tables(&stats);
+        let individual = tables
+            .iter()
+            .find(|table| table.name == "individual_keys")
+            .unwrap();
+        assert_eq!(
+            individual.rows,
+            [(vec!["B".to_string()], 5), (vec!["A".to_string()], 3)]
+        );
+
+        let mut csv = vec![];
+        write_csv(&tables, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "table,item1,item2,item3,count\n\
+             individual_keys,B,,,5\n\
+             individual_keys,A,,,3\n\
+             key_bigrams,A,B,,2\n\
+             chords,C,LControl,LShift,1\n\
+             char_bigrams,\",\",\" \",,4\n"
+        );
+
+        let mut json = vec![];
+        write_json(&tables, &mut json).unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json["key_bigrams"],
+            json!([{ "items": ["A", "B"], "count": 2 }])
+        );
+        assert_eq!(json["words"], json!([]));
+    }
+}
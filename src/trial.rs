@@ -0,0 +1,278 @@
+//! Measuring typing during a timed trial session, so a generated layout can
+//! be compared against how one types on their current layout.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use device_query::Keycode;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serde_util::{keycode_map, keycode_pair_map},
+    stats::{translate_key_to_char, Stats, INTERVAL_BUCKET_MS},
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrialReport {
+    pub duration_secs: f64,
+    pub key_presses: u64,
+    pub char_presses: u64,
+    pub backspaces: u64,
+    /// Keys that were backspaced right after being typed.
+    #[serde(with = "keycode_map", default)]
+    pub mistyped_keys: HashMap<Keycode, u64>,
+    #[serde(skip)]
+    last_char: Option<Keycode>,
+}
+
+impl TrialReport {
+    pub fn record(&mut self, code: Keycode, press: bool) {
+        if !press {
+            return;
+        }
+        self.key_presses += 1;
+        if code == Keycode::Backspace {
+            self.backspaces += 1;
+            if let Some(mistyped) = self.last_char.take() {
+                *self.mistyped_keys.entry(mistyped).or_insert(0) += 1;
+            }
+        } else if translate_key_to_char(&code, false).is_some() {
+            self.char_presses += 1;
+            self.last_char = Some(code);
+        }
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration_secs = duration.as_secs_f64();
+    }
+
+    /// Backspaces per character typed.
+    pub fn error_rate(&self) -> f64 {
+        if self.char_presses == 0 {
+            return 0.0;
+        }
+        self.backspaces as f64 / self.char_presses as f64
+    }
+
+    /// Characters typed per minute, not counting those that were deleted.
+    pub fn chars_per_minute(&self) -> f64 {
+        if self.duration_secs == 0.0 {
+            return 0.0;
+        }
+        let kept = self.char_presses.saturating_sub(self.backspaces);
+        kept as f64 * 60.0 / self.duration_secs
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+/// The time between consecutive key presses, summed up so it can be
+/// accumulated over sessions.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Latency {
+    pub total_ms: f64,
+    pub count: u64,
+    /// How many of the intervals fell in each bucket of
+    /// [`INTERVAL_BUCKET_MS`], keyed by its lower bound.  Latencies saved
+    /// before it was recorded have none.
+    #[serde(default)]
+    pub histogram: HashMap<u64, u64>,
+}
+
+impl Latency {
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total_ms / self.count as f64
+    }
+
+    /// The middle of the bucket holding the median interval, or the mean
+    /// without a histogram.
+    pub fn median_ms(&self) -> f64 {
+        let mut buckets: Vec<_> = self.histogram.iter().collect();
+        buckets.sort();
+        let total: u64 = self.histogram.values().sum();
+        let mut below = 0;
+        for (bucket, count) in buckets {
+            below += count;
+            if below * 2 >= total {
+                return (bucket + INTERVAL_BUCKET_MS / 2) as f64;
+            }
+        }
+        self.mean_ms()
+    }
+
+    pub(crate) fn add(&mut self, ms: f64) {
+        self.total_ms += ms;
+        self.count += 1;
+        let bucket = ms as u64 / INTERVAL_BUCKET_MS * INTERVAL_BUCKET_MS;
+        *self.histogram.entry(bucket).or_insert(0) += 1;
+    }
+
+    fn merge(&mut self, other: &Latency) {
+        self.total_ms += other.total_ms;
+        self.count += other.count;
+        for (bucket, count) in &other.histogram {
+            *self.histogram.entry(*bucket).or_insert(0) += count;
+        }
+    }
+}
+
+/// Flight times measured during typing tests, per key pressed and per pair
+/// of consecutively pressed keys.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LatencyStats {
+    #[serde(with = "keycode_map")]
+    pub keys: HashMap<Keycode, Latency>,
+    #[serde(with = "keycode_pair_map")]
+    pub bigrams: HashMap<(Keycode, Keycode), Latency>,
+}
+
+impl LatencyStats {
+    pub fn merge(&mut self, other: LatencyStats) {
+        for (code, latency) in other.keys {
+            self.keys.entry(code).or_default().merge(&latency);
+        }
+        for (bigram, latency) in other.bigrams {
+            self.bigrams.entry(bigram).or_default().merge(&latency);
+        }
+    }
+
+    /// The pairs of keys measured at least `min_count` times with their
+    /// median latency, fastest first.
+    pub fn transitions_by_speed(&self, min_count: u64) -> Vec<((Keycode, Keycode), f64)> {
+        let mut transitions: Vec<_> = self
+            .bigrams
+            .iter()
+            .filter(|(_, latency)| latency.count >= min_count)
+            .map(|(bigram, latency)| (*bigram, latency.median_ms()))
+            .collect();
+        transitions.sort_by(|a, b| {
+            a.1.total_cmp(&b.1)
+                .then_with(|| format!("{:?}", a.0).cmp(&format!("{:?}", b.0)))
+        });
+        transitions
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Scales the consecutive key counts in `stats` by how slow each pair of
+    /// keys was relative to the median of all pairs, so the optimizer works
+    /// harder on the transitions that are actually slow.  Medians keep a
+    /// few interrupted transitions from standing out.  Pairs that weren't
+    /// measured keep their counts.
+    pub fn weight_stats(&self, stats: &mut Stats) {
+        let mut total = Latency::default();
+        for latency in self.bigrams.values() {
+            total.merge(latency);
+        }
+        let median = total.median_ms();
+        if median == 0.0 {
+            return;
+        }
+        for (bigram, count) in stats.consecutive_key_counts.iter_mut() {
+            if let Some(latency) = self.bigrams.get(bigram) {
+                *count = (*count as f64 * latency.median_ms() / median).round() as u64;
+            }
+        }
+    }
+}
+
+/// Records the latencies of key presses as they happen.
+#[derive(Default)]
+pub struct LatencyRecorder {
+    last_press: Option<(Keycode, Instant)>,
+    pub stats: LatencyStats,
+}
+
+impl LatencyRecorder {
+    /// Continues recording on top of previously measured latencies.
+    pub fn new(stats: LatencyStats) -> Self {
+        Self {
+            last_press: None,
+            stats,
+        }
+    }
+
+    pub fn record(&mut self, code: Keycode, press: bool, at: Instant) {
+        if !press {
+            return;
+        }
+        if let Some((last_code, last_at)) = self.last_press {
+            let ms = at.duration_since(last_at).as_secs_f64() * 1000.0;
+            self.stats.keys.entry(code).or_default().add(ms);
+            self.stats
+                .bigrams
+                .entry((last_code, code))
+                .or_default()
+                .add(ms);
+        }
+        self.last_press = Some((code, at));
+    }
+
+    /// Forgets the last press, e.g. when the typist pauses to read a new
+    /// prompt.
+    pub fn pause(&mut self) {
+        self.last_press = None;
+    }
+}
+
+/// A typing test prompt of `n_words` words drawn from the `max_vocabulary`
+/// most common words in `stats`, weighted by how often they were typed.
+pub fn typing_test_prompt<R: Rng>(
+    stats: &Stats,
+    n_words: usize,
+    max_vocabulary: usize,
+    rng: &mut R,
+) -> Option<String> {
+    let mut words: Vec<_> = stats.word_counts.iter().collect();
+    words.sort_by_key(|x| std::cmp::Reverse(x.1));
+    words.truncate(max_vocabulary);
+    let distribution = WeightedIndex::new(words.iter().map(|(_, count)| **count)).ok()?;
+    let prompt: Vec<_> = (0..n_words)
+        .map(|_| words[distribution.sample(rng)].0.as_str())
+        .collect();
+    Some(prompt.join(" "))
+}
+
+/// Runs a trial session through `remapper` until `duration` has passed,
+/// handing every remapped event to `on_event` as well.
+#[cfg(target_os = "linux")]
+pub fn run_session(
+    remapper: &mut crate::remap::uinput::UinputRemapper,
+    duration: Duration,
+    mut on_event: impl FnMut(Keycode, bool),
+) -> std::io::Result<TrialReport> {
+    let start = std::time::Instant::now();
+    let mut report = TrialReport::default();
+    while start.elapsed() < duration {
+        remapper.process(|code, press| {
+            report.record(code, press);
+            on_event(code, press);
+        })?;
+    }
+    report.set_duration(start.elapsed());
+    Ok(report)
+}
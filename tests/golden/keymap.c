@@ -0,0 +1,17 @@
+#include QMK_KEYBOARD_H
+
+enum layers {
+    _BASE,
+};
+
+const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {
+    [_BASE] = LAYOUT(
+        KC_2, KC_LBRC, KC_RBRC, KC_S, KC_SPC, KC_V,
+        KC_1, KC_F, KC_Y, KC_R, KC_9, KC_BSPC,
+        KC_D, KC_T, KC_E, KC_5, KC_8, KC_H,
+        KC_LCTL, KC_UP, KC_Q, KC_6, KC_7, KC_RCTL,
+        KC_G, KC_DOWN, KC_A, KC_3, KC_PGUP, KC_PGDN,
+        KC_W, KC_B, KC_RSFT, KC_LSFT, KC_X, KC_N,
+        KC_LEFT, KC_RGHT, KC_Z, KC_4, KC_C
+    ),
+};
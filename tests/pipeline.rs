@@ -0,0 +1,132 @@
+//! Runs the whole pipeline on a tiny keyboard, from a synthetic key log to
+//! stats, a seeded search, the analysis, and the exports, and compares every
+//! output to the golden files in `tests/golden`.  Changes to scoring or the
+//! formats that change results fail here instead of going unnoticed.
+//!
+//! After a deliberate change, accept the new outputs with:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --test pipeline
+//! ```
+
+use std::{fmt::Write, path::Path};
+
+use keyboard_layout_generator::{
+    capture::write_synthetic_log,
+    cost_model::CostModel,
+    format::{layout_to_str, parse_keymap_config},
+    layout::Layout,
+    qmk::export_qmk,
+    scoring::{hand_patterns, same_finger_bigrams, thumb_load, LayoutScorer, ScoringConfig},
+    search::{simmulated_annealing, Schedule},
+    stats::{process_log, Stats},
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+const SEED: u64 = 7;
+
+#[test]
+fn test_pipeline() {
+    let keymap_config = parse_keymap_config(include_str!("data/tiny.layout")).unwrap();
+    let scoring = ScoringConfig::default();
+
+    // Synthetic log to stats, through a file like the keylogger's
+    let mut log = vec![];
+    write_synthetic_log(&mut log, include_str!("data/text.txt")).unwrap();
+    let log_path = std::env::temp_dir().join(format!("pipeline_{}.log", std::process::id()));
+    std::fs::write(&log_path, log).unwrap();
+    let mut stats = process_log(log_path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&log_path).unwrap();
+    check_golden("stats.txt", &stats_summary(&stats));
+    stats.count_keys_by_char(&keymap_config);
+
+    // A short search that finds the same layout every time
+    let max_possible_score = scoring.max_possible_score(&stats);
+    let score = |layout: &Layout| scoring.score(layout, &stats, &keymap_config);
+    let layout = simmulated_annealing(
+        &score,
+        &keymap_config.unpinned(),
+        max_possible_score,
+        &Schedule {
+            min_temperature: 0.01,
+            cooling: 0.999,
+            ..Schedule::default()
+        },
+        Layout::from_keymap(&keymap_config),
+        &mut StdRng::seed_from_u64(SEED),
+    );
+    check_golden(
+        "layout.txt",
+        &layout_to_str(&layout, &keymap_config).unwrap(),
+    );
+
+    let mut analysis = String::new();
+    let patterns = hand_patterns(&layout, &stats, &keymap_config);
+    let sfbs = same_finger_bigrams(&layout, &stats, &keymap_config);
+    let thumbs = thumb_load(&layout, &stats, &keymap_config);
+    writeln!(analysis, "score: {:.6}", score(&layout)).unwrap();
+    writeln!(analysis, "max possible score: {:.6}", max_possible_score).unwrap();
+    writeln!(analysis, "bigrams: {}", patterns.bigrams).unwrap();
+    writeln!(analysis, "alternations: {}", patterns.alternations).unwrap();
+    writeln!(analysis, "inward rolls: {}", patterns.inward_rolls).unwrap();
+    writeln!(analysis, "outward rolls: {}", patterns.outward_rolls).unwrap();
+    writeln!(analysis, "same finger bigrams: {}", sfbs.count).unwrap();
+    writeln!(analysis, "same finger distance: {:.6}", sfbs.weighted).unwrap();
+    writeln!(analysis, "left thumb: {}", thumbs.left_thumb).unwrap();
+    writeln!(analysis, "right thumb: {}", thumbs.right_thumb).unwrap();
+    check_golden("analysis.txt", &analysis);
+
+    check_golden(
+        "keymap.c",
+        &export_qmk(&layout, &[], &[], &keymap_config, 200).keymap_c,
+    );
+    let mut transition = vec![];
+    CostModel::new(&keymap_config, &scoring)
+        .write_transition_csv(&mut transition)
+        .unwrap();
+    check_golden("transition.csv", &String::from_utf8(transition).unwrap());
+}
+
+/// The counts of `stats`, sorted so they don't depend on the order of its
+/// maps.
+fn stats_summary(stats: &Stats) -> String {
+    let mut keys: Vec<String> = stats
+        .individual_key_counts
+        .iter()
+        .map(|(code, count)| format!("{:?} {}", code, count))
+        .collect();
+    keys.sort();
+    let mut bigrams: Vec<String> = stats
+        .consecutive_key_counts
+        .iter()
+        .map(|((a, b), count)| format!("{:?} {:?} {}", a, b, count))
+        .collect();
+    bigrams.sort();
+    format!("{}\n\n{}\n", keys.join("\n"), bigrams.join("\n"))
+}
+
+/// Compares `actual` to the golden file `name`, or overwrites it with
+/// `actual` when `UPDATE_GOLDEN` is set.
+fn check_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing, run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} changed, run with UPDATE_GOLDEN=1 to accept it if that's intended\n--- expected\n{}\n--- actual\n{}",
+        name,
+        expected,
+        actual
+    );
+}